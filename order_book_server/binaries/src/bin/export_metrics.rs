@@ -0,0 +1,70 @@
+#![allow(unused_crate_dependencies)]
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use clap::{Parser, ValueEnum};
+use server::market_metrics::{MetricsDatabase, export};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    /// Requires the `parquet` feature
+    Parquet,
+}
+
+/// Export a coin's stored metrics for a time range.
+/// Reads the database URL from the `DATABASE_URL` environment variable.
+#[derive(Debug, Parser)]
+#[command(author, version, about)]
+struct Args {
+    /// Coin to export (e.g., BTC)
+    #[arg(long)]
+    coin: String,
+
+    /// Start of the range, inclusive, as RFC 3339 (e.g., 2025-01-01T00:00:00Z)
+    #[arg(long)]
+    start: DateTime<Utc>,
+
+    /// End of the range, exclusive, as RFC 3339. Defaults to now.
+    #[arg(long)]
+    end: Option<DateTime<Utc>>,
+
+    /// Output format: csv, parquet
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+
+    /// File to write to. Defaults to stdout.
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// Tenant whose rows to export, as set by TENANT on the monitor
+    #[arg(long)]
+    tenant: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+
+    let args = Args::parse();
+    let database_url = std::env::var("DATABASE_URL").map_err(|_| "DATABASE_URL must be set")?;
+    let db = MetricsDatabase::new(&database_url, 1).await?.with_tenant(args.tenant.as_deref(), false)?;
+    let end = args.end.unwrap_or_else(Utc::now);
+
+    let writer: Box<dyn Write + Send> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    let rows = match args.format {
+        Format::Csv => export::export_csv(&db, &args.coin, args.start, end, writer).await?,
+        #[cfg(feature = "parquet")]
+        Format::Parquet => export::export_parquet(&db, &args.coin, args.start, end, writer).await?,
+        #[cfg(not(feature = "parquet"))]
+        Format::Parquet => return Err("Parquet export requires building with the `parquet` feature".into()),
+    };
+    eprintln!("Exported {rows} {} rows", args.coin);
+
+    Ok(())
+}
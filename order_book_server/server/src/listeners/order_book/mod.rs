@@ -335,6 +335,11 @@ impl OrderBookListener {
         self.order_book_state.as_mut().map(|o| o.compute_snapshot())
     }
 
+    // number of book diffs applied for a coin since the book was initialized
+    pub(crate) fn update_count(&self, coin: &Coin) -> u64 {
+        self.order_book_state.as_ref().map_or(0, |o| o.update_count(coin))
+    }
+
     // prevent snapshotting mutiple times at the same height
     fn l2_snapshots(&mut self, prevent_future_snaps: bool) -> Option<(u64, L2Snapshots)> {
         self.order_book_state.as_mut().and_then(|o| o.l2_snapshots(prevent_future_snaps))
@@ -19,6 +19,8 @@ pub(super) struct OrderBookState {
     time: u64,
     snapped: bool,
     ignore_spot: bool,
+    // cumulative number of book diffs applied per coin
+    update_counts: HashMap<Coin, u64>,
 }
 
 impl OrderBookState {
@@ -35,6 +37,7 @@ impl OrderBookState {
             height,
             order_book: OrderBooks::from_snapshots(snapshot, ignore_triggers),
             snapped: false,
+            update_counts: HashMap::new(),
         }
     }
 
@@ -57,6 +60,10 @@ impl OrderBookState {
         }
     }
 
+    pub(super) fn update_count(&self, coin: &Coin) -> u64 {
+        self.update_counts.get(coin).copied().unwrap_or_default()
+    }
+
     pub(super) fn compute_universe(&self) -> HashSet<Coin> {
         self.order_book.as_ref().keys().cloned().collect()
     }
@@ -93,6 +100,7 @@ impl OrderBookState {
             if coin.is_spot() && self.ignore_spot {
                 continue;
             }
+            *self.update_counts.entry(coin.clone()).or_default() += 1;
             let inner_diff = diff.diff().try_into()?;
             match inner_diff {
                 InnerOrderDiff::New { sz } => {
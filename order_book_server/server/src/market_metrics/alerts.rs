@@ -0,0 +1,545 @@
+use crate::market_metrics::types::MarketMetrics;
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How urgently an alert needs attention, ordered from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertSeverity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl AlertSeverity {
+    /// Grade how far `observed` goes past `threshold`: below it is `Info`, up to
+    /// `critical_multiple` times it is `Warning`, and beyond that `Critical`
+    #[must_use]
+    pub fn from_breach(observed: Decimal, threshold: Decimal, critical_multiple: Decimal) -> Self {
+        if threshold <= Decimal::ZERO || observed < threshold {
+            Self::Info
+        } else if observed < threshold * critical_multiple {
+            Self::Warning
+        } else {
+            Self::Critical
+        }
+    }
+}
+
+impl FromStr for AlertSeverity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "info" => Ok(Self::Info),
+            "warning" => Ok(Self::Warning),
+            "critical" => Ok(Self::Critical),
+            other => Err(format!("Unknown alert severity '{other}': expected info, warning or critical")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    /// `total_depth_5pct` fell sharply relative to its short rolling average
+    DepthCollapse,
+    /// A configured [`AlertRule`] on this metric was breached
+    Threshold(AlertMetric),
+}
+
+/// Metric an [`AlertRule`] watches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+    SpreadPct,
+    TotalDepth5pct,
+    /// Absolute funding rate, so rules catch extremes in either direction
+    FundingRatePct,
+}
+
+impl AlertMetric {
+    #[must_use]
+    pub fn value(self, metrics: &MarketMetrics) -> Option<Decimal> {
+        match self {
+            Self::SpreadPct => metrics.spread_pct,
+            Self::TotalDepth5pct => metrics.total_depth_5pct,
+            Self::FundingRatePct => metrics.funding_rate_pct.map(|rate| rate.abs()),
+        }
+    }
+}
+
+impl FromStr for AlertMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "spread_pct" => Ok(Self::SpreadPct),
+            "total_depth_5pct" => Ok(Self::TotalDepth5pct),
+            "funding_rate_pct" => Ok(Self::FundingRatePct),
+            other => Err(format!(
+                "Unknown alert metric '{other}': expected spread_pct, total_depth_5pct or funding_rate_pct"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertCondition {
+    Above,
+    Below,
+}
+
+/// Fires when a market's metric crosses a threshold, e.g. `spread_pct > 0.5`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub metric: AlertMetric,
+    pub condition: AlertCondition,
+    pub threshold: Decimal,
+}
+
+impl AlertRule {
+    /// How many times over the threshold `value` is, when it breaches the rule
+    fn breach(&self, value: Decimal) -> Option<Decimal> {
+        match self.condition {
+            AlertCondition::Above if value > self.threshold && self.threshold > Decimal::ZERO => {
+                Some(value / self.threshold)
+            }
+            AlertCondition::Above if value > self.threshold => Some(Decimal::MAX),
+            AlertCondition::Below if value < self.threshold && value > Decimal::ZERO => Some(self.threshold / value),
+            AlertCondition::Below if value < self.threshold => Some(Decimal::MAX),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for AlertRule {
+    type Err = String;
+
+    /// Parse `metric>threshold` or `metric<threshold`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (metric, condition, threshold) = if let Some((metric, threshold)) = s.split_once('>') {
+            (metric, AlertCondition::Above, threshold)
+        } else if let Some((metric, threshold)) = s.split_once('<') {
+            (metric, AlertCondition::Below, threshold)
+        } else {
+            return Err(format!("Invalid alert rule '{s}': expected metric>threshold or metric<threshold"));
+        };
+        Ok(Self {
+            metric: metric.parse()?,
+            condition,
+            threshold: threshold
+                .trim()
+                .parse()
+                .map_err(|e| format!("Invalid alert rule threshold '{threshold}': {e}"))?,
+        })
+    }
+}
+
+/// Evaluates per-market [`AlertRule`]s against each metrics row.
+///
+/// A rule fires once when it is first breached and stays quiet for as long as
+/// it remains breached, firing again only after the market has recovered.
+#[derive(Debug)]
+pub struct AlertEngine {
+    rules: HashMap<String, Vec<AlertRule>>,
+    critical_multiple: Decimal,
+    /// Rules currently breached, by coin and index into its rules
+    breached: HashSet<(String, usize)>,
+}
+
+impl AlertEngine {
+    /// A rule breached by `critical_multiple` times its threshold or more is critical
+    #[must_use]
+    pub fn new(rules: HashMap<String, Vec<AlertRule>>, critical_multiple: Decimal) -> Self {
+        Self { rules, critical_multiple, breached: HashSet::new() }
+    }
+
+    /// Alerts for rules that became breached with this row
+    pub fn evaluate(&mut self, metrics: &MarketMetrics) -> Vec<Alert> {
+        let Some(rules) = self.rules.get(&metrics.coin) else {
+            return Vec::new();
+        };
+
+        let mut alerts = Vec::new();
+        for (index, rule) in rules.iter().enumerate() {
+            let key = (metrics.coin.clone(), index);
+            let Some(value) = rule.metric.value(metrics) else {
+                continue;
+            };
+            let Some(breach) = rule.breach(value) else {
+                self.breached.remove(&key);
+                continue;
+            };
+            if self.breached.insert(key) {
+                let condition = if rule.condition == AlertCondition::Above { "above" } else { "below" };
+                alerts.push(Alert::new(
+                    &metrics.coin,
+                    AlertKind::Threshold(rule.metric),
+                    AlertSeverity::from_breach(breach, Decimal::ONE, self.critical_multiple),
+                    format!("{:?} at {value} is {condition} {}", rule.metric, rule.threshold),
+                ));
+            }
+        }
+        alerts
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub coin: String,
+    pub kind: AlertKind,
+    pub severity: AlertSeverity,
+    pub message: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Alert {
+    #[must_use]
+    pub fn new(coin: &str, kind: AlertKind, severity: AlertSeverity, message: String) -> Self {
+        Self { coin: coin.to_string(), kind, severity, message, timestamp: Utc::now() }
+    }
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{:?}/{:?}] {}: {}", self.severity, self.kind, self.coin, self.message)
+    }
+}
+
+/// Suppresses repeat alerts of the same kind for a coin within a cooldown period.
+///
+/// An alert more severe than the last one fired always goes through, so an
+/// escalation is never held back by the cooldown of a milder alert.
+#[derive(Debug)]
+pub struct AlertCooldowns {
+    cooldown: Duration,
+    last_fired: HashMap<(String, AlertKind), (DateTime<Utc>, AlertSeverity)>,
+    /// File the cooldowns are saved to whenever an alert fires
+    state_path: Option<PathBuf>,
+}
+
+/// One [`AlertCooldowns`] entry as saved in the state file
+#[derive(Debug, Serialize, Deserialize)]
+struct FiredAlert {
+    coin: String,
+    kind: AlertKind,
+    severity: AlertSeverity,
+    fired_at: DateTime<Utc>,
+}
+
+impl AlertCooldowns {
+    #[must_use]
+    pub fn new(cooldown: Duration) -> Self {
+        Self { cooldown, last_fired: HashMap::new(), state_path: None }
+    }
+
+    /// Save the cooldowns to `path` after every fired alert, first restoring any
+    /// saved there by a previous run. A missing file starts with no cooldowns, and
+    /// an unreadable one is logged and replaced.
+    pub fn with_state_file(mut self, path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        match fs::read(&path) {
+            Ok(bytes) => match serde_json::from_slice::<Vec<FiredAlert>>(&bytes) {
+                Ok(fired) => {
+                    self.last_fired = fired
+                        .into_iter()
+                        .map(|alert| ((alert.coin, alert.kind), (alert.fired_at, alert.severity)))
+                        .collect();
+                }
+                Err(e) => warn!("Ignoring unreadable alert state file {}: {e}", path.display()),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        self.state_path = Some(path);
+        Ok(self)
+    }
+
+    /// Write the cooldowns to a temporary file and rename it over the state
+    /// file, so a crash mid-write never leaves it truncated
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let fired: Vec<FiredAlert> = self
+            .last_fired
+            .iter()
+            .map(|((coin, kind), (fired_at, severity))| FiredAlert {
+                coin: coin.clone(),
+                kind: *kind,
+                severity: *severity,
+                fired_at: *fired_at,
+            })
+            .collect();
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(&fired)?)?;
+        fs::rename(tmp, path)
+    }
+
+    /// Returns whether the alert may fire, recording it as fired if so
+    pub fn try_fire(&mut self, alert: &Alert) -> bool {
+        let key = (alert.coin.clone(), alert.kind);
+        let cooling_down = self.last_fired.get(&key).is_some_and(|(last, severity)| {
+            alert.severity <= *severity
+                && alert.timestamp.signed_duration_since(*last).to_std().is_ok_and(|elapsed| elapsed < self.cooldown)
+        });
+        if cooling_down {
+            return false;
+        }
+        self.last_fired.insert(key, (alert.timestamp, alert.severity));
+        if let Some(path) = &self.state_path
+            && let Err(e) = self.save(path)
+        {
+            error!("Failed to save alert state to {}: {e}", path.display());
+        }
+        true
+    }
+}
+
+/// A destination alerts are delivered to
+pub trait AlertSink: Send + Sync {
+    /// Deliver the alert without blocking the caller
+    fn send(&self, alert: &Alert);
+}
+
+/// Writes alerts to the application log
+pub struct LogSink;
+
+impl AlertSink for LogSink {
+    fn send(&self, alert: &Alert) {
+        warn!("🚨 {alert}");
+    }
+}
+
+/// Posts each alert as JSON to a webhook URL, e.g. a Slack or `PagerDuty` integration
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookSink {
+    #[must_use]
+    pub fn new(url: String) -> Self {
+        Self { client: reqwest::Client::new(), url }
+    }
+}
+
+impl AlertSink for WebhookSink {
+    fn send(&self, alert: &Alert) {
+        let request = self.client.post(&self.url).json(alert);
+        let url = self.url.clone();
+        tokio::spawn(async move {
+            if let Err(e) = request.send().await.and_then(reqwest::Response::error_for_status) {
+                error!("Failed to deliver alert to {url}: {e}");
+            }
+        });
+    }
+}
+
+/// Name of the built-in sink that writes to the log
+pub const LOG_SINK: &str = "log";
+
+/// Sends each alert to the sinks configured for its severity
+pub struct AlertRouter {
+    sinks: HashMap<String, Arc<dyn AlertSink>>,
+    routes: HashMap<AlertSeverity, Vec<String>>,
+}
+
+impl AlertRouter {
+    /// Severities without a route go to the log only
+    #[must_use]
+    pub fn new(routes: HashMap<AlertSeverity, Vec<String>>) -> Self {
+        let mut sinks: HashMap<String, Arc<dyn AlertSink>> = HashMap::new();
+        sinks.insert(LOG_SINK.to_string(), Arc::new(LogSink));
+        Self { sinks, routes }
+    }
+
+    /// Register a sink under `name`, replacing any with the same name
+    pub fn add_sink(&mut self, name: &str, sink: Arc<dyn AlertSink>) {
+        self.sinks.insert(name.to_string(), sink);
+    }
+
+    /// Check that every routed sink has been registered
+    pub fn validate(&self) -> Result<(), String> {
+        for (severity, names) in &self.routes {
+            if let Some(name) = names.iter().find(|name| !self.sinks.contains_key(*name)) {
+                return Err(format!("Alert route for {severity:?} uses unknown sink '{name}'"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Deliver the alert, returning the names of the sinks it went to
+    #[must_use]
+    pub fn route(&self, alert: &Alert) -> Vec<&str> {
+        let names = self
+            .routes
+            .get(&alert.severity)
+            .map_or_else(|| vec![LOG_SINK], |names| names.iter().map(String::as_str).collect());
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let (name, sink) = self.sinks.get_key_value(name)?;
+                sink.send(alert);
+                Some(name.as_str())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market_metrics::test_util::temp_dir;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_cooldown_suppresses_repeat_alerts() {
+        let mut cooldowns = AlertCooldowns::new(Duration::from_secs(90));
+        let first = Alert::new("BTC", AlertKind::DepthCollapse, AlertSeverity::Warning, "depth collapsed".to_string());
+        assert!(cooldowns.try_fire(&first));
+
+        let mut repeat = first.clone();
+        repeat.timestamp = first.timestamp + chrono::Duration::seconds(60);
+        assert!(!cooldowns.try_fire(&repeat));
+
+        // An escalation is not held back by the milder alert's cooldown
+        let mut escalated = repeat.clone();
+        escalated.severity = AlertSeverity::Critical;
+        assert!(cooldowns.try_fire(&escalated));
+
+        // Other coins are tracked independently
+        let other = Alert::new("ETH", AlertKind::DepthCollapse, AlertSeverity::Warning, "depth collapsed".to_string());
+        assert!(cooldowns.try_fire(&other));
+
+        let mut later = escalated.clone();
+        later.timestamp = escalated.timestamp + chrono::Duration::seconds(91);
+        assert!(cooldowns.try_fire(&later));
+    }
+
+    #[test]
+    fn test_cooldown_survives_restart() {
+        let dir = temp_dir("alert_state");
+        let path = dir.join("cooldowns.json");
+        let cooldown = Duration::from_mins(5);
+
+        let mut cooldowns = AlertCooldowns::new(cooldown).with_state_file(&path).unwrap();
+        let kind = AlertKind::Threshold(AlertMetric::SpreadPct);
+        let first = Alert::new("BTC", kind, AlertSeverity::Warning, "spread wide".to_string());
+        assert!(cooldowns.try_fire(&first));
+        drop(cooldowns);
+
+        // After a restart the still-breached rule re-fires, but is held back
+        let mut restarted = AlertCooldowns::new(cooldown).with_state_file(&path).unwrap();
+        let mut repeat = first.clone();
+        repeat.timestamp = first.timestamp + chrono::Duration::seconds(60);
+        assert!(!restarted.try_fire(&repeat));
+
+        let mut later = first.clone();
+        later.timestamp = first.timestamp + chrono::Duration::seconds(301);
+        assert!(restarted.try_fire(&later));
+
+        // A corrupt state file starts fresh rather than failing startup
+        fs::write(&path, "not json").unwrap();
+        let mut fresh = AlertCooldowns::new(cooldown).with_state_file(&path).unwrap();
+        assert!(fresh.try_fire(&repeat));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    struct RecordingSink(Mutex<Vec<AlertSeverity>>);
+
+    impl AlertSink for RecordingSink {
+        fn send(&self, alert: &Alert) {
+            self.0.lock().unwrap().push(alert.severity);
+        }
+    }
+
+    #[test]
+    fn test_severe_breach_escalates_to_other_sink() {
+        let mut router = AlertRouter::new(HashMap::from([
+            (AlertSeverity::Warning, vec!["slack".to_string()]),
+            (AlertSeverity::Critical, vec!["pagerduty".to_string()]),
+        ]));
+        assert!(router.validate().is_err());
+
+        let slack = Arc::new(RecordingSink(Mutex::new(Vec::new())));
+        let pagerduty = Arc::new(RecordingSink(Mutex::new(Vec::new())));
+        router.add_sink("slack", slack.clone());
+        router.add_sink("pagerduty", pagerduty.clone());
+        router.validate().unwrap();
+
+        // A 50% depth drop threshold, escalating at 1.5x
+        let (threshold, critical_multiple) = (Decimal::new(5, 1), Decimal::new(15, 1));
+        let alert = |drop: Decimal| {
+            let severity = AlertSeverity::from_breach(drop, threshold, critical_multiple);
+            Alert::new("BTC", AlertKind::DepthCollapse, severity, format!("depth dropped {drop}"))
+        };
+
+        assert_eq!(router.route(&alert(Decimal::new(55, 2))), vec!["slack"]);
+        assert_eq!(router.route(&alert(Decimal::new(9, 1))), vec!["pagerduty"]);
+        assert_eq!(*slack.0.lock().unwrap(), vec![AlertSeverity::Warning]);
+        assert_eq!(*pagerduty.0.lock().unwrap(), vec![AlertSeverity::Critical]);
+
+        // Unrouted severities fall back to the log
+        assert_eq!(router.route(&alert(Decimal::new(1, 1))), vec![LOG_SINK]);
+    }
+
+    #[test]
+    fn test_rule_fires_once_until_recovered() {
+        let rules = HashMap::from([(
+            "BTC".to_string(),
+            vec!["spread_pct>0.5".parse().unwrap(), "total_depth_5pct<1000".parse().unwrap()],
+        )]);
+        let mut engine = AlertEngine::new(rules, Decimal::from(2));
+        let metrics = |spread: &str, depth: i64| {
+            let mut metrics = MarketMetrics::new("BTC".to_string());
+            metrics.spread_pct = Some(spread.parse().unwrap());
+            metrics.total_depth_5pct = Some(Decimal::from(depth));
+            metrics
+        };
+
+        assert!(engine.evaluate(&metrics("0.1", 5000)).is_empty());
+
+        let alerts = engine.evaluate(&metrics("0.6", 5000));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind, AlertKind::Threshold(AlertMetric::SpreadPct));
+        assert_eq!(alerts[0].severity, AlertSeverity::Warning);
+
+        // Still breached, and now critical, but already fired
+        assert!(engine.evaluate(&metrics("1.5", 5000)).is_empty());
+
+        // A depth crash fires independently of the spread rule
+        let alerts = engine.evaluate(&metrics("1.5", 100));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind, AlertKind::Threshold(AlertMetric::TotalDepth5pct));
+        assert_eq!(alerts[0].severity, AlertSeverity::Critical);
+
+        // Recovering re-arms the rule
+        assert!(engine.evaluate(&metrics("0.1", 100)).is_empty());
+        assert_eq!(engine.evaluate(&metrics("0.7", 100)).len(), 1);
+
+        // Markets without rules never alert
+        assert!(engine.evaluate(&MarketMetrics::new("ETH".to_string())).is_empty());
+    }
+
+    #[test]
+    fn test_funding_rule_uses_absolute_rate() {
+        let rule: AlertRule = "funding_rate_pct>0.05".parse().unwrap();
+        let mut engine = AlertEngine::new(HashMap::from([("BTC".to_string(), vec![rule])]), Decimal::from(2));
+        let mut metrics = MarketMetrics::new("BTC".to_string());
+        metrics.funding_rate_pct = Some(Decimal::new(-8, 2));
+        assert_eq!(engine.evaluate(&metrics).len(), 1);
+
+        assert!("open_interest>5".parse::<AlertRule>().is_err());
+        assert!("spread_pct=5".parse::<AlertRule>().is_err());
+    }
+}
@@ -0,0 +1,994 @@
+use crate::market_metrics::types::{BasketMetrics, MarketMetrics, PortfolioLiquidity};
+use chrono::{DateTime, Utc};
+use rust_decimal::{Decimal, MathematicalOps};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+/// Impact-adjusted fair price: the midpoint of Hyperliquid's impact prices.
+///
+/// The plain mid only reflects top of book, while the impact prices include the
+/// cost of moving size on each side. When those costs are asymmetric the result
+/// is skewed away from `mid` towards the more expensive side.
+/// Returns `None` when either impact price is absent.
+#[must_use]
+pub fn impact_adjusted_mid(
+    mid: Decimal,
+    impact_px_bid: Option<Decimal>,
+    impact_px_ask: Option<Decimal>,
+) -> Option<Decimal> {
+    let bid = impact_px_bid?;
+    let ask = impact_px_ask?;
+
+    // Half the difference between the ask-side and bid-side impact costs
+    let skew = ((ask - mid) - (mid - bid)) / Decimal::from(2);
+    Some(mid + skew)
+}
+
+/// Sample autocorrelation of `series` at the given lag.
+///
+/// Returns `None` when the series is too short (fewer than `lag + 2` samples)
+/// or constant, since the autocorrelation is undefined in both cases.
+#[must_use]
+pub fn autocorrelation(series: &[Decimal], lag: usize) -> Option<Decimal> {
+    if series.len() < lag + 2 {
+        return None;
+    }
+
+    let mean = series.iter().sum::<Decimal>() / Decimal::from(series.len());
+    let variance: Decimal = series.iter().map(|x| (x - mean) * (x - mean)).sum();
+    if variance.is_zero() {
+        return None;
+    }
+
+    let covariance: Decimal =
+        series.iter().skip(lag).zip(series.iter()).map(|(x, lagged)| (x - mean) * (lagged - mean)).sum();
+    Some(covariance / variance)
+}
+
+/// Roll's implied effective spread from a series of observed prices.
+///
+/// Bid-ask bounce makes successive price changes negatively correlated; under
+/// Roll's model `cov(Δp_t, Δp_{t-1}) = -s²/4`, so the spread is estimated as
+/// `2 * sqrt(-cov)`. Returns `None` when the covariance is non-negative (no
+/// bounce to measure) or there are too few prices to form two changes.
+#[must_use]
+pub fn roll_implied_spread(prices: &[Decimal]) -> Option<Decimal> {
+    let changes = prices.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>();
+    if changes.len() < 2 {
+        return None;
+    }
+
+    let current = &changes[1..];
+    let previous = &changes[..changes.len() - 1];
+    let n = Decimal::from(current.len());
+    let mean_current = current.iter().sum::<Decimal>() / n;
+    let mean_previous = previous.iter().sum::<Decimal>() / n;
+    let covariance =
+        current.iter().zip(previous).map(|(c, p)| (c - mean_current) * (p - mean_previous)).sum::<Decimal>() / n;
+
+    if covariance >= Decimal::ZERO {
+        return None;
+    }
+    Some(Decimal::from(2) * (-covariance).sqrt()?)
+}
+
+/// Volume-weighted average of `(mid, volume_24h)` samples, oldest first.
+///
+/// Each mid is weighted by how much the rolling 24h volume grew since the
+/// previous sample with a volume. Samples without a volume are skipped, so the
+/// volume traded across them is credited to the next sample that has one, and
+/// decreases (volume rolling out of the 24h window) carry no weight. Returns
+/// `None` when no volume was traded.
+#[must_use]
+pub fn session_vwap(samples: &[(Decimal, Option<Decimal>)]) -> Option<Decimal> {
+    let mut last_volume = None;
+    let (mut notional, mut volume) = (Decimal::ZERO, Decimal::ZERO);
+    for &(mid, volume_24h) in samples {
+        let Some(volume_24h) = volume_24h else { continue };
+        if let Some(last) = last_volume.replace(volume_24h) {
+            let delta = (volume_24h - last).max(Decimal::ZERO);
+            notional += mid * delta;
+            volume += delta;
+        }
+    }
+    (volume > Decimal::ZERO).then(|| notional / volume)
+}
+
+/// Amihud illiquidity of `(mid, volume_24h)` samples, oldest first: the mean of
+/// `|ln(mid / previous mid)| / volume traded` over consecutive samples.
+///
+/// Volume traded is the growth in the rolling 24h volume, as in
+/// [`session_vwap`]. Intervals where it didn't grow are left out instead of
+/// dividing by zero, and samples without a volume or a positive mid are
+/// skipped. Higher values mean each unit of volume moves the price further.
+/// Returns `None` when no interval traded any volume.
+#[must_use]
+pub fn amihud_illiquidity(samples: &[(Decimal, Option<Decimal>)]) -> Option<Decimal> {
+    let mut last = None;
+    let (mut total, mut intervals) = (Decimal::ZERO, 0u32);
+    for &(mid, volume_24h) in samples {
+        let Some(volume_24h) = volume_24h.filter(|_| mid > Decimal::ZERO) else { continue };
+        if let Some((last_mid, last_volume)) = last.replace((mid, volume_24h)) {
+            let delta = volume_24h - last_volume;
+            if delta > Decimal::ZERO {
+                total += (mid / last_mid).ln().abs() / delta;
+                intervals += 1;
+            }
+        }
+    }
+    (intervals > 0).then(|| total / Decimal::from(intervals))
+}
+
+/// Levels of one side at least `size_multiple` times that side's average level
+/// size and at least `min_distance_pct` (a fraction) away from `mid`, the
+/// candidates watched for spoofing
+#[must_use]
+pub fn large_far_levels(
+    levels: &[(Decimal, Decimal)],
+    mid: Decimal,
+    size_multiple: Decimal,
+    min_distance_pct: Decimal,
+) -> Vec<(Decimal, Decimal)> {
+    if levels.is_empty() || mid <= Decimal::ZERO {
+        return Vec::new();
+    }
+    let avg_size = levels.iter().map(|(_, size)| size).sum::<Decimal>() / Decimal::from(levels.len());
+    levels
+        .iter()
+        .filter(|(price, size)| *size >= avg_size * size_multiple && (price - mid).abs() / mid >= min_distance_pct)
+        .copied()
+        .collect()
+}
+
+/// Relative change in depth over `window` after each volatility spike, as `(spike_time, depth_change)`.
+///
+/// `samples` are `(timestamp, realized_vol, depth)`, oldest first. A spike is a
+/// sample whose volatility is at least `spike_factor` times the mean over all
+/// samples. Its response compares the depth of the first sample at least
+/// `window` later with the depth at the spike, so `-0.3` means the book was 30%
+/// thinner. Samples within the window of a spike don't start another one, and
+/// spikes too recent for a full window are left out.
+#[must_use]
+pub fn depth_response_to_vol_spikes(
+    samples: &[(DateTime<Utc>, Decimal, Decimal)],
+    spike_factor: Decimal,
+    window: chrono::Duration,
+) -> Vec<(DateTime<Utc>, Decimal)> {
+    if samples.is_empty() {
+        return Vec::new();
+    }
+    let mean_vol = samples.iter().map(|(_, vol, _)| vol).sum::<Decimal>() / Decimal::from(samples.len());
+    if mean_vol <= Decimal::ZERO {
+        return Vec::new();
+    }
+
+    let mut responses = Vec::new();
+    let mut quiet_from = None;
+    for (i, &(at, vol, depth)) in samples.iter().enumerate() {
+        if vol < mean_vol * spike_factor || depth <= Decimal::ZERO || quiet_from.is_some_and(|until| at < until) {
+            continue;
+        }
+        let Some((_, _, later)) = samples[i + 1..].iter().find(|(t, ..)| *t >= at + window) else {
+            break;
+        };
+        responses.push((at, later / depth - Decimal::ONE));
+        quiet_from = Some(at + window);
+    }
+    responses
+}
+
+/// Sample standard deviation of simple returns between consecutive prices.
+///
+/// Returns `None` with fewer than two returns or a non-positive price.
+#[must_use]
+pub fn return_volatility(prices: &[Decimal]) -> Option<Decimal> {
+    let returns = prices
+        .windows(2)
+        .map(|w| (w[0] > Decimal::ZERO).then(|| w[1] / w[0] - Decimal::ONE))
+        .collect::<Option<Vec<_>>>()?;
+    if returns.len() < 2 {
+        return None;
+    }
+
+    let n = Decimal::from(returns.len());
+    let mean = returns.iter().sum::<Decimal>() / n;
+    let variance = returns.iter().map(|r| (r - mean) * (r - mean)).sum::<Decimal>() / (n - Decimal::ONE);
+    variance.sqrt()
+}
+
+/// Stability of a depth series as `1 / (1 + cv)`, where `cv` is the sample
+/// coefficient of variation.
+///
+/// Scores near 1 mean depth held steady over the window and scores near 0 mean
+/// it swung widely relative to its mean. Returns `None` with fewer than two
+/// samples or a non-positive mean.
+#[must_use]
+pub fn depth_stability(depths: &[Decimal]) -> Option<Decimal> {
+    if depths.len() < 2 {
+        return None;
+    }
+
+    let n = Decimal::from(depths.len());
+    let mean = depths.iter().sum::<Decimal>() / n;
+    if mean <= Decimal::ZERO {
+        return None;
+    }
+    let variance = depths.iter().map(|d| (d - mean) * (d - mean)).sum::<Decimal>() / (n - Decimal::ONE);
+    let cv = variance.sqrt()? / mean;
+    Some(Decimal::ONE / (Decimal::ONE + cv))
+}
+
+/// Top-of-book microprice `(best_bid * ask_size + best_ask * bid_size) / (bid_size + ask_size)`.
+///
+/// Weights the mid towards the side with less resting size, since that queue
+/// is the one more likely to be depleted next. Returns `None` without size at
+/// the best prices.
+#[must_use]
+pub fn microprice(best_bid: Decimal, best_ask: Decimal, bid_size: Decimal, ask_size: Decimal) -> Option<Decimal> {
+    let top_size = bid_size + ask_size;
+    (top_size > Decimal::ZERO).then(|| (best_bid * ask_size + best_ask * bid_size) / top_size)
+}
+
+/// Depth imbalance `(bid - ask) / (bid + ask)`, from -1 (all asks) to 1 (all bids).
+///
+/// Returns `None` when there is no depth on either side.
+#[must_use]
+pub fn depth_imbalance(bid_depth: Decimal, ask_depth: Decimal) -> Option<Decimal> {
+    let total = bid_depth + ask_depth;
+    (total > Decimal::ZERO).then(|| (bid_depth - ask_depth) / total)
+}
+
+/// Touch notional that damps the volatility term of [`mid_confidence`] by `sqrt(2)`
+pub const TOUCH_REFERENCE_NOTIONAL: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+
+/// 95% confidence band `(low, high)` for the efficient price around the mid.
+///
+/// The band is centred on the [`microprice`]. Its half-width is the sum of two terms:
+///
+/// - half the spread: any price inside the touch is consistent with the book;
+/// - `1.96 * recent_vol * mid / sqrt(1 + touch_notional / 10_000)`: the
+///   expected move over the next sampling interval. Following the square-root
+///   impact law, the same order flow moves a deeper touch less, so the
+///   volatility is damped by the square root of the notional resting at the
+///   best prices, in units of [`TOUCH_REFERENCE_NOTIONAL`]. Notional rather than
+///   base size keeps the damping comparable between coins of any price.
+///
+/// `recent_vol` is the standard deviation of mid returns per interval, as
+/// given by [`return_volatility`]. Without size on either side the band is
+/// centred on the plain mid.
+#[must_use]
+pub fn mid_confidence(
+    best_bid: Decimal,
+    best_ask: Decimal,
+    bid_size: Decimal,
+    ask_size: Decimal,
+    recent_vol: Decimal,
+) -> (Decimal, Decimal) {
+    const Z_95: Decimal = Decimal::from_parts(196, 0, 0, false, 2);
+
+    let mid = (best_bid + best_ask) / Decimal::TWO;
+    let touch_notional = (bid_size * best_bid + ask_size * best_ask).max(Decimal::ZERO);
+    let center = microprice(best_bid, best_ask, bid_size, ask_size).unwrap_or(mid);
+
+    let damping = (Decimal::ONE + touch_notional / TOUCH_REFERENCE_NOTIONAL).sqrt().unwrap_or(Decimal::ONE);
+    let half_width = (best_ask - best_bid) / Decimal::TWO + Z_95 * recent_vol.abs() * mid / damping;
+    (center - half_width, center + half_width)
+}
+
+/// Funding rate discounted by how much liquidity there is to trade it.
+///
+/// The rate is scaled by `total_depth_5pct / reference_depth`, capped at 1, so
+/// funding on a book at least as deep as the reference passes through unchanged
+/// while the same rate on a thin book is a proportionally weaker signal. An
+/// empty book, or a non-positive reference, gives zero.
+#[must_use]
+pub fn liquidity_weighted_funding(
+    funding_rate_pct: Decimal,
+    total_depth_5pct: Decimal,
+    reference_depth: Decimal,
+) -> Decimal {
+    if total_depth_5pct <= Decimal::ZERO || reference_depth <= Decimal::ZERO {
+        return Decimal::ZERO;
+    }
+    funding_rate_pct * (total_depth_5pct / reference_depth).min(Decimal::ONE)
+}
+
+/// Basis in percent the perp should trade at over the oracle given the funding
+/// still to accrue before the next payment: `funding_rate_pct * hours_to_funding`.
+///
+/// Hyperliquid quotes an hourly rate, so a longer wait before the next payment
+/// means more funding priced into the mark. Negative hours count as zero.
+#[must_use]
+pub fn funding_implied_basis(funding_rate_pct: Decimal, hours_to_funding: Decimal) -> Decimal {
+    funding_rate_pct * hours_to_funding.max(Decimal::ZERO)
+}
+
+/// Hours from `at` until the next hourly Hyperliquid funding payment, in `(0, 1]`
+#[must_use]
+pub fn hours_to_next_funding(at: DateTime<Utc>) -> Decimal {
+    let seconds_into_hour = at.timestamp().rem_euclid(3600);
+    Decimal::from(3600 - seconds_into_hour) / Decimal::from(3600)
+}
+
+/// Observed mark-oracle basis minus the [`funding_implied_basis`], in percentage points.
+///
+/// Positive values mean the perp trades richer than funding explains. Returns
+/// `None` without funding timing or with a non-positive oracle price.
+#[must_use]
+pub fn basis_divergence(
+    mark_price: Decimal,
+    oracle_price: Decimal,
+    funding_rate_pct: Decimal,
+    hours_to_funding: Option<Decimal>,
+) -> Option<Decimal> {
+    let hours_to_funding = hours_to_funding?;
+    if oracle_price <= Decimal::ZERO {
+        return None;
+    }
+    let observed = (mark_price - oracle_price) / oracle_price * Decimal::ONE_HUNDRED;
+    Some(observed - funding_implied_basis(funding_rate_pct, hours_to_funding))
+}
+
+/// Premium of `mark_price` over another exchange's `reference_mark`, in percent.
+///
+/// Positive values mean the perp trades richer on Hyperliquid. Returns `None`
+/// with a non-positive reference mark.
+#[must_use]
+pub fn cross_exchange_basis_pct(mark_price: Decimal, reference_mark: Decimal) -> Option<Decimal> {
+    if reference_mark <= Decimal::ZERO {
+        return None;
+    }
+    Some((mark_price - reference_mark) / reference_mark * Decimal::ONE_HUNDRED)
+}
+
+/// Elasticity of cumulative depth with respect to distance from the mid.
+///
+/// For one side of the book (`levels` as `(price, size)`, best first), fits the
+/// log-log slope of cumulative notional depth against the percent distance of
+/// each level from `mid`. A slope of 1 means depth grows proportionally with
+/// distance; higher values mean liquidity is concentrated further out.
+/// Returns `None` with fewer than three usable levels.
+#[must_use]
+pub fn depth_elasticity(levels: &[(Decimal, Decimal)], mid: Decimal) -> Option<Decimal> {
+    if mid <= Decimal::ZERO {
+        return None;
+    }
+
+    let mut cumulative = Decimal::ZERO;
+    let points = levels
+        .iter()
+        .filter_map(|(price, size)| {
+            cumulative += price * size;
+            let distance = (price - mid).abs() / mid;
+            Some((distance.checked_ln()?, cumulative.checked_ln()?))
+        })
+        .collect::<Vec<_>>();
+
+    if points.len() < 3 {
+        return None;
+    }
+    ols_slope(&points)
+}
+
+/// Spread between the size-weighted average ask and bid prices.
+///
+/// Each level (`(price, size)`) contributes in proportion to its size, so when
+/// most of the size sits behind a thin top of book this is wider than the
+/// touch spread. Returns `None` when either side has no size.
+#[must_use]
+pub fn size_weighted_spread(bid_levels: &[(Decimal, Decimal)], ask_levels: &[(Decimal, Decimal)]) -> Option<Decimal> {
+    let vwap = |levels: &[(Decimal, Decimal)]| {
+        let size: Decimal = levels.iter().map(|(_, size)| size).sum();
+        (size > Decimal::ZERO).then(|| levels.iter().map(|(price, size)| price * size).sum::<Decimal>() / size)
+    };
+    Some(vwap(ask_levels)? - vwap(bid_levels)?)
+}
+
+/// Aggregate liquidity across the latest metrics of each market.
+///
+/// Markets without depth are left out of the depth total and count. The spread
+/// is weighted by `open_interest`, already a notional value, so markets
+/// missing it or a spread don't contribute to it.
+#[must_use]
+pub fn portfolio_liquidity(markets: &[MarketMetrics], timestamp: DateTime<Utc>) -> PortfolioLiquidity {
+    let depths = markets.iter().filter_map(|m| m.total_depth_5pct).collect::<Vec<_>>();
+
+    let weighted = markets
+        .iter()
+        .filter_map(|m| Some((m.spread_pct?, m.open_interest?)))
+        .filter(|(_, weight)| *weight > Decimal::ZERO)
+        .collect::<Vec<_>>();
+    let total_weight = weighted.iter().map(|(_, weight)| weight).sum::<Decimal>();
+    let weighted_spread_pct = (!total_weight.is_zero())
+        .then(|| weighted.iter().map(|(spread, weight)| spread * weight).sum::<Decimal>() / total_weight);
+
+    PortfolioLiquidity {
+        timestamp,
+        total_depth_5pct: depths.iter().sum(),
+        weighted_spread_pct,
+        market_count: i32::try_from(depths.len()).unwrap_or(i32::MAX),
+    }
+}
+
+/// Weighted mid, spread and depth of a basket from `(component metrics, weight)` pairs.
+///
+/// Weights are renormalized over the components that have each value, so a
+/// component missing its mid or spread is left out of that average rather
+/// than dragging it towards zero. Depth is summed unweighted.
+#[must_use]
+pub fn basket_metrics(components: &[(MarketMetrics, Decimal)]) -> BasketMetrics {
+    let weighted_mean = |values: Vec<(Decimal, Decimal)>| {
+        let total_weight = values.iter().map(|(_, weight)| weight).sum::<Decimal>();
+        (total_weight > Decimal::ZERO)
+            .then(|| values.iter().map(|(value, weight)| value * weight).sum::<Decimal>() / total_weight)
+    };
+
+    let mids = components.iter().filter_map(|(m, weight)| Some((m.mid_price?, *weight))).collect::<Vec<_>>();
+    let component_count = i32::try_from(mids.len()).unwrap_or(i32::MAX);
+    let spreads = components.iter().filter_map(|(m, weight)| Some((m.spread_pct?, *weight))).collect();
+
+    BasketMetrics {
+        mid_price: weighted_mean(mids),
+        spread_pct: weighted_mean(spreads),
+        total_depth_5pct: components.iter().filter_map(|(m, _)| m.total_depth_5pct).sum(),
+        component_count,
+    }
+}
+
+/// Mean-reversion half-life of `series`, in samples.
+///
+/// Fits the AR(1) model `x_t = a + b * x_{t-1}` by least squares; for a
+/// discretely sampled Ornstein-Uhlenbeck process the half-life is
+/// `-ln(2) / ln(b)`. Returns `None` unless `0 < b < 1`, i.e. when the series
+/// doesn't revert (random walk or trending) or oscillates, or with fewer than
+/// three samples.
+#[must_use]
+pub fn ar1_half_life(series: &[Decimal]) -> Option<Decimal> {
+    let points = series.windows(2).map(|w| (w[0], w[1])).collect::<Vec<_>>();
+    if points.len() < 2 {
+        return None;
+    }
+    let b = ols_slope(&points)?;
+    if b <= Decimal::ZERO || b >= Decimal::ONE {
+        return None;
+    }
+    Some(-Decimal::TWO.checked_ln()? / b.checked_ln()?)
+}
+
+/// Spread-capture profit of a passive market maker, per unit of base size.
+///
+/// A deliberately crude proxy for strategy research. Each row is assumed to
+/// complete one round trip (a buy and a sell of one unit), earning
+/// `spread_capture_frac` of that row's observed spread. Inventory risk, adverse
+/// selection, queue position, and fees are ignored, so the result is an upper
+/// bound on what a real strategy would earn. Rows without a spread are skipped.
+#[must_use]
+pub fn passive_mm_pnl(rows: &[MarketMetrics], spread_capture_frac: Decimal) -> Decimal {
+    rows.iter().filter_map(|row| row.spread).map(|spread| spread * spread_capture_frac).sum()
+}
+
+/// Each venue's share of price discovery for one coin, summing to 1.
+///
+/// Uses the Gonzalo-Granger component share, the usual tractable stand-in for
+/// Hasbrouck's information share (the two agree when venue innovations are
+/// uncorrelated). Each venue's price change is regressed on its lagged
+/// deviation from the other venues' mean price; a venue that has to correct
+/// towards the others is following, one that doesn't is leading. Series are
+/// aligned on their most recent common samples. A single venue gets a share
+/// of 1, and venues get equal shares when none of them adjusts.
+#[must_use]
+pub fn information_share<S: BuildHasher>(
+    prices_by_venue: &HashMap<String, Vec<Decimal>, S>,
+) -> HashMap<String, Decimal> {
+    let venue_count = prices_by_venue.len();
+    if venue_count <= 1 {
+        return prices_by_venue.keys().map(|venue| (venue.clone(), Decimal::ONE)).collect();
+    }
+
+    let len = prices_by_venue.values().map(Vec::len).min().unwrap_or_default();
+    let aligned =
+        prices_by_venue.iter().map(|(venue, prices)| (venue, &prices[prices.len() - len..])).collect::<Vec<_>>();
+    let others = Decimal::from(venue_count - 1);
+    let totals = (0..len).map(|t| aligned.iter().map(|(_, prices)| prices[t]).sum::<Decimal>()).collect::<Vec<_>>();
+
+    // Speed at which each venue corrects towards the others
+    let adjustments = aligned
+        .iter()
+        .map(|(venue, prices)| {
+            let points = (1..len)
+                .map(|t| {
+                    let deviation = prices[t - 1] - (totals[t - 1] - prices[t - 1]) / others;
+                    (deviation, prices[t] - prices[t - 1])
+                })
+                .collect::<Vec<_>>();
+            let speed = if points.len() < 2 { None } else { ols_slope(&points) };
+            ((*venue).clone(), speed.map_or(Decimal::ZERO, |alpha| (-alpha).max(Decimal::ZERO)))
+        })
+        .collect::<Vec<_>>();
+
+    let total_adjustment: Decimal = adjustments.iter().map(|(_, speed)| speed).sum();
+    let equal_share = Decimal::ONE / Decimal::from(venue_count);
+    adjustments
+        .into_iter()
+        .map(|(venue, speed)| {
+            let share = if total_adjustment.is_zero() {
+                equal_share
+            } else {
+                (Decimal::ONE - speed / total_adjustment) / others
+            };
+            (venue, share)
+        })
+        .collect()
+}
+
+/// SVG sparkline of `values` scaled to fill a `width` x `height` canvas.
+///
+/// Series longer than `width` are downsampled to one point per pixel by
+/// averaging consecutive samples. A constant series is drawn through the
+/// middle, and an empty one renders an empty canvas.
+#[cfg(feature = "sparkline")]
+#[must_use]
+pub fn render_sparkline(values: &[Decimal], width: u32, height: u32) -> Vec<u8> {
+    let bucket = values.len().div_ceil(width.max(1) as usize).max(1);
+    let points = values
+        .chunks(bucket)
+        .map(|chunk| chunk.iter().sum::<Decimal>() / Decimal::from(chunk.len()))
+        .collect::<Vec<_>>();
+
+    let min = points.iter().min().copied().unwrap_or_default();
+    let max = points.iter().max().copied().unwrap_or_default();
+    let (width_px, height_px) = (Decimal::from(width), Decimal::from(height));
+    let x_step = if points.len() > 1 { width_px / Decimal::from(points.len() - 1) } else { Decimal::ZERO };
+    let coordinates = points
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let y = if max == min { height_px / Decimal::TWO } else { height_px * (max - value) / (max - min) };
+            format!("{},{}", (x_step * Decimal::from(i)).round_dp(2).normalize(), y.round_dp(2).normalize())
+        })
+        .collect::<Vec<_>>();
+
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"0 0 {width} {height}\"><polyline fill=\"none\" stroke=\"currentColor\" \
+         stroke-width=\"1\" points=\"{}\"/></svg>",
+        coordinates.join(" ")
+    )
+    .into_bytes()
+}
+
+/// Ordinary least squares slope of `y` on `x` over `(x, y)` points
+fn ols_slope(points: &[(Decimal, Decimal)]) -> Option<Decimal> {
+    let n = Decimal::from(points.len());
+    let mean_x = points.iter().map(|(x, _)| x).sum::<Decimal>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<Decimal>() / n;
+
+    let covariance: Decimal = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let variance: Decimal = points.iter().map(|(x, _)| (x - mean_x) * (x - mean_x)).sum();
+    if variance.is_zero() {
+        return None;
+    }
+    Some(covariance / variance)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_impact_adjusted_mid_symmetric() {
+        let adjusted = impact_adjusted_mid(dec("100"), Some(dec("99")), Some(dec("101")));
+        assert_eq!(adjusted, Some(dec("100")));
+    }
+
+    #[test]
+    fn test_impact_adjusted_mid_asymmetric() {
+        // Selling is cheap (bid impact close to mid), buying is expensive
+        let mid = dec("100");
+        let adjusted = impact_adjusted_mid(mid, Some(dec("99.5")), Some(dec("102.5"))).unwrap();
+        assert_eq!(adjusted, dec("101"));
+        assert!(adjusted > mid);
+    }
+
+    #[test]
+    fn test_impact_adjusted_mid_missing_impacts() {
+        assert_eq!(impact_adjusted_mid(dec("100"), None, Some(dec("101"))), None);
+        assert_eq!(impact_adjusted_mid(dec("100"), Some(dec("99")), None), None);
+    }
+
+    #[test]
+    fn test_autocorrelation_persistent_series() {
+        // Slowly drifting spreads are highly persistent
+        let series = (0..50).map(|i| Decimal::from(100 + i)).collect::<Vec<_>>();
+        let rho = autocorrelation(&series, 1).unwrap();
+        assert!(rho > dec("0.9"), "expected high autocorrelation, got {rho}");
+    }
+
+    #[test]
+    fn test_autocorrelation_alternating_series() {
+        let series = (0..50).map(|i| Decimal::from(if i % 2 == 0 { 1 } else { 3 })).collect::<Vec<_>>();
+        let rho = autocorrelation(&series, 1).unwrap();
+        assert!(rho < dec("-0.9"), "expected negative autocorrelation, got {rho}");
+    }
+
+    #[test]
+    fn test_autocorrelation_short_or_constant_series() {
+        assert_eq!(autocorrelation(&[dec("1")], 1), None);
+        assert_eq!(autocorrelation(&[dec("1"), dec("2")], 1), None);
+        assert_eq!(autocorrelation(&[dec("2"); 10], 1), None);
+    }
+
+    #[test]
+    fn test_roll_implied_spread_bid_ask_bounce() {
+        use rand::{Rng, SeedableRng, rngs::StdRng};
+
+        // Constant fundamental value with trades randomly at the bid or the ask
+        let spread = dec("0.5");
+        let mut rng = StdRng::seed_from_u64(7);
+        let prices = (0..5000)
+            .map(|_| {
+                let half = spread / Decimal::from(2);
+                if rng.random_bool(0.5) { dec("100") + half } else { dec("100") - half }
+            })
+            .collect::<Vec<_>>();
+
+        let estimate = roll_implied_spread(&prices).unwrap();
+        assert!((estimate - spread).abs() < dec("0.05"), "estimate {estimate} too far from {spread}");
+    }
+
+    #[test]
+    fn test_roll_implied_spread_trending_series() {
+        // Steady trend has no negative serial covariance
+        let prices = (0..20).map(|i| Decimal::from(i * i)).collect::<Vec<_>>();
+        assert_eq!(roll_implied_spread(&prices), None);
+        assert_eq!(roll_implied_spread(&[dec("1"), dec("2")]), None);
+    }
+
+    #[test]
+    fn test_depth_elasticity_power_law_book() {
+        // Cumulative notional depth grows with the square of the distance from mid
+        let mid = dec("100");
+        let mut previous = Decimal::ZERO;
+        let bids = (1..=10)
+            .map(|i| {
+                let price = mid - Decimal::from(i);
+                let cumulative = Decimal::from(1000 * i * i);
+                let size = (cumulative - previous) / price;
+                previous = cumulative;
+                (price, size)
+            })
+            .collect::<Vec<_>>();
+
+        let elasticity = depth_elasticity(&bids, mid).unwrap();
+        assert!((elasticity - dec("2")).abs() < dec("0.001"), "elasticity {elasticity}");
+    }
+
+    #[test]
+    fn test_depth_elasticity_too_few_levels() {
+        let levels = [(dec("99"), dec("1")), (dec("98"), dec("1"))];
+        assert_eq!(depth_elasticity(&levels, dec("100")), None);
+
+        // A level at the mid has no log distance and is ignored
+        let levels = [(dec("100"), dec("1")), (dec("99"), dec("1")), (dec("98"), dec("1"))];
+        assert_eq!(depth_elasticity(&levels, dec("100")), None);
+    }
+
+    fn market(
+        coin: &str,
+        depth: Option<&str>,
+        spread_pct: &str,
+        open_interest: &str,
+        mark_price: &str,
+    ) -> MarketMetrics {
+        let mut metrics = MarketMetrics::new(coin.to_string());
+        metrics.total_depth_5pct = depth.map(dec);
+        metrics.spread_pct = Some(dec(spread_pct));
+        metrics.open_interest = Some(dec(open_interest));
+        metrics.mark_price = Some(dec(mark_price));
+        metrics
+    }
+
+    #[test]
+    fn test_portfolio_liquidity_aggregates_markets() {
+        let markets = [
+            // Open interest is notional, so the price doesn't weigh in again
+            market("BTC", Some("5000000"), "0.01", "1000000", "100000"),
+            market("ETH", Some("2000000"), "0.05", "3000000", "3000"),
+            // No depth reported, still contributes to the spread weighting
+            market("SOL", None, "0.1", "0", "150"),
+        ];
+
+        let snapshot = portfolio_liquidity(&markets, Utc::now());
+        assert_eq!(snapshot.total_depth_5pct, dec("7000000"));
+        assert_eq!(snapshot.market_count, 2);
+        // (0.01 * 1M + 0.05 * 3M) / 4M, SOL has zero open interest
+        assert_eq!(snapshot.weighted_spread_pct, Some(dec("0.04")));
+    }
+
+    #[test]
+    fn test_portfolio_liquidity_without_markets() {
+        let snapshot = portfolio_liquidity(&[], Utc::now());
+        assert_eq!(snapshot.total_depth_5pct, Decimal::ZERO);
+        assert_eq!(snapshot.weighted_spread_pct, None);
+        assert_eq!(snapshot.market_count, 0);
+    }
+
+    #[test]
+    fn test_passive_mm_pnl_accumulates_spread_capture() {
+        let rows = ["0.2", "0.4", "0.1"]
+            .iter()
+            .map(|spread| {
+                let mut row = MarketMetrics::new("BTC".to_string());
+                row.spread = Some(dec(spread));
+                row
+            })
+            .chain(std::iter::once(MarketMetrics::new("BTC".to_string())))
+            .collect::<Vec<_>>();
+
+        // Half of (0.2 + 0.4 + 0.1), the row without a spread contributes nothing
+        assert_eq!(passive_mm_pnl(&rows, dec("0.5")), dec("0.35"));
+        assert_eq!(passive_mm_pnl(&[], dec("0.5")), Decimal::ZERO);
+    }
+
+    /// Ornstein-Uhlenbeck-like spreads reverting to 0.05 with AR(1) coefficient `b`
+    pub(crate) fn mean_reverting_spreads(b: Decimal, n: usize, seed: u64) -> Vec<Decimal> {
+        use rand::{Rng, SeedableRng, rngs::StdRng};
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mean = dec("0.05");
+        let mut x = mean;
+        (0..n)
+            .map(|_| {
+                let noise = Decimal::from(rng.random_range(-5000..=5000)) / Decimal::from(1_000_000);
+                x = (mean + b * (x - mean) + noise).round_dp(6);
+                x
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ar1_half_life_mean_reverting_series() {
+        let series = mean_reverting_spreads(dec("0.8"), 2000, 11);
+
+        // -ln(2) / ln(0.8) ≈ 3.106 samples
+        let half_life = ar1_half_life(&series).unwrap();
+        assert!((half_life - dec("3.106")).abs() < dec("0.5"), "half-life {half_life}");
+    }
+
+    #[test]
+    fn test_ar1_half_life_non_reverting() {
+        let trending = (0..50).map(Decimal::from).collect::<Vec<_>>();
+        assert_eq!(ar1_half_life(&trending), None);
+
+        let alternating = (0..50).map(|i| Decimal::from(i % 2)).collect::<Vec<_>>();
+        assert_eq!(ar1_half_life(&alternating), None);
+        assert_eq!(ar1_half_life(&[dec("1"), dec("2")]), None);
+    }
+
+    #[test]
+    fn test_basket_metrics_weighted_components() {
+        let mut btc = MarketMetrics::new("BTC".to_string());
+        btc.mid_price = Some(dec("100"));
+        btc.spread_pct = Some(dec("0.01"));
+        btc.total_depth_5pct = Some(dec("1000"));
+        let mut eth = MarketMetrics::new("ETH".to_string());
+        eth.mid_price = Some(dec("40"));
+        eth.spread_pct = Some(dec("0.04"));
+        eth.total_depth_5pct = Some(dec("500"));
+
+        let basket = basket_metrics(&[(btc.clone(), dec("0.75")), (eth, dec("0.25"))]);
+        assert_eq!(basket.mid_price, Some(dec("85")));
+        assert_eq!(basket.spread_pct, Some(dec("0.0175")));
+        assert_eq!(basket.total_depth_5pct, dec("1500"));
+        assert_eq!(basket.component_count, 2);
+
+        // A component without data is left out and the weights renormalized
+        let basket = basket_metrics(&[(btc, dec("0.75")), (MarketMetrics::new("SOL".to_string()), dec("0.25"))]);
+        assert_eq!(basket.mid_price, Some(dec("100")));
+        assert_eq!(basket.spread_pct, Some(dec("0.01")));
+        assert_eq!(basket.component_count, 1);
+    }
+
+    #[test]
+    fn test_size_weighted_spread_wider_than_touch() {
+        // A thin touch 1 wide, with most size sitting 5 away on each side
+        let bids = [(dec("99.5"), dec("1")), (dec("95"), dec("9"))];
+        let asks = [(dec("100.5"), dec("1")), (dec("105"), dec("9"))];
+
+        let spread = size_weighted_spread(&bids, &asks).unwrap();
+        assert_eq!(spread, dec("9.1"));
+        assert!(spread > asks[0].0 - bids[0].0);
+
+        assert_eq!(size_weighted_spread(&bids, &[]), None);
+        assert_eq!(size_weighted_spread(&[(dec("99"), Decimal::ZERO)], &asks), None);
+    }
+
+    #[test]
+    fn test_information_share_favours_leading_venue() {
+        use rand::{Rng, SeedableRng, rngs::StdRng};
+
+        // The follower tracks the leader's previous price with some noise
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut leader = vec![dec("100")];
+        let mut follower = vec![dec("100")];
+        for t in 1..500 {
+            let step = Decimal::from(rng.random_range(-100..=100)) / Decimal::from(100);
+            let noise = Decimal::from(rng.random_range(-10..=10)) / Decimal::from(100);
+            leader.push(leader[t - 1] + step);
+            follower.push(leader[t - 1] + noise);
+        }
+
+        let shares =
+            information_share(&HashMap::from([("leader".to_string(), leader), ("follower".to_string(), follower)]));
+        assert!(shares["leader"] > dec("0.8"), "leader share {}", shares["leader"]);
+        assert_eq!(shares["leader"] + shares["follower"], Decimal::ONE);
+    }
+
+    #[cfg(feature = "sparkline")]
+    #[test]
+    fn test_render_sparkline_svg() {
+        let svg = render_sparkline(&[dec("1"), dec("3"), dec("2"), dec("5")], 90, 20);
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"90\" height=\"20\""));
+        assert!(svg.ends_with("</svg>"));
+        assert!(svg.contains("points=\"0,20 30,10 60,15 90,0\""), "{svg}");
+
+        // One point per pixel once the series is longer than the width
+        let long = (0..1000).map(Decimal::from).collect::<Vec<_>>();
+        let svg = String::from_utf8(render_sparkline(&long, 50, 10)).unwrap();
+        let points = svg.split("points=\"").nth(1).unwrap().split('"').next().unwrap();
+        assert_eq!(points.split(' ').count(), 50);
+        assert!(points.starts_with("0,10 ") && points.ends_with(" 50,0"));
+    }
+
+    #[test]
+    fn test_mid_confidence_widens_with_vol_and_narrows_with_depth() {
+        let width = |(low, high): (Decimal, Decimal)| high - low;
+        let (bid, ask) = (dec("99.9"), dec("100.1"));
+
+        // Without volatility the band is just the spread around a balanced book's mid
+        assert_eq!(mid_confidence(bid, ask, dec("10"), dec("10"), Decimal::ZERO), (bid, ask));
+
+        let calm = mid_confidence(bid, ask, dec("10"), dec("10"), dec("0.001"));
+        let volatile = mid_confidence(bid, ask, dec("10"), dec("10"), dec("0.01"));
+        assert!(width(volatile) > width(calm));
+
+        let deep = mid_confidence(bid, ask, dec("1000"), dec("1000"), dec("0.01"));
+        assert!(width(deep) < width(volatile));
+
+        // Damping follows notional, so a coin priced 100x lower needs 100x the size
+        let vol_term = |(low, high): (Decimal, Decimal), spread: Decimal, scale: Decimal| {
+            ((high - low - spread) * scale).round_dp(8)
+        };
+        let cheap = mid_confidence(dec("0.999"), dec("1.001"), dec("1000"), dec("1000"), dec("0.01"));
+        assert_eq!(vol_term(cheap, dec("0.002"), dec("100")), vol_term(volatile, dec("0.2"), Decimal::ONE));
+
+        // A thin ask pulls the band towards the ask
+        let (low, high) = mid_confidence(bid, ask, dec("90"), dec("10"), Decimal::ZERO);
+        assert_eq!((low + high) / Decimal::TWO, dec("100.08"));
+    }
+
+    #[test]
+    fn test_microprice_leans_towards_thin_side() {
+        let (bid, ask) = (dec("99"), dec("101"));
+        assert_eq!(microprice(bid, ask, dec("10"), dec("10")), Some(dec("100")));
+        assert_eq!(microprice(bid, ask, dec("30"), dec("10")), Some(dec("100.5")));
+        assert_eq!(microprice(bid, ask, Decimal::ZERO, Decimal::ZERO), None);
+    }
+
+    #[test]
+    fn test_session_vwap_weights_by_volume_delta() {
+        let samples = [
+            (dec("100"), Some(dec("1000"))),
+            (dec("102"), Some(dec("1010"))),
+            (dec("104"), None),
+            (dec("101"), Some(dec("1040"))),
+            (dec("103"), Some(dec("1030"))),
+        ];
+        // 10 traded at 102 and 30 at 101; the missing and falling volumes add nothing
+        assert_eq!(session_vwap(&samples), Some(dec("101.25")));
+        assert_eq!(session_vwap(&samples[..1]), None);
+        assert_eq!(session_vwap(&[(dec("100"), None), (dec("101"), None)]), None);
+    }
+
+    #[test]
+    fn test_amihud_skips_intervals_without_volume() {
+        let samples = [
+            (dec("100"), Some(dec("1000"))),
+            (dec("101"), Some(dec("1010"))),
+            (dec("101"), Some(dec("1010"))),
+            (dec("99"), Some(dec("1010"))),
+            (dec("99.99"), Some(dec("1040"))),
+        ];
+        // ln(1.01) / 10 and ln(1.01) / 30; the two moves without volume add nothing
+        assert_eq!(amihud_illiquidity(&samples).unwrap().round_dp(8), dec("0.00066336"));
+        assert_eq!(amihud_illiquidity(&samples[1..4]), None);
+        assert_eq!(amihud_illiquidity(&[(dec("100"), None), (dec("101"), None)]), None);
+    }
+
+    #[test]
+    fn test_depth_imbalance() {
+        assert_eq!(depth_imbalance(dec("300"), dec("100")), Some(dec("0.5")));
+        assert_eq!(depth_imbalance(Decimal::ZERO, dec("100")), Some(-Decimal::ONE));
+        assert_eq!(depth_imbalance(Decimal::ZERO, Decimal::ZERO), None);
+    }
+
+    #[test]
+    fn test_thin_book_discounts_funding() {
+        let reference = dec("1000000");
+        assert_eq!(liquidity_weighted_funding(dec("0.01"), dec("5000000"), reference), dec("0.01"));
+        assert_eq!(liquidity_weighted_funding(dec("-0.01"), dec("250000"), reference), dec("-0.0025"));
+        assert_eq!(liquidity_weighted_funding(dec("0.01"), Decimal::ZERO, reference), Decimal::ZERO);
+        assert_eq!(liquidity_weighted_funding(dec("0.01"), dec("250000"), Decimal::ZERO), Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_funding_implied_basis_and_divergence() {
+        assert_eq!(funding_implied_basis(dec("0.01"), dec("0.5")), dec("0.005"));
+        assert_eq!(funding_implied_basis(dec("-0.02"), dec("0.25")), dec("-0.005"));
+        assert_eq!(funding_implied_basis(dec("0.01"), dec("-1")), Decimal::ZERO);
+
+        let at = "2024-03-01T12:45:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(hours_to_next_funding(at), dec("0.25"));
+        let on_the_hour = "2024-03-01T13:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(hours_to_next_funding(on_the_hour), Decimal::ONE);
+
+        // Mark 0.02% over the oracle with 0.005% of funding still to accrue
+        let divergence = basis_divergence(dec("100.02"), dec("100"), dec("0.01"), Some(dec("0.5")));
+        assert_eq!(divergence, Some(dec("0.015")));
+        assert_eq!(basis_divergence(dec("100.02"), dec("100"), dec("0.01"), None), None);
+        assert_eq!(basis_divergence(dec("100.02"), Decimal::ZERO, dec("0.01"), Some(dec("0.5"))), None);
+    }
+
+    #[test]
+    fn test_cross_exchange_basis_pct() {
+        assert_eq!(cross_exchange_basis_pct(dec("100.5"), dec("100")), Some(dec("0.5")));
+        assert_eq!(cross_exchange_basis_pct(dec("99"), dec("100")), Some(dec("-1")));
+        assert_eq!(cross_exchange_basis_pct(dec("100"), Decimal::ZERO), None);
+    }
+
+    #[test]
+    fn test_return_volatility() {
+        assert_eq!(return_volatility(&[dec("100"), dec("101")]), None);
+        assert_eq!(return_volatility(&[dec("100"), dec("100"), dec("100")]), Some(Decimal::ZERO));
+        assert_eq!(return_volatility(&[dec("0"), dec("1"), dec("2")]), None);
+
+        // Returns of +1% and -1% have a sample standard deviation of sqrt(0.0002)
+        let vol = return_volatility(&[dec("100"), dec("101"), dec("99.99")]).unwrap();
+        assert!((vol - dec("0.0002").sqrt().unwrap()).abs() < dec("0.000001"), "{vol}");
+    }
+
+    #[test]
+    fn test_large_far_levels() {
+        let bids = [(dec("99.9"), dec("1")), (dec("99.5"), dec("30")), (dec("95"), dec("40")), (dec("94"), dec("1"))];
+        // Average size is 18: the 99.5 level is large but too close to mid
+        assert_eq!(large_far_levels(&bids, dec("100"), dec("1.5"), dec("0.01")), vec![(dec("95"), dec("40"))]);
+        assert!(large_far_levels(&[], dec("100"), dec("2"), dec("0.01")).is_empty());
+    }
+
+    #[test]
+    fn test_depth_stability_steady_vs_volatile() {
+        let steady = ["100", "101", "99", "100", "100"].map(dec);
+        let volatile = ["100", "20", "180", "50", "150"].map(dec);
+        let steady_score = depth_stability(&steady).unwrap();
+        let volatile_score = depth_stability(&volatile).unwrap();
+        assert!(steady_score > dec("0.99"), "{steady_score}");
+        assert!(volatile_score < dec("0.61"), "{volatile_score}");
+
+        assert_eq!(depth_stability(&[dec("100"), dec("100")]), Some(Decimal::ONE));
+        assert_eq!(depth_stability(&[dec("100")]), None);
+        assert_eq!(depth_stability(&[Decimal::ZERO, Decimal::ZERO]), None);
+    }
+
+    #[test]
+    fn test_information_share_single_venue() {
+        let shares = information_share(&HashMap::from([("hyperliquid".to_string(), vec![dec("1"), dec("2")])]));
+        assert_eq!(shares, HashMap::from([("hyperliquid".to_string(), Decimal::ONE)]));
+        assert!(information_share(&HashMap::<String, Vec<Decimal>>::new()).is_empty());
+    }
+}
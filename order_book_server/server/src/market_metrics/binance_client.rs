@@ -0,0 +1,252 @@
+use crate::market_metrics::hyperliquid_client::ApiError;
+use crate::market_metrics::market_data_provider::{MarketDataProvider, ProviderFuture};
+use crate::market_metrics::types::ProviderMarketData;
+use chrono::Utc;
+use log::{error, info, warn};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use serde::{Deserialize, de::DeserializeOwned};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+
+/// `source` of prices read from Binance
+pub const BINANCE_SOURCE: &str = "binance";
+
+/// Hours between Binance funding payments for most USDⓈ-M perpetuals
+const BINANCE_FUNDING_INTERVAL_HOURS: i64 = 8;
+
+/// Polls a cached price may miss before it is no longer served
+const STALE_AFTER_POLLS: u32 = 3;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PremiumIndex {
+    symbol: String,
+    mark_price: String,
+    index_price: String,
+    last_funding_rate: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BookTicker {
+    symbol: String,
+    bid_price: String,
+    ask_price: String,
+}
+
+/// Reads perpetual prices from the Binance USDⓈ-M futures API.
+///
+/// Every symbol's prices are fetched in two bulk requests each poll interval
+/// and served from a cache, so collecting a coin never waits on Binance and
+/// the request rate doesn't grow with the number of monitored coins.
+pub struct BinanceFuturesClient {
+    client: Client,
+    api_url: String,
+    /// Binance symbol per Hyperliquid coin, for coins not listed as `{coin}USDT`
+    symbols: HashMap<String, String>,
+    poll_interval: Duration,
+    /// Latest prices per Binance symbol
+    cached_data: RwLock<HashMap<String, ProviderMarketData>>,
+    /// Coins already reported as not listed, so each is only logged once
+    unlisted: StdMutex<HashSet<String>>,
+}
+
+impl BinanceFuturesClient {
+    #[must_use]
+    pub fn new(api_url: String, symbols: HashMap<String, String>, poll_interval: Duration) -> Self {
+        Self {
+            client: Client::new(),
+            api_url,
+            symbols,
+            poll_interval,
+            cached_data: RwLock::new(HashMap::new()),
+            unlisted: StdMutex::new(HashSet::new()),
+        }
+    }
+
+    /// The Binance symbol for a Hyperliquid coin, e.g. `BTCUSDT` for `BTC`
+    #[must_use]
+    pub fn symbol(&self, coin: &str) -> String {
+        self.symbols.get(coin).cloned().unwrap_or_else(|| format!("{}USDT", coin.to_uppercase()))
+    }
+
+    /// Refresh the cache every poll interval until `shutdown` is cancelled
+    pub fn start_polling(self: Arc<Self>, shutdown: CancellationToken) {
+        tokio::spawn(async move {
+            let mut interval = time::interval(self.poll_interval);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    () = shutdown.cancelled() => return,
+                }
+                if let Err(e) = self.fetch_and_cache_all_markets().await {
+                    error!("Failed to fetch Binance market data: {e}");
+                }
+            }
+        });
+    }
+
+    /// Fetch the mark, index and funding rate of every symbol from
+    /// `premiumIndex` and their mids from `bookTicker`, replacing the cache.
+    /// Returns the number of symbols cached.
+    pub async fn fetch_and_cache_all_markets(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let (premiums, books) = tokio::try_join!(
+            self.get::<Vec<PremiumIndex>>("/fapi/v1/premiumIndex"),
+            self.get::<Vec<BookTicker>>("/fapi/v1/ticker/bookTicker"),
+        )?;
+        let mids = books
+            .into_iter()
+            .filter_map(|book| {
+                let bid = Decimal::from_str(&book.bid_price).ok()?;
+                let ask = Decimal::from_str(&book.ask_price).ok()?;
+                (bid > Decimal::ZERO && ask > Decimal::ZERO).then(|| (book.symbol, (bid + ask) / Decimal::TWO))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let fetched_at = Utc::now();
+        let mut markets = HashMap::with_capacity(premiums.len());
+        for premium in premiums {
+            let parsed = (|| -> Result<_, rust_decimal::Error> {
+                let funding = Decimal::from_str(&premium.last_funding_rate)?;
+                Ok(ProviderMarketData {
+                    source: BINANCE_SOURCE.to_string(),
+                    symbol: premium.symbol.clone(),
+                    mark_price: Decimal::from_str(&premium.mark_price)?,
+                    index_price: Some(Decimal::from_str(&premium.index_price)?),
+                    mid_price: mids.get(&premium.symbol).copied(),
+                    funding_rate_pct: Some(
+                        funding * Decimal::ONE_HUNDRED / Decimal::from(BINANCE_FUNDING_INTERVAL_HOURS),
+                    ),
+                    fetched_at,
+                })
+            })();
+            // Symbols being listed or delisted can report empty prices
+            if let Ok(data) = parsed {
+                markets.insert(premium.symbol, data);
+            }
+        }
+        let count = markets.len();
+        *self.cached_data.write().await = markets;
+        Ok(count)
+    }
+
+    /// Cached prices for `coin`, `None` before the first poll, once they are
+    /// stale or when Binance doesn't list the coin
+    pub async fn market_data_for(&self, coin: &str) -> Option<ProviderMarketData> {
+        let symbol = self.symbol(coin);
+        let (data, polled) = {
+            let cache = self.cached_data.read().await;
+            (cache.get(&symbol).cloned(), !cache.is_empty())
+        };
+        let Some(data) = data else {
+            if polled && self.unlisted.lock().is_ok_and(|mut unlisted| unlisted.insert(coin.to_string())) {
+                info!("{coin}: Binance lists no {symbol} perpetual, skipping its cross-check");
+            }
+            return None;
+        };
+        let max_age = self.poll_interval * STALE_AFTER_POLLS;
+        let age = Utc::now().signed_duration_since(data.fetched_at).to_std().unwrap_or_default();
+        if age > max_age {
+            warn!("{coin}: Cached Binance data is {age:?} old, ignoring it");
+            return None;
+        }
+        Some(data)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, ApiError> {
+        let url = format!("{}{path}", self.api_url.trim_end_matches('/'));
+        let response = self.client.get(url).timeout(Duration::from_secs(5)).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(ApiError::from_status(status));
+        }
+        Ok(response.json().await?)
+    }
+}
+
+impl MarketDataProvider for BinanceFuturesClient {
+    fn source(&self) -> &'static str {
+        BINANCE_SOURCE
+    }
+
+    fn market_data<'a>(&'a self, coin: &'a str) -> ProviderFuture<'a> {
+        Box::pin(self.market_data_for(coin))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, routing::get};
+    use tokio::net::TcpListener;
+
+    /// Serve `premiumIndex` and `bookTicker` for `BTCUSDT` and `1000PEPEUSDT`
+    async fn start_mock_api() -> String {
+        let premium = |symbol: &str| {
+            serde_json::json!({
+                "symbol": symbol,
+                "markPrice": "100.50000000",
+                "indexPrice": "100.40000000",
+                "lastFundingRate": "0.00010000",
+                "time": 1_700_000_000_000_u64,
+            })
+        };
+        let book = |symbol: &str| {
+            serde_json::json!({
+                "symbol": symbol,
+                "bidPrice": "100.4",
+                "bidQty": "3",
+                "askPrice": "100.6",
+                "askQty": "2",
+            })
+        };
+        let premiums = Json(serde_json::json!([premium("BTCUSDT"), premium("1000PEPEUSDT")]));
+        let books = Json(serde_json::json!([book("BTCUSDT"), book("1000PEPEUSDT")]));
+        let app = Router::new()
+            .route("/fapi/v1/premiumIndex", get(async || premiums))
+            .route("/fapi/v1/ticker/bookTicker", get(async || books));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn test_serves_mapped_symbols_from_cache() {
+        let symbols = HashMap::from([("kPEPE".to_string(), "1000PEPEUSDT".to_string())]);
+        let client = BinanceFuturesClient::new(start_mock_api().await, symbols, Duration::from_mins(1));
+        assert_eq!(client.symbol("BTC"), "BTCUSDT");
+        assert_eq!(client.symbol("kPEPE"), "1000PEPEUSDT");
+        assert!(client.market_data("BTC").await.is_none());
+
+        assert_eq!(client.fetch_and_cache_all_markets().await.unwrap(), 2);
+        let btc = client.market_data("BTC").await.unwrap();
+        assert_eq!(btc.source, "binance");
+        assert_eq!(btc.symbol, "BTCUSDT");
+        assert_eq!(btc.mark_price, Decimal::new(1005, 1));
+        assert_eq!(btc.index_price, Some(Decimal::new(1004, 1)));
+        assert_eq!(btc.mid_price, Some(Decimal::new(1005, 1)));
+        // 0.01% per 8 hours
+        assert_eq!(btc.funding_rate_pct, Some(Decimal::new(125, 5)));
+
+        assert_eq!(client.market_data("kPEPE").await.unwrap().symbol, "1000PEPEUSDT");
+        assert!(client.market_data("UNLISTED").await.is_none());
+        assert!(client.market_data("UNLISTED").await.is_none());
+        assert_eq!(client.unlisted.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stale_prices_not_served() {
+        let client = BinanceFuturesClient::new(start_mock_api().await, HashMap::new(), Duration::from_millis(10));
+        client.fetch_and_cache_all_markets().await.unwrap();
+        assert!(client.market_data("BTC").await.is_some());
+        time::sleep(Duration::from_millis(50)).await;
+        assert!(client.market_data("BTC").await.is_none());
+    }
+}
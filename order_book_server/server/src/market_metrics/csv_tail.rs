@@ -0,0 +1,133 @@
+use crate::market_metrics::{
+    sinks::{CSV_HEADER, MetricsSink, SinkFormat},
+    types::MarketMetrics,
+};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Appends the latest metrics to a single `tail -f`-able CSV file.
+///
+/// When the file grows past `max_bytes` it is renamed to `<path>.1` (replacing
+/// any previous rotation) and a fresh file with a header is started.
+pub struct CsvTailWriter {
+    path: PathBuf,
+    max_bytes: u64,
+    format: SinkFormat,
+    file: Option<File>,
+    written: u64,
+}
+
+impl CsvTailWriter {
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        Self { path: path.into(), max_bytes, format: SinkFormat::Csv, file: None, written: 0 }
+    }
+
+    /// Write rows as JSON lines instead of CSV
+    #[must_use]
+    pub const fn with_format(mut self, format: SinkFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Append one row for the given metrics
+    pub fn append(&mut self, metrics: &MarketMetrics) -> io::Result<()> {
+        let line = self.format.encode(metrics)?;
+        self.append_line(&line)
+    }
+
+    fn append_line(&mut self, line: &str) -> io::Result<()> {
+        if self.written >= self.max_bytes {
+            self.rotate()?;
+        }
+
+        let file = self.open()?;
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    fn open(&mut self) -> io::Result<&mut File> {
+        if self.file.is_none() {
+            let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+            self.written = file.metadata()?.len();
+            if self.written == 0 && self.format == SinkFormat::Csv {
+                writeln!(file, "{CSV_HEADER}")?;
+                self.written = CSV_HEADER.len() as u64 + 1;
+            }
+            self.file = Some(file);
+        }
+        self.file.as_mut().ok_or_else(|| io::Error::other("CSV tail file not open"))
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        fs::rename(&self.path, rotated)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl MetricsSink for CsvTailWriter {
+    fn name(&self) -> &'static str {
+        "csv_tail"
+    }
+
+    fn accepted_formats(&self) -> &'static [SinkFormat] {
+        &[SinkFormat::Csv, SinkFormat::Json]
+    }
+
+    fn format(&self) -> SinkFormat {
+        self.format
+    }
+
+    fn write(&mut self, _metrics: &MarketMetrics, encoded: &str) -> io::Result<()> {
+        self.append_line(encoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market_metrics::test_util::temp_dir;
+    use rust_decimal::Decimal;
+
+    fn metrics(coin: &str, mark_price: i64) -> MarketMetrics {
+        let mut metrics = MarketMetrics::new(coin.to_string());
+        metrics.mark_price = Some(Decimal::from(mark_price));
+        metrics
+    }
+
+    #[test]
+    fn test_csv_tail_contains_latest_rows() {
+        let path = temp_dir("csv_tail_latest").join("tail.csv");
+        let mut writer = CsvTailWriter::new(&path, 1024 * 1024);
+        for (coin, price) in [("BTC", 100), ("ETH", 10), ("BTC", 101), ("ETH", 11)] {
+            writer.append(&metrics(coin, price)).unwrap();
+        }
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(lines.len(), 5);
+        assert!(lines[3].contains(",BTC,101,"));
+        assert!(lines[4].contains(",ETH,11,"));
+    }
+
+    #[test]
+    fn test_csv_tail_rotates() {
+        let path = temp_dir("csv_tail_rotate").join("rotate.csv");
+        let mut writer = CsvTailWriter::new(&path, 1);
+        writer.append(&metrics("BTC", 100)).unwrap();
+        writer.append(&metrics("BTC", 101)).unwrap();
+
+        let current = fs::read_to_string(&path).unwrap();
+        let rotated = fs::read_to_string(format!("{}.1", path.display())).unwrap();
+        assert!(rotated.contains(",BTC,100,"));
+        assert!(current.starts_with(CSV_HEADER));
+        assert!(current.contains(",BTC,101,"));
+    }
+}
@@ -1,33 +1,501 @@
-use crate::market_metrics::types::MarketMetrics;
-use deadpool_postgres::{Config, Manager, ManagerConfig, Pool, RecyclingMethod, Runtime};
-use log::{error, info};
-use rust_decimal::Decimal;
-use std::collections::HashSet;
-use tokio_postgres::NoTls;
+use crate::market_metrics::{
+    analytics,
+    db_tls::{self, DbSslMode},
+    types::{
+        BasketMetrics, Candle, EmptyBucketPolicy, FillEstimate, MarketMetrics, PortfolioLiquidity, ProviderMarketData,
+        SlippagePoint, TimingBreakdown,
+    },
+};
+use chrono::{DateTime, Datelike, Days, Months, NaiveTime, Utc};
+use deadpool_postgres::{
+    Config, Hook, HookError, Manager, ManagerConfig, Object, Pool, PoolError, RecyclingMethod, Runtime, SslMode,
+    TimeoutType, Timeouts,
+};
+use log::{error, info, warn};
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, hash_map::Entry};
+use std::fmt::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+use tokio_postgres::{NoTls, Row, error::SqlState, types::ToSql};
 
+const DEFAULT_SCHEMA: &str = "market_metrics";
+
+/// `application_name` reported by connections unless configured otherwise
+pub const DEFAULT_APPLICATION_NAME: &str = "anthias-risk-monitor";
+
+/// Columns written for each metrics row, in parameter order
+const INSERT_COLUMNS: &[&str] = &[
+    "coin",
+    "tenant",
+    "mark_price",
+    "oracle_price",
+    "mid_price",
+    "oracle_stale",
+    "source",
+    "provider_prices",
+    "suspected_bad_print",
+    "best_bid",
+    "best_ask",
+    "spread",
+    "spread_pct",
+    "book_crossed",
+    "spread_autocorr",
+    "size_weighted_spread",
+    "mid_ci_low",
+    "mid_ci_high",
+    "realized_vol",
+    "order_book_imbalance",
+    "microprice",
+    "funding_rate_pct",
+    "liquidity_weighted_funding",
+    "basis_divergence",
+    "basis_pct",
+    "funding_differential",
+    "open_interest",
+    "volume_24h",
+    "volume_anomaly",
+    "activity_staleness_secs",
+    "bid_depth_5pct",
+    "ask_depth_5pct",
+    "total_depth_5pct",
+    "bid_qty_5pct",
+    "ask_qty_5pct",
+    "depth_stability",
+    "bid_depth_10pct",
+    "ask_depth_10pct",
+    "total_depth_10pct",
+    "bid_depth_25pct",
+    "ask_depth_25pct",
+    "total_depth_25pct",
+    "depth_truncated",
+    "bid_depth_elasticity",
+    "ask_depth_elasticity",
+    "depth_levels",
+    "imbalance_term_structure",
+    "spread_by_size",
+    "slippage_curve",
+    "slippage_partial_fill",
+    "timing_breakdown",
+    "premium",
+    "impact_px_bid",
+    "impact_px_ask",
+    "impact_adjusted_mid",
+    "quote_update_rate",
+    "quote_stuffing_suspected",
+    "spoofing_suspected",
+    "spoofing_score",
+    "node_latency_ms",
+    "websocket_latency_ms",
+    "total_latency_ms",
+    "timestamp",
+];
+
+/// Longest accepted table prefix, excluding the separating `_`
+const MAX_TABLE_PREFIX_LEN: usize = 24;
+
+/// Name of each coin's raw metrics table unless configured otherwise
+pub const DEFAULT_TABLE_NAME_TEMPLATE: &str = "{coin}_metrics_raw";
+
+/// Stands for the coin in a table name template
+const COIN_PLACEHOLDER: &str = "{coin}";
+
+/// Longest accepted table name template, excluding the `{coin}` placeholder
+const MAX_TABLE_NAME_TEMPLATE_LEN: usize = 24;
+
+/// Rows per statement, keeping under Postgres' limit of 65535 bind parameters
+const MAX_BATCH_ROWS: usize = u16::MAX as usize / INSERT_COLUMNS.len();
+
+/// Columns bound as text and cast on insert
+const JSONB_COLUMNS: &[&str] = &[
+    "provider_prices",
+    "imbalance_term_structure",
+    "depth_levels",
+    "spread_by_size",
+    "slippage_curve",
+    "timing_breakdown",
+];
+
+/// Price columns that can be stored as unbounded `NUMERIC` instead of `DECIMAL(20, 8)`
+pub const PRICE_COLUMNS: &[&str] = &[
+    "mark_price",
+    "oracle_price",
+    "mid_price",
+    "best_bid",
+    "best_ask",
+    "spread",
+    "impact_px_bid",
+    "impact_px_ask",
+    "impact_adjusted_mid",
+    "mid_ci_low",
+    "mid_ci_high",
+    "microprice",
+];
+
+/// Default span of full-resolution rows returned by [`MetricsDatabase::query_tiered`]
+pub const DEFAULT_TIERED_RECENT_WINDOW: Duration = Duration::from_hours(24);
+
+/// Default bucket width of the downsampled rows returned by [`MetricsDatabase::query_tiered`]
+pub const DEFAULT_TIERED_HISTORY_BUCKET: Duration = Duration::from_mins(5);
+
+/// Realized volatility at this multiple of its mean over the lookback counts as
+/// a spike in [`MetricsDatabase::depth_response_to_vol_spike`]
+const VOL_SPIKE_FACTOR: Decimal = Decimal::TWO;
+
+/// How long after a volatility spike depth is compared with its level at the spike
+const DEPTH_RESPONSE_WINDOW: Duration = Duration::from_mins(1);
+
+/// Columns of each table's `UNIQUE` constraint, left alone when a conflicting row is updated
+const CONFLICT_COLUMNS: &[&str] = &["timestamp", "coin", "tenant"];
+
+/// Columns added to the per-coin tables by one schema version
+struct Migration {
+    version: i32,
+    columns: &'static [(&'static str, &'static str)],
+    /// Statements run after adding the columns, with `<table>` replaced by the
+    /// schema-qualified table and `<name>` by the bare table name
+    statements: &'static [&'static str],
+}
+
+/// Every change to the per-coin tables since the first release, oldest first.
+///
+/// Tables created by [`MetricsDatabase::ensure_market_table`] already have
+/// these columns; older tables get them from [`MetricsDatabase::apply_migrations`].
+/// A new column needs a step here as well as in the `CREATE TABLE`.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        columns: &[
+            ("order_book_imbalance", "DECIMAL(10, 8)"),
+            ("microprice", "DECIMAL(20, 8)"),
+            ("realized_vol", "DECIMAL(20, 8)"),
+            ("spread_autocorr", "DECIMAL(10, 6)"),
+            ("size_weighted_spread", "DECIMAL(20, 8)"),
+            ("mid_ci_low", "DECIMAL(20, 8)"),
+            ("mid_ci_high", "DECIMAL(20, 8)"),
+            ("bid_qty_5pct", "DECIMAL(20, 8)"),
+            ("ask_qty_5pct", "DECIMAL(20, 8)"),
+            ("depth_stability", "DECIMAL(10, 8)"),
+            ("bid_depth_elasticity", "DECIMAL(12, 6)"),
+            ("ask_depth_elasticity", "DECIMAL(12, 6)"),
+            ("depth_levels", "JSONB"),
+            ("imbalance_term_structure", "JSONB"),
+            ("spread_by_size", "JSONB"),
+            ("slippage_curve", "JSONB"),
+            ("slippage_partial_fill", "BOOLEAN NOT NULL DEFAULT FALSE"),
+            ("impact_adjusted_mid", "DECIMAL(20, 8)"),
+        ],
+        statements: &[],
+    },
+    Migration {
+        version: 2,
+        columns: &[
+            ("tenant", "VARCHAR(64) NOT NULL DEFAULT ''"),
+            ("oracle_stale", "BOOLEAN NOT NULL DEFAULT FALSE"),
+            ("suspected_bad_print", "BOOLEAN NOT NULL DEFAULT FALSE"),
+            ("liquidity_weighted_funding", "DECIMAL(12, 10)"),
+            ("basis_divergence", "DECIMAL(12, 8)"),
+            ("volume_anomaly", "BOOLEAN NOT NULL DEFAULT FALSE"),
+            ("activity_staleness_secs", "DECIMAL(12, 3)"),
+            ("timing_breakdown", "JSONB"),
+            ("quote_update_rate", "DECIMAL(20, 8)"),
+            ("quote_stuffing_suspected", "BOOLEAN NOT NULL DEFAULT FALSE"),
+            ("spoofing_suspected", "BOOLEAN NOT NULL DEFAULT FALSE"),
+            ("spoofing_score", "DECIMAL(10, 8)"),
+        ],
+        // Inserts name `(timestamp, coin, tenant)` as their conflict target, and
+        // the old `(timestamp, coin)` key would reject the same row for another tenant
+        statements: &[
+            "CREATE UNIQUE INDEX IF NOT EXISTS <name>_tenant_key ON <table> (timestamp, coin, tenant)",
+            "DO $$
+             DECLARE
+                 old_key NAME;
+             BEGIN
+                 SELECT conname INTO old_key FROM pg_constraint
+                 WHERE conrelid = '<table>'::regclass AND contype = 'u'
+                     AND (SELECT array_agg(attname::TEXT ORDER BY attname) FROM pg_attribute
+                          WHERE attrelid = conrelid AND attnum = ANY(conkey)) = ARRAY['coin', 'timestamp'];
+                 IF old_key IS NOT NULL THEN
+                     EXECUTE format('ALTER TABLE <table> DROP CONSTRAINT %I', old_key);
+                 END IF;
+             END $$",
+        ],
+    },
+    Migration {
+        version: 3,
+        columns: &[
+            ("source", "VARCHAR(32)"),
+            ("provider_prices", "JSONB"),
+            ("basis_pct", "DECIMAL(12, 8)"),
+            ("funding_differential", "DECIMAL(12, 10)"),
+        ],
+        statements: &[],
+    },
+    Migration { version: 4, columns: &[("book_crossed", "BOOLEAN NOT NULL DEFAULT FALSE")], statements: &[] },
+    Migration { version: 5, columns: &[("depth_truncated", "BOOLEAN NOT NULL DEFAULT FALSE")], statements: &[] },
+];
+
+/// Schema version of tables with every step of [`MIGRATIONS`] applied
+pub const CURRENT_SCHEMA_VERSION: i32 = 5;
+
+/// What an insert does with a row whose `(timestamp, coin)` is already stored,
+/// e.g. when two ticks share a timestamp or rows are replayed after a restart
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Overwrite the stored row with the new values
+    #[default]
+    Update,
+    /// Keep the stored row and drop the new one
+    Ignore,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "update" => Ok(Self::Update),
+            "ignore" => Ok(Self::Ignore),
+            other => Err(format!("Unknown insert conflict policy '{other}': expected update or ignore")),
+        }
+    }
+}
+
+/// Width of the time-range partitions each market table is split into
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionInterval {
+    Day,
+    /// Monday to Monday
+    Week,
+    #[default]
+    Month,
+}
+
+impl PartitionInterval {
+    /// Start of the partition holding `timestamp` and of the one after it
+    fn bounds(self, timestamp: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let date = timestamp.date_naive();
+        let (start, end) = match self {
+            Self::Day => (date, date + Days::new(1)),
+            Self::Week => {
+                let start = date - Days::new(u64::from(date.weekday().num_days_from_monday()));
+                (start, start + Days::new(7))
+            }
+            Self::Month => {
+                let start = date - Days::new(u64::from(date.day0()));
+                (start, start + Months::new(1))
+            }
+        };
+        (start.and_time(NaiveTime::MIN).and_utc(), end.and_time(NaiveTime::MIN).and_utc())
+    }
+}
+
+impl FromStr for PartitionInterval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "day" | "daily" => Ok(Self::Day),
+            "week" | "weekly" => Ok(Self::Week),
+            "month" | "monthly" => Ok(Self::Month),
+            other => Err(format!("Unknown partition interval '{other}': expected day, week or month")),
+        }
+    }
+}
+
+/// A database operation abandoned after its configured timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatabaseError {
+    /// Waiting for a pooled connection, opening one or running a statement took longer than `after`
+    Timeout { operation: &'static str, after: Duration },
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout { operation, after } => write!(f, "database {operation} timed out after {after:?}"),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+/// How long database operations may block, waiting indefinitely when unset
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DatabaseTimeouts {
+    /// Opening a new connection
+    pub connect: Option<Duration>,
+    /// Waiting for a pooled connection to become free
+    pub wait: Option<Duration>,
+    /// Running one insert or query
+    pub statement: Option<Duration>,
+}
+
+/// A batch insert that was rejected for some coins
+#[derive(Debug)]
+pub struct BatchInsertError {
+    /// Coins whose rows were not inserted, with the reason
+    pub failures: Vec<(String, String)>,
+    /// Set when some coins were rejected because the database timed out
+    pub timeout: Option<DatabaseError>,
+}
+
+impl BatchInsertError {
+    #[must_use]
+    pub fn failed_coins(&self) -> HashSet<&str> {
+        self.failures.iter().map(|(coin, _)| coin.as_str()).collect()
+    }
+}
+
+impl fmt::Display for BatchInsertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let failures = self.failures.iter().map(|(coin, e)| format!("{coin}: {e}")).collect::<Vec<_>>();
+        write!(f, "batch insert rejected for {}", failures.join("; "))
+    }
+}
+
+impl std::error::Error for BatchInsertError {}
+
+/// Clones share the connection pool and partition cache, so a clone can run
+/// statements without waiting on a lock held around the original
+#[derive(Clone)]
 pub struct MetricsDatabase {
     pool: Pool,
+    /// Limit on each insert and query, enforced by [`Self::timed`]
+    statement_timeout: Option<Duration>,
     created_tables: HashSet<String>,
+    empty_bucket_policy: EmptyBucketPolicy,
+    /// Schema holding the per-coin tables
+    schema: String,
+    /// Tenant label stored on every row, empty when running single-tenant
+    tenant: String,
+    /// Prepended to every table and index name, e.g. `prod_`; empty by default
+    table_prefix: String,
+    /// Raw table name with `{coin}` standing for the coin, e.g. `{coin}_metrics_raw`
+    table_name_template: String,
+    /// Price columns stored as `NUMERIC` without a fixed scale
+    unbounded_columns: Vec<String>,
+    /// Directory the DDL of each verified table is written to
+    schema_snapshot_dir: Option<PathBuf>,
+    conflict_policy: ConflictPolicy,
+    /// How far back [`Self::query_tiered`] returns every row
+    tiered_recent_window: Duration,
+    /// Bucket width [`Self::query_tiered`] downsamples older rows to
+    tiered_history_bucket: Duration,
+    partition_interval: PartitionInterval,
+    /// Whether each market table seen so far is a partitioned parent, read from
+    /// the catalog on first use; tables that predate partitioning are left as they are
+    partitioned_tables: Arc<StdMutex<HashMap<String, bool>>>,
+    /// Partitions known to exist, so inserts only touch the catalog when
+    /// rows cross into a new partition
+    partitions: Arc<StdMutex<HashSet<String>>>,
 }
 
 impl MetricsDatabase {
     pub async fn new(database_url: &str, max_connections: usize) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::connect(database_url, max_connections, DEFAULT_APPLICATION_NAME).await
+    }
+
+    /// Like [`Self::new`], with every connection setting `application_name` so
+    /// this service can be identified in `pg_stat_activity`
+    pub async fn connect(
+        database_url: &str,
+        max_connections: usize,
+        application_name: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::connect_with_tls(
+            database_url,
+            max_connections,
+            application_name,
+            DbSslMode::Disable,
+            None,
+            DatabaseTimeouts::default(),
+        )
+        .await
+    }
+
+    /// Like [`Self::connect`], securing connections according to `ssl_mode`.
+    ///
+    /// With TLS the server certificate is verified against the web PKI roots
+    /// and the PEM certificates in `ca_cert_path`, and connections never fall
+    /// back to plain TCP. Checkouts and statements exceeding `timeouts` fail
+    /// with [`DatabaseError::Timeout`], and every connection sets the statement
+    /// timeout as its server-side `statement_timeout`.
+    pub async fn connect_with_tls(
+        database_url: &str,
+        max_connections: usize,
+        application_name: &str,
+        ssl_mode: DbSslMode,
+        ca_cert_path: Option<&Path>,
+        timeouts: DatabaseTimeouts,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let mut cfg = Config::new();
         cfg.url = Some(database_url.to_string());
+        cfg.connect_timeout = timeouts.connect;
         cfg.manager = Some(ManagerConfig {
             recycling_method: RecyclingMethod::Fast,
         });
         cfg.pool = Some(deadpool_postgres::PoolConfig {
             max_size: max_connections,
-            timeouts: Default::default(),
+            timeouts: Timeouts { wait: timeouts.wait, create: timeouts.connect, recycle: timeouts.connect },
             queue_mode: Default::default(),
         });
 
-        let pool = cfg.create_pool(Some(Runtime::Tokio1), NoTls)?;
+        let builder = match ssl_mode {
+            DbSslMode::Disable => cfg.builder(NoTls)?,
+            DbSslMode::VerifyFull => {
+                cfg.ssl_mode = Some(SslMode::Require);
+                cfg.builder(db_tls::connector(ca_cert_path)?)?
+            }
+        };
+
+        let application_name = application_name.to_string();
+        // The server cancels overrunning statements itself, so prunes, rollups and
+        // backfills stop running even after `timed` has dropped the client future
+        let statement_timeout = timeouts.statement.map(|after| format!("{}ms", after.as_millis()));
+        let pool = builder
+            .runtime(Runtime::Tokio1)
+            .post_create(Hook::async_fn(move |client, _| {
+                let application_name = application_name.clone();
+                let statement_timeout = statement_timeout.clone();
+                Box::pin(async move {
+                    client
+                        .execute("SELECT set_config('application_name', $1, false)", &[&application_name])
+                        .await
+                        .map_err(HookError::Backend)?;
+                    if let Some(statement_timeout) = statement_timeout {
+                        client
+                            .execute("SELECT set_config('statement_timeout', $1, false)", &[&statement_timeout])
+                            .await
+                            .map_err(HookError::Backend)?;
+                    }
+                    Ok(())
+                })
+            }))
+            .build()?;
 
         let db = Self {
             pool,
+            statement_timeout: timeouts.statement,
             created_tables: HashSet::new(),
+            empty_bucket_policy: EmptyBucketPolicy::default(),
+            schema: DEFAULT_SCHEMA.to_string(),
+            tenant: String::new(),
+            table_prefix: String::new(),
+            table_name_template: DEFAULT_TABLE_NAME_TEMPLATE.to_string(),
+            unbounded_columns: Vec::new(),
+            schema_snapshot_dir: None,
+            conflict_policy: ConflictPolicy::default(),
+            tiered_recent_window: DEFAULT_TIERED_RECENT_WINDOW,
+            tiered_history_bucket: DEFAULT_TIERED_HISTORY_BUCKET,
+            partition_interval: PartitionInterval::default(),
+            partitioned_tables: Arc::new(StdMutex::new(HashMap::new())),
+            partitions: Arc::new(StdMutex::new(HashSet::new())),
         };
 
         // Create schema
@@ -37,135 +505,2725 @@ impl MetricsDatabase {
         Ok(db)
     }
 
+    /// Set how inserts handle rows that are already stored
+    #[must_use]
+    pub const fn with_conflict_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.conflict_policy = policy;
+        self
+    }
+
+    /// Set the resolution split of [`Self::query_tiered`]: rows newer than
+    /// `recent_window` are returned as stored, older ones one per `history_bucket`
+    #[must_use]
+    pub const fn with_tiered_resolution(mut self, recent_window: Duration, history_bucket: Duration) -> Self {
+        self.tiered_recent_window = recent_window;
+        self.tiered_history_bucket = history_bucket;
+        self
+    }
+
+    /// Set the width of the partitions market tables are split into.
+    ///
+    /// Only affects partitions created from now on, so it should not change
+    /// once a table holds partitions: ranges of different widths would overlap.
+    #[must_use]
+    pub const fn with_partition_interval(mut self, interval: PartitionInterval) -> Self {
+        self.partition_interval = interval;
+        self
+    }
+
+    /// Set how empty buckets are handled by [`Self::ohlc`]
+    #[must_use]
+    pub const fn with_empty_bucket_policy(mut self, policy: EmptyBucketPolicy) -> Self {
+        self.empty_bucket_policy = policy;
+        self
+    }
+
+    /// Tag every row with `tenant` so tenants sharing a database don't collide.
+    ///
+    /// With `tenant_schema` the tenant's tables also live in their own
+    /// `market_metrics_{tenant}` schema, created on first use.
+    pub fn with_tenant(
+        mut self,
+        tenant: Option<&str>,
+        tenant_schema: bool,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let Some(tenant) = tenant else {
+            return Ok(self);
+        };
+        let tenant = tenant.to_lowercase();
+        if !valid_tenant(&tenant) {
+            return Err(format!("Invalid tenant label '{tenant}': expected letters, digits, or '_'").into());
+        }
+
+        if tenant_schema {
+            self.schema = format!("{DEFAULT_SCHEMA}_{tenant}");
+        }
+        self.tenant = tenant;
+        Ok(self)
+    }
+
+    /// Prefix every table with `{prefix}_`, e.g. `prod_link_metrics_raw`, so
+    /// environments sharing a database and schema keep separate tables.
+    ///
+    /// The prefix must start with a letter and contain only letters, digits or
+    /// `_`; an empty prefix leaves table names unchanged.
+    pub fn with_table_prefix(mut self, prefix: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let prefix = prefix.trim_end_matches('_').to_lowercase();
+        if prefix.is_empty() {
+            return Ok(self);
+        }
+        if !valid_table_prefix(&prefix) {
+            return Err(format!(
+                "Invalid table prefix '{prefix}': expected a letter followed by up to \
+                 {MAX_TABLE_PREFIX_LEN} letters, digits, or '_'"
+            )
+            .into());
+        }
+        self.table_prefix = format!("{prefix}_");
+        Ok(self)
+    }
+
+    /// Name each coin's raw table from `template`, e.g. `perp_{coin}` for
+    /// `perp_btc`; any table prefix is still prepended.
+    ///
+    /// The template must contain `{coin}` and otherwise only letters, digits or
+    /// `_`, so every coin renders to an unquoted identifier.
+    pub fn with_table_name_template(mut self, template: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let template = template.trim().to_lowercase();
+        if !valid_table_name_template(&template) {
+            return Err(format!(
+                "Invalid table name template '{template}': expected '{{coin}}' and up to \
+                 {MAX_TABLE_NAME_TEMPLATE_LEN} letters, digits, or '_', not starting with a digit"
+            )
+            .into());
+        }
+        self.table_name_template = template;
+        Ok(self)
+    }
+
+    /// A pooled connection, failing with [`DatabaseError::Timeout`] when none
+    /// frees up or connects within the pool's timeouts
+    async fn client(&self) -> Result<Object, Box<dyn std::error::Error>> {
+        match self.pool.get().await {
+            Ok(client) => Ok(client),
+            Err(PoolError::Timeout(kind)) => {
+                let timeouts = self.pool.timeouts();
+                let (operation, after) = match kind {
+                    TimeoutType::Wait => ("connection wait", timeouts.wait),
+                    TimeoutType::Create => ("connect", timeouts.create),
+                    TimeoutType::Recycle => ("connection check", timeouts.recycle),
+                };
+                Err(DatabaseError::Timeout { operation, after: after.unwrap_or_default() }.into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Await `statement`, giving up with [`DatabaseError::Timeout`] once the statement timeout passes
+    async fn timed<F: Future>(&self, operation: &'static str, statement: F) -> Result<F::Output, DatabaseError> {
+        match self.statement_timeout {
+            Some(after) => {
+                tokio::time::timeout(after, statement).await.map_err(|_| DatabaseError::Timeout { operation, after })
+            }
+            None => Ok(statement.await),
+        }
+    }
+
+    /// Check a pooled connection can run a query
+    pub async fn ping(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let client = self.client().await?;
+        client.simple_query("SELECT 1").await?;
+        Ok(())
+    }
+
+    /// Name of a coin's metrics table, without the schema
+    fn market_table(&self, coin: &str) -> String {
+        let table = self.table_name_template.replace(COIN_PLACEHOLDER, &table_coin(coin));
+        unquoted_name(format!("{}{table}", self.table_prefix))
+    }
+
+    /// Stem of a coin's index names, kept as `{prefix}{coin}_metrics` for the
+    /// default template so existing tables don't gain duplicate indexes
+    fn index_stem(&self, coin: &str) -> String {
+        if self.table_name_template == DEFAULT_TABLE_NAME_TEMPLATE {
+            format!("{}{}_metrics", self.table_prefix, table_coin(coin))
+        } else {
+            self.market_table(coin)
+        }
+    }
+
+    /// Store the given price columns as unbounded `NUMERIC` so very low-priced
+    /// coins keep digits that `DECIMAL(20, 8)` would round away.
+    ///
+    /// Existing tables are widened in place the first time they are verified.
+    pub fn with_unbounded_numeric(mut self, columns: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        for column in columns {
+            let column = column.to_lowercase();
+            if !PRICE_COLUMNS.contains(&column.as_str()) {
+                return Err(
+                    format!("Unknown price column '{column}': expected one of {}", PRICE_COLUMNS.join(", ")).into()
+                );
+            }
+            if !self.unbounded_columns.contains(&column) {
+                self.unbounded_columns.push(column);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Write the DDL used for each market table to `{dir}/{version}/{schema}.{table}.sql`,
+    /// where `version` is this crate's version, so schema changes between
+    /// deployments can be diffed and replayed
+    #[must_use]
+    pub fn with_schema_snapshot_dir(mut self, dir: Option<&Path>) -> Self {
+        self.schema_snapshot_dir = dir.map(Path::to_path_buf);
+        self
+    }
+
     async fn create_schema(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let client = self.pool.get().await?;
-        client
-            .execute("CREATE SCHEMA IF NOT EXISTS market_metrics", &[])
-            .await?;
-        info!("Schema 'market_metrics' created/verified");
+        let client = self.client().await?;
+        client.execute(&format!("CREATE SCHEMA IF NOT EXISTS {}", self.schema), &[]).await?;
+        info!("Schema '{}' created/verified", self.schema);
         Ok(())
     }
 
+    /// Close the pool and wait up to `timeout` for checked-out connections to be returned.
+    ///
+    /// Idle connections are dropped immediately; busy ones are dropped as they
+    /// come back. Returns whether every connection was released in time.
+    pub async fn close(&self, timeout: Duration) -> bool {
+        self.pool.close();
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.pool.status().size > 0 {
+            if tokio::time::Instant::now() >= deadline {
+                warn!("Database pool still has {} connections after {timeout:?}", self.pool.status().size);
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        info!("Database pool closed");
+        true
+    }
+
     pub async fn ensure_market_table(&mut self, coin_symbol: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let table_name = format!("{}_metrics_raw", coin_symbol.to_lowercase());
+        let table_name = self.market_table(coin_symbol);
 
         if self.created_tables.contains(&table_name) {
             return Ok(());
         }
 
-        let client = self.pool.get().await?;
+        let client = self.client().await?;
+        let existed = self.table_exists(&client, &table_name).await?;
+
+        let mut ddl = self.create_table_sql(coin_symbol, &table_name);
+        client.batch_execute(&ddl).await?;
+        // Migrations take their own connection, which a single-connection pool only has once this one is back
+        drop(client);
+        // A table created just now already has every migrated column
+        self.migrate(coin_symbol, !existed).await?;
+
+        ddl.push_str(&self.widen_unbounded_columns(&table_name).await?);
+        if let Some(dir) = &self.schema_snapshot_dir {
+            let path = write_schema_snapshot(dir, &self.schema, &table_name, &ddl)?;
+            info!("✓ Wrote schema snapshot: {}", path.display());
+        }
+        self.created_tables.insert(table_name.clone());
+        info!("✓ Created/verified table: {}.{}", self.schema, table_name);
+
+        // The table may have been dropped and recreated since it was last seen
+        self.partitioned_tables.lock().map_err(|_| "Partition cache lock poisoned")?.remove(&table_name);
+        self.ensure_partition(coin_symbol, Utc::now()).await
+    }
 
+    /// DDL creating the schema, partitioned table and indexes for a coin's table if missing
+    fn create_table_sql(&self, coin_symbol: &str, table_name: &str) -> String {
         let schema_sql = format!(
             r#"
-            CREATE TABLE IF NOT EXISTS market_metrics.{table_name} (
-                id SERIAL PRIMARY KEY,
+            CREATE SCHEMA IF NOT EXISTS {schema};
+
+            CREATE TABLE IF NOT EXISTS {schema}.{table_name} (
+                id SERIAL,
                 timestamp TIMESTAMPTZ NOT NULL DEFAULT NOW(),
                 coin VARCHAR(20) NOT NULL,
+                tenant VARCHAR(64) NOT NULL DEFAULT '',
                 mark_price DECIMAL(20, 8),
                 oracle_price DECIMAL(20, 8),
                 mid_price DECIMAL(20, 8),
+                oracle_stale BOOLEAN NOT NULL DEFAULT FALSE,
+                source VARCHAR(32),
+                provider_prices JSONB,
+                suspected_bad_print BOOLEAN NOT NULL DEFAULT FALSE,
                 best_bid DECIMAL(20, 8),
                 best_ask DECIMAL(20, 8),
                 spread DECIMAL(20, 8),
                 spread_pct DECIMAL(10, 6),
+                book_crossed BOOLEAN NOT NULL DEFAULT FALSE,
+                spread_autocorr DECIMAL(10, 6),
+                size_weighted_spread DECIMAL(20, 8),
+                mid_ci_low DECIMAL(20, 8),
+                mid_ci_high DECIMAL(20, 8),
+                realized_vol DECIMAL(20, 8),
+                order_book_imbalance DECIMAL(10, 8),
+                microprice DECIMAL(20, 8),
                 funding_rate_pct DECIMAL(12, 10),
+                liquidity_weighted_funding DECIMAL(12, 10),
+                basis_divergence DECIMAL(12, 8),
+                basis_pct DECIMAL(12, 8),
+                funding_differential DECIMAL(12, 10),
                 open_interest DECIMAL(20, 8),
                 volume_24h DECIMAL(20, 8),
+                volume_anomaly BOOLEAN NOT NULL DEFAULT FALSE,
+                activity_staleness_secs DECIMAL(12, 3),
                 bid_depth_5pct DECIMAL(20, 8),
                 ask_depth_5pct DECIMAL(20, 8),
                 total_depth_5pct DECIMAL(20, 8),
+                bid_qty_5pct DECIMAL(20, 8),
+                ask_qty_5pct DECIMAL(20, 8),
+                depth_stability DECIMAL(10, 8),
                 bid_depth_10pct DECIMAL(20, 8),
                 ask_depth_10pct DECIMAL(20, 8),
                 total_depth_10pct DECIMAL(20, 8),
                 bid_depth_25pct DECIMAL(20, 8),
                 ask_depth_25pct DECIMAL(20, 8),
                 total_depth_25pct DECIMAL(20, 8),
+                depth_truncated BOOLEAN NOT NULL DEFAULT FALSE,
+                bid_depth_elasticity DECIMAL(12, 6),
+                ask_depth_elasticity DECIMAL(12, 6),
+                depth_levels JSONB,
+                imbalance_term_structure JSONB,
+                spread_by_size JSONB,
+                slippage_curve JSONB,
+                slippage_partial_fill BOOLEAN NOT NULL DEFAULT FALSE,
+                timing_breakdown JSONB,
                 premium DECIMAL(12, 10),
                 impact_px_bid DECIMAL(20, 8),
                 impact_px_ask DECIMAL(20, 8),
+                impact_adjusted_mid DECIMAL(20, 8),
+                quote_update_rate DECIMAL(20, 8),
+                quote_stuffing_suspected BOOLEAN NOT NULL DEFAULT FALSE,
+                spoofing_suspected BOOLEAN NOT NULL DEFAULT FALSE,
+                spoofing_score DECIMAL(10, 8),
                 node_latency_ms INTEGER,
                 websocket_latency_ms INTEGER,
                 total_latency_ms INTEGER,
                 created_at TIMESTAMPTZ DEFAULT NOW(),
-                UNIQUE(timestamp, coin)
-            );
+                PRIMARY KEY (id, timestamp),
+                UNIQUE(timestamp, coin, tenant)
+            ) PARTITION BY RANGE (timestamp);
 
-            CREATE INDEX IF NOT EXISTS idx_{coin_lower}_metrics_timestamp
-                ON market_metrics.{table_name}(timestamp DESC);
-            CREATE INDEX IF NOT EXISTS idx_{coin_lower}_metrics_coin_timestamp
-                ON market_metrics.{table_name}(coin, timestamp DESC);
+            CREATE INDEX IF NOT EXISTS idx_{index_stem}_timestamp
+                ON {schema}.{table_name}(timestamp DESC);
+            CREATE INDEX IF NOT EXISTS idx_{index_stem}_coin_timestamp
+                ON {schema}.{table_name}(coin, timestamp DESC);
             "#,
+            schema = self.schema,
             table_name = table_name,
-            coin_lower = coin_symbol.to_lowercase()
+            index_stem = self.index_stem(coin_symbol)
         );
 
-        client.batch_execute(&schema_sql).await?;
-        self.created_tables.insert(table_name.clone());
-        info!("✓ Created/verified table: market_metrics.{}", table_name);
+        dedent(&schema_sql)
+    }
+
+    async fn table_exists(&self, client: &Object, table_name: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let table = format!("{}.{table_name}", self.schema);
+        let row = client.query_one("SELECT to_regclass($1) IS NOT NULL", &[&table]).await?;
+        Ok(row.get(0))
+    }
+
+    /// Store the configured unbounded columns of a table as `NUMERIC`, returning the DDL run
+    async fn widen_unbounded_columns(&self, table_name: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let mut alter_sql = String::new();
+        for column in &self.unbounded_columns {
+            let _ = writeln!(alter_sql, "ALTER TABLE {}.{table_name} ALTER COLUMN {column} TYPE NUMERIC;", self.schema);
+        }
+        if !alter_sql.is_empty() {
+            let client = self.client().await?;
+            client.batch_execute(&alter_sql).await?;
+        }
+        Ok(alter_sql)
+    }
+
+    /// Bring a coin's table up to [`CURRENT_SCHEMA_VERSION`] by adding the
+    /// columns of each migration newer than the version recorded for it in
+    /// `schema_version`, so tables created by older releases accept inserts.
+    ///
+    /// Tables without a recorded version get every step; each only adds
+    /// missing columns. Returns the version the table was at before.
+    pub async fn apply_migrations(&self, coin_symbol: &str) -> Result<i32, Box<dyn std::error::Error>> {
+        self.migrate(coin_symbol, false).await
+    }
 
+    /// [`Self::apply_migrations`], recording a `created` table as current without running any step
+    async fn migrate(&self, coin_symbol: &str, created: bool) -> Result<i32, Box<dyn std::error::Error>> {
+        let table_name = self.market_table(coin_symbol);
+        let mut client = self.client().await?;
+        client
+            .batch_execute(&format!(
+                "CREATE SCHEMA IF NOT EXISTS {schema};
+                 CREATE TABLE IF NOT EXISTS {schema}.schema_version (
+                     table_name VARCHAR(128) PRIMARY KEY,
+                     version INTEGER NOT NULL,
+                     migrated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+                 );",
+                schema = self.schema
+            ))
+            .await?;
+
+        let transaction = client.transaction().await?;
+        // Lock the table's version row so concurrent monitors migrate it once
+        let (initial_version, on_conflict) =
+            if created { (CURRENT_SCHEMA_VERSION, "UPDATE SET version = EXCLUDED.version") } else { (0, "NOTHING") };
+        transaction
+            .execute(
+                &format!(
+                    "INSERT INTO {}.schema_version (table_name, version) VALUES ($1, $2) \
+                     ON CONFLICT (table_name) DO {on_conflict}",
+                    self.schema
+                ),
+                &[&table_name, &initial_version],
+            )
+            .await?;
+        let version: i32 = transaction
+            .query_one(
+                &format!("SELECT version FROM {}.schema_version WHERE table_name = $1 FOR UPDATE", self.schema),
+                &[&table_name],
+            )
+            .await?
+            .get(0);
+
+        let mut ddl = String::new();
+        for migration in MIGRATIONS.iter().filter(|migration| migration.version > version) {
+            let table = format!("{}.{table_name}", self.schema);
+            for (column, column_type) in migration.columns {
+                let _ = writeln!(ddl, "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS {column} {column_type};");
+            }
+            for statement in migration.statements {
+                let _ = writeln!(ddl, "{};", statement.replace("<table>", &table).replace("<name>", &table_name));
+            }
+        }
+        if version < CURRENT_SCHEMA_VERSION {
+            transaction.batch_execute(&ddl).await?;
+            transaction
+                .execute(
+                    &format!(
+                        "UPDATE {}.schema_version SET version = $2, migrated_at = NOW() WHERE table_name = $1",
+                        self.schema
+                    ),
+                    &[&table_name, &CURRENT_SCHEMA_VERSION],
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+        if version < CURRENT_SCHEMA_VERSION {
+            let schema = &self.schema;
+            info!("✓ Migrated {schema}.{table_name} from schema version {version} to {CURRENT_SCHEMA_VERSION}");
+        }
+        Ok(version)
+    }
+
+    /// Whether `table_name` is a partitioned parent, asking the catalog the
+    /// first time. A table that doesn't exist yet is not remembered.
+    async fn is_partitioned(&self, table_name: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let cached = {
+            let cache = self.partitioned_tables.lock().map_err(|_| "Partition cache lock poisoned")?;
+            cache.get(table_name).copied()
+        };
+        if let Some(partitioned) = cached {
+            return Ok(partitioned);
+        }
+        let client = self.client().await?;
+        let row = client
+            .query_one(
+                "SELECT to_regclass($1) IS NOT NULL, \
+                 EXISTS (SELECT 1 FROM pg_partitioned_table WHERE partrelid = to_regclass($1))",
+                &[&format!("{}.{table_name}", self.schema)],
+            )
+            .await?;
+        let (exists, partitioned) = (row.get::<_, bool>(0), row.get::<_, bool>(1));
+        if !exists {
+            return Ok(false);
+        }
+        if !partitioned {
+            warn!("{}.{table_name} predates partitioning and is left unpartitioned", self.schema);
+        }
+        self.partitioned_tables
+            .lock()
+            .map_err(|_| "Partition cache lock poisoned")?
+            .insert(table_name.to_string(), partitioned);
+        Ok(partitioned)
+    }
+
+    /// Create the partition of a coin's table holding `timestamp`, if the table
+    /// is partitioned and the partition doesn't exist yet
+    pub async fn ensure_partition(
+        &self,
+        coin_symbol: &str,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let table_name = self.market_table(coin_symbol);
+        if !self.is_partitioned(&table_name).await? {
+            return Ok(());
+        }
+        let (start, end) = self.partition_interval.bounds(timestamp);
+        let partition = format!("{table_name}_p{}", start.format("%Y%m%d"));
+        if self.partitions.lock().map_err(|_| "Partition cache lock poisoned")?.contains(&partition) {
+            return Ok(());
+        }
+
+        let client = self.client().await?;
+        client
+            .batch_execute(&format!(
+                "CREATE TABLE IF NOT EXISTS {schema}.{partition} PARTITION OF {schema}.{table_name} \
+                 FOR VALUES FROM ('{start}') TO ('{end}')",
+                schema = self.schema,
+                start = start.to_rfc3339(),
+                end = end.to_rfc3339(),
+            ))
+            .await?;
+        info!("✓ Created/verified partition: {}.{partition}", self.schema);
+        self.partitions.lock().map_err(|_| "Partition cache lock poisoned")?.insert(partition);
         Ok(())
     }
 
-    pub async fn insert_metrics(&self, metrics: &MarketMetrics) -> Result<(), Box<dyn std::error::Error>> {
-        let table_name = format!("{}_metrics_raw", metrics.coin.to_lowercase());
-        let client = self.pool.get().await?;
+    /// Delete a coin's rows older than `cutoff`, returning how many were removed.
+    ///
+    /// Partitions lying entirely before `cutoff` are dropped whole, which is far
+    /// cheaper than deleting their rows, unless they hold rows of other tenants.
+    /// Remaining old rows, e.g. in the partition straddling `cutoff` or in a
+    /// table that predates partitioning, are deleted.
+    pub async fn prune_older_than(
+        &self,
+        coin_symbol: &str,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let table_name = self.market_table(coin_symbol);
+        let client = self.client().await?;
+
+        let expired = client
+            .query(
+                r"SELECT c.relname FROM pg_inherits i JOIN pg_class c ON c.oid = i.inhrelid
+                  WHERE i.inhparent = to_regclass($1)
+                    AND substring(pg_get_expr(c.relpartbound, c.oid) FROM 'TO \(''([^'']+)''\)')::timestamptz <= $2",
+                &[&format!("{}.{table_name}", self.schema), &cutoff],
+            )
+            .await?;
+        let mut removed = 0;
+        for row in expired {
+            let partition: String = row.get(0);
+            let counts = client
+                .query_one(
+                    &format!(
+                        "SELECT COUNT(*) FILTER (WHERE tenant = $1), COUNT(*) FILTER (WHERE tenant <> $1) \
+                         FROM {}.{partition}",
+                        self.schema
+                    ),
+                    &[&self.tenant],
+                )
+                .await?;
+            if counts.get::<_, i64>(1) > 0 {
+                continue;
+            }
+            client.batch_execute(&format!("DROP TABLE {}.{partition}", self.schema)).await?;
+            self.partitions.lock().map_err(|_| "Partition cache lock poisoned")?.remove(&partition);
+            info!("✓ Dropped expired partition: {}.{partition}", self.schema);
+            removed += counts.get::<_, i64>(0).unsigned_abs();
+        }
+
+        let query = format!("DELETE FROM {}.{table_name} WHERE timestamp < $1 AND tenant = $2", self.schema);
+        removed += client.execute(&query, &[&cutoff, &self.tenant]).await?;
+        Ok(removed)
+    }
+
+    /// Name of a coin's rollup table for `interval`, without the schema: its raw
+    /// table's name with the interval in place of any `_raw` suffix, e.g.
+    /// `btc_metrics_1m`, or `perp_btc_1m` for the template `perp_{coin}`
+    fn rollup_table(&self, coin: &str, interval: Duration) -> Result<String, Box<dyn std::error::Error>> {
+        let secs = interval.as_secs();
+        if secs == 0 || interval.subsec_nanos() != 0 {
+            return Err(format!("Rollup interval must be a whole number of seconds, got {interval:?}").into());
+        }
+        let suffix = match secs {
+            secs if secs % 3600 == 0 => format!("{}h", secs / 3600),
+            secs if secs % 60 == 0 => format!("{}m", secs / 60),
+            secs => format!("{secs}s"),
+        };
+        let raw_table = self.market_table(coin);
+        Ok(format!("{}_{suffix}", raw_table.strip_suffix("_raw").unwrap_or(&raw_table)))
+    }
+
+    /// Create the table [`Self::compute_rollup`] writes a coin's `interval` buckets to
+    pub async fn ensure_rollup_table(
+        &self,
+        coin_symbol: &str,
+        interval: Duration,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let table_name = self.rollup_table(coin_symbol, interval)?;
+        let price_type =
+            if self.unbounded_columns.iter().any(|c| c == "mid_price") { "NUMERIC" } else { "DECIMAL(20, 8)" };
+        let client = self.client().await?;
+
+        let schema_sql = format!(
+            r"
+            CREATE SCHEMA IF NOT EXISTS {schema};
+
+            CREATE TABLE IF NOT EXISTS {schema}.{table_name} (
+                bucket TIMESTAMPTZ NOT NULL,
+                tenant VARCHAR(64) NOT NULL DEFAULT '',
+                open_mid {price_type},
+                high_mid {price_type},
+                low_mid {price_type},
+                close_mid {price_type},
+                avg_spread_pct DECIMAL(10, 6),
+                min_total_depth_5pct DECIMAL(20, 8),
+                last_funding_rate_pct DECIMAL(12, 10),
+                sample_count INTEGER NOT NULL,
+                PRIMARY KEY (bucket, tenant)
+            );
+            ",
+            schema = self.schema
+        );
+
+        client.batch_execute(&schema_sql).await?;
+        info!("✓ Created/verified table: {}.{table_name}", self.schema);
+        Ok(())
+    }
+
+    /// Aggregate a coin's raw rows from the last `window` into `interval` buckets
+    /// of its rollup table, returning the number of buckets written.
+    ///
+    /// Each bucket stores the open/high/low/close mid, average spread, minimum
+    /// 5% depth and last funding rate of its rows. Buckets are aligned to the
+    /// Unix epoch as in [`Self::ohlc`], starting with the one containing
+    /// `now - window`, and buckets already stored are overwritten so one still
+    /// filling when it was last rolled up catches up.
+    pub async fn compute_rollup(
+        &self,
+        coin_symbol: &str,
+        interval: Duration,
+        window: Duration,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let (raw_table, rollup_table) = (self.market_table(coin_symbol), self.rollup_table(coin_symbol, interval)?);
+        let client = self.client().await?;
 
         let query = format!(
-            r#"
-            INSERT INTO market_metrics.{} (
-                coin, mark_price, oracle_price, mid_price,
-                best_bid, best_ask, spread, spread_pct,
-                funding_rate_pct, open_interest, volume_24h,
-                bid_depth_5pct, ask_depth_5pct, total_depth_5pct,
-                bid_depth_10pct, ask_depth_10pct, total_depth_10pct,
-                bid_depth_25pct, ask_depth_25pct, total_depth_25pct,
-                premium, impact_px_bid, impact_px_ask,
-                node_latency_ms, websocket_latency_ms, total_latency_ms,
-                timestamp
-            ) VALUES (
-                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
-                $11, $12, $13, $14, $15, $16, $17, $18, $19, $20,
-                $21, $22, $23, $24, $25, $26, $27
+            r"
+            INSERT INTO {schema}.{rollup_table} (
+                bucket, tenant, open_mid, high_mid, low_mid, close_mid,
+                avg_spread_pct, min_total_depth_5pct, last_funding_rate_pct, sample_count
             )
-            "#,
-            table_name
+            SELECT
+                bucket,
+                $3,
+                (array_agg(mid_price ORDER BY timestamp ASC) FILTER (WHERE mid_price IS NOT NULL))[1],
+                MAX(mid_price),
+                MIN(mid_price),
+                (array_agg(mid_price ORDER BY timestamp DESC) FILTER (WHERE mid_price IS NOT NULL))[1],
+                AVG(spread_pct) FILTER (WHERE NOT book_crossed),
+                MIN(total_depth_5pct),
+                (array_agg(funding_rate_pct ORDER BY timestamp DESC) FILTER (WHERE funding_rate_pct IS NOT NULL))[1],
+                COUNT(*)
+            FROM (
+                SELECT
+                    to_timestamp(floor(extract(epoch FROM timestamp)::float8 / $2) * $2) AS bucket,
+                    timestamp,
+                    mid_price,
+                    spread_pct,
+                    book_crossed,
+                    total_depth_5pct,
+                    funding_rate_pct
+                FROM {schema}.{raw_table}
+                WHERE timestamp >= to_timestamp(floor(extract(epoch FROM $1::timestamptz)::float8 / $2) * $2)
+                  AND tenant = $3
+            ) bucketed
+            GROUP BY bucket
+            ON CONFLICT (bucket, tenant) DO UPDATE SET
+                open_mid = EXCLUDED.open_mid,
+                high_mid = EXCLUDED.high_mid,
+                low_mid = EXCLUDED.low_mid,
+                close_mid = EXCLUDED.close_mid,
+                avg_spread_pct = EXCLUDED.avg_spread_pct,
+                min_total_depth_5pct = EXCLUDED.min_total_depth_5pct,
+                last_funding_rate_pct = EXCLUDED.last_funding_rate_pct,
+                sample_count = EXCLUDED.sample_count
+            ",
+            schema = self.schema
+        );
+        let since = Utc::now() - chrono::Duration::from_std(window)?;
+        Ok(client.execute(&query, &[&since, &interval.as_secs_f64(), &self.tenant]).await?)
+    }
+
+    pub async fn ensure_portfolio_table(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let client = self.client().await?;
+
+        let schema_sql = format!(
+            r"
+            CREATE SCHEMA IF NOT EXISTS {schema};
+
+            CREATE TABLE IF NOT EXISTS {schema}.{prefix}portfolio_liquidity (
+                id SERIAL PRIMARY KEY,
+                ts TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                tenant VARCHAR(64) NOT NULL DEFAULT '',
+                total_depth_5pct DECIMAL(24, 8) NOT NULL,
+                weighted_spread_pct DECIMAL(10, 6),
+                market_count INTEGER NOT NULL,
+                UNIQUE(ts, tenant)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_{prefix}portfolio_liquidity_ts
+                ON {schema}.{prefix}portfolio_liquidity(ts DESC);
+            ",
+            schema = self.schema,
+            prefix = self.table_prefix
+        );
+
+        client.batch_execute(&schema_sql).await?;
+        info!("✓ Created/verified table: {}.{}portfolio_liquidity", self.schema, self.table_prefix);
+        Ok(())
+    }
+
+    pub async fn insert_portfolio_liquidity(
+        &self,
+        snapshot: &PortfolioLiquidity,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let client = self.client().await?;
+        let query = format!(
+            "INSERT INTO {}.{}portfolio_liquidity (ts, tenant, total_depth_5pct, weighted_spread_pct, market_count)
+             VALUES ($1, $2, $3, $4, $5)",
+            self.schema, self.table_prefix
+        );
+        client
+            .execute(
+                &query,
+                &[
+                    &snapshot.timestamp,
+                    &self.tenant,
+                    &snapshot.total_depth_5pct,
+                    &snapshot.weighted_spread_pct,
+                    &snapshot.market_count,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn ensure_basket_table(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let client = self.client().await?;
+
+        let schema_sql = format!(
+            r"
+            CREATE SCHEMA IF NOT EXISTS {schema};
+
+            CREATE TABLE IF NOT EXISTS {schema}.{prefix}basket_metrics (
+                id SERIAL PRIMARY KEY,
+                ts TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                basket VARCHAR(64) NOT NULL,
+                tenant VARCHAR(64) NOT NULL DEFAULT '',
+                mid_price DECIMAL(20, 8),
+                spread_pct DECIMAL(10, 6),
+                total_depth_5pct DECIMAL(24, 8) NOT NULL,
+                component_count INTEGER NOT NULL,
+                UNIQUE(ts, basket, tenant)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_{prefix}basket_metrics_basket_ts
+                ON {schema}.{prefix}basket_metrics(basket, ts DESC);
+            ",
+            schema = self.schema,
+            prefix = self.table_prefix
         );
 
+        client.batch_execute(&schema_sql).await?;
+        info!("✓ Created/verified table: {}.{}basket_metrics", self.schema, self.table_prefix);
+        Ok(())
+    }
+
+    pub async fn insert_basket_metrics(
+        &self,
+        basket: &str,
+        timestamp: DateTime<Utc>,
+        metrics: &BasketMetrics,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let client = self.client().await?;
+        let query = format!(
+            "INSERT INTO {}.{}basket_metrics
+             (ts, basket, tenant, mid_price, spread_pct, total_depth_5pct, component_count)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            self.schema, self.table_prefix
+        );
         client
             .execute(
                 &query,
                 &[
-                    &metrics.coin,
-                    &metrics.mark_price,
-                    &metrics.oracle_price,
+                    &timestamp,
+                    &basket,
+                    &self.tenant,
                     &metrics.mid_price,
-                    &metrics.best_bid,
-                    &metrics.best_ask,
-                    &metrics.spread,
                     &metrics.spread_pct,
-                    &metrics.funding_rate_pct,
-                    &metrics.open_interest,
-                    &metrics.volume_24h,
-                    &metrics.bid_depth_5pct,
-                    &metrics.ask_depth_5pct,
                     &metrics.total_depth_5pct,
-                    &metrics.bid_depth_10pct,
-                    &metrics.ask_depth_10pct,
-                    &metrics.total_depth_10pct,
-                    &metrics.bid_depth_25pct,
-                    &metrics.ask_depth_25pct,
-                    &metrics.total_depth_25pct,
-                    &metrics.premium,
-                    &metrics.impact_px_bid,
-                    &metrics.impact_px_ask,
-                    &metrics.node_latency_ms,
-                    &metrics.websocket_latency_ms,
-                    &metrics.total_latency_ms,
-                    &metrics.timestamp,
+                    &metrics.component_count,
                 ],
             )
             .await?;
+        Ok(())
+    }
+
+    pub async fn ensure_information_share_table(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let client = self.client().await?;
 
+        let schema_sql = format!(
+            r"
+            CREATE SCHEMA IF NOT EXISTS {schema};
+
+            CREATE TABLE IF NOT EXISTS {schema}.{prefix}information_share (
+                id SERIAL PRIMARY KEY,
+                ts TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                coin VARCHAR(20) NOT NULL,
+                venue VARCHAR(64) NOT NULL,
+                tenant VARCHAR(64) NOT NULL DEFAULT '',
+                share DECIMAL(10, 8) NOT NULL,
+                UNIQUE(ts, coin, venue, tenant)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_{prefix}information_share_coin_ts
+                ON {schema}.{prefix}information_share(coin, ts DESC);
+            ",
+            schema = self.schema,
+            prefix = self.table_prefix
+        );
+
+        client.batch_execute(&schema_sql).await?;
+        info!("✓ Created/verified table: {}.{}information_share", self.schema, self.table_prefix);
+        Ok(())
+    }
+
+    /// Store each venue's price discovery share for a coin, see [`analytics::information_share`]
+    pub async fn insert_information_share(
+        &self,
+        coin: &str,
+        timestamp: DateTime<Utc>,
+        shares: &HashMap<String, Decimal>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let client = self.client().await?;
+        let query = format!(
+            "INSERT INTO {}.{}information_share (ts, coin, venue, tenant, share) VALUES ($1, $2, $3, $4, $5)",
+            self.schema, self.table_prefix
+        );
+        for (venue, share) in shares {
+            client.execute(&query, &[&timestamp, &coin, venue, &self.tenant, share]).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn insert_metrics(&self, metrics: &MarketMetrics) -> Result<(), Box<dyn std::error::Error>> {
+        self.insert_metrics_batch(std::slice::from_ref(metrics)).await?;
         Ok(())
     }
+
+    /// Insert many rows with one multi-row `INSERT` per coin table.
+    ///
+    /// Very large batches are split to stay within the bind parameter limit.
+    /// All statements go out pipelined on a single connection. Each coin's rows
+    /// succeed or fail together; the error names the coins that were rejected
+    /// so only their rows need retrying. Rows sharing a `(timestamp, coin)` key
+    /// would conflict with each other, so only the last one is inserted.
+    pub async fn insert_metrics_batch(&self, metrics: &[MarketMetrics]) -> Result<(), BatchInsertError> {
+        self.insert_rows(metrics, self.conflict_policy).await
+    }
+
+    /// Fill gaps in a coin's history with `rows`, returning how many were inserted.
+    ///
+    /// Rows landing in a `bucket` that already holds a stored row are dropped,
+    /// and the rest never overwrite a conflicting row whatever the configured
+    /// [`ConflictPolicy`], so live rows collected since the gap are kept.
+    pub async fn backfill_metrics(
+        &self,
+        coin: &str,
+        rows: &[MarketMetrics],
+        bucket: Duration,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let (Some(start), Some(end)) =
+            (rows.iter().map(|row| row.timestamp).min(), rows.iter().map(|row| row.timestamp).max())
+        else {
+            return Ok(0);
+        };
+        let query = format!(
+            "SELECT DISTINCT floor(extract(epoch FROM timestamp) / $1::int8)::int8 FROM {}.{}
+             WHERE timestamp >= $2 AND timestamp <= $3 AND tenant = $4",
+            self.schema,
+            self.market_table(coin)
+        );
+        let bucket_secs = i64::try_from(bucket.as_secs().max(1))?;
+        let client = self.client().await?;
+        let stored = self.timed("query", client.query(&query, &[&bucket_secs, &start, &end, &self.tenant])).await??;
+        drop(client);
+        let stored = stored.iter().map(|row| row.get::<_, i64>(0)).collect::<HashSet<_>>();
+
+        let missing = rows
+            .iter()
+            .filter(|row| !stored.contains(&row.timestamp.timestamp().div_euclid(bucket_secs)))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.insert_rows(&missing, ConflictPolicy::Ignore).await?;
+        Ok(missing.len())
+    }
+
+    async fn insert_rows(&self, metrics: &[MarketMetrics], policy: ConflictPolicy) -> Result<(), BatchInsertError> {
+        let mut by_coin: HashMap<&str, Vec<&MarketMetrics>> = HashMap::new();
+        let mut positions = HashMap::new();
+        for row in metrics {
+            let rows = by_coin.entry(row.coin.as_str()).or_default();
+            match positions.entry((row.coin.as_str(), row.timestamp)) {
+                Entry::Occupied(position) => {
+                    warn!("{}: duplicate row for {} in batch, keeping the latest", row.coin, row.timestamp);
+                    rows[*position.get()] = row;
+                }
+                Entry::Vacant(position) => {
+                    position.insert(rows.len());
+                    rows.push(row);
+                }
+            }
+        }
+
+        // Rows crossing into a new partition need it created before they can be inserted
+        let (mut failures, mut timeout) = (Vec::new(), None);
+        for (coin, rows) in &by_coin {
+            for row in rows {
+                if let Err(e) = self.ensure_partition(coin, row.timestamp).await {
+                    timeout = timeout.or_else(|| e.downcast_ref::<DatabaseError>().copied());
+                    failures.push(((*coin).to_string(), e.to_string()));
+                    break;
+                }
+            }
+        }
+        by_coin.retain(|coin, _| !failures.iter().any(|(failed, _)| failed == coin));
+        if by_coin.is_empty() {
+            return if failures.is_empty() { Ok(()) } else { Err(BatchInsertError { failures, timeout }) };
+        }
+
+        let client = match self.client().await {
+            Ok(client) => client,
+            Err(e) => {
+                failures.extend(by_coin.keys().map(|coin| ((*coin).to_string(), e.to_string())));
+                let timeout = timeout.or_else(|| e.downcast_ref::<DatabaseError>().copied());
+                return Err(BatchInsertError { failures, timeout });
+            }
+        };
+
+        let chunks =
+            by_coin.iter().flat_map(|(coin, rows)| rows.chunks(MAX_BATCH_ROWS).map(move |chunk| (coin, chunk)));
+        let inserts = chunks.map(|(coin, rows)| {
+            let client = &client;
+            async move {
+                let json = rows.iter().map(|row| JsonColumns::new(row)).collect::<Vec<_>>();
+                let params =
+                    rows.iter().zip(&json).flat_map(|(row, json)| self.insert_params(row, json)).collect::<Vec<_>>();
+                let query = self.batch_insert_query(coin, rows.len(), policy);
+                match self.timed("insert", client.execute(&query, &params)).await {
+                    Ok(result) => result.map(drop).map_err(|e| ((*coin).to_string(), e.to_string(), None)),
+                    Err(timeout) => Err(((*coin).to_string(), timeout.to_string(), Some(timeout))),
+                }
+            }
+        });
+
+        for (coin, e, timed_out) in futures_util::future::join_all(inserts).await.into_iter().filter_map(Result::err) {
+            timeout = timeout.or(timed_out);
+            failures.push((coin, e));
+        }
+        if failures.is_empty() { Ok(()) } else { Err(BatchInsertError { failures, timeout }) }
+    }
+
+    /// `INSERT` of `rows` rows into a coin's table, with [`INSERT_COLUMNS`] per row
+    fn batch_insert_query(&self, coin: &str, rows: usize, policy: ConflictPolicy) -> String {
+        let values = (0..rows)
+            .map(|row| {
+                let placeholders = INSERT_COLUMNS
+                    .iter()
+                    .enumerate()
+                    .map(|(i, column)| {
+                        let cast = if JSONB_COLUMNS.contains(column) { "::text::jsonb" } else { "" };
+                        format!("${}{cast}", row * INSERT_COLUMNS.len() + i + 1)
+                    })
+                    .collect::<Vec<_>>();
+                format!("({})", placeholders.join(", "))
+            })
+            .collect::<Vec<_>>();
+        let on_conflict = match policy {
+            ConflictPolicy::Update => {
+                let updates = INSERT_COLUMNS
+                    .iter()
+                    .filter(|column| !CONFLICT_COLUMNS.contains(column))
+                    .map(|column| format!("{column} = EXCLUDED.{column}"))
+                    .collect::<Vec<_>>();
+                format!("DO UPDATE SET {}", updates.join(", "))
+            }
+            ConflictPolicy::Ignore => "DO NOTHING".to_string(),
+        };
+        format!(
+            "INSERT INTO {}.{} ({}) VALUES {} ON CONFLICT ({}) {on_conflict}",
+            self.schema,
+            self.market_table(coin),
+            INSERT_COLUMNS.join(", "),
+            values.join(", "),
+            CONFLICT_COLUMNS.join(", ")
+        )
+    }
+
+    /// Parameters for one row, in [`INSERT_COLUMNS`] order
+    fn insert_params<'a>(
+        &'a self,
+        metrics: &'a MarketMetrics,
+        json: &'a JsonColumns,
+    ) -> [&'a (dyn ToSql + Sync); INSERT_COLUMNS.len()] {
+        [
+            &metrics.coin,
+            &self.tenant,
+            &metrics.mark_price,
+            &metrics.oracle_price,
+            &metrics.mid_price,
+            &metrics.oracle_stale,
+            &metrics.source,
+            &json.provider_prices,
+            &metrics.suspected_bad_print,
+            &metrics.best_bid,
+            &metrics.best_ask,
+            &metrics.spread,
+            &metrics.spread_pct,
+            &metrics.book_crossed,
+            &metrics.spread_autocorr,
+            &metrics.size_weighted_spread,
+            &metrics.mid_ci_low,
+            &metrics.mid_ci_high,
+            &metrics.realized_vol,
+            &metrics.order_book_imbalance,
+            &metrics.microprice,
+            &metrics.funding_rate_pct,
+            &metrics.liquidity_weighted_funding,
+            &metrics.basis_divergence,
+            &metrics.basis_pct,
+            &metrics.funding_differential,
+            &metrics.open_interest,
+            &metrics.volume_24h,
+            &metrics.volume_anomaly,
+            &metrics.activity_staleness_secs,
+            &metrics.bid_depth_5pct,
+            &metrics.ask_depth_5pct,
+            &metrics.total_depth_5pct,
+            &metrics.bid_qty_5pct,
+            &metrics.ask_qty_5pct,
+            &metrics.depth_stability,
+            &metrics.bid_depth_10pct,
+            &metrics.ask_depth_10pct,
+            &metrics.total_depth_10pct,
+            &metrics.bid_depth_25pct,
+            &metrics.ask_depth_25pct,
+            &metrics.total_depth_25pct,
+            &metrics.depth_truncated,
+            &metrics.bid_depth_elasticity,
+            &metrics.ask_depth_elasticity,
+            &json.depth_levels,
+            &json.imbalance_term_structure,
+            &json.spread_by_size,
+            &json.slippage_curve,
+            &metrics.slippage_partial_fill,
+            &json.timing_breakdown,
+            &metrics.premium,
+            &metrics.impact_px_bid,
+            &metrics.impact_px_ask,
+            &metrics.impact_adjusted_mid,
+            &metrics.quote_update_rate,
+            &metrics.quote_stuffing_suspected,
+            &metrics.spoofing_suspected,
+            &metrics.spoofing_score,
+            &metrics.node_latency_ms,
+            &metrics.websocket_latency_ms,
+            &metrics.total_latency_ms,
+            &metrics.timestamp,
+        ]
+    }
+
+    /// Roll's implied effective spread for a coin from stored mids in `[start, end)`.
+    ///
+    /// Uses the serial covariance of successive mid changes, see
+    /// [`analytics::roll_implied_spread`]. Returns `None` when the covariance is
+    /// non-negative or there are too few rows in the range.
+    pub async fn roll_implied_spread(
+        &self,
+        coin: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Option<Decimal>, Box<dyn std::error::Error>> {
+        let mids = self.mid_prices(coin, start, end).await?;
+        Ok(analytics::roll_implied_spread(&mids))
+    }
+
+    /// Mean-reversion half-life of `spread_pct` for a coin in `[start, end)`, in seconds.
+    ///
+    /// Fits an AR(1) on the stored spreads (see [`analytics::ar1_half_life`]) and
+    /// converts the half-life from samples to seconds using the average spacing
+    /// of the rows. Returns `None` when the fit isn't mean-reverting.
+    pub async fn spread_half_life(
+        &self,
+        coin: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Option<Decimal>, Box<dyn std::error::Error>> {
+        let table_name = self.market_table(coin);
+        let client = self.client().await?;
+
+        let query = format!(
+            "SELECT timestamp, spread_pct FROM {schema}.{table_name}
+             WHERE timestamp >= $1 AND timestamp < $2 AND tenant = $3 AND spread_pct IS NOT NULL AND NOT book_crossed
+             ORDER BY timestamp",
+            schema = self.schema
+        );
+        let rows = self.timed("query", client.query(&query, &[&start, &end, &self.tenant])).await??;
+        let spreads = rows.iter().map(|row| row.get::<_, Decimal>(1)).collect::<Vec<_>>();
+
+        let Some(half_life) = analytics::ar1_half_life(&spreads) else {
+            return Ok(None);
+        };
+        let (Some(first), Some(last)) = (rows.first(), rows.last()) else {
+            return Ok(None);
+        };
+        let elapsed = last.get::<_, DateTime<Utc>>(0) - first.get::<_, DateTime<Utc>>(0);
+        let elapsed_secs = Decimal::from(elapsed.num_milliseconds()) / Decimal::from(1000);
+        let sample_interval = elapsed_secs / Decimal::from(rows.len() - 1);
+        Ok(Some(half_life * sample_interval))
+    }
+
+    /// How `total_depth_5pct` responded to each realized volatility spike within
+    /// the last `lookback`, as `(spike_time, depth_change)`.
+    ///
+    /// A spike is a row whose `realized_vol` is at least twice its mean over the
+    /// lookback; `depth_change` is the relative change in depth one minute
+    /// later, see [`analytics::depth_response_to_vol_spikes`]. Negative values
+    /// mean liquidity pulled back after the spike, values near 0 that it held.
+    pub async fn depth_response_to_vol_spike(
+        &self,
+        coin: &str,
+        lookback: Duration,
+    ) -> Result<Vec<(DateTime<Utc>, Decimal)>, Box<dyn std::error::Error>> {
+        let table_name = self.market_table(coin);
+        let client = self.client().await?;
+
+        let query = format!(
+            "SELECT timestamp, realized_vol, total_depth_5pct FROM {schema}.{table_name}
+             WHERE timestamp >= $1 AND tenant = $2 AND realized_vol IS NOT NULL AND total_depth_5pct IS NOT NULL
+             ORDER BY timestamp",
+            schema = self.schema
+        );
+        let start = Utc::now() - chrono::Duration::from_std(lookback)?;
+        let samples = self
+            .timed("query", client.query(&query, &[&start, &self.tenant]))
+            .await??
+            .iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect::<Vec<_>>();
+        Ok(analytics::depth_response_to_vol_spikes(
+            &samples,
+            VOL_SPIKE_FACTOR,
+            chrono::Duration::from_std(DEPTH_RESPONSE_WINDOW)?,
+        ))
+    }
+
+    /// Percent deviation of the latest stored mid from the VWAP since `session_start`.
+    ///
+    /// The VWAP weights each stored mid by the growth in `volume_24h` since the
+    /// previous row, see [`analytics::session_vwap`]. Positive values mean the
+    /// price is rich relative to the session. Returns `None` when no rows with a
+    /// mid were stored or no volume traded in the session.
+    pub async fn session_vwap_deviation(
+        &self,
+        coin: &str,
+        session_start: DateTime<Utc>,
+    ) -> Result<Option<Decimal>, Box<dyn std::error::Error>> {
+        let table_name = self.market_table(coin);
+        let client = self.client().await?;
+
+        let query = format!(
+            "SELECT mid_price, volume_24h FROM {schema}.{table_name}
+             WHERE timestamp >= $1 AND tenant = $2 AND mid_price IS NOT NULL
+             ORDER BY timestamp",
+            schema = self.schema
+        );
+        let rows = self.timed("query", client.query(&query, &[&session_start, &self.tenant])).await??;
+        let samples = rows.iter().map(|row| (row.get(0), row.get(1))).collect::<Vec<_>>();
+
+        let (Some(vwap), Some((latest, _))) = (analytics::session_vwap(&samples), samples.last()) else {
+            return Ok(None);
+        };
+        if vwap.is_zero() {
+            return Ok(None);
+        }
+        Ok(Some((latest - vwap) / vwap * Decimal::ONE_HUNDRED))
+    }
+
+    /// Amihud illiquidity of a coin over `[start, end)` from its stored mids and
+    /// 24h volumes, see [`analytics::amihud_illiquidity`].
+    ///
+    /// Returns `None` when no volume traded between the stored rows.
+    pub async fn amihud_illiquidity(
+        &self,
+        coin: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Option<Decimal>, Box<dyn std::error::Error>> {
+        let table_name = self.market_table(coin);
+        let client = self.client().await?;
+
+        let query = format!(
+            "SELECT mid_price, volume_24h FROM {schema}.{table_name}
+             WHERE timestamp >= $1 AND timestamp < $2 AND tenant = $3 AND mid_price IS NOT NULL
+             ORDER BY timestamp",
+            schema = self.schema
+        );
+        let rows = self.timed("query", client.query(&query, &[&start, &end, &self.tenant])).await??;
+        let samples = rows.iter().map(|row| (row.get(0), row.get(1))).collect::<Vec<_>>();
+        Ok(analytics::amihud_illiquidity(&samples))
+    }
+
+    /// Mid-price OHLC candles for a coin in `[start, end)`, bucketed by `bucket`.
+    ///
+    /// Buckets are aligned to the Unix epoch. Buckets with no stored mids are
+    /// skipped or carried forward from the previous close depending on the
+    /// configured [`EmptyBucketPolicy`].
+    pub async fn ohlc(
+        &self,
+        coin: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        bucket: Duration,
+    ) -> Result<Vec<Candle>, Box<dyn std::error::Error>> {
+        if bucket.is_zero() {
+            return Err("Candle bucket must be non-zero".into());
+        }
+
+        let table_name = self.market_table(coin);
+        let client = self.client().await?;
+
+        let query = format!(
+            r"
+            SELECT
+                bucket,
+                (array_agg(mid_price ORDER BY timestamp ASC))[1] AS open,
+                MAX(mid_price) AS high,
+                MIN(mid_price) AS low,
+                (array_agg(mid_price ORDER BY timestamp DESC))[1] AS close
+            FROM (
+                SELECT
+                    to_timestamp(floor(extract(epoch FROM timestamp)::float8 / $3) * $3) AS bucket,
+                    timestamp,
+                    mid_price
+                FROM {schema}.{table_name}
+                WHERE timestamp >= $1 AND timestamp < $2 AND tenant = $4 AND mid_price IS NOT NULL
+            ) bucketed
+            GROUP BY bucket
+            ORDER BY bucket
+            ",
+            schema = self.schema
+        );
+        let rows =
+            self.timed("query", client.query(&query, &[&start, &end, &bucket.as_secs_f64(), &self.tenant])).await??;
+
+        let candles = rows
+            .iter()
+            .map(|row| Candle {
+                timestamp: row.get("bucket"),
+                open: row.get("open"),
+                high: row.get("high"),
+                low: row.get("low"),
+                close: row.get("close"),
+            })
+            .collect();
+
+        Ok(match self.empty_bucket_policy {
+            EmptyBucketPolicy::Skip => candles,
+            EmptyBucketPolicy::CarryForward => carry_forward_candles(candles, bucket),
+        })
+    }
+
+    /// Stored non-null mid prices for a coin in `[start, end)`, oldest first
+    async fn mid_prices(
+        &self,
+        coin: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Decimal>, Box<dyn std::error::Error>> {
+        let table_name = self.market_table(coin);
+        let client = self.client().await?;
+
+        let query = format!(
+            "SELECT mid_price FROM {schema}.{table_name}
+             WHERE timestamp >= $1 AND timestamp < $2 AND tenant = $3 AND mid_price IS NOT NULL
+             ORDER BY timestamp",
+            schema = self.schema
+        );
+        let rows = self.timed("query", client.query(&query, &[&start, &end, &self.tenant])).await??;
+
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Stored metrics rows for a coin in `[start, end)`, oldest first, at most `limit` of them.
+    ///
+    /// Returns an empty vec when the coin's table hasn't been created yet.
+    pub async fn query_metrics(
+        &self,
+        coin: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<MarketMetrics>, Box<dyn std::error::Error>> {
+        let query = format!(
+            "SELECT {columns} FROM {schema}.{table_name}
+             WHERE timestamp >= $1 AND timestamp < $2 AND tenant = $3
+             ORDER BY timestamp, coin, tenant
+             LIMIT $4",
+            columns = select_columns(),
+            schema = self.schema,
+            table_name = self.market_table(coin)
+        );
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        self.query_metrics_rows(&query, &[&start, &end, &self.tenant, &limit]).await
+    }
+
+    /// The page of [`Self::query_metrics`] following the row keyed `after`, the
+    /// `(timestamp, coin)` of the last row of the previous page.
+    ///
+    /// Pages follow the `(timestamp, coin, tenant)` key rather than the timestamp
+    /// alone, so rows sharing a timestamp are neither skipped nor repeated.
+    pub async fn query_metrics_after(
+        &self,
+        coin: &str,
+        after: (DateTime<Utc>, &str),
+        end: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<MarketMetrics>, Box<dyn std::error::Error>> {
+        let query = format!(
+            "SELECT {columns} FROM {schema}.{table_name}
+             WHERE (timestamp, coin, tenant) > ($1, $2, $3) AND timestamp < $4 AND tenant = $3
+             ORDER BY timestamp, coin, tenant
+             LIMIT $5",
+            columns = select_columns(),
+            schema = self.schema,
+            table_name = self.market_table(coin)
+        );
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let (timestamp, row_coin) = after;
+        self.query_metrics_rows(&query, &[&timestamp, &row_coin, &self.tenant, &end, &limit]).await
+    }
+
+    /// Stored metrics rows for a coin in `[start, end)`, oldest first, at full
+    /// resolution within the recent window and downsampled before it.
+    ///
+    /// Rows newer than `now - tiered_recent_window` are returned as stored.
+    /// Older rows are reduced to the last row of each epoch-aligned
+    /// `tiered_history_bucket`, so long ranges stay cheap to chart while the
+    /// recent past keeps every tick. Returns an empty vec when the coin's table
+    /// hasn't been created yet.
+    pub async fn query_tiered(
+        &self,
+        coin: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<MarketMetrics>, Box<dyn std::error::Error>> {
+        if self.tiered_history_bucket.is_zero() {
+            return Err("Tiered history bucket must be non-zero".into());
+        }
+        let cutoff = Utc::now() - chrono::Duration::from_std(self.tiered_recent_window)?;
+
+        let query = format!(
+            r"
+            (SELECT {columns} FROM {schema}.{table_name}
+             WHERE timestamp >= GREATEST($1::timestamptz, $3::timestamptz) AND timestamp < $2 AND tenant = $5)
+            UNION ALL
+            (SELECT DISTINCT ON (floor(extract(epoch FROM timestamp)::float8 / $4)) {columns}
+             FROM {schema}.{table_name}
+             WHERE timestamp >= $1 AND timestamp < LEAST($2::timestamptz, $3::timestamptz) AND tenant = $5
+             ORDER BY floor(extract(epoch FROM timestamp)::float8 / $4), timestamp DESC)
+            ORDER BY timestamp
+            ",
+            columns = select_columns(),
+            schema = self.schema,
+            table_name = self.market_table(coin)
+        );
+        let bucket_secs = self.tiered_history_bucket.as_secs_f64();
+        self.query_metrics_rows(&query, &[&start, &end, &cutoff, &bucket_secs, &self.tenant]).await
+    }
+
+    /// The most recently stored metrics row for a coin, `None` if there is none
+    /// or the coin's table hasn't been created yet
+    pub async fn latest_metrics(&self, coin: &str) -> Result<Option<MarketMetrics>, Box<dyn std::error::Error>> {
+        let query = format!(
+            "SELECT {columns} FROM {schema}.{table_name}
+             WHERE tenant = $1
+             ORDER BY timestamp DESC
+             LIMIT 1",
+            columns = select_columns(),
+            schema = self.schema,
+            table_name = self.market_table(coin)
+        );
+        Ok(self.query_metrics_rows(&query, &[&self.tenant]).await?.pop())
+    }
+
+    /// Run a `SELECT` of [`select_columns`], treating a missing table as no rows
+    async fn query_metrics_rows(
+        &self,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<MarketMetrics>, Box<dyn std::error::Error>> {
+        let client = self.client().await?;
+        let rows = match self.timed("query", client.query(query, params)).await? {
+            Ok(rows) => rows,
+            Err(e) if e.code() == Some(&SqlState::UNDEFINED_TABLE) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        rows.iter().map(metrics_from_row).collect()
+    }
+}
+
+/// [`INSERT_COLUMNS`] for reading rows back, with JSONB columns as text
+fn select_columns() -> String {
+    INSERT_COLUMNS
+        .iter()
+        .map(
+            |column| {
+                if JSONB_COLUMNS.contains(column) {
+                    format!("{column}::text AS {column}")
+                } else {
+                    (*column).to_string()
+                }
+            },
+        )
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Rebuild a [`MarketMetrics`] from a row selected with [`select_columns`]
+fn metrics_from_row(row: &Row) -> Result<MarketMetrics, Box<dyn std::error::Error>> {
+    let depth_levels: Option<String> = row.try_get("depth_levels")?;
+    let term_structure: Option<String> = row.try_get("imbalance_term_structure")?;
+    let spread_by_size: Option<String> = row.try_get("spread_by_size")?;
+    let slippage_curve: Option<String> = row.try_get("slippage_curve")?;
+    let timing_breakdown: Option<String> = row.try_get("timing_breakdown")?;
+    let provider_prices: Option<String> = row.try_get("provider_prices")?;
+    Ok(MarketMetrics {
+        coin: row.try_get("coin")?,
+        timestamp: row.try_get("timestamp")?,
+        mark_price: row.try_get("mark_price")?,
+        oracle_price: row.try_get("oracle_price")?,
+        mid_price: row.try_get("mid_price")?,
+        oracle_stale: row.try_get("oracle_stale")?,
+        source: row.try_get("source")?,
+        provider_prices: provider_prices.as_deref().map(serde_json::from_str).transpose()?.unwrap_or_default(),
+        suspected_bad_print: row.try_get("suspected_bad_print")?,
+        best_bid: row.try_get("best_bid")?,
+        best_ask: row.try_get("best_ask")?,
+        spread: row.try_get("spread")?,
+        spread_pct: row.try_get("spread_pct")?,
+        book_crossed: row.try_get("book_crossed")?,
+        spread_autocorr: row.try_get("spread_autocorr")?,
+        size_weighted_spread: row.try_get("size_weighted_spread")?,
+        mid_ci_low: row.try_get("mid_ci_low")?,
+        mid_ci_high: row.try_get("mid_ci_high")?,
+        realized_vol: row.try_get("realized_vol")?,
+        order_book_imbalance: row.try_get("order_book_imbalance")?,
+        microprice: row.try_get("microprice")?,
+        funding_rate_pct: row.try_get("funding_rate_pct")?,
+        liquidity_weighted_funding: row.try_get("liquidity_weighted_funding")?,
+        basis_divergence: row.try_get("basis_divergence")?,
+        basis_pct: row.try_get("basis_pct")?,
+        funding_differential: row.try_get("funding_differential")?,
+        open_interest: row.try_get("open_interest")?,
+        volume_24h: row.try_get("volume_24h")?,
+        volume_anomaly: row.try_get("volume_anomaly")?,
+        activity_staleness_secs: row.try_get("activity_staleness_secs")?,
+        bid_depth_5pct: row.try_get("bid_depth_5pct")?,
+        ask_depth_5pct: row.try_get("ask_depth_5pct")?,
+        total_depth_5pct: row.try_get("total_depth_5pct")?,
+        bid_qty_5pct: row.try_get("bid_qty_5pct")?,
+        ask_qty_5pct: row.try_get("ask_qty_5pct")?,
+        depth_stability: row.try_get("depth_stability")?,
+        bid_depth_10pct: row.try_get("bid_depth_10pct")?,
+        ask_depth_10pct: row.try_get("ask_depth_10pct")?,
+        total_depth_10pct: row.try_get("total_depth_10pct")?,
+        bid_depth_25pct: row.try_get("bid_depth_25pct")?,
+        ask_depth_25pct: row.try_get("ask_depth_25pct")?,
+        total_depth_25pct: row.try_get("total_depth_25pct")?,
+        depth_truncated: row.try_get("depth_truncated")?,
+        bid_depth_elasticity: row.try_get("bid_depth_elasticity")?,
+        ask_depth_elasticity: row.try_get("ask_depth_elasticity")?,
+        depth_levels: depth_levels.as_deref().map(parse_depth_levels_json).transpose()?.unwrap_or_default(),
+        imbalance_term_structure: term_structure
+            .as_deref()
+            .map(parse_term_structure_json)
+            .transpose()?
+            .unwrap_or_default(),
+        spread_by_size: spread_by_size.as_deref().map(parse_spread_by_size_json).transpose()?.unwrap_or_default(),
+        slippage_curve: slippage_curve.as_deref().map(serde_json::from_str).transpose()?.unwrap_or_default(),
+        slippage_partial_fill: row.try_get("slippage_partial_fill")?,
+        timing_breakdown: timing_breakdown.as_deref().map(serde_json::from_str).transpose()?,
+        premium: row.try_get("premium")?,
+        impact_px_bid: row.try_get("impact_px_bid")?,
+        impact_px_ask: row.try_get("impact_px_ask")?,
+        impact_adjusted_mid: row.try_get("impact_adjusted_mid")?,
+        quote_update_rate: row.try_get("quote_update_rate")?,
+        quote_stuffing_suspected: row.try_get("quote_stuffing_suspected")?,
+        spoofing_suspected: row.try_get("spoofing_suspected")?,
+        spoofing_score: row.try_get("spoofing_score")?,
+        node_latency_ms: row.try_get("node_latency_ms")?,
+        websocket_latency_ms: row.try_get("websocket_latency_ms")?,
+        total_latency_ms: row.try_get("total_latency_ms")?,
+    })
+}
+
+/// Write `ddl` to `{dir}/{version}/{schema}.{table_name}.sql`, returning the path
+fn write_schema_snapshot(dir: &Path, schema: &str, table_name: &str, ddl: &str) -> std::io::Result<PathBuf> {
+    let version = env!("CARGO_PKG_VERSION");
+    let dir = dir.join(version);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{schema}.{table_name}.sql"));
+    std::fs::write(&path, format!("-- {schema}.{table_name}, server {version}\n{ddl}"))?;
+    Ok(path)
+}
+
+/// Strip blank lines and the common indentation SQL picks up from being embedded in Rust source
+fn dedent(sql: &str) -> String {
+    let lines = sql.lines().filter(|line| !line.trim().is_empty()).collect::<Vec<_>>();
+    let indent = lines.iter().map(|line| line.len() - line.trim_start().len()).min().unwrap_or_default();
+    lines.into_iter().map(|line| line.get(indent..).unwrap_or(line).trim_end()).fold(String::new(), |mut sql, line| {
+        sql.push_str(line);
+        sql.push('\n');
+        sql
+    })
+}
+
+/// Tenant labels are embedded in schema names, so only allow identifier characters
+fn valid_tenant(tenant: &str) -> bool {
+    !tenant.is_empty() && tenant.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether `prefix` is a short unquoted identifier, leaving room for the coin
+/// and suffix within Postgres' 63 byte limit on names
+fn valid_table_prefix(prefix: &str) -> bool {
+    prefix.len() <= MAX_TABLE_PREFIX_LEN
+        && prefix.starts_with(|c: char| c.is_ascii_alphabetic())
+        && prefix.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether `template` names a table through `{coin}` and stays a short
+/// unquoted identifier whatever the coin
+fn valid_table_name_template(template: &str) -> bool {
+    let rest = template.replace(COIN_PLACEHOLDER, "");
+    template.contains(COIN_PLACEHOLDER)
+        && rest.len() <= MAX_TABLE_NAME_TEMPLATE_LEN
+        && !template.starts_with(|c: char| c.is_ascii_digit())
+        && rest.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A coin symbol as it appears in table names: lowercased, with characters
+/// outside identifiers such as the `@` of spot pairs replaced by `_`
+fn table_coin(coin: &str) -> String {
+    coin.to_lowercase().chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect()
+}
+
+/// `name` with a leading `_` when a coin like `1000BONK` would start it with a digit
+fn unquoted_name(name: String) -> String {
+    if name.starts_with(|c: char| c.is_ascii_digit()) { format!("_{name}") } else { name }
+}
+
+/// Fill gaps between candles with flat candles at the previous close
+fn carry_forward_candles(candles: Vec<Candle>, bucket: Duration) -> Vec<Candle> {
+    let Ok(step) = chrono::Duration::from_std(bucket) else {
+        return candles;
+    };
+
+    let mut filled: Vec<Candle> = Vec::with_capacity(candles.len());
+    for candle in candles {
+        if let Some(previous) = filled.last().cloned() {
+            let mut timestamp = previous.timestamp + step;
+            while timestamp < candle.timestamp {
+                filled.push(Candle {
+                    timestamp,
+                    open: previous.close,
+                    high: previous.close,
+                    low: previous.close,
+                    close: previous.close,
+                });
+                timestamp += step;
+            }
+        }
+        filled.push(candle);
+    }
+    filled
+}
+
+/// JSONB column values for one row, bound as text and cast on insert
+struct JsonColumns {
+    provider_prices: Option<String>,
+    depth_levels: Option<String>,
+    imbalance_term_structure: Option<String>,
+    spread_by_size: Option<String>,
+    slippage_curve: Option<String>,
+    timing_breakdown: Option<String>,
+}
+
+impl JsonColumns {
+    fn new(metrics: &MarketMetrics) -> Self {
+        Self {
+            provider_prices: provider_prices_json(&metrics.provider_prices),
+            depth_levels: depth_levels_json(&metrics.depth_levels),
+            imbalance_term_structure: term_structure_json(&metrics.imbalance_term_structure),
+            spread_by_size: spread_by_size_json(&metrics.spread_by_size),
+            slippage_curve: slippage_curve_json(&metrics.slippage_curve),
+            timing_breakdown: metrics.timing_breakdown.as_ref().map(timing_breakdown_json),
+        }
+    }
+}
+
+/// JSONB text `[{"band_pct": 1, "bid": 500, "ask": 400, "total": 900}, ...]`, or NULL without an order book
+fn depth_levels_json(depth_levels: &[(Decimal, (Decimal, Decimal))]) -> Option<String> {
+    if depth_levels.is_empty() {
+        return None;
+    }
+    let levels = depth_levels
+        .iter()
+        .map(|(level, (bid, ask))| {
+            serde_json::json!({
+                "band_pct": (level * Decimal::ONE_HUNDRED).to_f64(),
+                "bid": bid.to_f64(),
+                "ask": ask.to_f64(),
+                "total": (bid + ask).to_f64(),
+            })
+        })
+        .collect();
+    Some(serde_json::Value::Array(levels).to_string())
+}
+
+/// `(level, (bid, ask))` depth per level, as in [`MarketMetrics::depth_levels`]
+type DepthLevels = Vec<(Decimal, (Decimal, Decimal))>;
+
+/// Inverse of [`depth_levels_json`]
+fn parse_depth_levels_json(json: &str) -> Result<DepthLevels, serde_json::Error> {
+    #[derive(Deserialize)]
+    struct Level {
+        band_pct: Decimal,
+        bid: Decimal,
+        ask: Decimal,
+    }
+    let levels: Vec<Level> = serde_json::from_str(json)?;
+    Ok(levels.into_iter().map(|l| (l.band_pct / Decimal::ONE_HUNDRED, (l.bid, l.ask))).collect())
+}
+
+/// JSONB text `[{"band_pct": 5, "imbalance": 0.5}, ...]`, or NULL when no band had depth
+fn term_structure_json(term_structure: &[(Decimal, Decimal)]) -> Option<String> {
+    if term_structure.is_empty() {
+        return None;
+    }
+    let bands = term_structure
+        .iter()
+        .map(|(band_pct, imbalance)| {
+            serde_json::json!({ "band_pct": band_pct.to_f64(), "imbalance": imbalance.to_f64() })
+        })
+        .collect();
+    Some(serde_json::Value::Array(bands).to_string())
+}
+
+/// Inverse of [`term_structure_json`]
+fn parse_term_structure_json(json: &str) -> Result<Vec<(Decimal, Decimal)>, serde_json::Error> {
+    #[derive(Deserialize)]
+    struct Band {
+        band_pct: Decimal,
+        imbalance: Decimal,
+    }
+    let bands: Vec<Band> = serde_json::from_str(json)?;
+    Ok(bands.into_iter().map(|b| (b.band_pct, b.imbalance)).collect())
+}
+
+/// JSONB text `[{"size": 10000, "spread_pct": 0.05}, ...]`, with a null spread for
+/// sizes the book couldn't fill, or NULL without an order book
+fn spread_by_size_json(spread_by_size: &[(Decimal, Option<Decimal>)]) -> Option<String> {
+    if spread_by_size.is_empty() {
+        return None;
+    }
+    let sizes = spread_by_size
+        .iter()
+        .map(|(size, spread_pct)| {
+            serde_json::json!({ "size": size.to_f64(), "spread_pct": spread_pct.and_then(|s| s.to_f64()) })
+        })
+        .collect();
+    Some(serde_json::Value::Array(sizes).to_string())
+}
+
+/// Inverse of [`spread_by_size_json`]
+fn parse_spread_by_size_json(json: &str) -> Result<Vec<(Decimal, Option<Decimal>)>, serde_json::Error> {
+    #[derive(Deserialize)]
+    struct Size {
+        size: Decimal,
+        spread_pct: Option<Decimal>,
+    }
+    let sizes: Vec<Size> = serde_json::from_str(json)?;
+    Ok(sizes.into_iter().map(|s| (s.size, s.spread_pct)).collect())
+}
+
+/// JSONB text `[{"size": 10000, "buy": {...}, "sell": {...}}, ...]`, where each side is
+/// `{"avg_price": 101.2, "slippage_pct": 0.5, "filled_notional": 10000, "partial": false}`,
+/// or NULL without an order book
+fn slippage_curve_json(curve: &[SlippagePoint]) -> Option<String> {
+    if curve.is_empty() {
+        return None;
+    }
+    let fill = |fill: &FillEstimate| {
+        serde_json::json!({
+            "avg_price": fill.avg_price.and_then(|p| p.to_f64()),
+            "slippage_pct": fill.slippage_pct.and_then(|s| s.to_f64()),
+            "filled_notional": fill.filled_notional.to_f64(),
+            "partial": fill.partial,
+        })
+    };
+    let points = curve
+        .iter()
+        .map(|point| {
+            serde_json::json!({ "size": point.size.to_f64(), "buy": fill(&point.buy), "sell": fill(&point.sell) })
+        })
+        .collect();
+    Some(serde_json::Value::Array(points).to_string())
+}
+
+/// JSONB text `[{"source": "binance", "symbol": "BTCUSDT", "mark_price": "100.5", ...}, ...]`,
+/// or NULL when no provider had data
+fn provider_prices_json(prices: &[ProviderMarketData]) -> Option<String> {
+    if prices.is_empty() {
+        return None;
+    }
+    serde_json::to_string(prices).ok()
+}
+
+/// JSONB text `{"hl_lookup_ms": 1.2, "book_snapshot_ms": 0.4, ..., "total_ms": 3.1}`
+fn timing_breakdown_json(timing: &TimingBreakdown) -> String {
+    serde_json::json!({
+        "hl_lookup_ms": timing.hl_lookup_ms,
+        "book_snapshot_ms": timing.book_snapshot_ms,
+        "depth_computation_ms": timing.depth_computation_ms,
+        "total_ms": timing.total_ms,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market_metrics::test_util::temp_dir;
+    use chrono::Duration;
+
+    /// Database for integration tests, only available when `TEST_DATABASE_URL` is set
+    async fn test_database(coin: &str) -> Option<MetricsDatabase> {
+        let url = std::env::var("TEST_DATABASE_URL").ok()?;
+        let mut db = MetricsDatabase::new(&url, 2).await.unwrap();
+        let table_name = db.market_table(coin);
+        db.pool
+            .get()
+            .await
+            .unwrap()
+            .batch_execute(&format!("DROP TABLE IF EXISTS market_metrics.{table_name}"))
+            .await
+            .unwrap();
+        db.ensure_market_table(coin).await.unwrap();
+        Some(db)
+    }
+
+    #[tokio::test]
+    async fn test_roll_implied_spread_from_stored_mids() {
+        let Some(db) = test_database("ROLLTEST").await else { return };
+
+        // Mids bouncing between bid and ask of a constant 0.2 wide market
+        let start = Utc::now() - Duration::hours(1);
+        let bounces = [1, 0, 0, 1, 0, 1, 1, 0, 1, 0, 0, 0, 1, 1, 0, 1, 0, 1, 1, 1, 0, 0, 1, 0, 1, 0, 0, 1, 1, 0];
+        for (i, side) in bounces.iter().enumerate() {
+            let mut metrics = MarketMetrics::new("ROLLTEST".to_string());
+            metrics.timestamp = start + Duration::seconds(i64::try_from(i).unwrap());
+            metrics.mid_price = Some(if *side == 1 { Decimal::new(1001, 1) } else { Decimal::new(999, 1) });
+            db.insert_metrics(&metrics).await.unwrap();
+        }
+
+        let estimate = db.roll_implied_spread("ROLLTEST", start, Utc::now()).await.unwrap().unwrap();
+        let true_spread = Decimal::new(2, 1);
+        assert!((estimate - true_spread).abs() < Decimal::new(1, 1), "estimate {estimate}");
+    }
+
+    #[tokio::test]
+    async fn test_spread_half_life_from_stored_spreads() {
+        let Some(db) = test_database("HALFLIFETEST").await else { return };
+
+        // Spreads sampled every 2 seconds with a half-life of ~3.1 samples
+        let start = Utc::now() - Duration::hours(1);
+        let spreads = analytics::tests::mean_reverting_spreads(Decimal::new(8, 1), 500, 3);
+        for (i, spread_pct) in spreads.into_iter().enumerate() {
+            let mut metrics = MarketMetrics::new("HALFLIFETEST".to_string());
+            metrics.timestamp = start + Duration::seconds(2 * i64::try_from(i).unwrap());
+            metrics.spread_pct = Some(spread_pct);
+            db.insert_metrics(&metrics).await.unwrap();
+        }
+
+        let half_life = db.spread_half_life("HALFLIFETEST", start, Utc::now()).await.unwrap().unwrap();
+        assert!((half_life - Decimal::new(62, 1)).abs() < Decimal::from(2), "half-life {half_life}s");
+    }
+
+    #[tokio::test]
+    async fn test_session_vwap_deviation_from_stored_rows() {
+        let Some(db) = test_database("VWAPTEST").await else { return };
+
+        let session_start = Utc::now() - Duration::hours(1);
+        // The first row predates the session, the third has no volume
+        let rows = [(-1, "50", Some("0")), (0, "100", Some("1000")), (1, "102", Some("1010")), (2, "104", None)];
+        let rows = rows.into_iter().chain([(3, "101", Some("1040")), (4, "103", Some("1030"))]);
+        for (minute, mid, volume) in rows {
+            let mut metrics = MarketMetrics::new("VWAPTEST".to_string());
+            metrics.timestamp = session_start + Duration::minutes(minute);
+            metrics.mid_price = Some(Decimal::from_str(mid).unwrap());
+            metrics.volume_24h = volume.map(|v| Decimal::from_str(v).unwrap());
+            db.insert_metrics(&metrics).await.unwrap();
+        }
+
+        // VWAP of 101.25 from 10 traded at 102 and 30 at 101, latest mid 103
+        let deviation = db.session_vwap_deviation("VWAPTEST", session_start).await.unwrap().unwrap();
+        assert_eq!(deviation.round_dp(4), Decimal::new(17284, 4));
+
+        assert_eq!(db.session_vwap_deviation("VWAPTEST", Utc::now()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_amihud_illiquidity_from_stored_rows() {
+        let Some(db) = test_database("AMIHUDTEST").await else { return };
+
+        let start = Utc::now() - Duration::hours(1);
+        // The last row falls outside the window, the volume doesn't grow into the third
+        let rows = [(0, "100", "1000"), (1, "101", "1010"), (2, "103", "1010"), (3, "104.03", "1030")];
+        let rows = rows.into_iter().chain([(9, "50", "2000")]);
+        for (minute, mid, volume) in rows {
+            let mut metrics = MarketMetrics::new("AMIHUDTEST".to_string());
+            metrics.timestamp = start + Duration::minutes(minute);
+            metrics.mid_price = Some(Decimal::from_str(mid).unwrap());
+            metrics.volume_24h = Some(Decimal::from_str(volume).unwrap());
+            db.insert_metrics(&metrics).await.unwrap();
+        }
+
+        // ln(1.01) / 10 and ln(1.01) / 20
+        let end = start + Duration::minutes(5);
+        let amihud = db.amihud_illiquidity("AMIHUDTEST", start, end).await.unwrap().unwrap();
+        assert_eq!(amihud.round_dp(8), Decimal::new(74627, 8));
+
+        assert_eq!(db.amihud_illiquidity("AMIHUDTEST", start, start + Duration::minutes(1)).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_depth_response_to_vol_spike() {
+        let Some(db) = test_database("VOLSPIKETEST").await else { return };
+
+        // Rows every 5s for 10 minutes; vol spikes twice 20s apart, depth drops
+        // by 40% a minute after the first spike and recovers later
+        let start = Utc::now() - Duration::minutes(10);
+        let spike = start + Duration::minutes(2);
+        let rows = (0..120)
+            .map(|i| {
+                let mut metrics = MarketMetrics::new("VOLSPIKETEST".to_string());
+                metrics.timestamp = start + Duration::seconds(i * 5);
+                let since_spike = metrics.timestamp - spike;
+                let spiking = since_spike == Duration::zero() || since_spike == Duration::seconds(20);
+                metrics.realized_vol = Some(if spiking { Decimal::from(2) } else { Decimal::new(2, 1) });
+                let thin = since_spike >= Duration::seconds(30) && since_spike < Duration::minutes(3);
+                metrics.total_depth_5pct = Some(Decimal::from(if thin { 600 } else { 1_000 }));
+                metrics
+            })
+            .collect::<Vec<_>>();
+        db.insert_metrics_batch(&rows).await.unwrap();
+
+        let lookback = std::time::Duration::from_mins;
+        let responses = db.depth_response_to_vol_spike("VOLSPIKETEST", lookback(15)).await.unwrap();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].0.timestamp_micros(), spike.timestamp_micros());
+        assert_eq!(responses[0].1, Decimal::new(-4, 1));
+
+        // Nothing stands out in a calm window
+        assert!(db.depth_response_to_vol_spike("VOLSPIKETEST", lookback(2)).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tiered_query_downsamples_history() {
+        let Some(db) = test_database("TIERTEST").await else { return };
+        let db = db.with_tiered_resolution(std::time::Duration::from_hours(1), std::time::Duration::from_mins(5));
+
+        // Ten minutes of history two days back, aligned to a bucket boundary,
+        // and three rows in the recent window
+        let two_days_ago = (Utc::now() - Duration::days(2)).timestamp();
+        let history_start = DateTime::from_timestamp(two_days_ago / 300 * 300, 0).unwrap();
+        let history = (0..10).map(|minute| history_start + Duration::minutes(minute));
+        let recent_start = Utc::now() - Duration::minutes(10);
+        let recent = (0..3).map(|minute| recent_start + Duration::minutes(minute));
+        for timestamp in history.chain(recent) {
+            let mut metrics = MarketMetrics::new("TIERTEST".to_string());
+            metrics.timestamp = timestamp;
+            metrics.mid_price = Some(Decimal::from(timestamp.timestamp() % 1000));
+            db.insert_metrics(&metrics).await.unwrap();
+        }
+
+        let rows = db.query_tiered("TIERTEST", Utc::now() - Duration::days(3), Utc::now()).await.unwrap();
+        let timestamps = rows.iter().map(|row| row.timestamp.timestamp()).collect::<Vec<_>>();
+        let expected = [history_start + Duration::minutes(4), history_start + Duration::minutes(9)]
+            .into_iter()
+            .chain((0..3).map(|minute| recent_start + Duration::minutes(minute)))
+            .map(|timestamp| timestamp.timestamp())
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps, expected);
+        // Downsampled rows keep the values of the last row in their bucket
+        assert_eq!(rows[0].mid_price, Some(Decimal::from(expected[0] % 1000)));
+
+        assert!(db.query_tiered("NOTIERS", Utc::now() - Duration::days(3), Utc::now()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ohlc_candles_from_stored_mids() {
+        let Some(db) = test_database("OHLCTEST").await else { return };
+
+        // Two minutes of mids, aligned to a minute boundary
+        let start = DateTime::from_timestamp(Utc::now().timestamp() / 60 * 60 - 600, 0).unwrap();
+        let mids = [(0, 100), (10, 105), (20, 98), (50, 102), (60, 110), (90, 111)];
+        for (offset, mid) in mids {
+            let mut metrics = MarketMetrics::new("OHLCTEST".to_string());
+            metrics.timestamp = start + Duration::seconds(offset);
+            metrics.mid_price = Some(Decimal::from(mid));
+            db.insert_metrics(&metrics).await.unwrap();
+        }
+
+        let candles =
+            db.ohlc("OHLCTEST", start, start + Duration::minutes(5), std::time::Duration::from_mins(1)).await.unwrap();
+        assert_eq!(candles.len(), 2);
+        assert_eq!(
+            candles[0],
+            Candle {
+                timestamp: start,
+                open: Decimal::from(100),
+                high: Decimal::from(105),
+                low: Decimal::from(98),
+                close: Decimal::from(102),
+            }
+        );
+        assert_eq!(candles[1].open, Decimal::from(110));
+        assert_eq!(candles[1].close, Decimal::from(111));
+    }
+
+    #[test]
+    fn test_carry_forward_fills_empty_buckets() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let candle = |minutes: i64, close: i64| Candle {
+            timestamp: start + Duration::minutes(minutes),
+            open: Decimal::from(close),
+            high: Decimal::from(close),
+            low: Decimal::from(close),
+            close: Decimal::from(close),
+        };
+
+        let filled = carry_forward_candles(vec![candle(0, 100), candle(3, 103)], std::time::Duration::from_mins(1));
+        assert_eq!(filled.len(), 4);
+        assert_eq!(filled[1], candle(1, 100));
+        assert_eq!(filled[2], candle(2, 100));
+        assert_eq!(filled[3], candle(3, 103));
+    }
+
+    #[tokio::test]
+    async fn test_tenants_do_not_collide() {
+        let Some(db) = test_database("TENANTTEST").await else { return };
+        let url = std::env::var("TEST_DATABASE_URL").unwrap();
+        let mut team_a = MetricsDatabase::new(&url, 2).await.unwrap().with_tenant(Some("team_a"), false).unwrap();
+        let mut team_b = MetricsDatabase::new(&url, 2).await.unwrap().with_tenant(Some("team_b"), false).unwrap();
+        team_a.ensure_market_table("TENANTTEST").await.unwrap();
+        team_b.ensure_market_table("TENANTTEST").await.unwrap();
+
+        let mut metrics = MarketMetrics::new("TENANTTEST".to_string());
+        metrics.mid_price = Some(Decimal::from(100));
+        team_a.insert_metrics(&metrics).await.unwrap();
+        metrics.mid_price = Some(Decimal::from(200));
+        team_b.insert_metrics(&metrics).await.unwrap();
+
+        let rows = db
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query("SELECT tenant FROM market_metrics.tenanttest_metrics_raw ORDER BY tenant", &[])
+            .await
+            .unwrap();
+        let tenants = rows.iter().map(|row| row.get::<_, String>(0)).collect::<Vec<_>>();
+        assert_eq!(tenants, ["team_a", "team_b"]);
+
+        // Reads are scoped to the tenant
+        let range = (metrics.timestamp - Duration::seconds(1), metrics.timestamp + Duration::seconds(1));
+        let mids = team_b.mid_prices("TENANTTEST", range.0, range.1).await.unwrap();
+        assert_eq!(mids, [Decimal::from(200)]);
+    }
+
+    #[tokio::test]
+    async fn test_table_prefix_targets_prefixed_tables() {
+        let Some(plain) = test_database("PREFIXTEST").await else { return };
+        let url = std::env::var("TEST_DATABASE_URL").unwrap();
+        let mut db = MetricsDatabase::new(&url, 2).await.unwrap().with_table_prefix("staging_").unwrap();
+        let client = plain.pool.get().await.unwrap();
+        client.batch_execute("DROP TABLE IF EXISTS market_metrics.staging_prefixtest_metrics_raw").await.unwrap();
+        db.ensure_market_table("PREFIXTEST").await.unwrap();
+        db.ensure_portfolio_table().await.unwrap();
+
+        let mut metrics = MarketMetrics::new("PREFIXTEST".to_string());
+        metrics.mid_price = Some(Decimal::from(42));
+        db.insert_metrics(&metrics).await.unwrap();
+
+        let count = async |table: &str| {
+            let row = client.query_one(&format!("SELECT COUNT(*) FROM market_metrics.{table}"), &[]).await.unwrap();
+            row.get::<_, i64>(0)
+        };
+        assert_eq!(count("staging_prefixtest_metrics_raw").await, 1);
+        assert_eq!(count("prefixtest_metrics_raw").await, 0);
+        assert_eq!(count("staging_portfolio_liquidity").await, 0);
+
+        let range = (metrics.timestamp - Duration::seconds(1), metrics.timestamp + Duration::seconds(1));
+        let stored = db.query_metrics("PREFIXTEST", range.0, range.1, 10).await.unwrap();
+        assert_eq!(stored.iter().map(|row| row.mid_price).collect::<Vec<_>>(), [Some(Decimal::from(42))]);
+        assert_eq!(db.latest_metrics("PREFIXTEST").await.unwrap().unwrap().mid_price, Some(Decimal::from(42)));
+        assert!(plain.latest_metrics("PREFIXTEST").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_table_name_template_renders_odd_coins() {
+        let Some(plain) = test_database("@107").await else { return };
+        let url = std::env::var("TEST_DATABASE_URL").unwrap();
+        let mut db = MetricsDatabase::new(&url, 2).await.unwrap().with_table_name_template("Perp_{coin}").unwrap();
+        assert_eq!(db.market_table("@107"), "perp__107");
+        assert_eq!(db.market_table("kPEPE"), "perp_kpepe");
+        assert_eq!(plain.market_table("@107"), "_107_metrics_raw");
+        assert_eq!(plain.market_table("1000BONK"), "_1000bonk_metrics_raw");
+        // Rollup tables follow the template too
+        let minute = std::time::Duration::from_mins(1);
+        assert_eq!(db.rollup_table("kPEPE", minute).unwrap(), "perp_kpepe_1m");
+        assert_eq!(plain.rollup_table("1000BONK", minute).unwrap(), "_1000bonk_metrics_1m");
+
+        let client = plain.pool.get().await.unwrap();
+        client.batch_execute("DROP TABLE IF EXISTS market_metrics.perp__107").await.unwrap();
+        db.ensure_market_table("@107").await.unwrap();
+
+        let mut metrics = MarketMetrics::new("@107".to_string());
+        metrics.mid_price = Some(Decimal::from(7));
+        db.insert_metrics(&metrics).await.unwrap();
+        let row = client.query_one("SELECT COUNT(*) FROM market_metrics.perp__107", &[]).await.unwrap();
+        assert_eq!(row.get::<_, i64>(0), 1);
+        assert_eq!(db.latest_metrics("@107").await.unwrap().unwrap().mid_price, Some(Decimal::from(7)));
+        assert!(plain.latest_metrics("@107").await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid_table_name_template_rejected() {
+        assert!(valid_table_name_template(DEFAULT_TABLE_NAME_TEMPLATE));
+        assert!(valid_table_name_template("perp_{coin}_v2"));
+        assert!(!valid_table_name_template("metrics_raw"));
+        assert!(!valid_table_name_template("2_{coin}"));
+        assert!(!valid_table_name_template("{coin}-raw"));
+        assert!(!valid_table_name_template("{coin}; DROP TABLE x"));
+        assert!(!valid_table_name_template(&format!("{{coin}}{}", "a".repeat(MAX_TABLE_NAME_TEMPLATE_LEN + 1))));
+    }
+
+    #[test]
+    fn test_invalid_table_prefix_rejected() {
+        assert!(valid_table_prefix("prod"));
+        assert!(valid_table_prefix("dev_2"));
+        assert!(!valid_table_prefix("2prod"));
+        assert!(!valid_table_prefix("prod; DROP TABLE"));
+        assert!(!valid_table_prefix(&"a".repeat(MAX_TABLE_PREFIX_LEN + 1)));
+    }
+
+    #[test]
+    fn test_invalid_tenant_rejected() {
+        assert!(valid_tenant("desk_1"));
+        assert!(!valid_tenant("desk-1; DROP TABLE"));
+        assert!(!valid_tenant(""));
+    }
+
+    #[tokio::test]
+    async fn test_close_releases_connections() {
+        let Some(db) = test_database("CLOSETEST").await else { return };
+
+        // A connection still checked out when shutdown starts
+        let client = db.pool.get().await.unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            drop(client);
+        });
+
+        assert!(db.close(std::time::Duration::from_secs(2)).await);
+        assert!(db.pool.is_closed());
+        assert_eq!(db.pool.status().size, 0);
+        assert!(db.pool.get().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unbounded_numeric_keeps_full_precision() {
+        let Some(db) = test_database("NUMERICTEST").await else { return };
+
+        // The table already exists with DECIMAL(20, 8) and gets widened in place
+        let mut db = db.with_unbounded_numeric(&["MID_PRICE".to_string()]).unwrap();
+        db.created_tables.clear();
+        db.ensure_market_table("NUMERICTEST").await.unwrap();
+
+        let price = Decimal::from_str_exact("0.000000012345678912345").unwrap();
+        let mut metrics = MarketMetrics::new("NUMERICTEST".to_string());
+        metrics.mid_price = Some(price);
+        metrics.mark_price = Some(price);
+        db.insert_metrics(&metrics).await.unwrap();
+
+        let row = db
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query_one("SELECT mid_price, mark_price FROM market_metrics.numerictest_metrics_raw", &[])
+            .await
+            .unwrap();
+        assert_eq!(row.get::<_, Decimal>(0), price);
+        // Columns left bounded still round to 8 decimals
+        assert_eq!(row.get::<_, Decimal>(1), Decimal::new(1, 8));
+    }
+
+    #[tokio::test]
+    async fn test_schema_snapshot_recreates_table() {
+        let Some(db) = test_database("SNAPSHOTTEST").await else { return };
+        let dir = temp_dir("schema_snapshot");
+        let mut db =
+            db.with_unbounded_numeric(&["mid_price".to_string()]).unwrap().with_schema_snapshot_dir(Some(&dir));
+        db.created_tables.clear();
+        db.ensure_market_table("SNAPSHOTTEST").await.unwrap();
+
+        let client = db.pool.get().await.unwrap();
+        let columns = async || {
+            client
+                .query(
+                    "SELECT column_name::text, data_type::text, numeric_precision, numeric_scale \
+                     FROM information_schema.columns \
+                     WHERE table_schema = 'market_metrics' AND table_name = 'snapshottest_metrics_raw' \
+                     ORDER BY ordinal_position",
+                    &[],
+                )
+                .await
+                .unwrap()
+                .iter()
+                .map(|row| {
+                    let precision: (Option<i32>, Option<i32>) = (row.get(2), row.get(3));
+                    (row.get::<_, String>(0), row.get::<_, String>(1), precision)
+                })
+                .collect::<Vec<_>>()
+        };
+        let created = columns().await;
+
+        // Replaying the snapshot on an empty database gives the same table
+        let path = dir.join(env!("CARGO_PKG_VERSION")).join("market_metrics.snapshottest_metrics_raw.sql");
+        let ddl = std::fs::read_to_string(&path).unwrap();
+        assert!(ddl.contains("ALTER COLUMN mid_price TYPE NUMERIC"));
+        client.batch_execute("DROP TABLE market_metrics.snapshottest_metrics_raw").await.unwrap();
+        client.batch_execute(&ddl).await.unwrap();
+        assert_eq!(columns().await, created);
+        assert!(created.iter().any(|(name, data_type, ..)| name == "mid_price" && data_type == "numeric"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_migrations_cover_every_inserted_column() {
+        // Columns of the first release's table
+        let original = [
+            "timestamp",
+            "coin",
+            "mark_price",
+            "oracle_price",
+            "mid_price",
+            "best_bid",
+            "best_ask",
+            "spread",
+            "spread_pct",
+            "funding_rate_pct",
+            "open_interest",
+            "volume_24h",
+            "bid_depth_5pct",
+            "ask_depth_5pct",
+            "total_depth_5pct",
+            "bid_depth_10pct",
+            "ask_depth_10pct",
+            "total_depth_10pct",
+            "bid_depth_25pct",
+            "ask_depth_25pct",
+            "total_depth_25pct",
+            "premium",
+            "impact_px_bid",
+            "impact_px_ask",
+            "node_latency_ms",
+            "websocket_latency_ms",
+            "total_latency_ms",
+        ];
+        let migrated = MIGRATIONS.iter().flat_map(|migration| migration.columns.iter().map(|(column, _)| *column));
+        let covered = original.into_iter().chain(migrated).collect::<HashSet<_>>();
+        let missing = INSERT_COLUMNS.iter().filter(|column| !covered.contains(*column)).collect::<Vec<_>>();
+        assert!(missing.is_empty(), "columns without a migration: {missing:?}");
+
+        let versions = MIGRATIONS.iter().map(|migration| migration.version).collect::<Vec<_>>();
+        assert_eq!(versions, (1..=CURRENT_SCHEMA_VERSION).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_old_tables_migrated_before_insert() {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let mut db = MetricsDatabase::new(&url, 1).await.unwrap();
+        let client = db.pool.get().await.unwrap();
+        client
+            .batch_execute(
+                "CREATE SCHEMA IF NOT EXISTS market_metrics;
+                 DROP TABLE IF EXISTS market_metrics.migratetest_metrics_raw;
+                 CREATE TABLE IF NOT EXISTS market_metrics.schema_version (
+                     table_name VARCHAR(128) PRIMARY KEY,
+                     version INTEGER NOT NULL,
+                     migrated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+                 );
+                 DELETE FROM market_metrics.schema_version WHERE table_name = 'migratetest_metrics_raw';
+                 CREATE TABLE market_metrics.migratetest_metrics_raw (
+                     id SERIAL PRIMARY KEY,
+                     timestamp TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                     coin VARCHAR(20) NOT NULL,
+                     mark_price DECIMAL(20, 8), oracle_price DECIMAL(20, 8), mid_price DECIMAL(20, 8),
+                     best_bid DECIMAL(20, 8), best_ask DECIMAL(20, 8), spread DECIMAL(20, 8), spread_pct DECIMAL(10, 6),
+                     funding_rate_pct DECIMAL(12, 10), open_interest DECIMAL(20, 8), volume_24h DECIMAL(20, 8),
+                     bid_depth_5pct DECIMAL(20, 8), ask_depth_5pct DECIMAL(20, 8), total_depth_5pct DECIMAL(20, 8),
+                     bid_depth_10pct DECIMAL(20, 8), ask_depth_10pct DECIMAL(20, 8), total_depth_10pct DECIMAL(20, 8),
+                     bid_depth_25pct DECIMAL(20, 8), ask_depth_25pct DECIMAL(20, 8), total_depth_25pct DECIMAL(20, 8),
+                     premium DECIMAL(12, 10), impact_px_bid DECIMAL(20, 8), impact_px_ask DECIMAL(20, 8),
+                     node_latency_ms INTEGER, websocket_latency_ms INTEGER, total_latency_ms INTEGER,
+                     created_at TIMESTAMPTZ DEFAULT NOW(),
+                     UNIQUE(timestamp, coin)
+                 );",
+            )
+            .await
+            .unwrap();
+        drop(client);
+
+        db.ensure_market_table("MIGRATETEST").await.unwrap();
+        let mut metrics = MarketMetrics::new("MIGRATETEST".to_string());
+        metrics.microprice = Some(Decimal::from(100));
+        metrics.source = Some("hyperliquid".to_string());
+        db.insert_metrics(&metrics).await.unwrap();
+        let stored = db.latest_metrics("MIGRATETEST").await.unwrap().unwrap();
+        assert_eq!(stored.microprice, Some(Decimal::from(100)));
+        assert_eq!(stored.source.as_deref(), Some("hyperliquid"));
+
+        // Only `(timestamp, coin, tenant)` is unique now
+        let client = db.pool.get().await.unwrap();
+        client
+            .batch_execute(
+                "INSERT INTO market_metrics.migratetest_metrics_raw (timestamp, coin, tenant)
+                 VALUES ('2026-01-01', 'MIGRATETEST', 'a'), ('2026-01-01', 'MIGRATETEST', 'b')",
+            )
+            .await
+            .unwrap();
+        drop(client);
+
+        // The table is recorded as current, so later runs skip every step
+        assert_eq!(db.apply_migrations("MIGRATETEST").await.unwrap(), CURRENT_SCHEMA_VERSION);
+    }
+
+    #[tokio::test]
+    async fn test_timeouts_reported_as_database_errors() {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let timeouts = DatabaseTimeouts {
+            connect: Some(std::time::Duration::from_secs(5)),
+            wait: Some(std::time::Duration::from_millis(100)),
+            statement: Some(std::time::Duration::from_millis(100)),
+        };
+        let db = MetricsDatabase::connect_with_tls(&url, 1, "timeout-test", DbSslMode::Disable, None, timeouts)
+            .await
+            .unwrap();
+
+        let held = db.client().await.unwrap();
+        let err = db.query_metrics("BTC", Utc::now() - Duration::hours(1), Utc::now(), 10).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<DatabaseError>(),
+            Some(&DatabaseError::Timeout {
+                operation: "connection wait",
+                after: std::time::Duration::from_millis(100)
+            })
+        );
+
+        // The server cancels the statement, rather than leaving it running on the connection
+        let err = held.simple_query("SELECT pg_sleep(1)").await.unwrap_err();
+        assert_eq!(err.code(), Some(&SqlState::QUERY_CANCELED));
+
+        // ... and the client gives up too when the server doesn't answer in time
+        held.batch_execute("SET statement_timeout = 0").await.unwrap();
+        let err = db.timed("query", held.simple_query("SELECT pg_sleep(1)")).await.unwrap_err();
+        assert_eq!(err, DatabaseError::Timeout { operation: "query", after: std::time::Duration::from_millis(100) });
+        assert_eq!(err.to_string(), "database query timed out after 100ms");
+    }
+
+    #[tokio::test]
+    async fn test_partitions_created_as_rows_cross_boundaries() {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let mut db = MetricsDatabase::new(&url, 2).await.unwrap().with_partition_interval(PartitionInterval::Day);
+        let drop_table = "DROP TABLE IF EXISTS market_metrics.parttest_metrics_raw";
+        db.pool.get().await.unwrap().batch_execute(drop_table).await.unwrap();
+        db.ensure_market_table("PARTTEST").await.unwrap();
+        let client = db.pool.get().await.unwrap();
+
+        let partitions = async || {
+            client
+                .query(
+                    "SELECT c.relname FROM pg_inherits i JOIN pg_class c ON c.oid = i.inhrelid \
+                     WHERE i.inhparent = 'market_metrics.parttest_metrics_raw'::regclass ORDER BY c.relname",
+                    &[],
+                )
+                .await
+                .unwrap()
+                .iter()
+                .map(|row| row.get::<_, String>(0))
+                .collect::<Vec<_>>()
+        };
+        let today = format!("parttest_metrics_raw_p{}", Utc::now().format("%Y%m%d"));
+        assert_eq!(partitions().await, vec![today.clone()]);
+
+        let now = Utc::now();
+        let rows = [now, now - Duration::days(1), now - Duration::days(40)].map(|timestamp| {
+            let mut metrics = MarketMetrics::new("PARTTEST".to_string());
+            metrics.timestamp = timestamp;
+            metrics
+        });
+        db.insert_metrics_batch(&rows).await.unwrap();
+
+        let mut expected = [now - Duration::days(40), now - Duration::days(1)]
+            .map(|day| format!("parttest_metrics_raw_p{}", day.format("%Y%m%d")))
+            .to_vec();
+        expected.push(today);
+        assert_eq!(partitions().await, expected);
+        let stored =
+            db.query_metrics("PARTTEST", now - Duration::days(41), now + Duration::minutes(1), 10).await.unwrap();
+        assert_eq!(stored.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_prune_drops_expired_partitions_and_deletes_old_rows() {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let connect = async |tenant| {
+            let db = MetricsDatabase::new(&url, 2).await.unwrap().with_partition_interval(PartitionInterval::Day);
+            db.with_tenant(tenant, false).unwrap()
+        };
+        let mut db = connect(None).await;
+        let mut other = connect(Some("other")).await;
+        let drop_table = "DROP TABLE IF EXISTS market_metrics.prunetest_metrics_raw";
+        db.pool.get().await.unwrap().batch_execute(drop_table).await.unwrap();
+        db.ensure_market_table("PRUNETEST").await.unwrap();
+        other.ensure_market_table("PRUNETEST").await.unwrap();
+
+        // Cut off at noon yesterday, inside that day's partition
+        let today = Utc::now().date_naive().and_time(NaiveTime::MIN).and_utc();
+        let cutoff = today - Duration::hours(12);
+        let row = |timestamp| {
+            let mut metrics = MarketMetrics::new("PRUNETEST".to_string());
+            metrics.timestamp = timestamp;
+            metrics
+        };
+        let ours = [cutoff + Duration::hours(1), cutoff - Duration::hours(1), cutoff - Duration::days(3)].map(row);
+        db.insert_metrics_batch(&ours).await.unwrap();
+        let shared = [row(cutoff - Duration::days(5)), row(cutoff - Duration::days(5) + Duration::minutes(1))];
+        db.insert_metrics_batch(&shared[..1]).await.unwrap();
+        other.insert_metrics_batch(&shared[1..]).await.unwrap();
+
+        assert_eq!(db.prune_older_than("PRUNETEST", cutoff).await.unwrap(), 3);
+
+        let client = db.pool.get().await.unwrap();
+        let remaining = client
+            .query("SELECT timestamp, tenant FROM market_metrics.prunetest_metrics_raw ORDER BY timestamp", &[])
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| (row.get::<_, DateTime<Utc>>(0), row.get::<_, String>(1)))
+            .collect::<Vec<_>>();
+        assert_eq!(remaining, [(shared[1].timestamp, "other".to_string()), (ours[0].timestamp, String::new())]);
+        // The partition only we used is dropped, the one shared with the other tenant stays
+        let exists = async |timestamp: DateTime<Utc>| {
+            let name = format!("market_metrics.prunetest_metrics_raw_p{}", timestamp.format("%Y%m%d"));
+            let row = client.query_one("SELECT to_regclass($1) IS NOT NULL", &[&name]).await.unwrap();
+            row.get::<_, bool>(0)
+        };
+        assert!(!exists(ours[2].timestamp).await);
+        assert!(exists(shared[0].timestamp).await);
+
+        // Dropped partitions are recreated for late rows
+        db.insert_metrics(&ours[2]).await.unwrap();
+        assert!(exists(ours[2].timestamp).await);
+    }
+
+    #[tokio::test]
+    async fn test_rollup_aggregates_and_upserts_buckets() {
+        let Some(db) = test_database("ROLLUP").await else { return };
+        let interval = std::time::Duration::from_mins(1);
+        let drop_rollup = "DROP TABLE IF EXISTS market_metrics.rollup_metrics_1m";
+        db.pool.get().await.unwrap().batch_execute(drop_rollup).await.unwrap();
+        db.ensure_rollup_table("ROLLUP", interval).await.unwrap();
+
+        let minute = Utc::now().timestamp() / 60 * 60 - 180;
+        let row = |secs: i64, mid: i64, funding: Option<i64>| {
+            let mut metrics = MarketMetrics::new("ROLLUP".to_string());
+            metrics.timestamp = DateTime::from_timestamp(minute + secs, 0).unwrap();
+            metrics.mid_price = Some(Decimal::from(mid));
+            metrics.spread_pct = Some(Decimal::new(mid, 3));
+            metrics.total_depth_5pct = Some(Decimal::from(mid * 10));
+            metrics.funding_rate_pct = funding.map(|f| Decimal::new(f, 4));
+            metrics
+        };
+        let rows = [row(0, 100, Some(1)), row(20, 103, Some(2)), row(40, 99, None), row(60, 101, Some(3))];
+        db.insert_metrics_batch(&rows).await.unwrap();
+
+        let window = std::time::Duration::from_mins(10);
+        assert_eq!(db.compute_rollup("ROLLUP", interval, window).await.unwrap(), 2);
+        // A late row in a rolled up bucket is picked up by the next run
+        db.insert_metrics(&row(50, 105, None)).await.unwrap();
+        assert_eq!(db.compute_rollup("ROLLUP", interval, window).await.unwrap(), 2);
+
+        let buckets = db
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query(
+                "SELECT bucket, open_mid, high_mid, low_mid, close_mid, avg_spread_pct, min_total_depth_5pct,
+                        last_funding_rate_pct, sample_count
+                 FROM market_metrics.rollup_metrics_1m ORDER BY bucket",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(buckets.len(), 2);
+        let first = &buckets[0];
+        assert_eq!(first.get::<_, DateTime<Utc>>(0), rows[0].timestamp);
+        let prices = (1..=4).map(|i| first.get::<_, Decimal>(i)).collect::<Vec<_>>();
+        assert_eq!(prices, [100, 105, 99, 105].map(Decimal::from));
+        assert_eq!(first.get::<_, Decimal>(5), Decimal::new(10175, 5));
+        assert_eq!(first.get::<_, Decimal>(6), Decimal::from(990));
+        assert_eq!(first.get::<_, Decimal>(7), Decimal::new(2, 4));
+        assert_eq!(first.get::<_, i32>(8), 4);
+        assert_eq!(buckets[1].get::<_, i32>(8), 1);
+    }
+
+    #[test]
+    fn test_partition_bounds() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let bounds = |interval: PartitionInterval| interval.bounds(at("2024-02-29T13:45:00Z"));
+        assert_eq!(bounds(PartitionInterval::Day), (at("2024-02-29T00:00:00Z"), at("2024-03-01T00:00:00Z")));
+        assert_eq!(bounds(PartitionInterval::Week), (at("2024-02-26T00:00:00Z"), at("2024-03-04T00:00:00Z")));
+        assert_eq!(bounds(PartitionInterval::Month), (at("2024-02-01T00:00:00Z"), at("2024-03-01T00:00:00Z")));
+        assert_eq!("Monthly".parse(), Ok(PartitionInterval::Month));
+        assert!("hourly".parse::<PartitionInterval>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connections_report_application_name() {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let db = MetricsDatabase::connect(&url, 2, "anthias-risk-monitor-test").await.unwrap();
+
+        let row = db
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query_one("SELECT application_name FROM pg_stat_activity WHERE pid = pg_backend_pid()", &[])
+            .await
+            .unwrap();
+        assert_eq!(row.get::<_, String>(0), "anthias-risk-monitor-test");
+    }
+
+    #[tokio::test]
+    async fn test_batch_insert_across_tables() {
+        let Some(db) = test_database("BATCHA").await else { return };
+        let Some(_other) = test_database("BATCHB").await else { return };
+
+        let start = Utc::now() - Duration::minutes(5);
+        let rows = (0..3)
+            .flat_map(|i| {
+                ["BATCHA", "BATCHB"].map(|coin| {
+                    let mut metrics = MarketMetrics::new(coin.to_string());
+                    metrics.timestamp = start + Duration::seconds(i);
+                    metrics.mid_price = Some(Decimal::from(100 + i));
+                    metrics.imbalance_term_structure = vec![(Decimal::from(5), Decimal::new(1, 1))];
+                    metrics
+                })
+            })
+            .collect::<Vec<_>>();
+        db.insert_metrics_batch(&rows).await.unwrap();
+
+        let client = db.pool.get().await.unwrap();
+        for table in ["batcha_metrics_raw", "batchb_metrics_raw"] {
+            let row = client
+                .query_one(&format!("SELECT COUNT(*), MAX(mid_price) FROM market_metrics.{table}"), &[])
+                .await
+                .unwrap();
+            assert_eq!(row.get::<_, i64>(0), 3);
+            assert_eq!(row.get::<_, Decimal>(1), Decimal::from(102));
+        }
+
+        // With BATCHB's table gone only BATCHB is reported
+        client.batch_execute("DROP TABLE market_metrics.batchb_metrics_raw").await.unwrap();
+        let mut fresh = MarketMetrics::new("BATCHA".to_string());
+        fresh.timestamp = start + Duration::seconds(10);
+        let err = db.insert_metrics_batch(&[fresh, rows[1].clone()]).await.unwrap_err();
+        assert_eq!(err.failed_coins(), HashSet::from(["BATCHB"]));
+        assert!(err.to_string().contains("BATCHB"));
+    }
+
+    #[tokio::test]
+    async fn test_depth_levels_stored_as_json() {
+        let Some(db) = test_database("DEPTHLEVELS").await else { return };
+
+        let mut metrics = MarketMetrics::new("DEPTHLEVELS".to_string());
+        metrics.depth_levels = vec![
+            (Decimal::new(1, 2), (Decimal::from(500), Decimal::from(400))),
+            (Decimal::new(2, 2), (Decimal::from(900), Decimal::from(1100))),
+        ];
+        db.insert_metrics(&metrics).await.unwrap();
+
+        let row = db
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query_one(
+                "SELECT (depth_levels -> 1 ->> 'band_pct')::float8, (depth_levels -> 1 ->> 'total')::float8 \
+                 FROM market_metrics.depthlevels_metrics_raw",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert!((row.get::<_, f64>(0) - 2.0).abs() < f64::EPSILON);
+        assert!((row.get::<_, f64>(1) - 2000.0).abs() < f64::EPSILON);
+    }
+
+    #[tokio::test]
+    async fn test_batch_duplicate_key_keeps_last_row() {
+        let Some(db) = test_database("DUPKEY").await else { return };
+
+        let timestamp = Utc::now();
+        let rows = [100, 101, 102].map(|mid| {
+            let mut metrics = MarketMetrics::new("DUPKEY".to_string());
+            metrics.timestamp = timestamp;
+            metrics.mid_price = Some(Decimal::from(mid));
+            metrics
+        });
+        let mut other = MarketMetrics::new("DUPKEY".to_string());
+        other.timestamp = timestamp + Duration::seconds(1);
+        db.insert_metrics_batch(&[rows[0].clone(), other, rows[1].clone(), rows[2].clone()]).await.unwrap();
+
+        let row = db
+            .pool
+            .get()
+            .await
+            .unwrap()
+            .query_one(
+                "SELECT COUNT(*), MAX(mid_price) FILTER (WHERE timestamp = $1) FROM market_metrics.dupkey_metrics_raw",
+                &[&timestamp],
+            )
+            .await
+            .unwrap();
+        assert_eq!(row.get::<_, i64>(0), 2);
+        assert_eq!(row.get::<_, Decimal>(1), Decimal::from(102));
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_insert_updates_or_ignores() {
+        let Some(db) = test_database("CONFLICTTEST").await else { return };
+
+        let mut metrics = MarketMetrics::new("CONFLICTTEST".to_string());
+        metrics.mid_price = Some(Decimal::from(100));
+        db.insert_metrics(&metrics).await.unwrap();
+
+        // Replaying the same timestamp overwrites by default
+        metrics.mid_price = Some(Decimal::from(101));
+        db.insert_metrics(&metrics).await.unwrap();
+        assert_eq!(db.latest_metrics("CONFLICTTEST").await.unwrap().unwrap().mid_price, Some(Decimal::from(101)));
+
+        let db = db.with_conflict_policy(ConflictPolicy::Ignore);
+        metrics.mid_price = Some(Decimal::from(102));
+        db.insert_metrics(&metrics).await.unwrap();
+        let stored = db.query_metrics("CONFLICTTEST", metrics.timestamp, Utc::now(), 10).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].mid_price, Some(Decimal::from(101)));
+
+        assert_eq!("IGNORE".parse(), Ok(ConflictPolicy::Ignore));
+        assert!("replace".parse::<ConflictPolicy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_backfill_fills_only_empty_buckets() {
+        let Some(db) = test_database("BACKFILLTEST").await else { return };
+
+        let start = DateTime::from_timestamp(Utc::now().timestamp() / 60 * 60 - 600, 0).unwrap();
+        let mut live = MarketMetrics::new("BACKFILLTEST".to_string());
+        live.mid_price = Some(Decimal::from(100));
+        for minute in [1, 3] {
+            live.timestamp = start + Duration::minutes(minute) + Duration::seconds(17);
+            db.insert_metrics(&live).await.unwrap();
+        }
+
+        let candles = (0..5)
+            .map(|minute| {
+                let mut row = MarketMetrics::new("BACKFILLTEST".to_string());
+                row.timestamp = start + Duration::minutes(minute);
+                row.mid_price = Some(Decimal::from(200));
+                row
+            })
+            .collect::<Vec<_>>();
+        let bucket = std::time::Duration::from_mins(1);
+        assert_eq!(db.backfill_metrics("BACKFILLTEST", &candles, bucket).await.unwrap(), 3);
+        assert_eq!(db.backfill_metrics("BACKFILLTEST", &candles, bucket).await.unwrap(), 0);
+
+        let stored = db.query_metrics("BACKFILLTEST", start, Utc::now(), 10).await.unwrap();
+        let mids = stored.iter().map(|row| row.mid_price.unwrap().to_i64().unwrap()).collect::<Vec<_>>();
+        assert_eq!(mids, [200, 100, 200, 100, 200]);
+    }
+
+    #[tokio::test]
+    async fn test_rounded_metrics_fit_column_scales() {
+        let Some(db) = test_database("ROUNDTEST").await else { return };
+        let config: crate::market_metrics::MetricsConfig =
+            serde_json::from_value(serde_json::json!({ "database_url": "", "target_markets": ["ROUNDTEST"] })).unwrap();
+
+        let mut metrics = MarketMetrics::new("ROUNDTEST".to_string());
+        metrics.mid_price = Some(Decimal::from_str("12345.000000025").unwrap());
+        metrics.spread_pct = Some(Decimal::from_str("0.0123456789012345678901234").unwrap());
+        metrics.funding_rate_pct = Some(Decimal::from_str("0.0000123456789012345678901").unwrap());
+        metrics.total_depth_5pct = Some(Decimal::from_str("987654321.123456785").unwrap());
+        metrics.round_for_storage(&config);
+        db.insert_metrics(&metrics).await.unwrap();
+
+        // Stored exactly as rounded, so Postgres had nothing left to round
+        let range = (metrics.timestamp - Duration::seconds(1), metrics.timestamp + Duration::seconds(1));
+        let stored = db.query_metrics("ROUNDTEST", range.0, range.1, 10).await.unwrap();
+        assert_eq!(stored[0].mid_price, Some(Decimal::from_str("12345.00000002").unwrap()));
+        assert_eq!(stored[0].spread_pct, Some(Decimal::from_str("0.012346").unwrap()));
+        assert_eq!(stored[0].funding_rate_pct, Some(Decimal::from_str("0.0000123457").unwrap()));
+        assert_eq!(stored[0].total_depth_5pct, Some(Decimal::from_str("987654321.12345678").unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_query_metrics_round_trip() {
+        let Some(db) = test_database("QUERYBACK").await else { return };
+
+        let start = Utc::now() - Duration::minutes(10);
+        let rows = (0..3)
+            .map(|i| {
+                let mut metrics = MarketMetrics::new("QUERYBACK".to_string());
+                metrics.timestamp = start + Duration::minutes(i);
+                metrics.mark_price = Some(Decimal::new(100_000 + i, 3));
+                metrics.volume_anomaly = i == 1;
+                metrics.node_latency_ms = Some(40);
+                metrics.order_book_imbalance = Some(Decimal::new(-25, 2));
+                metrics.microprice = Some(Decimal::new(100_005, 3));
+                metrics.depth_levels = vec![(Decimal::new(5, 2), (Decimal::from(500), Decimal::from(300)))];
+                metrics.imbalance_term_structure = vec![(Decimal::from(5), Decimal::new(25, 2))];
+                metrics.spread_by_size =
+                    vec![(Decimal::from(10_000), Some(Decimal::new(5, 2))), (Decimal::from(1_000_000), None)];
+                let fill = |avg_price: Option<Decimal>, partial| FillEstimate {
+                    avg_price,
+                    slippage_pct: avg_price.map(|avg| (avg - Decimal::ONE_HUNDRED).abs()),
+                    filled_notional: Decimal::from(5_000),
+                    partial,
+                };
+                metrics.slippage_curve = vec![SlippagePoint {
+                    size: Decimal::from(10_000),
+                    buy: fill(Some(Decimal::new(1015, 1)), true),
+                    sell: fill(None, true),
+                }];
+                metrics.slippage_partial_fill = true;
+                metrics.book_crossed = true;
+                metrics.depth_truncated = true;
+                metrics.timing_breakdown = Some(TimingBreakdown {
+                    hl_lookup_ms: 1.5,
+                    book_snapshot_ms: 0.25,
+                    depth_computation_ms: 0.125,
+                    total_ms: 2.0,
+                });
+                metrics.source = Some("hyperliquid".to_string());
+                metrics.basis_pct = Some(Decimal::new(-25, 3));
+                metrics.funding_differential = Some(Decimal::new(875, 5));
+                metrics.provider_prices = vec![ProviderMarketData {
+                    source: "binance".to_string(),
+                    symbol: "QUERYBACKUSDT".to_string(),
+                    mark_price: Decimal::new(100_100, 3),
+                    index_price: Some(Decimal::new(100_050, 3)),
+                    mid_price: None,
+                    funding_rate_pct: Some(Decimal::new(125, 5)),
+                    fetched_at: start,
+                }];
+                metrics
+            })
+            .collect::<Vec<_>>();
+        db.insert_metrics_batch(&rows).await.unwrap();
+
+        let queried = db.query_metrics("QUERYBACK", start, start + Duration::minutes(5), 2).await.unwrap();
+        assert_eq!(queried.len(), 2);
+        assert_eq!(queried[0].mark_price, Some(Decimal::new(100_000, 3)));
+        assert!(queried[1].volume_anomaly && !queried[0].volume_anomaly);
+        assert_eq!(queried[0].timestamp.timestamp_micros(), start.timestamp_micros());
+        assert_eq!(queried[0].node_latency_ms, Some(40));
+        assert_eq!(queried[0].spread, None);
+        assert_eq!(queried[0].order_book_imbalance, Some(Decimal::new(-25, 2)));
+        assert_eq!(queried[0].microprice, Some(Decimal::new(100_005, 3)));
+        assert_eq!(queried[0].depth_levels, rows[0].depth_levels);
+        assert_eq!(queried[0].imbalance_term_structure, rows[0].imbalance_term_structure);
+        assert_eq!(queried[0].spread_by_size, rows[0].spread_by_size);
+        assert_eq!(queried[0].slippage_curve, rows[0].slippage_curve);
+        assert!(queried[0].slippage_partial_fill && queried[0].book_crossed && queried[0].depth_truncated);
+        assert_eq!(queried[0].timing_breakdown, rows[0].timing_breakdown);
+        assert_eq!(queried[0].source.as_deref(), Some("hyperliquid"));
+        assert_eq!(queried[0].provider_prices, rows[0].provider_prices);
+        assert_eq!(queried[0].basis_pct, Some(Decimal::new(-25, 3)));
+        assert_eq!(queried[0].funding_differential, Some(Decimal::new(875, 5)));
+
+        let latest = db.latest_metrics("QUERYBACK").await.unwrap().unwrap();
+        assert_eq!(latest.mark_price, Some(Decimal::new(100_002, 3)));
+
+        // Coins without a table read as empty rather than failing
+        assert!(db.query_metrics("NOTABLE", start, Utc::now(), 10).await.unwrap().is_empty());
+        assert!(db.latest_metrics("NOTABLE").await.unwrap().is_none());
+    }
+    #[tokio::test]
+    async fn test_query_metrics_after_pages_past_shared_timestamps() {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let tenant = format!("paging_{}", Utc::now().timestamp_micros());
+        let mut db = MetricsDatabase::new(&url, 2).await.unwrap().with_tenant(Some(&tenant), false).unwrap();
+        db.ensure_market_table("PAGETEST").await.unwrap();
+
+        // Rows of different coins can share a timestamp in one table. Whole
+        // seconds survive the microsecond precision of stored timestamps
+        let start = DateTime::from_timestamp((Utc::now() - Duration::minutes(5)).timestamp(), 0).unwrap();
+        let client = db.pool.get().await.unwrap();
+        for (offset, coin) in [(0, "PAGETEST"), (0, "PAGETESTB"), (0, "PAGETESTC"), (1, "PAGETEST")] {
+            let timestamp = start + Duration::seconds(offset);
+            client
+                .execute(
+                    "INSERT INTO market_metrics.pagetest_metrics_raw (timestamp, coin, tenant) VALUES ($1, $2, $3)",
+                    &[&timestamp, &coin, &tenant],
+                )
+                .await
+                .unwrap();
+        }
+        drop(client);
+
+        let end = Utc::now();
+        let mut page = db.query_metrics("PAGETEST", start, end, 2).await.unwrap();
+        let mut keys = Vec::new();
+        while let Some(last) = page.last() {
+            keys.extend(page.iter().map(|row| ((row.timestamp - start).num_seconds(), row.coin.clone())));
+            page = db.query_metrics_after("PAGETEST", (last.timestamp, &last.coin), end, 2).await.unwrap();
+        }
+        let expected = [(0, "PAGETEST"), (0, "PAGETESTB"), (0, "PAGETESTC"), (1, "PAGETEST")]
+            .map(|(offset, coin)| (offset, coin.to_string()));
+        assert_eq!(keys, expected);
+    }
 }
@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
+
+/// How connections to Postgres are secured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DbSslMode {
+    /// Plain TCP, for databases on localhost or a private network
+    #[default]
+    Disable,
+    /// Require TLS and verify the server certificate and host name against the
+    /// bundled web PKI roots plus any configured CA certificate
+    VerifyFull,
+}
+
+impl FromStr for DbSslMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "disable" => Ok(Self::Disable),
+            "verify-full" => Ok(Self::VerifyFull),
+            other => Err(format!("Unknown sslmode '{other}': expected disable or verify-full")),
+        }
+    }
+}
+
+/// Rustls connector trusting the web PKI roots and the PEM certificates in `ca_cert_path`.
+///
+/// Managed providers that sign with a private CA (e.g. RDS) need their bundle
+/// passed as `ca_cert_path`.
+#[cfg(feature = "tls")]
+pub fn connector(
+    ca_cert_path: Option<&Path>,
+) -> Result<tokio_postgres_rustls::MakeRustlsConnect, Box<dyn std::error::Error>> {
+    use rustls::pki_types::{CertificateDer, pem::PemObject};
+
+    let mut roots = rustls::RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+    if let Some(path) = ca_cert_path {
+        for cert in CertificateDer::pem_file_iter(path)? {
+            roots.add(cert?)?;
+        }
+    }
+
+    let provider = std::sync::Arc::new(rustls::crypto::ring::default_provider());
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(tokio_postgres_rustls::MakeRustlsConnect::new(config))
+}
+
+/// Without the `tls` feature there is no connector to build
+#[cfg(not(feature = "tls"))]
+pub fn connector(_ca_cert_path: Option<&Path>) -> Result<tokio_postgres::NoTls, Box<dyn std::error::Error>> {
+    Err("TLS database connections require building with the `tls` feature".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sslmode() {
+        assert_eq!("disable".parse(), Ok(DbSslMode::Disable));
+        assert_eq!("Verify-Full".parse(), Ok(DbSslMode::VerifyFull));
+        assert!("prefer".parse::<DbSslMode>().is_err());
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_connector_rejects_missing_ca_file() {
+        assert!(connector(None).is_ok());
+        assert!(connector(Some(Path::new("/nonexistent/ca.pem"))).is_err());
+    }
+
+    #[cfg(not(feature = "tls"))]
+    #[test]
+    fn test_connector_requires_feature() {
+        let err = connector(None).unwrap_err();
+        assert!(err.to_string().contains("`tls` feature"));
+    }
+}
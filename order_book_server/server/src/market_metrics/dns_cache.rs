@@ -0,0 +1,87 @@
+use log::warn;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+type DnsCache = Arc<Mutex<HashMap<String, (Instant, Vec<SocketAddr>)>>>;
+
+/// DNS resolver that caches lookups for a fixed TTL.
+///
+/// The poll loops hit the same API host several times a second, so resolving
+/// it once per TTL removes a per-request lookup. A lookup that fails after the
+/// TTL falls back to the last addresses resolved, keeping polling alive through
+/// brief resolver outages.
+#[derive(Debug, Clone)]
+pub struct CachingResolver {
+    ttl: Duration,
+    cache: DnsCache,
+}
+
+impl CachingResolver {
+    #[must_use]
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, cache: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Cached addresses for `host`, if resolved within the TTL
+    #[must_use]
+    pub fn cached(&self, host: &str) -> Option<Vec<SocketAddr>> {
+        let (expires_at, addrs) = self.cache.lock().ok()?.get(host)?.clone();
+        (Instant::now() < expires_at).then_some(addrs)
+    }
+}
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        if let Some(addrs) = self.cached(&host) {
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            return Box::pin(async move { Ok(addrs) });
+        }
+
+        let cache = self.cache.clone();
+        let ttl = self.ttl;
+        Box::pin(async move {
+            let addrs = match tokio::net::lookup_host((host.as_str(), 0)).await {
+                Ok(addrs) => addrs.collect::<Vec<_>>(),
+                Err(e) => {
+                    let stale = cache.lock().ok().and_then(|cache| cache.get(&host).map(|(_, addrs)| addrs.clone()));
+                    let Some(addrs) = stale else {
+                        return Err(e.into());
+                    };
+                    warn!("DNS lookup for {host} failed, using its expired addresses: {e}");
+                    let addrs: Addrs = Box::new(addrs.into_iter());
+                    return Ok(addrs);
+                }
+            };
+            if let Ok(mut cache) = cache.lock() {
+                cache.insert(host, (Instant::now() + ttl, addrs.clone()));
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_failed_lookup_falls_back_to_expired_addresses() {
+        let resolver = CachingResolver::new(Duration::from_mins(1));
+        let addr: SocketAddr = "10.0.0.1:0".parse().unwrap();
+        // `.invalid` never resolves
+        let host = "api.hyperliquid.invalid";
+        resolver.cache.lock().unwrap().insert(host.to_string(), (Instant::now(), vec![addr]));
+        assert_eq!(resolver.cached(host), None);
+
+        let addrs = resolver.resolve(host.parse().unwrap()).await.unwrap();
+        assert_eq!(addrs.collect::<Vec<_>>(), vec![addr]);
+
+        let unknown = CachingResolver::new(Duration::from_mins(1));
+        assert!(unknown.resolve(host.parse().unwrap()).await.is_err());
+    }
+}
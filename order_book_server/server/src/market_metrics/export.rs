@@ -0,0 +1,403 @@
+use crate::market_metrics::{database::MetricsDatabase, types::MarketMetrics};
+use chrono::{DateTime, Utc};
+use serde::de::{Deserialize, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde_json::Value;
+use std::fmt;
+use std::io::{BufWriter, Write};
+
+/// Rows read from the database at a time, so an export never holds more than
+/// one page in memory
+const PAGE_ROWS: usize = 10_000;
+
+/// The keys of a serialized object, in the order they were written
+struct FieldNames(Vec<String>);
+
+impl<'de> Deserialize<'de> for FieldNames {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NamesVisitor;
+
+        impl<'de> Visitor<'de> for NamesVisitor {
+            type Value = FieldNames;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a metrics object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut names = Vec::new();
+                while let Some((name, IgnoredAny)) = map.next_entry()? {
+                    names.push(name);
+                }
+                Ok(FieldNames(names))
+            }
+        }
+
+        deserializer.deserialize_map(NamesVisitor)
+    }
+}
+
+/// Column names of an export: every `MarketMetrics` field, in declaration order
+pub fn columns() -> Result<Vec<String>, serde_json::Error> {
+    // `serde_json::Value` sorts object keys, but the serialized text keeps declaration order
+    let json = serde_json::to_string(&MarketMetrics::new(String::new()))?;
+    Ok(serde_json::from_str::<FieldNames>(&json)?.0)
+}
+
+/// Call `on_page` with a coin's stored rows in `[start, end)`, oldest first,
+/// `page_rows` at a time. Returns the number of rows read.
+async fn for_each_page<F>(
+    db: &MetricsDatabase,
+    coin: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    page_rows: usize,
+    mut on_page: F,
+) -> Result<usize, Box<dyn std::error::Error>>
+where
+    F: FnMut(&[MarketMetrics]) -> Result<(), Box<dyn std::error::Error>>,
+{
+    let mut page = db.query_metrics(coin, start, end, page_rows).await?;
+    let mut total = 0;
+    while !page.is_empty() {
+        on_page(&page)?;
+        total += page.len();
+        if page.len() < page_rows {
+            break;
+        }
+        let last = &page[page.len() - 1];
+        page = db.query_metrics_after(coin, (last.timestamp, &last.coin), end, page_rows).await?;
+    }
+    Ok(total)
+}
+
+/// Write a coin's stored rows in `[start, end)` to `writer` as CSV with a
+/// header of [`columns`], reading them from the database a page at a time.
+///
+/// Missing values are empty cells; nested fields such as `depth_levels` are
+/// written as JSON. Returns the number of rows written.
+pub async fn export_csv<W: Write>(
+    db: &MetricsDatabase,
+    coin: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    writer: W,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    export_csv_paged(db, coin, start, end, writer, PAGE_ROWS).await
+}
+
+async fn export_csv_paged<W: Write>(
+    db: &MetricsDatabase,
+    coin: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    writer: W,
+    page_rows: usize,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut writer = BufWriter::new(writer);
+    let columns = columns()?;
+    writeln!(writer, "{}", columns.join(","))?;
+    let rows = for_each_page(db, coin, start, end, page_rows, |page| {
+        for metrics in page {
+            let row = serde_json::to_value(metrics)?;
+            let cells = columns.iter().map(|column| csv_cell(&row[column])).collect::<Vec<_>>();
+            writeln!(writer, "{}", cells.join(","))?;
+        }
+        Ok(())
+    })
+    .await?;
+    writer.flush()?;
+    Ok(rows)
+}
+
+/// A JSON value as a CSV cell, quoted when it holds a delimiter, quote or newline
+fn csv_cell(value: &Value) -> String {
+    let text = match value {
+        Value::Null => return String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    if text.contains([',', '"', '\n', '\r']) { format!("\"{}\"", text.replace('"', "\"\"")) } else { text }
+}
+
+/// Write a coin's stored rows in `[start, end)` to `writer` as Parquet, one
+/// row group per page read from the database.
+///
+/// Columns follow [`columns`]. `timestamp` is a UTC microsecond timestamp,
+/// latencies are `INT64`, flags are `BOOLEAN` and decimals are strings of
+/// their exact stored value, as are text columns and nested fields as JSON.
+/// Returns the number of rows written.
+#[cfg(feature = "parquet")]
+pub async fn export_parquet<W: Write + Send>(
+    db: &MetricsDatabase,
+    coin: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    writer: W,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    use parquet::arrow::ArrowWriter;
+    use std::sync::Arc;
+
+    let schema = Arc::new(parquet_export::schema()?);
+    let mut writer = ArrowWriter::try_new(writer, schema.clone(), None)?;
+    let rows = for_each_page(db, coin, start, end, PAGE_ROWS, |page| {
+        writer.write(&parquet_export::record_batch(&schema, page)?)?;
+        writer.flush()?;
+        Ok(())
+    })
+    .await?;
+    writer.close()?;
+    Ok(rows)
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_export {
+    use super::{MarketMetrics, Value, columns};
+    use arrow_array::{ArrayRef, BooleanArray, Int64Array, RecordBatch, StringArray, TimestampMicrosecondArray};
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use std::sync::Arc;
+
+    /// Decimal columns, written as text: unbounded `NUMERIC` columns have no
+    /// fixed scale to give a `Decimal128`, and a double would round what is stored
+    const DECIMAL_COLUMNS: [&str; 42] = [
+        "mark_price",
+        "oracle_price",
+        "mid_price",
+        "best_bid",
+        "best_ask",
+        "spread",
+        "spread_pct",
+        "spread_autocorr",
+        "size_weighted_spread",
+        "mid_ci_low",
+        "mid_ci_high",
+        "realized_vol",
+        "order_book_imbalance",
+        "microprice",
+        "funding_rate_pct",
+        "liquidity_weighted_funding",
+        "basis_divergence",
+        "basis_pct",
+        "funding_differential",
+        "open_interest",
+        "volume_24h",
+        "activity_staleness_secs",
+        "bid_depth_5pct",
+        "ask_depth_5pct",
+        "total_depth_5pct",
+        "bid_qty_5pct",
+        "ask_qty_5pct",
+        "depth_stability",
+        "bid_depth_10pct",
+        "ask_depth_10pct",
+        "total_depth_10pct",
+        "bid_depth_25pct",
+        "ask_depth_25pct",
+        "total_depth_25pct",
+        "bid_depth_elasticity",
+        "ask_depth_elasticity",
+        "premium",
+        "impact_px_bid",
+        "impact_px_ask",
+        "impact_adjusted_mid",
+        "quote_update_rate",
+        "spoofing_score",
+    ];
+
+    /// Text columns and nested fields, the latter written as JSON
+    const TEXT_COLUMNS: [&str; 8] = [
+        "coin",
+        "source",
+        "provider_prices",
+        "depth_levels",
+        "imbalance_term_structure",
+        "spread_by_size",
+        "slippage_curve",
+        "timing_breakdown",
+    ];
+
+    const BOOLEAN_COLUMNS: [&str; 8] = [
+        "oracle_stale",
+        "suspected_bad_print",
+        "book_crossed",
+        "volume_anomaly",
+        "depth_truncated",
+        "slippage_partial_fill",
+        "quote_stuffing_suspected",
+        "spoofing_suspected",
+    ];
+
+    const INTEGER_COLUMNS: [&str; 3] = ["node_latency_ms", "websocket_latency_ms", "total_latency_ms"];
+
+    /// Arrow schema of [`super::columns`], failing for a column without a declared type
+    pub(super) fn schema() -> Result<Schema, Box<dyn std::error::Error>> {
+        let fields = columns()?
+            .into_iter()
+            .map(|name| match column_type(&name) {
+                Some(data_type) => Ok(Field::new(name, data_type, true)),
+                None => Err(format!("no Parquet type declared for column {name}")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Schema::new(fields))
+    }
+
+    fn column_type(column: &str) -> Option<DataType> {
+        if column == "timestamp" {
+            Some(DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())))
+        } else if BOOLEAN_COLUMNS.contains(&column) {
+            Some(DataType::Boolean)
+        } else if INTEGER_COLUMNS.contains(&column) {
+            Some(DataType::Int64)
+        } else if DECIMAL_COLUMNS.contains(&column) || TEXT_COLUMNS.contains(&column) {
+            Some(DataType::Utf8)
+        } else {
+            None
+        }
+    }
+
+    /// One page of rows as a batch of `schema`
+    pub(super) fn record_batch(
+        schema: &Arc<Schema>,
+        page: &[MarketMetrics],
+    ) -> Result<RecordBatch, Box<dyn std::error::Error>> {
+        let rows = page.iter().map(serde_json::to_value).collect::<Result<Vec<_>, _>>()?;
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let values = rows.iter().map(|row| &row[field.name().as_str()]);
+                let array: ArrayRef = match field.data_type() {
+                    DataType::Timestamp(..) => Arc::new(
+                        page.iter()
+                            .map(|metrics| Some(metrics.timestamp.timestamp_micros()))
+                            .collect::<TimestampMicrosecondArray>()
+                            .with_timezone("UTC"),
+                    ),
+                    DataType::Int64 => Arc::new(values.map(Value::as_i64).collect::<Int64Array>()),
+                    DataType::Boolean => Arc::new(values.map(Value::as_bool).collect::<BooleanArray>()),
+                    _ => Arc::new(values.map(as_string).collect::<StringArray>()),
+                };
+                array
+            })
+            .collect();
+        Ok(RecordBatch::try_new(schema.clone(), columns)?)
+    }
+
+    fn as_string(value: &Value) -> Option<String> {
+        match value {
+            Value::Null => None,
+            Value::String(s) => Some(s.clone()),
+            other => Some(other.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_columns_follow_field_order() {
+        let columns = columns().unwrap();
+        assert_eq!(columns[..3], ["coin", "timestamp", "mark_price"]);
+        let fields = serde_json::to_value(MarketMetrics::new(String::new())).unwrap();
+        assert_eq!(columns.len(), fields.as_object().unwrap().len());
+    }
+
+    #[test]
+    fn test_csv_cells_escaped() {
+        assert_eq!(csv_cell(&Value::Null), "");
+        assert_eq!(csv_cell(&serde_json::json!("1.5")), "1.5");
+        assert_eq!(csv_cell(&serde_json::json!(true)), "true");
+        assert_eq!(csv_cell(&serde_json::json!(["a", "b"])), r#""[""a"",""b""]""#);
+    }
+
+    /// Database with rows for `coin` under a tenant unique to this run, only
+    /// available when `TEST_DATABASE_URL` is set
+    async fn seeded_database(coin: &str, rows: i64) -> Option<(MetricsDatabase, DateTime<Utc>)> {
+        let url = std::env::var("TEST_DATABASE_URL").ok()?;
+        let tenant = format!("export_{}", Utc::now().timestamp_micros());
+        let mut db = MetricsDatabase::new(&url, 2).await.unwrap().with_tenant(Some(&tenant), false).unwrap();
+        db.ensure_market_table(coin).await.unwrap();
+        let start = Utc::now() - chrono::Duration::minutes(10);
+        let rows = (0..rows)
+            .map(|i| {
+                let mut metrics = MarketMetrics::new(coin.to_string());
+                metrics.timestamp = start + chrono::Duration::seconds(i);
+                metrics.mark_price = Some(Decimal::from(100 + i));
+                metrics
+            })
+            .collect::<Vec<_>>();
+        db.insert_metrics_batch(&rows).await.unwrap();
+        Some((db, start))
+    }
+
+    #[tokio::test]
+    async fn test_csv_export_pages_through_range() {
+        let Some((db, start)) = seeded_database("EXPORTTEST", 5).await else { return };
+
+        let mut out = Vec::new();
+        let written = export_csv_paged(&db, "EXPORTTEST", start, Utc::now(), &mut out, 2).await.unwrap();
+        assert_eq!(written, 5);
+
+        let csv = String::from_utf8(out).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], columns().unwrap().join(","));
+        assert_eq!(lines.len(), 6);
+        let mark_prices = lines[1..]
+            .iter()
+            .map(|line| line.split(',').nth(2).unwrap().parse::<Decimal>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(mark_prices, (100..105).map(Decimal::from).collect::<Vec<_>>());
+
+        let mut out = Vec::new();
+        let end = start + chrono::Duration::seconds(2);
+        assert_eq!(export_csv(&db, "EXPORTTEST", start, end, &mut out).await.unwrap(), 2);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_schema_declares_every_column() {
+        use arrow_schema::DataType;
+
+        let schema = parquet_export::schema().unwrap();
+        let type_of = |name: &str| schema.field_with_name(name).unwrap().data_type().clone();
+        assert_eq!(type_of("mark_price"), DataType::Utf8);
+        assert_eq!(type_of("book_crossed"), DataType::Boolean);
+        assert_eq!(type_of("total_latency_ms"), DataType::Int64);
+        assert_eq!(type_of("depth_levels"), DataType::Utf8);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[tokio::test]
+    async fn test_parquet_export_round_trips() {
+        use crate::market_metrics::test_util::temp_dir;
+        use arrow_array::{Array, StringArray};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let Some((db, start)) = seeded_database("PARQUETTEST", 3).await else { return };
+        // More digits than a double holds
+        let mut precise = MarketMetrics::new("PARQUETTEST".to_string());
+        precise.timestamp = start + chrono::Duration::seconds(3);
+        precise.mark_price = Some(Decimal::from_str_exact("123456789.12345678").unwrap());
+        db.insert_metrics_batch(&[precise]).await.unwrap();
+        let dir = temp_dir("export_parquet");
+        let path = dir.join("export.parquet");
+        let file = std::fs::File::create(&path).unwrap();
+        assert_eq!(export_parquet(&db, "PARQUETTEST", start, Utc::now(), file).await.unwrap(), 4);
+
+        let reader =
+            ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap()).unwrap().build().unwrap();
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        let batch = &batches[0];
+        let names = batch.schema().fields().iter().map(|field| field.name().clone()).collect::<Vec<_>>();
+        assert_eq!(names, columns().unwrap());
+        let mark_price = batch.column_by_name("mark_price").unwrap().as_any().downcast_ref::<StringArray>().unwrap();
+        let mark_prices = mark_price.iter().map(|price| price.unwrap().parse::<Decimal>().unwrap()).collect::<Vec<_>>();
+        let mut expected = (100..103).map(Decimal::from).collect::<Vec<_>>();
+        expected.push(Decimal::from_str_exact("123456789.12345678").unwrap());
+        assert_eq!(mark_prices, expected);
+        assert_eq!(batch.column_by_name("mid_price").unwrap().null_count(), 4);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,55 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// Insert state of one market
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MarketHealth {
+    /// When a row of the market was last inserted
+    pub last_insert: Option<DateTime<Utc>>,
+    /// Most recent collection or insert failure, kept after later successes
+    pub last_error: Option<String>,
+    /// A row was inserted within the market's allowed age
+    pub fresh: bool,
+}
+
+/// Readiness of the monitor, served at `/health`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HealthReport {
+    /// Every check below passed
+    pub healthy: bool,
+    pub database_reachable: bool,
+    /// Seconds since Hyperliquid market data was last fetched, `None` before the first fetch
+    pub market_data_age_secs: Option<f64>,
+    pub market_data_fresh: bool,
+    pub markets: BTreeMap<String, MarketHealth>,
+}
+
+impl HealthReport {
+    #[must_use]
+    pub fn new(
+        database_reachable: bool,
+        market_data_age: Option<Duration>,
+        max_market_data_age: Duration,
+        markets: BTreeMap<String, MarketHealth>,
+    ) -> Self {
+        let market_data_fresh = market_data_age.is_some_and(|age| age <= max_market_data_age);
+        Self {
+            healthy: database_reachable && market_data_fresh && markets.values().all(|market| market.fresh),
+            database_reachable,
+            market_data_age_secs: market_data_age.map(|age| age.as_secs_f64()),
+            market_data_fresh,
+            markets,
+        }
+    }
+}
+
+/// Future returned by [`HealthCheck::check`]
+pub type HealthFuture<'a> = Pin<Box<dyn Future<Output = HealthReport> + Send + 'a>>;
+
+/// Source of the report served at `/health`
+pub trait HealthCheck: Send + Sync {
+    fn check(&self) -> HealthFuture<'_>;
+}
@@ -1,14 +1,36 @@
-use crate::market_metrics::types::HyperliquidMarketData;
-use log::{error, info};
-use reqwest::Client;
+use crate::market_metrics::dns_cache::CachingResolver;
+use crate::market_metrics::market_data_provider::{MarketDataProvider, ProviderFuture};
+use crate::market_metrics::metrics_exporter::ExporterMetrics;
+use crate::market_metrics::retry::retry_with_jitter;
+use crate::market_metrics::types::{HyperliquidMarketData, ProviderMarketData};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use reqwest::{Client, StatusCode};
 use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
 use std::str::FromStr;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tokio::time;
+use tokio_util::sync::CancellationToken;
+
+/// `source` of prices read from Hyperliquid
+pub const HYPERLIQUID_SOURCE: &str = "hyperliquid";
+
+/// `source` of rows backfilled from Hyperliquid candles
+pub const HYPERLIQUID_CANDLES_SOURCE: &str = "hyperliquid_candles";
+
+/// Most candles `candleSnapshot` returns per request
+const MAX_CANDLES_PER_REQUEST: usize = 5000;
+
+/// Limit on each API request unless configured otherwise
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Serialize)]
 struct MetaRequest {
@@ -16,6 +38,91 @@ struct MetaRequest {
     request_type: String,
 }
 
+#[derive(Debug, Serialize)]
+struct ActiveAssetCtxRequest {
+    #[serde(rename = "type")]
+    request_type: String,
+    coin: String,
+}
+
+/// Shape of both the `activeAssetCtx` HTTP response and websocket update
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct ActiveAssetCtxResponse {
+    pub(super) coin: String,
+    pub(super) ctx: AssetContext,
+}
+
+#[derive(Debug, Serialize)]
+struct L2BookRequest {
+    #[serde(rename = "type")]
+    request_type: String,
+    coin: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct L2BookResponse {
+    time: u64,
+    levels: [Vec<L2Level>; 2],
+}
+
+#[derive(Debug, Deserialize)]
+struct L2Level {
+    px: String,
+    sz: String,
+}
+
+/// An order book snapshot from the `l2Book` endpoint, levels as `(price, size)` best first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct L2Book {
+    pub bids: Vec<(Decimal, Decimal)>,
+    pub asks: Vec<(Decimal, Decimal)>,
+    /// Snapshot time in epoch millis
+    pub time: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct CandleSnapshotRequest {
+    #[serde(rename = "type")]
+    request_type: String,
+    req: CandleRange,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CandleRange {
+    coin: String,
+    interval: String,
+    start_time: i64,
+    end_time: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CandleResponse {
+    t: i64,
+    #[serde(rename = "T")]
+    close_time: i64,
+    o: String,
+    h: String,
+    l: String,
+    c: String,
+    v: String,
+}
+
+/// One OHLCV bar from the `candleSnapshot` endpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candle {
+    /// Open time in epoch millis
+    pub open_time: i64,
+    /// Close time in epoch millis
+    pub close_time: i64,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    /// Volume in base units
+    pub volume: Decimal,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 struct AssetMeta {
     name: String,
@@ -23,7 +130,7 @@ struct AssetMeta {
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct AssetContext {
+pub(super) struct AssetContext {
     mark_px: String,
     oracle_px: String,
     mid_px: Option<String>,
@@ -34,29 +141,249 @@ struct AssetContext {
     impact_pxs: Option<Vec<String>>,
 }
 
+/// Latest market data per coin, shared by whichever feed keeps it up to date
+pub type MarketDataCache = Arc<RwLock<HashMap<String, HyperliquidMarketData>>>;
+
+/// Where a coin's market data and order book are collected from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataSource {
+    /// Market data from the bulk HTTP poll, order book from the node
+    #[default]
+    Poll,
+    /// Market data streamed over the websocket feed, order book from the node
+    Websocket,
+    /// Market data from the bulk HTTP poll, order book fetched from the
+    /// `l2Book` endpoint each tick, for coins the node book is too thin for
+    L2Rest,
+}
+
+impl FromStr for DataSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "poll" => Ok(Self::Poll),
+            "websocket" => Ok(Self::Websocket),
+            "l2_rest" => Ok(Self::L2Rest),
+            other => Err(format!("Unknown data source '{other}': expected poll, websocket or l2_rest")),
+        }
+    }
+}
+
+/// A failed HTTP request to the Hyperliquid API or another endpoint we call
+#[derive(Debug)]
+pub struct ApiError {
+    message: String,
+    /// Timeouts, connection failures, rate limiting and 5xx responses are
+    /// worth retrying; other 4xx responses and malformed bodies are not
+    retryable: bool,
+}
+
+impl ApiError {
+    pub(crate) fn from_status(status: StatusCode) -> Self {
+        Self {
+            message: format!("API error: {status}"),
+            retryable: status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+        }
+    }
+
+    #[must_use]
+    pub const fn is_retryable(&self) -> bool {
+        self.retryable
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        Self { retryable: e.is_timeout() || e.is_connect() || e.is_request(), message: e.to_string() }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// A numeric field from the API that did not hold a decimal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub field: &'static str,
+    pub raw: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid {} '{}'", self.field, self.raw)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse the API's string-encoded decimal `raw`, naming `field` if it is not one
+pub(crate) fn parse_decimal(field: &'static str, raw: &str) -> Result<Decimal, ParseError> {
+    Decimal::from_str(raw).map_err(|_| ParseError { field, raw: raw.to_string() })
+}
+
+/// An info endpoint response body with the latency of the request, in milliseconds
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<(serde_json::Value, i32), ApiError>> + Send + 'a>>;
+
+/// Where the `metaAndAssetCtxs` and `activeAssetCtx` responses come from,
+/// replaceable so the parsing and polling in [`HyperliquidClient`] can be
+/// exercised without the API
+pub trait HyperliquidTransport: Send + Sync {
+    fn fetch_meta_and_ctxs(&self) -> TransportFuture<'_>;
+
+    fn fetch_active_asset_ctx<'a>(&'a self, coin: &'a str) -> TransportFuture<'a>;
+}
+
+/// Returns a canned `metaAndAssetCtxs` response
+#[cfg(test)]
+pub(crate) struct MockTransport {
+    pub(crate) response: serde_json::Value,
+}
+
+#[cfg(test)]
+impl HyperliquidTransport for MockTransport {
+    fn fetch_meta_and_ctxs(&self) -> TransportFuture<'_> {
+        Box::pin(async { Ok((self.response.clone(), 0)) })
+    }
+
+    fn fetch_active_asset_ctx<'a>(&'a self, coin: &'a str) -> TransportFuture<'a> {
+        let message = format!("no activeAssetCtx mocked for {coin}");
+        Box::pin(async move { Err(ApiError { message, retryable: false }) })
+    }
+}
+
 pub struct HyperliquidClient {
     client: Client,
+    /// Replaces the HTTP requests for `metaAndAssetCtxs` and `activeAssetCtx` when set
+    transport: Option<Arc<dyn HyperliquidTransport>>,
     api_url: String,
-    cached_data: Arc<RwLock<HashMap<String, HyperliquidMarketData>>>,
+    cached_data: MarketDataCache,
+    /// Market data pushed by the websocket feed, kept apart so the bulk poll can't overwrite it
+    streamed_data: MarketDataCache,
     poll_interval: Duration,
+    /// Coins refreshed individually on a faster interval than the bulk poll
+    coin_poll_intervals: HashMap<String, Duration>,
+    dns_cache: Option<Arc<CachingResolver>>,
+    /// Limit on each attempt of a request
+    request_timeout: Duration,
+    max_retries: u32,
+    retry_backoff: Duration,
+    /// Cached data older than this is treated as missing
+    max_staleness: Option<Duration>,
+    /// Histogram each successful request's latency is observed in
+    latency_metrics: Option<Arc<ExporterMetrics>>,
+    /// Markets whose asset context was unusable in the last bulk poll, so each
+    /// is only logged when it starts failing rather than on every poll
+    unusable_markets: StdMutex<HashSet<String>>,
 }
 
 impl HyperliquidClient {
     pub fn new(api_url: String, poll_interval: Duration) -> Self {
         Self {
             client: Client::new(),
+            transport: None,
             api_url,
             cached_data: Arc::new(RwLock::new(HashMap::new())),
+            streamed_data: Arc::new(RwLock::new(HashMap::new())),
             poll_interval,
+            coin_poll_intervals: HashMap::new(),
+            dns_cache: None,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            max_retries: 0,
+            retry_backoff: Duration::ZERO,
+            max_staleness: None,
+            latency_metrics: None,
+            unusable_markets: StdMutex::new(HashSet::new()),
         }
     }
 
-    /// Start background polling task
-    pub fn start_polling(self: Arc<Self>) {
+    /// Have [`Self::get_market_data`] return `None` for data fetched more than
+    /// `max_staleness` ago, so a stalled poll can't keep serving old prices
+    #[must_use]
+    pub const fn with_max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = Some(max_staleness);
+        self
+    }
+
+    /// Retry failed requests up to `max_retries` times, with a jittered delay
+    /// doubling from `backoff`
+    #[must_use]
+    pub const fn with_retries(mut self, max_retries: u32, backoff: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// Give up on each request attempt after `timeout`, 5 seconds by default
+    #[must_use]
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Observe the latency of every successful API request in `metrics`
+    #[must_use]
+    pub fn with_latency_metrics(mut self, metrics: Arc<ExporterMetrics>) -> Self {
+        self.latency_metrics = Some(metrics);
+        self
+    }
+
+    /// Fetch `metaAndAssetCtxs` and `activeAssetCtx` responses from `transport` instead of the API
+    #[must_use]
+    pub fn with_transport(mut self, transport: Arc<dyn HyperliquidTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Poll the given coins individually via `activeAssetCtx` alongside the bulk poll
+    #[must_use]
+    pub fn with_coin_poll_intervals(mut self, coin_poll_intervals: HashMap<String, Duration>) -> Self {
+        self.coin_poll_intervals = coin_poll_intervals;
+        self
+    }
+
+    /// Cache DNS lookups for the API host for `ttl` instead of resolving on every new connection
+    pub fn with_dns_cache_ttl(mut self, ttl: Duration) -> Result<Self, reqwest::Error> {
+        let resolver = Arc::new(CachingResolver::new(ttl));
+        self.client = Client::builder().dns_resolver(resolver.clone()).build()?;
+        self.dns_cache = Some(resolver);
+        Ok(self)
+    }
+
+    /// Start background polling tasks, which stop once `shutdown` is cancelled
+    pub fn start_polling(self: Arc<Self>, shutdown: CancellationToken) {
+        for (coin, poll_interval) in &self.coin_poll_intervals {
+            let client = self.clone();
+            let coin = coin.clone();
+            let poll_interval = *poll_interval;
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                let mut interval = time::interval(poll_interval);
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => {}
+                        () = shutdown.cancelled() => return,
+                    }
+                    if let Err(e) = client.fetch_and_cache_market(&coin).await {
+                        error!("Failed to fetch market data for {coin}: {e}");
+                    }
+                }
+            });
+        }
+
         tokio::spawn(async move {
             let mut interval = time::interval(self.poll_interval);
             loop {
-                interval.tick().await;
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    () = shutdown.cancelled() => return,
+                }
                 if let Err(e) = self.fetch_and_cache_all_markets().await {
                     error!("Failed to fetch market data: {}", e);
                 }
@@ -64,101 +391,209 @@ impl HyperliquidClient {
         });
     }
 
-    /// Fetch and cache all market data from Hyperliquid API
-    async fn fetch_and_cache_all_markets(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let request = MetaRequest {
-            request_type: "metaAndAssetCtxs".to_string(),
+    /// Fetch and cache a single market's data from Hyperliquid API
+    async fn fetch_and_cache_market(&self, coin: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let (data, latency_ms) = if let Some(transport) = &self.transport {
+            let (body, latency_ms) = transport.fetch_active_asset_ctx(coin).await?;
+            (serde_json::from_value::<ActiveAssetCtxResponse>(body)?, latency_ms)
+        } else {
+            let request = ActiveAssetCtxRequest { request_type: "activeAssetCtx".to_string(), coin: coin.to_string() };
+            let budget = self.coin_poll_intervals.get(coin).copied();
+            self.post_info::<_, ActiveAssetCtxResponse>(&request, budget).await?
         };
+        let mut market_data = parse_asset_context(data.coin.clone(), data.ctx)?;
+        market_data.latency_ms = Some(latency_ms);
+
+        self.cached_data.write().await.insert(data.coin, market_data);
+
+        Ok(())
+    }
 
-        let response = self
-            .client
-            .post(&self.api_url)
-            .json(&request)
-            .timeout(Duration::from_secs(5))
-            .send()
-            .await?;
+    /// Fetch and cache all market data from Hyperliquid API
+    pub(crate) async fn fetch_and_cache_all_markets(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let requested_at = Utc::now();
+        let (data, latency_ms) = if let Some(transport) = &self.transport {
+            transport.fetch_meta_and_ctxs().await?
+        } else {
+            let request = MetaRequest { request_type: "metaAndAssetCtxs".to_string() };
+            self.post_info::<_, serde_json::Value>(&request, Some(self.poll_interval)).await?
+        };
+        let market_data_map = {
+            let mut unusable = self.unusable_markets.lock().map_err(|_| "Unusable markets lock poisoned")?;
+            parse_meta_and_ctxs(&data, latency_ms, &mut unusable)?
+        };
 
-        if !response.status().is_success() {
-            return Err(format!("API error: {}", response.status()).into());
+        // Merge per coin, keeping entries a per-coin fetch cached after this
+        // request was sent, as they are newer than the snapshot
+        let mut cache = self.cached_data.write().await;
+        cache.retain(|coin, cached| market_data_map.contains_key(coin) || cached.fetched_at > requested_at);
+        for (coin, market_data) in market_data_map {
+            if cache.get(&coin).is_none_or(|cached| cached.fetched_at <= requested_at) {
+                cache.insert(coin, market_data);
+            }
         }
+        info!("Updated market data cache: {} markets", cache.len());
 
-        let data: serde_json::Value = response.json().await?;
+        Ok(())
+    }
 
-        // Parse response: [universe_obj, asset_ctxs]
-        let array = data
-            .as_array()
-            .ok_or("Expected array response")?;
+    /// POST `body` to the info endpoint, retrying retryable failures with
+    /// backoff. Returns the response with the latency of the attempt that succeeded.
+    ///
+    /// Each attempt may take the request timeout, but no retry starts once
+    /// `budget`, the poll interval for polled requests, has passed since the
+    /// first attempt, so retries don't hold up the next poll.
+    async fn post_info<B: Serialize + Sync, T: DeserializeOwned>(
+        &self,
+        body: &B,
+        budget: Option<Duration>,
+    ) -> Result<(T, i32), ApiError> {
+        let first_attempt = Instant::now();
+        let within_budget = || budget.is_none_or(|budget| first_attempt.elapsed() < budget);
+        let attempts = AtomicU32::new(0);
+        let is_retryable = |e: &ApiError| e.is_retryable() && within_budget();
+        let (result, _) = retry_with_jitter(self.max_retries, self.retry_backoff, is_retryable, || async {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            let started = Instant::now();
+            let response = self.client.post(&self.api_url).json(body).timeout(self.request_timeout).send().await?;
 
-        if array.len() != 2 {
-            return Err("Expected 2 elements in response".into());
-        }
+            let status = response.status();
+            if !status.is_success() {
+                return Err(ApiError::from_status(status));
+            }
+            let data = response.json().await?;
+            Ok((data, elapsed_ms(started)))
+        })
+        .await;
 
-        // Extract universe
-        let universe_obj = &array[0];
-        let universe = if let Some(u) = universe_obj.get("universe") {
-            u.as_array().ok_or("Expected universe array")?
-        } else {
-            universe_obj.as_array().ok_or("Expected universe array")?
-        };
+        match (result, attempts.into_inner()) {
+            (Ok(response), attempts) => {
+                if attempts > 1 {
+                    warn!("Hyperliquid request succeeded after {} retries", attempts - 1);
+                }
+                if let Some(metrics) = &self.latency_metrics {
+                    metrics.observe_latency(f64::from(response.1));
+                }
+                Ok(response)
+            }
+            (Err(mut e), attempts) => {
+                if attempts > 1 {
+                    e.message = format!("{} after {attempts} attempts", e.message);
+                }
+                if let Some(budget) = budget
+                    && e.retryable
+                    && !within_budget()
+                {
+                    e.message = format!("{}, retries stopped at the {budget:?} poll interval", e.message);
+                }
+                Err(e)
+            }
+        }
+    }
 
-        let asset_ctxs = array[1]
-            .as_array()
-            .ok_or("Expected asset_ctxs array")?;
+    /// Fetch a coin's order book snapshot from the `l2Book` endpoint
+    pub async fn fetch_l2_book(&self, coin: &str) -> Result<L2Book, Box<dyn std::error::Error>> {
+        let request = L2BookRequest { request_type: "l2Book".to_string(), coin: coin.to_string() };
+        let (book, _) = self.post_info::<_, L2BookResponse>(&request, None).await?;
 
-        // Parse into structured data
-        let mut market_data_map = HashMap::new();
+        let [bids, asks] = book.levels.map(|levels| {
+            levels
+                .iter()
+                .filter_map(|level| Some((Decimal::from_str(&level.px).ok()?, Decimal::from_str(&level.sz).ok()?)))
+                .collect()
+        });
+        Ok(L2Book { bids, asks, time: book.time })
+    }
 
-        for (i, meta_val) in universe.iter().enumerate() {
-            if i >= asset_ctxs.len() {
+    /// Fetch a coin's `interval` candles (e.g. `"1m"`) opening in `[start, end]`,
+    /// oldest first, paging through ranges longer than one response holds
+    pub async fn fetch_candles(
+        &self,
+        coin: &str,
+        interval: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>, Box<dyn std::error::Error>> {
+        let mut candles = Vec::new();
+        let mut start_time = start.timestamp_millis();
+        while start_time <= end.timestamp_millis() {
+            let request = CandleSnapshotRequest {
+                request_type: "candleSnapshot".to_string(),
+                req: CandleRange {
+                    coin: coin.to_string(),
+                    interval: interval.to_string(),
+                    start_time,
+                    end_time: end.timestamp_millis(),
+                },
+            };
+            let (page, _) = self.post_info::<_, Vec<CandleResponse>>(&request, None).await?;
+            let (full_page, page_start) = (page.len() >= MAX_CANDLES_PER_REQUEST, start_time);
+            for candle in page {
+                if candle.t < start_time {
+                    continue;
+                }
+                start_time = candle.close_time + 1;
+                candles.push(Candle {
+                    open_time: candle.t,
+                    close_time: candle.close_time,
+                    open: parse_decimal("o", &candle.o)?,
+                    high: parse_decimal("h", &candle.h)?,
+                    low: parse_decimal("l", &candle.l)?,
+                    close: parse_decimal("c", &candle.c)?,
+                    volume: parse_decimal("v", &candle.v)?,
+                });
+            }
+            if !full_page || start_time == page_start {
                 break;
             }
+        }
+        Ok(candles)
+    }
 
-            let meta: AssetMeta = serde_json::from_value(meta_val.clone())?;
-            let ctx: AssetContext = serde_json::from_value(asset_ctxs[i].clone())?;
-
-            let market_data = HyperliquidMarketData {
-                coin: meta.name.clone(),
-                mark_price: Decimal::from_str(&ctx.mark_px).unwrap_or_default(),
-                oracle_price: Decimal::from_str(&ctx.oracle_px).unwrap_or_default(),
-                mid_price: ctx
-                    .mid_px
-                    .and_then(|s| Decimal::from_str(&s).ok())
-                    .unwrap_or_default(),
-                funding_rate_pct: Decimal::from_str(&ctx.funding).unwrap_or_default() * Decimal::from(100),
-                open_interest: Decimal::from_str(&ctx.open_interest).unwrap_or_default()
-                    * Decimal::from_str(&ctx.mark_px).unwrap_or_default(),
-                volume_24h: Decimal::from_str(&ctx.day_ntl_vlm).unwrap_or_default(),
-                premium: ctx
-                    .premium
-                    .and_then(|s| Decimal::from_str(&s).ok())
-                    .unwrap_or_default(),
-                impact_px_bid: ctx
-                    .impact_pxs
-                    .as_ref()
-                    .and_then(|v| v.get(0))
-                    .and_then(|s| Decimal::from_str(s).ok()),
-                impact_px_ask: ctx
-                    .impact_pxs
-                    .as_ref()
-                    .and_then(|v| v.get(1))
-                    .and_then(|s| Decimal::from_str(s).ok()),
-            };
+    /// Get cached market data for a specific coin, unless it is older than the
+    /// configured max staleness
+    pub async fn get_market_data(&self, coin: &str) -> Option<HyperliquidMarketData> {
+        self.read_fresh(&self.cached_data, coin).await
+    }
 
-            market_data_map.insert(meta.name, market_data);
+    /// Like [`Self::get_market_data`], for coins fed by the websocket stream
+    pub async fn get_streamed_market_data(&self, coin: &str) -> Option<HyperliquidMarketData> {
+        self.read_fresh(&self.streamed_data, coin).await
+    }
+
+    async fn read_fresh(&self, cache: &MarketDataCache, coin: &str) -> Option<HyperliquidMarketData> {
+        let data = cache.read().await.get(coin).cloned()?;
+        if let Some(max_staleness) = self.max_staleness
+            && age(data.fetched_at) > max_staleness
+        {
+            warn!("{coin}: Cached Hyperliquid data is {:?} old, ignoring it", age(data.fetched_at));
+            return None;
         }
+        Some(data)
+    }
 
-        // Update cache
-        let mut cache = self.cached_data.write().await;
-        *cache = market_data_map;
-        info!("Updated market data cache: {} markets", cache.len());
+    /// Time since the most recently fetched market data, `None` before the first fetch
+    pub async fn last_update_age(&self) -> Option<Duration> {
+        let polled = self.cached_data.read().await.values().map(|data| data.fetched_at).max();
+        let streamed = self.streamed_data.read().await.values().map(|data| data.fetched_at).max();
+        polled.max(streamed).map(age)
+    }
 
-        Ok(())
+    /// Cached market data for every coin in the latest universe
+    pub async fn all_market_data(&self) -> Vec<HyperliquidMarketData> {
+        self.cached_data.read().await.values().cloned().collect()
     }
 
-    /// Get cached market data for a specific coin
-    pub async fn get_market_data(&self, coin: &str) -> Option<HyperliquidMarketData> {
-        let cache = self.cached_data.read().await;
-        cache.get(coin).cloned()
+    /// The cache read by [`Self::get_market_data`], for feeding it from another source
+    #[must_use]
+    pub fn cache(&self) -> MarketDataCache {
+        self.cached_data.clone()
+    }
+
+    /// The cache read by [`Self::get_streamed_market_data`], fed by the websocket client
+    #[must_use]
+    pub fn stream_cache(&self) -> MarketDataCache {
+        self.streamed_data.clone()
     }
 
     /// Get fresh market data by fetching immediately
@@ -169,3 +604,520 @@ impl HyperliquidClient {
             .ok_or_else(|| format!("Coin {} not found in market data", coin).into())
     }
 }
+
+impl MarketDataProvider for HyperliquidClient {
+    fn source(&self) -> &'static str {
+        HYPERLIQUID_SOURCE
+    }
+
+    /// Streamed data when the coin is fed by the websocket, the polled cache otherwise
+    fn market_data<'a>(&'a self, coin: &'a str) -> ProviderFuture<'a> {
+        Box::pin(async move {
+            let data = match self.get_streamed_market_data(coin).await {
+                Some(data) => data,
+                None => self.get_market_data(coin).await?,
+            };
+            Some(ProviderMarketData {
+                source: HYPERLIQUID_SOURCE.to_string(),
+                symbol: data.coin,
+                mark_price: data.mark_price,
+                index_price: Some(data.oracle_price),
+                mid_price: data.mid_price,
+                funding_rate_pct: Some(data.funding_rate_pct),
+                fetched_at: data.fetched_at,
+            })
+        })
+    }
+}
+
+/// Milliseconds since `started`, saturating at `i32::MAX`
+fn elapsed_ms(started: Instant) -> i32 {
+    i32::try_from(started.elapsed().as_millis()).unwrap_or(i32::MAX)
+}
+
+/// Time elapsed since `fetched_at`, zero if it is in the future
+fn age(fetched_at: DateTime<Utc>) -> Duration {
+    Utc::now().signed_duration_since(fetched_at).to_std().unwrap_or_default()
+}
+
+/// Parse a `metaAndAssetCtxs` response, `[{"universe": [meta, ..]}, [ctx, ..]]`,
+/// into market data per coin.
+///
+/// The universe may also be sent as a bare array. Metas and contexts are paired
+/// by position, so a response with a different number of each is rejected
+/// rather than risk attributing a context to the wrong coin.
+///
+/// Markets with an unusable context are skipped and kept in `unusable`, with a
+/// warning only for those that weren't already there.
+fn parse_meta_and_ctxs(
+    data: &serde_json::Value,
+    latency_ms: i32,
+    unusable: &mut HashSet<String>,
+) -> Result<HashMap<String, HyperliquidMarketData>, Box<dyn std::error::Error>> {
+    let array = data.as_array().ok_or("Expected array response")?;
+    let [universe_obj, asset_ctxs] = array.as_slice() else {
+        return Err(format!("Expected 2 elements in response, got {}", array.len()).into());
+    };
+
+    let universe = universe_obj.get("universe").unwrap_or(universe_obj).as_array().ok_or("Expected universe array")?;
+    let asset_ctxs = asset_ctxs.as_array().ok_or("Expected asset_ctxs array")?;
+    if universe.len() != asset_ctxs.len() {
+        return Err(format!("Universe has {} assets but {} contexts", universe.len(), asset_ctxs.len()).into());
+    }
+
+    let mut market_data_map = HashMap::new();
+    for (meta_val, ctx_val) in universe.iter().zip(asset_ctxs) {
+        let meta = AssetMeta::deserialize(meta_val)?;
+        let ctx = AssetContext::deserialize(ctx_val)?;
+
+        let mut market_data = match parse_asset_context(meta.name.clone(), ctx) {
+            Ok(market_data) => market_data,
+            Err(e) => {
+                if unusable.insert(meta.name.clone()) {
+                    warn!("{}: Skipping market with unusable asset context until it parses: {e}", meta.name);
+                }
+                continue;
+            }
+        };
+        unusable.remove(&meta.name);
+        market_data.latency_ms = Some(latency_ms);
+        market_data_map.insert(meta.name, market_data);
+    }
+    Ok(market_data_map)
+}
+
+/// Convert a raw asset context into structured market data. The mark and
+/// oracle prices must parse; the remaining fields default when they don't.
+pub(super) fn parse_asset_context(coin: String, ctx: AssetContext) -> Result<HyperliquidMarketData, ParseError> {
+    let mark_price = parse_decimal("markPx", &ctx.mark_px)?;
+    let oracle_price = parse_decimal("oraclePx", &ctx.oracle_px)?;
+    Ok(HyperliquidMarketData {
+        coin,
+        mark_price,
+        oracle_price,
+        mid_price: ctx.mid_px.and_then(|s| parse_decimal("midPx", &s).ok()),
+        funding_rate_pct: parse_decimal("funding", &ctx.funding).unwrap_or_default() * Decimal::from(100),
+        open_interest: parse_decimal("openInterest", &ctx.open_interest).unwrap_or_default() * mark_price,
+        volume_24h: parse_decimal("dayNtlVlm", &ctx.day_ntl_vlm).unwrap_or_default(),
+        premium: ctx.premium.and_then(|s| parse_decimal("premium", &s).ok()).unwrap_or_default(),
+        impact_px_bid: ctx.impact_pxs.as_ref().and_then(|v| v.get(0)).and_then(|s| parse_decimal("impactPxs", s).ok()),
+        impact_px_ask: ctx.impact_pxs.as_ref().and_then(|v| v.get(1)).and_then(|s| parse_decimal("impactPxs", s).ok()),
+        latency_ms: None,
+        fetched_at: Utc::now(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, routing::post};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpListener;
+    use tokio::sync::Notify;
+
+    fn asset_ctx(mark_px: usize) -> serde_json::Value {
+        serde_json::json!({
+            "markPx": mark_px.to_string(),
+            "oraclePx": "100.0",
+            "midPx": "100.0",
+            "funding": "0.0001",
+            "openInterest": "1000.0",
+            "dayNtlVlm": "50000.0",
+            "premium": "0.0",
+            "impactPxs": ["99.9", "100.1"]
+        })
+    }
+
+    fn meta_and_ctxs(mark_px: usize) -> serde_json::Value {
+        serde_json::json!([
+            { "universe": [{ "name": "BTC" }, { "name": "ETH" }] },
+            [asset_ctx(mark_px), asset_ctx(mark_px)]
+        ])
+    }
+
+    /// Serve `metaAndAssetCtxs`, reporting the request count as the mark price
+    async fn start_mock_api() -> (String, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let app = Router::new().route(
+            "/info",
+            post({
+                let requests = requests.clone();
+                async move || Json(meta_and_ctxs(requests.fetch_add(1, Ordering::SeqCst) + 1))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/info", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, requests)
+    }
+
+    /// Answers both requests without the network, reporting the request count as the mark price
+    #[derive(Default)]
+    struct CountingTransport {
+        bulk_requests: AtomicUsize,
+        coin_requests: AtomicUsize,
+    }
+
+    impl HyperliquidTransport for CountingTransport {
+        fn fetch_meta_and_ctxs(&self) -> TransportFuture<'_> {
+            let n = self.bulk_requests.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move { Ok((meta_and_ctxs(n), 0)) })
+        }
+
+        fn fetch_active_asset_ctx<'a>(&'a self, coin: &'a str) -> TransportFuture<'a> {
+            let n = self.coin_requests.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move { Ok((serde_json::json!({ "coin": coin, "ctx": asset_ctx(n) }), 0)) })
+        }
+    }
+
+    /// Holds each bulk response until released, to land it after a per-coin fetch
+    #[derive(Default)]
+    struct HeldBulkTransport {
+        bulk_sent: Notify,
+        release_bulk: Notify,
+    }
+
+    impl HyperliquidTransport for HeldBulkTransport {
+        fn fetch_meta_and_ctxs(&self) -> TransportFuture<'_> {
+            Box::pin(async {
+                self.bulk_sent.notify_one();
+                self.release_bulk.notified().await;
+                Ok((meta_and_ctxs(100), 0))
+            })
+        }
+
+        fn fetch_active_asset_ctx<'a>(&'a self, coin: &'a str) -> TransportFuture<'a> {
+            Box::pin(async move { Ok((serde_json::json!({ "coin": coin, "ctx": asset_ctx(200) }), 0)) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bulk_poll_keeps_newer_coin_fetch() {
+        let transport = Arc::new(HeldBulkTransport::default());
+        let client =
+            Arc::new(HyperliquidClient::new(String::new(), Duration::from_secs(30)).with_transport(transport.clone()));
+        let bulk = tokio::spawn({
+            let client = client.clone();
+            async move { client.fetch_and_cache_all_markets().await.map_err(|e| e.to_string()) }
+        });
+        transport.bulk_sent.notified().await;
+        client.fetch_and_cache_market("BTC").await.unwrap();
+        transport.release_bulk.notify_one();
+        bulk.await.unwrap().unwrap();
+
+        // The snapshot was requested before BTC's fetch landed, so only ETH takes it
+        assert_eq!(client.get_market_data("BTC").await.unwrap().mark_price, Decimal::from(200));
+        assert_eq!(client.get_market_data("ETH").await.unwrap().mark_price, Decimal::from(100));
+
+        // A snapshot requested afterwards is the newer one
+        transport.release_bulk.notify_one();
+        client.fetch_and_cache_all_markets().await.unwrap();
+        assert_eq!(client.get_market_data("BTC").await.unwrap().mark_price, Decimal::from(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fast_polled_coin_updates_more_often() {
+        let transport = Arc::new(CountingTransport::default());
+        let client = Arc::new(
+            HyperliquidClient::new(String::new(), Duration::from_secs(30))
+                .with_transport(transport.clone())
+                .with_coin_poll_intervals(HashMap::from([("BTC".to_string(), Duration::from_millis(20))])),
+        );
+        client.clone().start_polling(CancellationToken::new());
+
+        // Ticks at 0ms, 20ms, ..., 280ms
+        time::sleep(Duration::from_millis(290)).await;
+
+        assert_eq!(transport.bulk_requests.load(Ordering::SeqCst), 1);
+        assert_eq!(transport.coin_requests.load(Ordering::SeqCst), 15);
+
+        // BTC reflects the latest targeted fetch, ETH only the single bulk fetch
+        let btc = client.get_market_data("BTC").await.unwrap();
+        let eth = client.get_market_data("ETH").await.unwrap();
+        assert!(btc.mark_price > Decimal::from(1));
+        assert_eq!(eth.mark_price, Decimal::from(1));
+        assert!(btc.latency_ms.is_some() && eth.latency_ms.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_candles_pages_through_long_ranges() {
+        // One minute candles from `startTime`, at most 5000 per response
+        let app = Router::new().route(
+            "/info",
+            post(async |Json(body): Json<serde_json::Value>| {
+                let start = body["req"]["startTime"].as_i64().unwrap();
+                let end = body["req"]["endTime"].as_i64().unwrap();
+                let candles = ((start + 59_999) / 60_000 * 60_000..=end)
+                    .step_by(60_000)
+                    .take(MAX_CANDLES_PER_REQUEST)
+                    .map(|t| {
+                        let close = (t / 60_000).to_string();
+                        serde_json::json!({
+                            "t": t, "T": t + 59_999, "s": body["req"]["coin"], "i": "1m",
+                            "o": "1.0", "c": close, "h": "2.0", "l": "0.5", "v": "10.0", "n": 3
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                Json(serde_json::Value::from(candles))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/info", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = HyperliquidClient::new(url, Duration::from_secs(30));
+        let start = DateTime::from_timestamp(1_700_000_040, 0).unwrap();
+        let end = start + chrono::Duration::minutes(6000);
+        let candles = client.fetch_candles("BTC", "1m", start, end).await.unwrap();
+
+        assert_eq!(candles.len(), 6001);
+        assert!(candles.windows(2).all(|pair| pair[1].open_time == pair[0].open_time + 60_000));
+        assert_eq!(candles[0].open_time, start.timestamp_millis());
+        assert_eq!(candles[6000].close, Decimal::from(end.timestamp() / 60));
+        assert_eq!(candles[0].volume, Decimal::from(10));
+    }
+
+    #[test]
+    fn test_null_mid_px_is_none() {
+        let mut ctx = asset_ctx(100);
+        ctx["midPx"] = serde_json::Value::Null;
+        let ctx: AssetContext = serde_json::from_value(ctx).unwrap();
+
+        let market_data = parse_asset_context("BTC".to_string(), ctx).unwrap();
+        assert_eq!(market_data.mid_price, None);
+        assert_eq!(market_data.mark_price, Decimal::from(100));
+    }
+
+    #[tokio::test]
+    async fn test_skips_markets_with_unparseable_prices() {
+        let mut bad_mark = asset_ctx(100);
+        bad_mark["markPx"] = serde_json::json!("garbage");
+        let mut bad_premium = asset_ctx(300);
+        bad_premium["premium"] = serde_json::json!("n/a");
+        let client = mock_client(serde_json::json!([
+            [{ "name": "BTC" }, { "name": "ETH" }, { "name": "SOL" }],
+            [bad_mark, asset_ctx(200), bad_premium]
+        ]));
+        client.fetch_and_cache_all_markets().await.unwrap();
+
+        assert!(client.get_market_data("BTC").await.is_none());
+        assert_eq!(client.get_market_data("ETH").await.unwrap().mark_price, Decimal::from(200));
+        assert_eq!(client.get_market_data("SOL").await.unwrap().premium, Decimal::ZERO);
+
+        // A market that keeps failing is remembered rather than logged each poll
+        client.fetch_and_cache_all_markets().await.unwrap();
+        assert_eq!(*client.unusable_markets.lock().unwrap(), HashSet::from(["BTC".to_string()]));
+        let mut unusable = client.unusable_markets.lock().unwrap().clone();
+        let fixed = serde_json::json!([[{ "name": "BTC" }], [asset_ctx(100)]]);
+        assert_eq!(parse_meta_and_ctxs(&fixed, 0, &mut unusable).unwrap().len(), 1);
+        assert!(unusable.is_empty());
+
+        let mut bad_oracle: AssetContext = serde_json::from_value(asset_ctx(1)).unwrap();
+        bad_oracle.oracle_px = String::new();
+        assert_eq!(
+            parse_asset_context("BTC".to_string(), bad_oracle).unwrap_err(),
+            ParseError { field: "oraclePx", raw: String::new() }
+        );
+    }
+
+    /// Client fed `response` as the bulk market data
+    fn mock_client(response: serde_json::Value) -> HyperliquidClient {
+        HyperliquidClient::new(String::new(), Duration::from_secs(30))
+            .with_transport(Arc::new(MockTransport { response }))
+    }
+
+    #[tokio::test]
+    async fn test_parses_mocked_meta_and_ctxs() {
+        let mut eth = asset_ctx(200);
+        eth.as_object_mut().unwrap().remove("midPx");
+        eth.as_object_mut().unwrap().remove("premium");
+        let client = mock_client(serde_json::json!([
+            { "universe": [{ "name": "BTC" }, { "name": "ETH" }] },
+            [asset_ctx(100), eth]
+        ]));
+        client.fetch_and_cache_all_markets().await.unwrap();
+
+        let btc = client.get_market_data("BTC").await.unwrap();
+        assert_eq!(btc.mid_price, Some(Decimal::from(100)));
+        assert_eq!(btc.impact_px_ask, Some(Decimal::new(1001, 1)));
+        let eth = client.get_market_data("ETH").await.unwrap();
+        assert_eq!(eth.mark_price, Decimal::from(200));
+        assert_eq!(eth.mid_price, None);
+        assert_eq!(eth.premium, Decimal::ZERO);
+
+        // The universe may also be a bare array
+        let client = mock_client(serde_json::json!([[{ "name": "SOL" }], [asset_ctx(5)]]));
+        client.fetch_and_cache_all_markets().await.unwrap();
+        assert_eq!(client.get_market_data("SOL").await.unwrap().mark_price, Decimal::from(5));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_malformed_meta_and_ctxs() {
+        let mut no_mark = asset_ctx(1);
+        no_mark.as_object_mut().unwrap().remove("markPx");
+        let malformed = [
+            (serde_json::json!({ "universe": [] }), "Expected array response"),
+            (serde_json::json!([{ "universe": [] }]), "Expected 2 elements"),
+            (serde_json::json!([{ "universe": "BTC" }, []]), "Expected universe array"),
+            (serde_json::json!([{ "universe": [] }, {}]), "Expected asset_ctxs array"),
+            (serde_json::json!([[{ "name": "BTC" }, { "name": "ETH" }], [asset_ctx(1)]]), "2 assets but 1 contexts"),
+            (serde_json::json!([[{ "name": "BTC" }], [no_mark]]), "markPx"),
+            (serde_json::json!([[{ "ticker": "BTC" }], [asset_ctx(1)]]), "name"),
+        ];
+        for (response, expected) in malformed {
+            let client = mock_client(response.clone());
+            client.cache().write().await.insert(
+                "BTC".to_string(),
+                parse_asset_context("BTC".to_string(), serde_json::from_value(asset_ctx(7)).unwrap()).unwrap(),
+            );
+
+            let err = client.fetch_and_cache_all_markets().await.unwrap_err().to_string();
+            assert!(err.contains(expected), "{response}: {err}");
+            // A bad response leaves the previous data in place
+            assert_eq!(client.get_market_data("BTC").await.unwrap().mark_price, Decimal::from(7));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dns_cache_resolves_api_host() {
+        let (url, bulk_requests) = start_mock_api().await;
+        let url = url.replace("127.0.0.1", "localhost");
+        let client =
+            HyperliquidClient::new(url, Duration::from_secs(30)).with_dns_cache_ttl(Duration::from_mins(5)).unwrap();
+
+        client.fetch_and_cache_all_markets().await.unwrap();
+        assert_eq!(bulk_requests.load(Ordering::SeqCst), 1);
+        assert!(client.get_market_data("BTC").await.is_some());
+
+        let cached = client.dns_cache.as_ref().unwrap().cached("localhost").unwrap();
+        assert!(!cached.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stale_data_is_rejected() {
+        let client =
+            HyperliquidClient::new(String::new(), Duration::from_secs(30)).with_max_staleness(Duration::from_secs(10));
+        assert_eq!(client.last_update_age().await, None);
+
+        let mut btc = parse_asset_context("BTC".to_string(), serde_json::from_value(asset_ctx(1)).unwrap()).unwrap();
+        let mut eth = btc.clone();
+        eth.coin = "ETH".to_string();
+        btc.fetched_at = Utc::now() - chrono::Duration::seconds(60);
+        client.cache().write().await.extend([("BTC".to_string(), btc), ("ETH".to_string(), eth)]);
+
+        assert!(client.get_market_data("BTC").await.is_none());
+        assert!(client.get_market_data("ETH").await.is_some());
+        assert!(client.last_update_age().await.unwrap() < Duration::from_secs(10));
+    }
+
+    /// Answer with `status` while `failures` remain, then with a successful bulk
+    /// response. Returns the URL and the request count.
+    async fn start_failing_api(failures: Arc<AtomicUsize>, status: StatusCode) -> (String, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let app = Router::new().route(
+            "/info",
+            post({
+                let requests = requests.clone();
+                async move || {
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    if failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok() {
+                        return Err(status);
+                    }
+                    Ok(Json(serde_json::json!([{ "universe": [{ "name": "BTC" }] }, [asset_ctx(7)]])))
+                }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/info", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, requests)
+    }
+
+    #[test]
+    fn test_parse_data_source() {
+        assert_eq!("poll".parse(), Ok(DataSource::Poll));
+        assert_eq!(" Websocket".parse(), Ok(DataSource::Websocket));
+        assert_eq!("l2_rest".parse(), Ok(DataSource::L2Rest));
+        assert!("grpc".parse::<DataSource>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors() {
+        let (url, requests) = start_failing_api(Arc::new(AtomicUsize::new(2)), StatusCode::SERVICE_UNAVAILABLE).await;
+        let client = HyperliquidClient::new(url, Duration::from_secs(30)).with_retries(3, Duration::from_millis(1));
+
+        client.fetch_and_cache_all_markets().await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert_eq!(client.get_market_data("BTC").await.unwrap().mark_price, Decimal::from(7));
+    }
+
+    #[tokio::test]
+    async fn test_latency_observed_once_per_request() {
+        let (url, _) = start_mock_api().await;
+        let metrics = Arc::new(ExporterMetrics::default());
+        let client = HyperliquidClient::new(url, Duration::from_secs(30)).with_latency_metrics(metrics.clone());
+
+        // One bulk request covering two markets
+        client.fetch_and_cache_all_markets().await.unwrap();
+        assert!(metrics.render().contains("anthias_hyperliquid_latency_ms_count 1\n"));
+        client.fetch_and_cache_all_markets().await.unwrap();
+        assert!(metrics.render().contains("anthias_hyperliquid_latency_ms_count 2\n"));
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors() {
+        let (url, requests) = start_failing_api(Arc::new(AtomicUsize::new(1)), StatusCode::BAD_REQUEST).await;
+        let client = HyperliquidClient::new(url, Duration::from_secs(30)).with_retries(3, Duration::from_millis(1));
+
+        assert!(client.fetch_and_cache_all_markets().await.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_retries_keep_cache() {
+        let failures = Arc::new(AtomicUsize::new(0));
+        let (url, requests) = start_failing_api(failures.clone(), StatusCode::TOO_MANY_REQUESTS).await;
+        let client = HyperliquidClient::new(url, Duration::from_secs(30)).with_retries(2, Duration::from_millis(1));
+        client.fetch_and_cache_all_markets().await.unwrap();
+
+        failures.store(usize::MAX, Ordering::SeqCst);
+        let err = client.fetch_and_cache_all_markets().await.unwrap_err();
+        assert_eq!(err.to_string(), "API error: 429 Too Many Requests after 3 attempts");
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+        assert_eq!(client.get_market_data("BTC").await.unwrap().mark_price, Decimal::from(7));
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_at_poll_interval() {
+        let failures = Arc::new(AtomicUsize::new(usize::MAX));
+        let (url, requests) = start_failing_api(failures, StatusCode::SERVICE_UNAVAILABLE).await;
+        let client = HyperliquidClient::new(url, Duration::from_millis(200))
+            .with_retries(u32::MAX, Duration::from_millis(40))
+            .with_timeout(Duration::from_millis(50));
+
+        let started = Instant::now();
+        let err = client.fetch_and_cache_all_markets().await.unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(err.to_string().ends_with("retries stopped at the 200ms poll interval"), "{err}");
+        assert!(requests.load(Ordering::SeqCst) > 1);
+    }
+
+    #[tokio::test]
+    async fn test_first_attempt_may_outlast_poll_interval() {
+        let app = Router::new().route(
+            "/info",
+            post(async || {
+                time::sleep(Duration::from_millis(150)).await;
+                Json(serde_json::json!([{ "universe": [{ "name": "BTC" }] }, [asset_ctx(7)]]))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/info", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        // The response takes longer than the poll interval but within the request timeout
+        let client = HyperliquidClient::new(url, Duration::from_millis(50)).with_timeout(Duration::from_secs(5));
+        client.fetch_and_cache_all_markets().await.unwrap();
+        assert!(client.get_market_data("BTC").await.is_some());
+    }
+}
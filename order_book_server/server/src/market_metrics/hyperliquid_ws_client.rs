@@ -0,0 +1,234 @@
+use crate::market_metrics::hyperliquid_client::{ActiveAssetCtxResponse, MarketDataCache, parse_asset_context};
+use futures_util::{SinkExt, StreamExt};
+use log::{error, info, warn};
+use reqwest::Url;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::{select, sync::mpsc, time};
+use tokio_util::sync::CancellationToken;
+use yawc::{FrameView, OpCode, WebSocket};
+
+pub const DEFAULT_WS_URL: &str = "wss://api.hyperliquid.xyz/ws";
+
+/// Hyperliquid drops connections that stay silent for a minute
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Deserialize)]
+struct WsMessage {
+    channel: String,
+    #[serde(default)]
+    data: serde_json::Value,
+}
+
+/// Keeps the market data cache up to date from Hyperliquid's websocket feed.
+///
+/// Subscribes to `activeAssetCtx` for each coin and writes every pushed update
+/// into the same cache [`super::HyperliquidClient`] reads from, reconnecting
+/// with a doubling backoff whenever the socket drops. Coins sent through
+/// [`Self::subscriber`] are subscribed on the live connection and every
+/// reconnect after it.
+pub struct HyperliquidWsClient {
+    ws_url: Url,
+    coins: Vec<String>,
+    cached_data: MarketDataCache,
+    subscriber: mpsc::UnboundedSender<String>,
+    subscriptions: mpsc::UnboundedReceiver<String>,
+}
+
+impl HyperliquidWsClient {
+    pub fn new(
+        ws_url: &str,
+        coins: Vec<String>,
+        cached_data: MarketDataCache,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let (subscriber, subscriptions) = mpsc::unbounded_channel();
+        Ok(Self { ws_url: Url::parse(ws_url)?, coins, cached_data, subscriber, subscriptions })
+    }
+
+    /// Sender of coins to subscribe to once started, e.g. markets added at runtime
+    #[must_use]
+    pub fn subscriber(&self) -> mpsc::UnboundedSender<String> {
+        self.subscriber.clone()
+    }
+
+    /// Connect in the background and keep reconnecting until `shutdown` is cancelled
+    pub fn start(mut self, shutdown: CancellationToken) {
+        tokio::spawn(async move {
+            select! {
+                () = self.run() => {}
+                () = shutdown.cancelled() => info!("Hyperliquid websocket feed stopped"),
+            }
+        });
+    }
+
+    async fn run(&mut self) {
+        let mut delay = INITIAL_RECONNECT_DELAY;
+        loop {
+            match WebSocket::connect(self.ws_url.clone()).await {
+                Ok(ws) => {
+                    info!("Connected to Hyperliquid websocket at {}", self.ws_url);
+                    delay = INITIAL_RECONNECT_DELAY;
+                    match self.stream(ws).await {
+                        Ok(()) => warn!("Hyperliquid websocket closed, reconnecting in {delay:?}"),
+                        Err(e) => error!("Hyperliquid websocket error: {e}, reconnecting in {delay:?}"),
+                    }
+                }
+                Err(e) => error!("Failed to connect to Hyperliquid websocket: {e}, retrying in {delay:?}"),
+            }
+            time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+
+    /// Subscribe and apply updates until the connection closes
+    async fn stream(&mut self, mut ws: WebSocket) -> Result<(), yawc::WebSocketError> {
+        for coin in &self.coins {
+            ws.send(subscribe_frame(coin)).await?;
+        }
+
+        let mut heartbeat = time::interval(HEARTBEAT_INTERVAL);
+        heartbeat.tick().await;
+        loop {
+            select! {
+                _ = heartbeat.tick() => ws.send(FrameView::text(r#"{"method":"ping"}"#)).await?,
+                Some(coin) = self.subscriptions.recv() => {
+                    if !self.coins.contains(&coin) {
+                        ws.send(subscribe_frame(&coin)).await?;
+                        info!("{coin}: Subscribed to Hyperliquid websocket updates");
+                        self.coins.push(coin);
+                    }
+                }
+                frame = ws.next() => {
+                    let Some(frame) = frame else { return Ok(()) };
+                    match frame.opcode {
+                        OpCode::Text => self.apply_update(&frame.payload).await,
+                        OpCode::Close => return Ok(()),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    async fn apply_update(&self, payload: &[u8]) {
+        let message = match serde_json::from_slice::<WsMessage>(payload) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Unparseable Hyperliquid websocket message: {e}");
+                return;
+            }
+        };
+        // Subscription acks and pongs carry no market data
+        if message.channel != "activeAssetCtx" {
+            return;
+        }
+
+        match serde_json::from_value::<ActiveAssetCtxResponse>(message.data) {
+            Ok(update) => match parse_asset_context(update.coin.clone(), update.ctx) {
+                Ok(market_data) => {
+                    self.cached_data.write().await.insert(update.coin, market_data);
+                }
+                Err(e) => warn!("{}: Ignoring activeAssetCtx update: {e}", update.coin),
+            },
+            Err(e) => warn!("Invalid activeAssetCtx update: {e}"),
+        }
+    }
+}
+
+fn subscribe_frame(coin: &str) -> FrameView {
+    let subscribe = serde_json::json!({
+        "method": "subscribe",
+        "subscription": { "type": "activeAssetCtx", "coin": coin },
+    });
+    FrameView::text(subscribe.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, routing::get};
+    use rust_decimal::Decimal;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::RwLock;
+
+    /// Push one update per connection with the connection number as the mark
+    /// price, dropping the first connection right after its update
+    async fn start_mock_ws(connections: Arc<AtomicUsize>) -> String {
+        let app = Router::new().route(
+            "/ws",
+            get(async move |incoming: yawc::IncomingUpgrade| {
+                let (resp, fut) = incoming.upgrade(yawc::Options::default()).unwrap();
+                let n = connections.fetch_add(1, Ordering::SeqCst) + 1;
+                tokio::spawn(async move {
+                    let Ok(mut ws) = fut.await else { return };
+                    while let Some(frame) = ws.next().await {
+                        if std::str::from_utf8(&frame.payload).is_ok_and(|text| text.contains("activeAssetCtx")) {
+                            break;
+                        }
+                    }
+                    let update = serde_json::json!({
+                        "channel": "activeAssetCtx",
+                        "data": {
+                            "coin": "BTC",
+                            "ctx": {
+                                "markPx": n.to_string(),
+                                "oraclePx": n.to_string(),
+                                "midPx": null,
+                                "funding": "0.0001",
+                                "openInterest": "1000.0",
+                                "dayNtlVlm": "1000000.0",
+                            },
+                        },
+                    });
+                    ws.send(FrameView::text(update.to_string())).await.unwrap();
+                    if n > 1 {
+                        while ws.next().await.is_some() {}
+                    }
+                });
+                resp
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_updates_cache_and_reconnects() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let cache: MarketDataCache = Arc::new(RwLock::new(HashMap::new()));
+        let url = start_mock_ws(connections.clone()).await;
+        HyperliquidWsClient::new(&url, vec!["BTC".to_string()], cache.clone()).unwrap().start(CancellationToken::new());
+
+        // The second connection's update only arrives after reconnecting
+        let deadline = time::Instant::now() + Duration::from_secs(5);
+        while cache.read().await.get("BTC").is_none_or(|data| data.mark_price != Decimal::from(2)) {
+            assert!(time::Instant::now() < deadline, "no update after reconnect");
+            time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(connections.load(Ordering::SeqCst) >= 2);
+    }
+
+    #[tokio::test]
+    async fn test_subscribes_coins_added_after_start() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let cache: MarketDataCache = Arc::new(RwLock::new(HashMap::new()));
+        let url = start_mock_ws(connections.clone()).await;
+        let client = HyperliquidWsClient::new(&url, Vec::new(), cache.clone()).unwrap();
+        let subscriber = client.subscriber();
+        client.start(CancellationToken::new());
+        subscriber.send("BTC".to_string()).unwrap();
+
+        // Updates stop after the first connection unless BTC is subscribed again on reconnect
+        let deadline = time::Instant::now() + Duration::from_secs(5);
+        while cache.read().await.get("BTC").is_none_or(|data| data.mark_price != Decimal::from(2)) {
+            assert!(time::Instant::now() < deadline, "no update for the added coin after reconnect");
+            time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use crate::market_metrics::{
+    sinks::{MetricsSink, SinkFormat},
+    types::MarketMetrics,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use flate2::{Compression, write::GzEncoder};
+use log::{error, info};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+/// Appends every metrics row as a JSON line (or CSV row) to an archive file.
+///
+/// The active file is rotated when the day of the incoming rows changes or it
+/// grows past `max_bytes`. Rotated files are renamed to
+/// `<stem>.<date>.<n>.<ext>` and, when compression is enabled, gzipped in a
+/// background thread. The plain file is only removed once the `.gz` is
+/// complete, so an interrupted compression never loses data.
+pub struct JsonlArchiveWriter {
+    path: PathBuf,
+    max_bytes: u64,
+    format: SinkFormat,
+    compress: bool,
+    file: Option<File>,
+    written: u64,
+    /// Day of the rows in the active file
+    day: Option<NaiveDate>,
+    compressions: Vec<JoinHandle<io::Result<PathBuf>>>,
+}
+
+impl JsonlArchiveWriter {
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes,
+            format: SinkFormat::Json,
+            compress: false,
+            file: None,
+            written: 0,
+            day: None,
+            compressions: Vec::new(),
+        }
+    }
+
+    /// Archive rows as CSV instead of JSON lines
+    #[must_use]
+    pub const fn with_format(mut self, format: SinkFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Gzip rotated files in the background
+    #[must_use]
+    pub const fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Append one JSON line for the given metrics
+    pub fn append(&mut self, metrics: &MarketMetrics) -> io::Result<()> {
+        let line = self.format.encode(metrics)?;
+        self.append_line(&line, metrics.timestamp.date_naive())
+    }
+
+    fn append_line(&mut self, line: &str, day: NaiveDate) -> io::Result<()> {
+        self.open()?;
+        let day_changed = self.day.is_some_and(|current| current != day);
+        if self.written > 0 && (day_changed || self.written >= self.max_bytes) {
+            self.rotate()?;
+            self.open()?;
+        }
+
+        let file = self.file.as_mut().ok_or_else(|| io::Error::other("JSONL archive file not open"))?;
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+        self.written += line.len() as u64;
+        self.day = Some(day);
+        Ok(())
+    }
+
+    /// Wait for in-flight compressions, returning the compressed file paths
+    pub fn wait_for_compression(&mut self) -> Vec<PathBuf> {
+        self.compressions
+            .drain(..)
+            .filter_map(|handle| match handle.join() {
+                Ok(Ok(path)) => Some(path),
+                Ok(Err(e)) => {
+                    error!("Failed to compress JSONL archive: {e}");
+                    None
+                }
+                Err(_) => {
+                    error!("JSONL archive compression thread panicked");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        if self.file.is_none() {
+            let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+            let metadata = file.metadata()?;
+            self.written = metadata.len();
+            // Rows left over from a previous run belong to the day they were written
+            self.day = (self.written > 0)
+                .then(|| metadata.modified().ok())
+                .flatten()
+                .map(|modified| DateTime::<Utc>::from(modified).date_naive());
+            self.file = Some(file);
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        let day = self.day.unwrap_or_else(|| Utc::now().date_naive());
+        let rotated = self.rotated_path(day);
+        fs::rename(&self.path, &rotated)?;
+        self.written = 0;
+        self.day = None;
+        info!("Rotated JSONL archive to {}", rotated.display());
+
+        if self.compress {
+            self.compressions.retain(|handle| !handle.is_finished());
+            self.compressions.push(thread::spawn(move || gzip_file(&rotated)));
+        }
+        Ok(())
+    }
+
+    /// First `<stem>.<day>.<n>.<ext>` not already taken by a plain or compressed file
+    fn rotated_path(&self, day: NaiveDate) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = self.path.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+        (1..=u32::MAX)
+            .map(|n| self.path.with_file_name(format!("{stem}.{day}.{n}{extension}")))
+            .find(|candidate| !candidate.exists() && !gz_path(candidate).exists())
+            .unwrap_or_else(|| self.path.with_extension("rotated"))
+    }
+}
+
+impl MetricsSink for JsonlArchiveWriter {
+    fn name(&self) -> &'static str {
+        "jsonl_archive"
+    }
+
+    fn accepted_formats(&self) -> &'static [SinkFormat] {
+        &[SinkFormat::Json, SinkFormat::Csv]
+    }
+
+    fn format(&self) -> SinkFormat {
+        self.format
+    }
+
+    fn write(&mut self, metrics: &MarketMetrics, encoded: &str) -> io::Result<()> {
+        self.append_line(encoded, metrics.timestamp.date_naive())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.wait_for_compression();
+        if let Some(file) = &self.file {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    let mut gz = path.as_os_str().to_owned();
+    gz.push(".gz");
+    PathBuf::from(gz)
+}
+
+/// Compress `path` to `<path>.gz` via a temporary file, removing `path` only on success
+fn gzip_file(path: &Path) -> io::Result<PathBuf> {
+    let gz = gz_path(path);
+    let mut tmp = gz.clone().into_os_string();
+    tmp.push(".tmp");
+
+    let result = (|| {
+        let mut encoder = GzEncoder::new(File::create(&tmp)?, Compression::default());
+        io::copy(&mut BufReader::new(File::open(path)?), &mut encoder)?;
+        encoder.finish()?.sync_all()?;
+        fs::rename(&tmp, &gz)
+    })();
+    if let Err(e) = result {
+        let _unused = fs::remove_file(&tmp);
+        return Err(e);
+    }
+
+    fs::remove_file(path)?;
+    Ok(gz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market_metrics::test_util::temp_dir;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn metrics(coin: &str, timestamp: DateTime<Utc>) -> MarketMetrics {
+        let mut metrics = MarketMetrics::new(coin.to_string());
+        metrics.timestamp = timestamp;
+        metrics
+    }
+
+    #[test]
+    fn test_rotated_file_is_compressed() {
+        let dir = temp_dir("jsonl_archive_compress");
+        let path = dir.join("metrics.jsonl");
+        let mut writer = JsonlArchiveWriter::new(&path, 1).with_compression(true);
+        let now = Utc::now();
+        writer.append(&metrics("BTC", now)).unwrap();
+        writer.append(&metrics("ETH", now)).unwrap();
+
+        let compressed = writer.wait_for_compression();
+        assert_eq!(compressed.len(), 1);
+        assert!(compressed[0].to_string_lossy().ends_with(".jsonl.gz"));
+
+        let mut contents = String::new();
+        GzDecoder::new(File::open(&compressed[0]).unwrap()).read_to_string(&mut contents).unwrap();
+        let rotated: MarketMetrics = serde_json::from_str(contents.trim_end()).unwrap();
+        assert_eq!(rotated.coin, "BTC");
+
+        // Only the active file and the archive remain
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        let active = fs::read_to_string(&path).unwrap();
+        assert!(active.contains("\"coin\":\"ETH\""));
+    }
+
+    #[test]
+    fn test_rotates_on_day_change() {
+        let dir = temp_dir("jsonl_archive_daily");
+        let path = dir.join("metrics.jsonl");
+        let mut writer = JsonlArchiveWriter::new(&path, u64::MAX);
+        let now = Utc::now();
+        writer.append(&metrics("BTC", now - chrono::Duration::days(1))).unwrap();
+        writer.append(&metrics("BTC", now)).unwrap();
+        writer.append(&metrics("BTC", now)).unwrap();
+
+        let yesterday = (now - chrono::Duration::days(1)).date_naive();
+        let rotated = fs::read_to_string(dir.join(format!("metrics.{yesterday}.1.jsonl"))).unwrap();
+        assert_eq!(rotated.lines().count(), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+    }
+}
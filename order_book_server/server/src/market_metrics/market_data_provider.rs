@@ -0,0 +1,17 @@
+use crate::market_metrics::types::ProviderMarketData;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Future returned by [`MarketDataProvider::market_data`]
+pub type ProviderFuture<'a> = Pin<Box<dyn Future<Output = Option<ProviderMarketData>> + Send + 'a>>;
+
+/// An exchange the monitor reads a coin's prices from, so Hyperliquid can be
+/// cross-checked against other venues
+pub trait MarketDataProvider: Send + Sync {
+    /// Name stored as the `source` of the provider's prices, e.g. `hyperliquid`
+    fn source(&self) -> &'static str;
+
+    /// Latest prices for `coin`, named as on Hyperliquid, or `None` when the
+    /// provider has no fresh data for it
+    fn market_data<'a>(&'a self, coin: &'a str) -> ProviderFuture<'a>;
+}
@@ -0,0 +1,270 @@
+use crate::market_metrics::{health::HealthCheck, types::MarketMetrics};
+use axum::{
+    Json, Router,
+    http::{StatusCode, header},
+    response::IntoResponse,
+    routing::get,
+};
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+/// Upper bounds of the Hyperliquid latency histogram buckets, in milliseconds
+const LATENCY_BUCKETS_MS: [f64; 9] = [10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0];
+
+/// Latest gauge values for one coin
+#[derive(Debug, Default, Clone, Copy)]
+struct CoinGauges {
+    mark_price: Option<Decimal>,
+    spread_pct: Option<Decimal>,
+    total_depth_5pct: Option<Decimal>,
+}
+
+/// Reads one gauge from a coin's values
+type GaugeValue = fn(&CoinGauges) -> Option<Decimal>;
+
+/// Per-coin gauges as `(name, help, value)`
+const GAUGES: [(&str, &str, GaugeValue); 3] = [
+    ("anthias_mark_price", "Latest mark price", |g| g.mark_price),
+    ("anthias_spread_pct", "Latest bid-ask spread as a percent of the mid", |g| g.spread_pct),
+    ("anthias_total_depth_5pct", "Latest notional depth within 5% of the mid", |g| g.total_depth_5pct),
+];
+
+/// The current value of one gauge or counter, labelled with its coin if it has one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub name: &'static str,
+    pub coin: Option<String>,
+    pub value: f64,
+}
+
+#[derive(Debug, Default)]
+struct LatencyHistogram {
+    /// Observation count per bucket in [`LATENCY_BUCKETS_MS`], not cumulative
+    buckets: [u64; LATENCY_BUCKETS_MS.len()],
+    count: u64,
+    sum_ms: f64,
+}
+
+/// Monitor state exposed to Prometheus, updated as metrics are collected and inserted
+#[derive(Debug, Default)]
+pub struct ExporterMetrics {
+    coins: Mutex<BTreeMap<String, CoinGauges>>,
+    inserted_rows: AtomicU64,
+    failed_rows: AtomicU64,
+    latency: Mutex<LatencyHistogram>,
+}
+
+impl ExporterMetrics {
+    /// Update the coin's gauges from a collected row
+    pub fn record_row(&self, metrics: &MarketMetrics) {
+        if let Ok(mut coins) = self.coins.lock() {
+            coins.insert(
+                metrics.coin.clone(),
+                CoinGauges {
+                    mark_price: metrics.mark_price,
+                    spread_pct: metrics.spread_pct,
+                    total_depth_5pct: metrics.total_depth_5pct,
+                },
+            );
+        }
+    }
+
+    /// Count the outcome of a batch insert
+    pub fn record_inserts(&self, inserted: usize, failed: usize) {
+        self.inserted_rows.fetch_add(inserted as u64, Ordering::Relaxed);
+        self.failed_rows.fetch_add(failed as u64, Ordering::Relaxed);
+    }
+
+    /// Add one Hyperliquid request's latency to the histogram
+    pub fn observe_latency(&self, latency_ms: f64) {
+        let Ok(mut histogram) = self.latency.lock() else {
+            return;
+        };
+        if let Some(bucket) = LATENCY_BUCKETS_MS.iter().position(|bound| latency_ms <= *bound) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.count += 1;
+        histogram.sum_ms += latency_ms;
+    }
+
+    /// Counters as `(name, help, value)`
+    const fn counters(&self) -> [(&'static str, &'static str, &AtomicU64); 2] {
+        [
+            ("anthias_inserted_rows_total", "Metrics rows inserted into the database", &self.inserted_rows),
+            ("anthias_failed_rows_total", "Metrics rows the database rejected after retries", &self.failed_rows),
+        ]
+    }
+
+    /// Current value of every gauge and counter, for pushing with remote write
+    #[must_use]
+    pub fn samples(&self) -> Vec<Sample> {
+        let coins = self.coins.lock().map(|coins| coins.clone()).unwrap_or_default();
+        let mut samples = Vec::new();
+        for (name, _, value) in GAUGES {
+            for (coin, coin_gauges) in &coins {
+                if let Some(value) = value(coin_gauges).and_then(|v| v.to_f64()) {
+                    samples.push(Sample { name, coin: Some(coin.clone()), value });
+                }
+            }
+        }
+        for (name, _, counter) in self.counters() {
+            let value = counter.load(Ordering::Relaxed) as f64;
+            samples.push(Sample { name, coin: None, value });
+        }
+        samples
+    }
+
+    /// Everything recorded so far in the Prometheus text exposition format
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let coins = self.coins.lock().map(|coins| coins.clone()).unwrap_or_default();
+        for (name, help, value) in GAUGES {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} gauge");
+            for (coin, coin_gauges) in &coins {
+                if let Some(value) = value(coin_gauges).and_then(|v| v.to_f64()) {
+                    let _ = writeln!(out, "{name}{{coin=\"{}\"}} {value}", escape_label(coin));
+                }
+            }
+        }
+
+        for (name, help, counter) in self.counters() {
+            let _ = writeln!(
+                out,
+                "# HELP {name} {help}\n# TYPE {name} counter\n{name} {}",
+                counter.load(Ordering::Relaxed)
+            );
+        }
+
+        let name = "anthias_hyperliquid_latency_ms";
+        let _ = writeln!(out, "# HELP {name} Hyperliquid API request latency\n# TYPE {name} histogram");
+        if let Ok(histogram) = self.latency.lock() {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS_MS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+            }
+            let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", histogram.count);
+            let _ = writeln!(out, "{name}_sum {}\n{name}_count {}", histogram.sum_ms, histogram.count);
+        }
+        out
+    }
+}
+
+/// Escape a Prometheus label value
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Serve `/metrics`, and `/health` answering 503 while `health` reports a failed
+/// check, on `listener` until `shutdown` is cancelled
+pub async fn serve(
+    listener: TcpListener,
+    metrics: Arc<ExporterMetrics>,
+    health: Arc<dyn HealthCheck>,
+    shutdown: CancellationToken,
+) -> io::Result<()> {
+    let app = Router::new()
+        .route(
+            "/metrics",
+            get(async move || {
+                ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics.render()).into_response()
+            }),
+        )
+        .route(
+            "/health",
+            get(async move || {
+                let report = health.check().await;
+                let status = if report.healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+                (status, Json(report)).into_response()
+            }),
+        );
+    axum::serve(listener, app).with_graceful_shutdown(shutdown.cancelled_owned()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market_metrics::health::{HealthFuture, HealthReport, MarketHealth};
+    use std::time::Duration;
+
+    /// Reports fresh market data and a database that is reachable when `database_up` is set
+    struct StaticHealth {
+        database_up: bool,
+    }
+
+    impl HealthCheck for StaticHealth {
+        fn check(&self) -> HealthFuture<'_> {
+            let markets = [("BTC".to_string(), MarketHealth { fresh: true, ..MarketHealth::default() })];
+            let age = Some(Duration::from_secs(1));
+            let report = HealthReport::new(self.database_up, age, Duration::from_secs(5), markets.into());
+            Box::pin(async move { report })
+        }
+    }
+
+    async fn serve_health(database_up: bool) -> (String, CancellationToken) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/health", listener.local_addr().unwrap());
+        let shutdown = CancellationToken::new();
+        let health = Arc::new(StaticHealth { database_up });
+        tokio::spawn(serve(listener, Arc::new(ExporterMetrics::default()), health, shutdown.clone()));
+        (url, shutdown)
+    }
+
+    #[tokio::test]
+    async fn test_health_status_follows_checks() {
+        let (url, shutdown) = serve_health(true).await;
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["healthy"], true);
+        assert_eq!(body["markets"]["BTC"]["last_insert"], serde_json::Value::Null);
+        shutdown.cancel();
+
+        let (url, shutdown) = serve_health(false).await;
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!((body["healthy"].clone(), body["database_reachable"].clone()), (false.into(), false.into()));
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_scrape_reports_gauges_counters_and_latency() {
+        let metrics = Arc::new(ExporterMetrics::default());
+        let mut row = MarketMetrics::new("BTC".to_string());
+        row.mark_price = Some(Decimal::new(650_005, 1));
+        row.spread_pct = Some(Decimal::new(2, 3));
+        metrics.record_row(&row);
+        metrics.observe_latency(40.0);
+        metrics.observe_latency(3000.0);
+        metrics.record_inserts(2, 1);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/metrics", listener.local_addr().unwrap());
+        let shutdown = CancellationToken::new();
+        let health = Arc::new(StaticHealth { database_up: true });
+        let server = tokio::spawn(serve(listener, metrics, health, shutdown.clone()));
+
+        let body = reqwest::get(&url).await.unwrap().text().await.unwrap();
+        assert!(body.contains("anthias_mark_price{coin=\"BTC\"} 65000.5\n"), "{body}");
+        assert!(body.contains("anthias_spread_pct{coin=\"BTC\"} 0.002\n"));
+        // No depth was collected, so the gauge has no sample
+        assert!(!body.contains("anthias_total_depth_5pct{"));
+        assert!(body.contains("anthias_inserted_rows_total 2\n"));
+        assert!(body.contains("anthias_failed_rows_total 1\n"));
+        assert!(body.contains("anthias_hyperliquid_latency_ms_bucket{le=\"25\"} 0\n"));
+        assert!(body.contains("anthias_hyperliquid_latency_ms_bucket{le=\"50\"} 1\n"));
+        assert!(body.contains("anthias_hyperliquid_latency_ms_bucket{le=\"+Inf\"} 2\n"));
+        assert!(body.contains("anthias_hyperliquid_latency_ms_sum 3040\n"));
+
+        shutdown.cancel();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -1,10 +1,30 @@
+pub mod alerts;
+pub mod analytics;
+pub mod binance_client;
 pub mod config;
+pub mod csv_tail;
 pub mod database;
+pub mod db_tls;
+pub mod dns_cache;
+pub mod export;
+pub mod health;
 pub mod hyperliquid_client;
+pub mod hyperliquid_ws_client;
+pub mod jsonl_archive;
+pub mod market_data_provider;
+pub mod metrics_exporter;
 pub mod monitor;
+#[cfg(feature = "remote-write")]
+pub mod remote_write;
+pub mod retry;
+pub mod secrets;
+pub mod sinks;
+#[cfg(test)]
+mod test_util;
+pub mod trackers;
 pub mod types;
 
-pub use config::MetricsConfig;
+pub use config::{MetricsConfig, MetricsConfigBuilder};
 pub use database::MetricsDatabase;
 pub use hyperliquid_client::HyperliquidClient;
 pub use monitor::MarketMetricsMonitor;
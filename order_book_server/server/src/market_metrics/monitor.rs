@@ -1,22 +1,266 @@
 use crate::listeners::order_book::OrderBookListener;
+#[cfg(feature = "remote-write")]
+use crate::market_metrics::remote_write::RemoteWriteClient;
 use crate::market_metrics::{
-    HyperliquidClient, MetricsConfig, MetricsDatabase, MarketMetrics,
-    types::OrderBookMetrics,
+    HyperliquidClient, MarketMetrics, MetricsConfig, MetricsDatabase,
+    alerts::{Alert, AlertCooldowns, AlertEngine, AlertKind, AlertRouter, AlertSeverity, WebhookSink},
+    analytics,
+    binance_client::BinanceFuturesClient,
+    config::{ConfigError, MarketExclusions},
+    csv_tail::CsvTailWriter,
+    database::BatchInsertError,
+    health::{HealthCheck, HealthFuture, HealthReport, MarketHealth},
+    hyperliquid_client::{Candle, DataSource, HYPERLIQUID_CANDLES_SOURCE, HYPERLIQUID_SOURCE},
+    hyperliquid_ws_client::HyperliquidWsClient,
+    jsonl_archive::JsonlArchiveWriter,
+    market_data_provider::MarketDataProvider,
+    metrics_exporter::{self, ExporterMetrics},
+    retry::{BreakerState, CircuitBreaker, retry_with_budget},
+    sinks::SinkDispatcher,
+    trackers::{
+        ActivityStalenessTracker, BadPrintGuard, BadPrintPolicy, BookSide, DepthCollapseDetector,
+        OracleStalenessTracker, QuoteStuffingDetector, RollingWindow, SpoofingDetector, UpdateRateTracker,
+        VolatilityTracker, VolumeAnomalyDetector,
+    },
+    types::{
+        FillEstimate, HyperliquidMarketData, OrderBookMetrics, ProviderMarketData, SlippagePoint, TimingBreakdown,
+        fill_against,
+    },
 };
 use crate::order_book::Coin;
-use chrono::Utc;
-use log::{error, info, warn};
+use chrono::{DateTime, Utc};
+use log::{debug, error, info, warn};
 use rust_decimal::Decimal;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::str::FromStr;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio::time::{interval, Duration};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tokio::sync::{Mutex, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+/// Metrics queued per observer before new rows are dropped
+const OBSERVER_QUEUE_SIZE: usize = 1024;
+
+/// Longest `/health` waits for the database before reporting it unreachable
+const HEALTH_DATABASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Candle interval startup backfill reads, and the bucket it fills gaps in
+const BACKFILL_CANDLE_INTERVAL: (&str, Duration) = ("1m", Duration::from_mins(1));
+
+/// Per-market state carried between ticks of a monitoring task
+struct MarketState {
+    update_rate: UpdateRateTracker,
+    quote_stuffing: QuoteStuffingDetector,
+    spoofing: SpoofingDetector,
+    spreads: RollingWindow,
+    mids: RollingWindow,
+    depths: RollingWindow,
+    volatility: VolatilityTracker,
+    depth_collapse: Option<DepthCollapseDetector>,
+    oracle_staleness: OracleStalenessTracker,
+    volume_anomaly: VolumeAnomalyDetector,
+    activity_staleness: ActivityStalenessTracker,
+    bad_print: BadPrintGuard,
+}
+
+impl MarketState {
+    fn new(config: &MetricsConfig, coin: &str) -> Self {
+        Self {
+            update_rate: UpdateRateTracker::default(),
+            quote_stuffing: QuoteStuffingDetector::new(config.quote_stuffing_window, config.quote_stuffing_factor),
+            spoofing: SpoofingDetector::new(config.spoofing_window, config.spoofing_min_pulls),
+            spreads: RollingWindow::new(config.spread_autocorr_window),
+            mids: RollingWindow::new(config.mid_vol_window),
+            depths: RollingWindow::new(config.depth_stability_window),
+            volatility: VolatilityTracker::new(config.realized_vol_window()),
+            depth_collapse: config
+                .depth_drop_alert_pct_for(coin)
+                .map(|pct| DepthCollapseDetector::new(config.depth_drop_window, pct)),
+            oracle_staleness: OracleStalenessTracker::new(config.oracle_stale_ticks),
+            volume_anomaly: VolumeAnomalyDetector::new(config.volume_spike_pct, config.volume_flat_pct),
+            activity_staleness: ActivityStalenessTracker::new(config.activity_min_volume_change_pct),
+            bad_print: BadPrintGuard::new(config.max_tick_move_pct),
+        }
+    }
+
+    /// Update the anomaly trackers from a collected row, setting its flags and
+    /// logging newly detected anomalies
+    fn detect_anomalies(&mut self, metrics: &mut MarketMetrics) {
+        let coin = &metrics.coin;
+        if let Some(rate) = metrics.quote_update_rate {
+            metrics.quote_stuffing_suspected = self.quote_stuffing.update(rate);
+            if metrics.quote_stuffing_suspected {
+                warn!("{coin}: abnormal order book update rate ({rate} updates/s), possible quote stuffing");
+            }
+        }
+
+        if let (Some(oracle), Some(mark)) = (metrics.oracle_price, metrics.mark_price) {
+            let was_stale = self.oracle_staleness.is_stale();
+            metrics.oracle_stale = self.oracle_staleness.update(oracle, mark);
+            if metrics.oracle_stale && !was_stale {
+                warn!("{coin}: oracle price stuck at {oracle} while mark moves, oracle feed may be stale");
+            }
+        }
+
+        if let (Some(volume), Some(open_interest), Some(mark)) =
+            (metrics.volume_24h, metrics.open_interest, metrics.mark_price)
+        {
+            metrics.volume_anomaly = self.volume_anomaly.update(volume, open_interest, mark);
+            if metrics.volume_anomaly {
+                warn!("{coin}: 24h volume jumped to {volume} with flat open interest and price, possible wash trading");
+            }
+        }
+
+        if let Some(volume) = metrics.volume_24h {
+            metrics.activity_staleness_secs = self.activity_staleness.update(volume, Instant::now());
+        }
+    }
+
+    /// Update the spoofing detector from a book snapshot, warning when spoofing
+    /// becomes suspected
+    fn detect_spoofing(&mut self, metrics: &mut MarketMetrics, book: &OrderBookMetrics) {
+        let bids = BookSide { best: book.best_bid, prices: &book.bid_prices, large: &book.large_bids };
+        let asks = BookSide { best: book.best_ask, prices: &book.ask_prices, large: &book.large_asks };
+        let was_suspected = self.spoofing.is_suspected();
+        let (suspected, score) = self.spoofing.update(bids, asks);
+        metrics.spoofing_suspected = suspected;
+        metrics.spoofing_score = Some(score);
+        if suspected && !was_suspected {
+            warn!("{}: large orders away from mid repeatedly pulled (score {score}), possible spoofing", metrics.coin);
+        }
+    }
+}
+
+/// Outcome of a step of the monitor, sent to the channel passed to
+/// [`MarketMetricsMonitor::new_with_events`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MonitorEvent {
+    /// A row stamped `timestamp` was collected and queued for insertion
+    CollectionSucceeded { coin: String, timestamp: DateTime<Utc> },
+    /// Collecting a row failed; the market's loop carries on with the next tick
+    CollectionFailed { coin: String, error: String },
+    /// No fresh Hyperliquid market data was cached for the coin
+    HyperliquidStale { coin: String },
+    /// No order book snapshot was available for the coin
+    OrderBookUnavailable { coin: String },
+    /// The database rejected the coin's rows after retries
+    InsertFailed { coin: String, error: String },
+    /// The breaker pausing inserts while the database keeps failing changed state
+    InsertBreaker { state: BreakerState },
+}
+
+/// A running market loop
+struct MarketTask {
+    /// Child of the monitor's shutdown token, cancelled to stop only this market
+    cancel: CancellationToken,
+    handle: JoinHandle<()>,
+}
+
+/// Running markets missing from recent universe fetches, carried between
+/// discovery refreshes so a transient partial fetch can't stop markets that were fine
+#[derive(Debug, Default)]
+struct DiscoveryState {
+    /// Consecutive refreshes each running market has been missing from the universe
+    missing: HashMap<String, u32>,
+}
+
+impl DiscoveryState {
+    /// Running markets to stop because they are no longer `wanted`: at once if
+    /// `universe` lists them, or once they are missing from it on `confirmations`
+    /// refreshes in a row
+    fn markets_to_stop(
+        &mut self,
+        running: &HashSet<String>,
+        wanted: &HashSet<String>,
+        universe: &HashSet<String>,
+        confirmations: u32,
+    ) -> Vec<String> {
+        self.missing.retain(|market, _| running.contains(market) && !universe.contains(market));
+        let mut stop = Vec::new();
+        for market in running.difference(wanted) {
+            if !universe.contains(market) {
+                let streak = self.missing.entry(market.clone()).or_default();
+                *streak += 1;
+                if *streak < confirmations {
+                    warn!("🔍 {market} is missing from the universe fetch, keeping it until {confirmations} agree");
+                    continue;
+                }
+                self.missing.remove(market);
+            }
+            stop.push(market.clone());
+        }
+        stop
+    }
+}
+
+/// Where collected rows are written
+enum MetricsStore {
+    Postgres {
+        database: Arc<Mutex<MetricsDatabase>>,
+        /// Clone of `database` sharing its pool, for long statements such as
+        /// retention pruning that must not hold up inserts behind the lock,
+        /// and health pings that must not wait behind them
+        unlocked: Arc<MetricsDatabase>,
+    },
+    /// `dry_run`: rows are printed as JSON and no database is used
+    Stdout,
+}
 
 pub struct MarketMetricsMonitor {
     config: MetricsConfig,
-    database: Arc<Mutex<MetricsDatabase>>,
+    store: MetricsStore,
     hyperliquid_client: Arc<HyperliquidClient>,
+    /// Coins to subscribe to on the websocket feed, set when any coin may be streamed
+    ws_subscriber: Option<mpsc::UnboundedSender<String>>,
+    /// Exchanges whose prices are stored with each row, Hyperliquid first
+    providers: Vec<Arc<dyn MarketDataProvider>>,
     orderbook_listener: Arc<Mutex<OrderBookListener>>,
+    /// File sinks written alongside the database
+    sinks: StdMutex<SinkDispatcher>,
+    alert_cooldowns: StdMutex<AlertCooldowns>,
+    alert_router: AlertRouter,
+    alert_engine: StdMutex<AlertEngine>,
+    /// Most recent metrics per market, aggregated into portfolio snapshots
+    latest_metrics: StdMutex<HashMap<String, MarketMetrics>>,
+    /// Rows collected since the last flush, inserted together once per tick
+    pending_inserts: StdMutex<Vec<MarketMetrics>>,
+    /// Total insert retries across all markets
+    insert_retries: AtomicU64,
+    /// Pauses inserts after repeated failed flushes
+    insert_breaker: StdMutex<CircuitBreaker>,
+    /// Latest row per market collected while inserts were paused
+    paused_rows: StdMutex<HashMap<String, MarketMetrics>>,
+    /// Gauges and counters served by the Prometheus exporter
+    exporter_metrics: Arc<ExporterMetrics>,
+    /// Running market loops, cancelling one stops its loop after the current tick
+    market_tasks: StdMutex<HashMap<String, MarketTask>>,
+    /// Markets monitored regardless of discovery: `target_markets` plus those
+    /// added with [`Self::add_market`], minus those removed with [`Self::remove_market`]
+    pinned_markets: StdMutex<HashSet<String>>,
+    /// `target_markets` included `ALL`, so every listed market is discovered
+    all_markets: bool,
+    /// Markets never started, whether pinned, listed or discovered
+    exclusions: MarketExclusions,
+    /// Cancelled by [`Self::shutdown`] to stop every background loop
+    shutdown: CancellationToken,
+    /// Loops spawned by [`Self::start`], awaited on shutdown so in-flight collections finish
+    tasks: TaskTracker,
+    /// Queues feeding the registered observer callbacks
+    observers: Vec<mpsc::Sender<MarketMetrics>>,
+    /// Last insert and error per market, reported at `/health`
+    market_health: StdMutex<HashMap<String, MarketHealth>>,
+    discovery: StdMutex<DiscoveryState>,
+    /// Channel passed to [`Self::new_with_events`]
+    events: Option<mpsc::Sender<MonitorEvent>>,
+    /// Events dropped since the channel was last found full, so the warning is
+    /// logged once each time it fills up rather than per event
+    dropped_events: AtomicU64,
 }
 
 impl MarketMetricsMonitor {
@@ -24,218 +268,2689 @@ impl MarketMetricsMonitor {
         config: MetricsConfig,
         orderbook_listener: Arc<Mutex<OrderBookListener>>,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        // Create database connection
-        let mut database = MetricsDatabase::new(
-            &config.database_url,
-            config.max_db_connections,
-        )
-        .await?;
+        Self::new_with_events(config, orderbook_listener, None).await
+    }
+
+    /// Like [`Self::new`], also sending a [`MonitorEvent`] for every collection,
+    /// stale or missing input and rejected insert to `events`.
+    ///
+    /// Events are dropped while the channel is full, so a slow consumer can't
+    /// delay collection.
+    pub(crate) async fn new_with_events(
+        config: MetricsConfig,
+        orderbook_listener: Arc<Mutex<OrderBookListener>>,
+        events: Option<mpsc::Sender<MonitorEvent>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        config.validate()?;
+        config.validate_data_sources()?;
+        config.validate_market_overrides()?;
+        let all_markets = config.monitors_all_markets();
+        let exclusions = config.market_exclusions()?;
+        let mut config = config;
+        config.expand_all_markets();
+        let (excluded, target_markets) =
+            config.target_markets.into_iter().partition::<Vec<_>, _>(|market| exclusions.excludes(market));
+        config.target_markets = target_markets;
+        #[cfg(not(feature = "remote-write"))]
+        if config.remote_write_url.is_some() {
+            return Err("Prometheus remote write requires building with the `remote-write` feature".into());
+        }
 
-        // Ensure tables exist for all target markets
-        for market in &config.target_markets {
-            database.ensure_market_table(market).await?;
+        let store = if config.dry_run {
+            info!("  - Dry run: printing rows instead of writing to the database");
+            MetricsStore::Stdout
+        } else {
+            let database = connect_database(&config).await?;
+            MetricsStore::Postgres { unlocked: Arc::new(database.clone()), database: Arc::new(Mutex::new(database)) }
+        };
+
+        let exporter_metrics = Arc::new(ExporterMetrics::default());
+        let hyperliquid_client = Arc::new(hyperliquid_client(&config, exporter_metrics.clone())?);
+        let shutdown = CancellationToken::new();
+        let mut providers: Vec<Arc<dyn MarketDataProvider>> = vec![hyperliquid_client.clone()];
+        if let Some(url) = &config.binance_api_url {
+            let symbols = config.binance_symbols.clone();
+            let binance = Arc::new(BinanceFuturesClient::new(url.clone(), symbols, config.poll_interval()));
+            binance.clone().start_polling(shutdown.clone());
+            providers.push(binance);
         }
 
-        let database = Arc::new(Mutex::new(database));
+        let ws_subscriber = start_market_data(&config, &hyperliquid_client, &shutdown)?;
 
-        // Create Hyperliquid client
-        let hyperliquid_client = Arc::new(HyperliquidClient::new(
-            config.hyperliquid_api_url.clone(),
-            config.poll_interval(),
-        ));
+        // With `ALL`, fetch the universe now so every listed market has its table
+        // and starts on the first discovery refresh rather than a poll later
+        if all_markets {
+            ensure_universe_tables(&config, &hyperliquid_client, &exclusions, &store).await?;
+        }
 
-        // Start background polling for Hyperliquid data
-        hyperliquid_client.clone().start_polling();
+        let sinks = sinks(&config)?;
+        let mut alert_cooldowns = AlertCooldowns::new(config.alert_cooldown());
+        if let Some(path) = &config.alert_state_path {
+            alert_cooldowns = alert_cooldowns.with_state_file(path)?;
+        }
+        let alert_cooldowns = StdMutex::new(alert_cooldowns);
+        let alert_router = alert_router(&config)?;
+        let alert_engine =
+            StdMutex::new(AlertEngine::new(config.effective_alert_rules(), config.alert_critical_multiple));
 
-        info!(" Market metrics monitor initialized");
-        info!("  - Target markets: {:?}", config.target_markets);
-        info!("  - Monitoring interval: {:?}", config.monitoring_interval());
-        info!("  - Poll interval: {:?}", config.poll_interval());
+        log_settings(&config, &exclusions, &excluded);
 
+        let pinned_markets = config.target_markets.iter().cloned().collect();
+        let insert_breaker = CircuitBreaker::new(config.db_breaker_threshold, config.db_breaker_cooldown());
         Ok(Self {
             config,
-            database,
+            store,
             hyperliquid_client,
+            ws_subscriber,
+            providers,
             orderbook_listener,
+            sinks: StdMutex::new(sinks),
+            alert_cooldowns,
+            alert_router,
+            alert_engine,
+            latest_metrics: StdMutex::new(HashMap::new()),
+            pending_inserts: StdMutex::new(Vec::new()),
+            insert_retries: AtomicU64::new(0),
+            insert_breaker: StdMutex::new(insert_breaker),
+            paused_rows: StdMutex::new(HashMap::new()),
+            exporter_metrics,
+            market_tasks: StdMutex::new(HashMap::new()),
+            pinned_markets: StdMutex::new(pinned_markets),
+            all_markets,
+            exclusions,
+            shutdown,
+            tasks: TaskTracker::new(),
+            observers: Vec::new(),
+            market_health: StdMutex::new(HashMap::new()),
+            discovery: StdMutex::new(DiscoveryState::default()),
+            events,
+            dropped_events: AtomicU64::new(0),
         })
     }
 
+    /// Call `f` with every computed metrics row, before it is written to the sinks.
+    ///
+    /// Callbacks run on their own task behind a bounded queue, so a slow
+    /// observer misses rows instead of delaying collection. Must be called
+    /// from within a Tokio runtime.
+    pub fn with_observer(&mut self, f: impl Fn(&MarketMetrics) + Send + Sync + 'static) {
+        let (tx, mut rx) = mpsc::channel::<MarketMetrics>(OBSERVER_QUEUE_SIZE);
+        tokio::spawn(async move {
+            while let Some(metrics) = rx.recv().await {
+                f(&metrics);
+            }
+        });
+        self.observers.push(tx);
+    }
+
+    /// Also store `provider`'s prices for every coin with each row
+    pub fn with_provider(&mut self, provider: Arc<dyn MarketDataProvider>) {
+        self.providers.push(provider);
+    }
+
+    fn emit(&self, event: MonitorEvent) {
+        let Some(events) = &self.events else {
+            return;
+        };
+        match events.try_send(event) {
+            Ok(()) => {
+                let dropped = self.dropped_events.swap(0, Ordering::Relaxed);
+                if dropped > 0 {
+                    warn!("Event channel drained after dropping {dropped} events");
+                }
+            }
+            Err(mpsc::error::TrySendError::Full(event)) => {
+                if self.dropped_events.fetch_add(1, Ordering::Relaxed) == 0 {
+                    warn!("Event channel full, dropping {event:?} and later events until it drains");
+                }
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {}
+        }
+    }
+
+    /// Stop all monitoring loops, letting in-flight collections finish, then
+    /// insert pending rows, flush the file sinks and close the database pool,
+    /// waiting up to `db_drain_timeout` for inserts to release their connections
+    pub async fn shutdown(&self) {
+        info!("Shutting down market metrics monitor");
+        self.shutdown.cancel();
+        self.tasks.close();
+        self.tasks.wait().await;
+
+        self.flush_pending_inserts().await;
+        match self.sinks.lock() {
+            Ok(mut sinks) => {
+                for (sink, e) in sinks.flush() {
+                    error!("Failed to flush {sink} sink: {e}");
+                }
+            }
+            Err(_) => error!("Sink lock poisoned, skipping flush"),
+        }
+
+        if let Some(database) = self.database() {
+            database.lock().await.close(self.config.db_drain_timeout()).await;
+        }
+    }
+
+    /// The database rows are written to, `None` in a dry run
+    const fn database(&self) -> Option<&Arc<Mutex<MetricsDatabase>>> {
+        match &self.store {
+            MetricsStore::Postgres { database, .. } => Some(database),
+            MetricsStore::Stdout => None,
+        }
+    }
+
+    /// The database without its lock, see [`MetricsStore::Postgres`]
+    const fn unlocked_database(&self) -> Option<&Arc<MetricsDatabase>> {
+        match &self.store {
+            MetricsStore::Postgres { unlocked, .. } => Some(unlocked),
+            MetricsStore::Stdout => None,
+        }
+    }
+
+    /// Total number of insert retries since startup
+    #[must_use]
+    pub fn insert_retries(&self) -> u64 {
+        self.insert_retries.load(Ordering::Relaxed)
+    }
+
+    /// Gauges and counters exposed by the Prometheus exporter
+    #[must_use]
+    pub fn exporter_metrics(&self) -> Arc<ExporterMetrics> {
+        self.exporter_metrics.clone()
+    }
+
     /// Start monitoring all configured markets
     pub async fn start(self: Arc<Self>) {
         info!("🎯 Starting market metrics monitoring");
 
+        if let Some(addr) = &self.config.metrics_exporter_addr {
+            match TcpListener::bind(addr).await {
+                Ok(listener) => {
+                    info!("  - Prometheus metrics at http://{addr}/metrics, health check at http://{addr}/health");
+                    let health = self.clone();
+                    let server =
+                        metrics_exporter::serve(listener, self.exporter_metrics(), health, self.shutdown.child_token());
+                    self.tasks.spawn(async move {
+                        if let Err(e) = server.await {
+                            error!("Prometheus exporter failed: {e}");
+                        }
+                    });
+                }
+                Err(e) => error!("Failed to bind Prometheus exporter to {addr}: {e}"),
+            }
+        }
+
+        #[cfg(feature = "remote-write")]
+        if let Some(url) = &self.config.remote_write_url {
+            let config = &self.config;
+            match RemoteWriteClient::new(url.clone(), &config.remote_write_headers, config.remote_write_max_retries) {
+                Ok(client) => {
+                    info!("  - Pushing Prometheus metrics to {url}");
+                    let interval = config.remote_write_interval();
+                    self.tasks.spawn(client.run(self.exporter_metrics(), interval, self.shutdown.child_token()));
+                }
+                Err(e) => error!("Invalid remote write configuration: {e}"),
+            }
+        }
+
         // Spawn a monitoring task for each market
         for market in &self.config.target_markets {
+            self.spawn_market(market.clone());
+        }
+
+        if let Some(path) = self.config.config_path.clone() {
+            info!("  - Reloading target markets from {path} on change or SIGHUP");
+            let monitor = self.clone();
+            self.tasks.spawn(async move {
+                monitor.watch_config(&path).await;
+            });
+        }
+
+        if let Some(discovery_interval) = self.config.discovery_interval() {
+            let monitor = self.clone();
+            self.tasks.spawn(async move {
+                let mut interval = interval(discovery_interval);
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => monitor.refresh_target_markets().await,
+                        () = monitor.shutdown.cancelled() => return,
+                    }
+                }
+            });
+        }
+
+        // Pending rows left at cancellation are inserted by `shutdown`
+        let monitor = self.clone();
+        self.tasks.spawn(async move {
+            let mut interval = interval(monitor.config.monitoring_interval());
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => monitor.flush_pending_inserts().await,
+                    () = monitor.shutdown.cancelled() => return,
+                }
+            }
+        });
+
+        let monitor = self.clone();
+        self.tasks.spawn(async move {
+            monitor.monitor_portfolio().await;
+        });
+
+        if let Some(backfill) = self.config.backfill() {
+            let monitor = self.clone();
+            self.tasks.spawn(async move {
+                monitor.backfill_history(backfill).await;
+            });
+        }
+
+        if let Some(retention) = self.config.retention() {
+            let monitor = self.clone();
+            self.tasks.spawn(async move {
+                monitor.prune_old_rows(retention).await;
+            });
+        }
+
+        let rollup_intervals = self.config.rollup_intervals();
+        if !rollup_intervals.is_empty() {
             let monitor = self.clone();
-            let market = market.clone();
-            tokio::spawn(async move {
-                monitor.monitor_market(market).await;
+            self.tasks.spawn(async move {
+                monitor.refresh_rollups(&rollup_intervals).await;
             });
         }
 
         info!("✅ All market monitoring tasks started");
     }
 
-    /// Monitor a single market continuously
-    async fn monitor_market(&self, market: String) {
-        let mut interval = interval(self.config.monitoring_interval());
-        info!("📊 Started monitoring {}", market);
+    /// Spawn the monitoring loop for a market unless one is already running
+    fn spawn_market(self: &Arc<Self>, market: String) {
+        let Ok(mut tasks) = self.market_tasks.lock() else {
+            error!("Market task lock poisoned, not starting {market}");
+            return;
+        };
+        if tasks.contains_key(&market) || self.shutdown.is_cancelled() {
+            return;
+        }
+        let cancel = self.shutdown.child_token();
+        let monitor = self.clone();
+        let (name, market_cancel) = (market.clone(), cancel.clone());
+        let handle = self.tasks.spawn(async move {
+            monitor.monitor_market(name, market_cancel).await;
+        });
+        tasks.insert(market.clone(), MarketTask { cancel, handle });
+        drop(tasks);
+
+        // Subscribing again to a coin streamed since startup is a no-op
+        if self.config.data_source(&market) == DataSource::Websocket
+            && let Some(subscriber) = &self.ws_subscriber
+            && subscriber.send(market.clone()).is_err()
+        {
+            warn!("{market}: Websocket feed stopped, not subscribing");
+        }
+    }
+
+    /// Cancel a market's loop and forget its latest metrics, returning the
+    /// loop's handle if one was running
+    fn stop_market(&self, market: &str) -> Option<JoinHandle<()>> {
+        let task = self.market_tasks.lock().ok()?.remove(market)?;
+        task.cancel.cancel();
+        if let Ok(mut latest) = self.latest_metrics.lock() {
+            latest.remove(market);
+        }
+        Some(task.handle)
+    }
+
+    /// Start monitoring `coin` without restarting the monitor.
+    ///
+    /// Creates the market's table first, and keeps the market running through
+    /// discovery refreshes until it is removed. A market assigned the
+    /// websocket source is subscribed to on the running feed. Excluded markets
+    /// are rejected.
+    pub async fn add_market(self: &Arc<Self>, coin: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.exclusions.excludes(coin) {
+            return Err(format!("{coin} is excluded by excluded_markets or excluded_market_patterns").into());
+        }
+        if let Some(database) = self.database() {
+            database.lock().await.ensure_market_table(coin).await?;
+        }
+        self.pinned_markets.lock().map_err(|_| "Pinned market lock poisoned")?.insert(coin.to_string());
+        info!("➕ Adding {coin} to monitored markets");
+        self.spawn_market(coin.to_string());
+        Ok(())
+    }
+
+    /// Stop monitoring `coin`, waiting for an in-flight collection to queue its
+    /// row. Returns whether the market was being monitored.
+    ///
+    /// With discovery enabled, a market that meets the discovery thresholds is
+    /// picked up again on the next refresh.
+    pub async fn remove_market(&self, coin: &str) -> bool {
+        if let Ok(mut pinned) = self.pinned_markets.lock() {
+            pinned.remove(coin);
+        }
+        let Some(handle) = self.stop_market(coin) else {
+            return false;
+        };
+        info!("➖ Removing {coin} from monitored markets");
+        if let Err(e) = handle.await {
+            error!("{coin}: Monitoring task failed: {e}");
+        }
+        true
+    }
+
+    /// Reload `target_markets` from `path` whenever the file changes or the
+    /// process receives SIGHUP, until the monitor shuts down
+    async fn watch_config(self: &Arc<Self>, path: &str) {
+        let mut interval = interval(self.config.config_reload_interval());
+        let mut modified = modified_at(path);
+        #[cfg(unix)]
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+            .inspect_err(|e| error!("Failed to listen for SIGHUP, only watching {path} for changes: {e}"))
+            .ok();
+        #[cfg(not(unix))]
+        let mut hangup: Option<()> = None;
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let latest = modified_at(path);
+                    if latest == modified {
+                        continue;
+                    }
+                    modified = latest;
+                }
+                () = hangup_received(&mut hangup) => info!("🔄 SIGHUP received, reloading {path}"),
+                () = self.shutdown.cancelled() => return,
+            }
+            self.reload_target_markets(path).await;
+        }
+    }
+
+    /// Start and stop market loops to match the `target_markets` of the config
+    /// file at `path`. A file that fails to load or validate changes nothing.
+    ///
+    /// Added markets get their table before their loop starts; removed markets
+    /// finish any collection in progress before their loop exits. Markets
+    /// excluded at startup stay excluded, as other settings in the file only
+    /// take effect after a restart.
+    pub async fn reload_target_markets(self: &Arc<Self>, path: &str) {
+        let mut config = match MetricsConfig::from_file(path) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("🔄 Failed to reload {path}, keeping the running markets: {e}");
+                return;
+            }
+        };
+        let Ok(pinned) = self.pinned_markets.lock().map(|pinned| pinned.clone()) else {
+            error!("Pinned market lock poisoned, skipping reload of {path}");
+            return;
+        };
+        if config.monitors_all_markets() != self.all_markets {
+            warn!("🔄 Switching to or from ALL markets in {path} only takes effect after a restart");
+        }
+        config.expand_all_markets();
+        let target =
+            config.target_markets.into_iter().filter(|coin| !self.exclusions.excludes(coin)).collect::<HashSet<_>>();
+
+        let mut added = Vec::new();
+        for coin in target.difference(&pinned) {
+            match self.add_market(coin).await {
+                Ok(()) => added.push(coin.clone()),
+                Err(e) => error!("🔄 {coin}: Failed to add reloaded market: {e}"),
+            }
+        }
+        let mut removed = Vec::new();
+        for coin in pinned.difference(&target) {
+            self.remove_market(coin).await;
+            removed.push(coin.clone());
+        }
+        added.sort();
+        removed.sort();
+        info!("🔄 Reloaded {path}: added {added:?}, removed {removed:?}");
+    }
+
+    /// Re-read the universe and start or stop market loops to match the discovery criteria.
+    ///
+    /// Pinned markets always keep running; other markets are monitored while
+    /// their volume and open interest meet the thresholds. A market missing
+    /// from the universe, as after a partial fetch, is only stopped once it has
+    /// been missing on `discovery_confirmations` refreshes in a row.
+    pub async fn refresh_target_markets(self: &Arc<Self>) {
+        let market_data = self.hyperliquid_client.all_market_data().await;
+        let universe = market_data.iter().map(|data| data.coin.clone()).collect::<HashSet<_>>();
+        let Ok(pinned) = self.pinned_markets.lock().map(|pinned| pinned.clone()) else {
+            error!("Pinned market lock poisoned, skipping market discovery");
+            return;
+        };
+        let wanted =
+            discover_markets(&market_data, self.config.discovery_min_volume, self.config.discovery_min_open_interest)
+                .into_iter()
+                .filter(|market| !self.exclusions.excludes(market))
+                .chain(pinned)
+                .collect::<HashSet<_>>();
+
+        let Ok(running) = self.market_tasks.lock().map(|tasks| tasks.keys().cloned().collect::<HashSet<_>>()) else {
+            error!("Market task lock poisoned, skipping market discovery");
+            return;
+        };
+
+        for market in wanted.difference(&running) {
+            if let Some(database) = self.database()
+                && let Err(e) = database.lock().await.ensure_market_table(market).await
+            {
+                error!("{market}: Failed to create table for discovered market: {e}");
+                continue;
+            }
+            info!("🔍 Discovered {market}, starting monitoring");
+            self.spawn_market(market.clone());
+        }
+
+        let confirmations = self.config.discovery_confirmations;
+        let Ok(stop) =
+            self.discovery.lock().map(|mut d| d.markets_to_stop(&running, &wanted, &universe, confirmations))
+        else {
+            error!("Discovery state lock poisoned, skipping market removal");
+            return;
+        };
+        for market in stop {
+            info!("🔍 {market} no longer meets discovery thresholds, stopping monitoring");
+            self.stop_market(&market);
+        }
+    }
+
+    /// Monitor a single market until `cancel` fires, either for this market alone or for shutdown.
+    ///
+    /// Cancellation is only observed between ticks, so a collection in progress
+    /// always completes and queues its row for the final flush.
+    async fn monitor_market(&self, market: String, cancel: CancellationToken) {
+        let period = self.config.monitoring_interval_for(&market);
+        let mut interval = interval(period);
+        let mut state = MarketState::new(&self.config, &market);
+        info!("📊 Started monitoring {market} every {period:?}");
 
         loop {
-            interval.tick().await;
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = cancel.cancelled() => {
+                    if self.shutdown.is_cancelled() {
+                        info!("📊 Stopped monitoring {market} for shutdown");
+                    } else {
+                        info!("📊 Stopped monitoring {market}");
+                    }
+                    return;
+                }
+            }
 
-            match self.collect_and_store_metrics(&market).await {
-                Ok(_) => {}
+            match self.collect_and_store_metrics(&market, &mut state).await {
+                Ok(Some(timestamp)) => {
+                    self.emit(MonitorEvent::CollectionSucceeded { coin: market.clone(), timestamp });
+                }
+                // A suspected bad print was skipped
+                Ok(None) => {}
                 Err(e) => {
                     error!("Failed to collect metrics for {}: {}", market, e);
+                    self.record_market_health(&market, Err(e.to_string()));
+                    self.emit(MonitorEvent::CollectionFailed { coin: market.clone(), error: e.to_string() });
+                }
+            }
+        }
+    }
+
+    /// Periodically aggregate the latest metrics of all markets into a portfolio
+    /// snapshot and the configured baskets, until the monitor shuts down
+    async fn monitor_portfolio(&self) {
+        let Some(database) = self.database() else { return };
+        let mut interval = interval(self.config.portfolio_interval());
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = self.shutdown.cancelled() => return,
+            }
+
+            let latest = match self.latest_metrics.lock() {
+                Ok(latest) => latest.clone(),
+                Err(e) => {
+                    error!("Latest metrics lock poisoned: {e}");
+                    continue;
+                }
+            };
+            if latest.is_empty() {
+                continue;
+            }
+
+            let now = Utc::now();
+            let markets = latest.values().cloned().collect::<Vec<_>>();
+            let snapshot = analytics::portfolio_liquidity(&markets, now);
+            let db = database.lock().await;
+            if let Err(e) = db.insert_portfolio_liquidity(&snapshot).await {
+                error!("Failed to store portfolio liquidity: {e}");
+            }
+
+            for (basket, weights) in &self.config.baskets {
+                let components = weights
+                    .iter()
+                    .map(|(coin, weight)| {
+                        let metrics = latest.get(coin).cloned().unwrap_or_else(|| MarketMetrics::new(coin.clone()));
+                        (metrics, *weight)
+                    })
+                    .collect::<Vec<_>>();
+                let metrics = analytics::basket_metrics(&components);
+                if let Err(e) = db.insert_basket_metrics(basket, now, &metrics).await {
+                    error!("Failed to store basket {basket}: {e}");
+                }
+            }
+        }
+    }
+
+    /// Periodically delete rows older than `retention` from the table of every
+    /// running market, until the monitor shuts down
+    async fn prune_old_rows(&self, retention: Duration) {
+        let Some(database) = self.unlocked_database() else { return };
+        let mut interval = interval(self.config.retention_cleanup_interval());
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = self.shutdown.cancelled() => return,
+            }
+
+            let Ok(markets) = self.market_tasks.lock().map(|tasks| tasks.keys().cloned().collect::<Vec<_>>()) else {
+                error!("Market task lock poisoned, skipping retention cleanup");
+                continue;
+            };
+            let cutoff = time_before(Utc::now(), retention);
+            for coin in markets {
+                let result = database.prune_older_than(&coin, cutoff).await;
+                match result {
+                    Ok(removed) => info!("🧹 {coin}: pruned {removed} rows older than {cutoff}"),
+                    Err(e) => error!("{coin}: Failed to prune rows older than {cutoff}: {e}"),
+                }
+            }
+        }
+    }
+
+    /// Fill gaps in the last `window` of each target market's history from
+    /// candles, leaving minutes that already have a stored row untouched
+    async fn backfill_history(&self, window: Duration) {
+        let Some(database) = self.database() else { return };
+        let (interval, bucket) = BACKFILL_CANDLE_INTERVAL;
+        let end = Utc::now();
+        let start = time_before(end, window);
+        for coin in &self.config.target_markets {
+            if self.shutdown.is_cancelled() {
+                return;
+            }
+            let candles = match self.hyperliquid_client.fetch_candles(coin, interval, start, end).await {
+                Ok(candles) => candles,
+                Err(e) => {
+                    error!("{coin}: Failed to fetch candles for backfill: {e}");
+                    continue;
+                }
+            };
+            let rows = candles.iter().filter_map(|candle| candle_metrics(coin, candle)).collect::<Vec<_>>();
+            let result = database.lock().await.backfill_metrics(coin, &rows, bucket).await;
+            match result {
+                Ok(inserted) => info!("⏪ {coin}: backfilled {inserted} of {} candles since {start}", rows.len()),
+                Err(e) => error!("{coin}: Failed to backfill history: {e}"),
+            }
+        }
+    }
+
+    /// Periodically aggregate the recent rows of every running market into its
+    /// rollup table for each of `buckets`, until the monitor shuts down
+    async fn refresh_rollups(&self, buckets: &[Duration]) {
+        let Some(database) = self.database() else { return };
+        let refresh = self.config.rollup_refresh_interval();
+        let mut interval = interval(refresh);
+        let mut created_tables = HashSet::new();
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = self.shutdown.cancelled() => return,
+            }
+
+            let Ok(markets) = self.market_tasks.lock().map(|tasks| tasks.keys().cloned().collect::<Vec<_>>()) else {
+                error!("Market task lock poisoned, skipping rollup refresh");
+                continue;
+            };
+            for coin in markets {
+                for &bucket in buckets {
+                    // Reach back past the bucket that was still filling at the previous refresh
+                    let window = refresh + bucket * 2;
+                    let db = database.lock().await;
+                    let result = async {
+                        if created_tables.insert((coin.clone(), bucket))
+                            && let Err(e) = db.ensure_rollup_table(&coin, bucket).await
+                        {
+                            created_tables.remove(&(coin.clone(), bucket));
+                            return Err(e);
+                        }
+                        db.compute_rollup(&coin, bucket, window).await
+                    }
+                    .await;
+                    drop(db);
+                    if let Err(e) = result {
+                        error!("{coin}: Failed to refresh {}s rollup: {e}", bucket.as_secs());
+                    }
                 }
             }
         }
     }
 
+    /// Alert when the row's `total_depth_5pct` collapses against its rolling average
+    fn check_depth_collapse(&self, coin: &str, state: &mut MarketState, metrics: &MarketMetrics) {
+        if let (Some(detector), Some(depth)) = (&mut state.depth_collapse, metrics.total_depth_5pct)
+            && let Some(avg) = detector.update(depth)
+        {
+            let drop = Decimal::ONE - depth / avg;
+            let threshold = self.config.depth_drop_alert_pct_for(coin).unwrap_or_default();
+            self.fire_alert(Alert::new(
+                coin,
+                AlertKind::DepthCollapse,
+                AlertSeverity::from_breach(drop, threshold, self.config.alert_critical_multiple),
+                format!("total_depth_5pct collapsed to {depth} from rolling average {avg}"),
+            ));
+        }
+    }
+
     /// Collect metrics for a market and store in database
-    async fn collect_and_store_metrics(&self, coin: &str) -> Result<(), Box<dyn std::error::Error>> {
+    async fn collect_and_store_metrics(
+        &self,
+        coin: &str,
+        state: &mut MarketState,
+    ) -> Result<Option<DateTime<Utc>>, Box<dyn std::error::Error>> {
+        let (started, mut timing) = (Instant::now(), TimingBreakdown::default());
         let mut metrics = MarketMetrics::new(coin.to_string());
-        metrics.timestamp = Utc::now();
 
         // Get Hyperliquid market data
-        if let Some(hl_data) = self.hyperliquid_client.get_market_data(coin).await {
+        let source = self.config.data_source(coin);
+        if let Some(hl_data) = self.get_market_data(coin, source, &mut timing).await {
             metrics.merge_hyperliquid_data(hl_data);
         } else {
             warn!("{}: No Hyperliquid data available", coin);
         }
+        self.merge_provider_prices(&mut metrics).await;
 
         // Get orderbook metrics
-        if let Some(ob_metrics) = self.get_orderbook_metrics(coin).await {
+        let mut top_sizes = None;
+        if let Some(ob_metrics) = self.get_orderbook_metrics(coin, source, &mut timing).await {
+            top_sizes = Some((ob_metrics.best_bid_size, ob_metrics.best_ask_size));
+            if let Some(update_count) = ob_metrics.update_count {
+                metrics.quote_update_rate = state.update_rate.update(update_count, Instant::now());
+            }
+            state.detect_spoofing(&mut metrics, &ob_metrics);
             metrics.merge_orderbook_data(ob_metrics);
         } else {
             warn!("{}: No orderbook data available", coin);
         }
 
-        // Insert into database
-        let db = self.database.lock().await;
-        db.insert_metrics(&metrics).await?;
-
-        let price = metrics.mark_price.unwrap_or_default();
-        info!("📊 {}: ${} - metrics inserted ✅", coin, price);
-
-        Ok(())
-    }
-
-    /// Extract orderbook metrics from the listener
-    async fn get_orderbook_metrics(&self, coin: &str) -> Option<OrderBookMetrics> {
-        let mut listener = self.orderbook_listener.lock().await;
+        if self.check_bad_print(&mut metrics, &mut state.bad_print) == Some(BadPrintPolicy::Skip) {
+            return Ok(None);
+        }
 
-        // Get snapshot from listener
-        let snapshot = listener.compute_snapshot()?;
-        let coin_obj = Coin::new(coin);
+        state.detect_anomalies(&mut metrics);
 
-        // Find the snapshot for this coin and store the value to extend its lifetime
-        let snapshot_value = snapshot.snapshot.value();
-        let (_, snapshot_data) = snapshot_value
-            .iter()
-            .find(|(c, _)| **c == coin_obj)?;
+        // Derived metrics, keeping suspected bad prints out of the rolling windows
+        if let Some(spread_pct) = metrics.spread_pct {
+            if !metrics.suspected_bad_print {
+                state.spreads.push(spread_pct);
+            }
+            metrics.spread_autocorr = analytics::autocorrelation(&state.spreads.to_vec(), 1);
+        }
+        if let Some(mid) = metrics.mid_price {
+            metrics.impact_adjusted_mid =
+                analytics::impact_adjusted_mid(mid, metrics.impact_px_bid, metrics.impact_px_ask);
+            if !metrics.suspected_bad_print {
+                state.mids.push(mid);
+                metrics.realized_vol = state.volatility.update(mid, Instant::now());
+            }
+        }
+        if let (Some(best_bid), Some(best_ask), Some((bid_size, ask_size))) =
+            (metrics.best_bid, metrics.best_ask, top_sizes)
+        {
+            let recent_vol = analytics::return_volatility(&state.mids.to_vec()).unwrap_or_default();
+            let (low, high) = analytics::mid_confidence(best_bid, best_ask, bid_size, ask_size, recent_vol);
+            metrics.mid_ci_low = Some(low);
+            metrics.mid_ci_high = Some(high);
+        }
+        if let Some(depth) = metrics.total_depth_5pct {
+            state.depths.push(depth);
+            metrics.depth_stability = analytics::depth_stability(&state.depths.to_vec());
+            if let Some(funding) = metrics.funding_rate_pct {
+                metrics.liquidity_weighted_funding =
+                    Some(analytics::liquidity_weighted_funding(funding, depth, self.config.funding_reference_depth));
+            }
+        }
 
-        // Parse bids and asks
-        let bids = &snapshot_data.as_ref()[0];
-        let asks = &snapshot_data.as_ref()[1];
+        if let (Some(mark), Some(oracle), Some(funding)) =
+            (metrics.mark_price, metrics.oracle_price, metrics.funding_rate_pct)
+        {
+            let hours_to_funding = Some(analytics::hours_to_next_funding(metrics.timestamp));
+            metrics.basis_divergence = analytics::basis_divergence(mark, oracle, funding, hours_to_funding);
+        }
 
-        if bids.is_empty() || asks.is_empty() {
-            return None;
+        self.check_depth_collapse(coin, state, &metrics);
+        let alerts = self.alert_engine.lock().map_err(|_| "Alert engine lock poisoned")?.evaluate(&metrics);
+        for alert in alerts {
+            self.fire_alert(alert);
         }
 
-        // Calculate best prices (limit_px and sz are Px/Sz types with to_str() method)
-        let best_bid = Decimal::from_str(&bids[0].limit_px.to_str()).ok()?;
-        let best_ask = Decimal::from_str(&asks[0].limit_px.to_str()).ok()?;
-        let mid_price = (best_bid + best_ask) / Decimal::from(2);
+        self.record_timing(&mut metrics, timing, started);
+        // Observers, sinks and the exporter see the row as it is stored
+        metrics.round_for_storage(&self.config);
+        for observer in &self.observers {
+            if let Err(mpsc::error::TrySendError::Full(_)) = observer.try_send(metrics.clone()) {
+                warn!("{coin}: observer queue full, dropping metrics row");
+            }
+        }
 
-        // Calculate spread
-        let spread = best_ask - best_bid;
-        let spread_pct = (spread / mid_price) * Decimal::from(100);
+        let failures = self.sinks.lock().map_err(|_| "Sink lock poisoned")?.dispatch(&metrics);
+        for (sink, e) in failures {
+            error!("{coin}: Failed to write {sink} sink: {e}");
+        }
 
-        // Calculate depth at various levels (convert Px/Sz to Decimal via to_str())
-        let bid_levels = bids
-            .iter()
-            .filter_map(|order| {
-                Some((
-                    Decimal::from_str(&order.limit_px.to_str()).ok()?,
-                    Decimal::from_str(&order.sz.to_str()).ok()?,
-                ))
-            })
-            .collect::<Vec<_>>();
+        self.exporter_metrics.record_row(&metrics);
+        self.latest_metrics
+            .lock()
+            .map_err(|_| "Latest metrics lock poisoned")?
+            .insert(coin.to_string(), metrics.clone());
 
-        let ask_levels = asks
-            .iter()
-            .filter_map(|order| {
-                Some((
-                    Decimal::from_str(&order.limit_px.to_str()).ok()?,
-                    Decimal::from_str(&order.sz.to_str()).ok()?,
-                ))
-            })
-            .collect::<Vec<_>>();
+        if let Some(mid) = metrics.mid_price {
+            state.bad_print.record(mid);
+        }
+        info!("📊 {}: ${} - metrics collected", coin, metrics.mark_price.unwrap_or_default());
+        let timestamp = metrics.timestamp;
+        self.pending_inserts.lock().map_err(|_| "Pending inserts lock poisoned")?.push(metrics);
 
-        let depths = calculate_liquidity_depth(&bid_levels, &ask_levels, mid_price);
+        Ok(Some(timestamp))
+    }
 
-        Some(OrderBookMetrics {
-            best_bid,
-            best_ask,
-            mid_price,
-            spread,
-            spread_pct,
-            total_bids: bids.len(),
-            total_asks: asks.len(),
-            bid_depth_5pct: depths.0,
-            ask_depth_5pct: depths.1,
-            total_depth_5pct: depths.0 + depths.1,
-            bid_depth_10pct: depths.2,
-            ask_depth_10pct: depths.3,
-            total_depth_10pct: depths.2 + depths.3,
-            bid_depth_25pct: depths.4,
-            ask_depth_25pct: depths.5,
-            total_depth_25pct: depths.4 + depths.5,
-        })
+    /// Complete `timing` with the collection total, logging it and keeping
+    /// it on the row when `store_timing_breakdown` is set
+    fn record_timing(&self, metrics: &mut MarketMetrics, mut timing: TimingBreakdown, started: Instant) {
+        timing.total_ms = elapsed_ms(started);
+        debug!("{}: collection timing {timing:?}", metrics.coin);
+        if self.config.store_timing_breakdown {
+            metrics.timing_breakdown = Some(timing);
+        }
     }
-}
 
-/// Calculate liquidity depth at 5%, 10%, and 25% levels
-fn calculate_liquidity_depth(
-    bids: &[(Decimal, Decimal)],
-    asks: &[(Decimal, Decimal)],
-    mid_price: Decimal,
-) -> (Decimal, Decimal, Decimal, Decimal, Decimal, Decimal) {
-    let percentages = [
-        Decimal::from_str("0.05").unwrap(),
-        Decimal::from_str("0.10").unwrap(),
-        Decimal::from_str("0.25").unwrap(),
-    ];
+    /// Set `suspected_bad_print` when the mid jumped further than `max_tick_move_pct`
+    /// from the last stored mid, returning the policy for handling the row. A
+    /// jump that persists is accepted as the new baseline, see [`BadPrintGuard::check`].
+    fn check_bad_print(&self, metrics: &mut MarketMetrics, guard: &mut BadPrintGuard) -> Option<BadPrintPolicy> {
+        let mid = metrics.mid_price?;
+        if !guard.check(mid) {
+            return None;
+        }
+        metrics.suspected_bad_print = true;
+        let policy = self.config.bad_print_policy;
+        let action = if policy == BadPrintPolicy::Skip { "skipping row" } else { "storing flagged" };
+        let max_move = self.config.max_tick_move_pct.unwrap_or_default();
+        warn!("{}: mid {mid} moved more than {max_move} in one tick, suspected bad print, {action}", metrics.coin);
+        Some(policy)
+    }
 
-    let mut results = vec![];
+    /// Insert every row collected since the last flush in one batch, retrying
+    /// only the coins whose rows were rejected within the tick's budget
+    async fn flush_pending_inserts(&self) {
+        let Ok(rows) = self.pending_inserts.lock().map(|mut pending| std::mem::take(&mut *pending)) else {
+            error!("Pending inserts lock poisoned, skipping flush");
+            return;
+        };
+        if rows.is_empty() {
+            return;
+        }
+        let Some(database) = self.database() else {
+            Self::print_rows(&rows);
+            return;
+        };
+        let Some(rows) = self.admit_inserts(rows) else { return };
+        let row_count = rows.len();
+        let coins = rows.iter().map(|row| row.coin.clone()).collect::<HashSet<_>>();
 
-    for pct in &percentages {
-        let bid_threshold = mid_price * (Decimal::ONE - pct);
-        let ask_threshold = mid_price * (Decimal::ONE + pct);
+        let remaining = StdMutex::new(rows);
+        let started = Instant::now();
+        let db = database.lock().await;
+        let (max_retries, backoff) = (self.config.insert_max_retries, self.config.insert_retry_backoff());
+        let (result, retries) = retry_with_budget(max_retries, backoff, || async {
+            let rows = remaining.lock().map(|rows| rows.clone()).unwrap_or_default();
+            let result = db.insert_metrics_batch(&rows).await;
+            if let (Err(e), Ok(mut rows)) = (&result, remaining.lock()) {
+                let failed = e.failed_coins();
+                rows.retain(|row| failed.contains(row.coin.as_str()));
+            }
+            result
+        })
+        .await;
+        drop(db);
+        debug!("Metrics batch insert of {row_count} rows took {:.3}ms", elapsed_ms(started));
 
-        let bid_depth: Decimal = bids
-            .iter()
-            .filter(|(price, _)| *price >= bid_threshold)
-            .map(|(price, size)| price * size)
-            .sum();
+        let failures = result.as_ref().err().map(|e| e.failures.iter().cloned().collect::<HashMap<_, _>>());
+        for coin in coins {
+            let outcome = failures.as_ref().and_then(|failures| failures.get(&coin)).map_or(Ok(()), |e| Err(e.clone()));
+            if let Err(error) = &outcome {
+                self.emit(MonitorEvent::InsertFailed { coin: coin.clone(), error: error.clone() });
+            }
+            self.record_market_health(&coin, outcome);
+        }
 
-        let ask_depth: Decimal = asks
-            .iter()
-            .filter(|(price, _)| *price <= ask_threshold)
-            .map(|(price, size)| price * size)
-            .sum();
+        let failed = if result.is_ok() { 0 } else { remaining.lock().map_or(row_count, |rows| rows.len()) };
+        self.exporter_metrics.record_inserts(row_count - failed, failed);
+        self.record_insert_outcome(failed < row_count);
+        if retries > 0 {
+            self.insert_retries.fetch_add(u64::from(retries), Ordering::Relaxed);
+            warn!("Metrics batch insert needed {retries} retries");
+        }
+        match result {
+            Ok(()) => info!("📊 Inserted {row_count} metrics rows ✅"),
+            Err(BatchInsertError { timeout: Some(timeout), .. }) => {
+                warn!("Metrics insert abandoned, {timeout}; dropped {failed} rows, collection continues");
+            }
+            Err(e) => error!("Failed to insert metrics: {e}"),
+        }
+    }
+
+    /// Print `rows` as pretty JSON in place of inserting them, for a dry run.
+    /// Nothing is written, so insert health and counters are left alone.
+    fn print_rows(rows: &[MarketMetrics]) {
+        for row in rows {
+            match serde_json::to_string_pretty(row) {
+                Ok(json) => println!("{json}"),
+                Err(e) => error!("{}: Failed to serialize metrics row: {e}", row.coin),
+            }
+        }
+    }
+
+    /// The rows to insert if the insert breaker lets this flush through. While
+    /// it is open, the latest row per market is kept for when the database recovers.
+    fn admit_inserts(&self, rows: Vec<MarketMetrics>) -> Option<Vec<MarketMetrics>> {
+        let Ok(mut breaker) = self.insert_breaker.lock() else { return Some(rows) };
+        let previous = breaker.state();
+        if breaker.allow(Instant::now()) {
+            drop(breaker);
+            if previous == BreakerState::Open {
+                info!("Probing the database with {} rows after pausing inserts", rows.len());
+                self.emit(MonitorEvent::InsertBreaker { state: BreakerState::HalfOpen });
+            }
+            return Some(rows);
+        }
+        drop(breaker);
+        if self.config.db_breaker_flush_on_recovery
+            && let Ok(mut paused) = self.paused_rows.lock()
+        {
+            for row in rows {
+                paused.insert(row.coin.clone(), row);
+            }
+        }
+        None
+    }
+
+    /// Feed a flush's outcome to the insert breaker, logging when it opens or
+    /// closes and queueing the rows kept while paused once it closes
+    fn record_insert_outcome(&self, success: bool) {
+        let now = Instant::now();
+        let transition = self.insert_breaker.lock().ok().and_then(|mut breaker| breaker.record(success, now));
+        let Some(state) = transition else { return };
+        match state {
+            BreakerState::Open => {
+                let cooldown = self.config.db_breaker_cooldown();
+                warn!("Database inserts keep failing, pausing inserts for {cooldown:?} while collection continues");
+            }
+            BreakerState::Closed => {
+                let paused = self.paused_rows.lock().map(|mut paused| std::mem::take(&mut *paused)).unwrap_or_default();
+                info!("Database inserts recovered, queueing {} rows collected while paused", paused.len());
+                if let Ok(mut pending) = self.pending_inserts.lock() {
+                    pending.extend(paused.into_values());
+                }
+            }
+            BreakerState::HalfOpen => {}
+        }
+        self.emit(MonitorEvent::InsertBreaker { state });
+    }
+
+    /// Note a market's rows were inserted now, or the error that stopped them
+    fn record_market_health(&self, coin: &str, outcome: Result<(), String>) {
+        let Ok(mut market_health) = self.market_health.lock() else {
+            error!("Market health lock poisoned, dropping {coin} status");
+            return;
+        };
+        let health = market_health.entry(coin.to_string()).or_default();
+        match outcome {
+            Ok(()) => health.last_insert = Some(Utc::now()),
+            Err(e) => health.last_error = Some(e),
+        }
+    }
+
+    /// Route an alert to its severity's sinks unless one of the same kind
+    /// fired for the coin within the cooldown
+    fn fire_alert(&self, alert: Alert) {
+        if self.alert_cooldowns.lock().map_or(true, |mut cooldowns| cooldowns.try_fire(&alert))
+            && self.alert_router.route(&alert).is_empty()
+        {
+            error!("No sink delivered alert: {alert}");
+        }
+    }
+
+    /// Cached Hyperliquid market data from the feed `source` keeps up to date
+    async fn get_market_data(
+        &self,
+        coin: &str,
+        source: DataSource,
+        timing: &mut TimingBreakdown,
+    ) -> Option<HyperliquidMarketData> {
+        let started = Instant::now();
+        let data = match source {
+            DataSource::Websocket => self.hyperliquid_client.get_streamed_market_data(coin).await,
+            DataSource::Poll | DataSource::L2Rest => self.hyperliquid_client.get_market_data(coin).await,
+        };
+        timing.hl_lookup_ms = elapsed_ms(started);
+        if data.is_none() {
+            self.emit(MonitorEvent::HyperliquidStale { coin: coin.to_string() });
+        }
+        data
+    }
+
+    /// Store the prices of every provider with fresh data for the coin, fetched
+    /// concurrently. When Hyperliquid and another exchange both have fresh data,
+    /// also compare their marks and funding.
+    async fn merge_provider_prices(&self, metrics: &mut MarketMetrics) {
+        let requests = self.providers.iter().map(|provider| provider.market_data(&metrics.coin));
+        let prices: Vec<ProviderMarketData> =
+            futures_util::future::join_all(requests).await.into_iter().flatten().collect();
+
+        let hyperliquid = prices.iter().find(|p| p.source == HYPERLIQUID_SOURCE);
+        let other = prices.iter().find(|p| p.source != HYPERLIQUID_SOURCE);
+        if let (Some(hyperliquid), Some(other)) = (hyperliquid, other) {
+            metrics.basis_pct = analytics::cross_exchange_basis_pct(hyperliquid.mark_price, other.mark_price);
+            if let (Some(funding), Some(other_funding)) = (hyperliquid.funding_rate_pct, other.funding_rate_pct) {
+                metrics.funding_differential = Some(funding - other_funding);
+            }
+        }
+        metrics.provider_prices = prices;
+    }
+
+    /// Orderbook metrics from the node listener, or the `l2Book` endpoint for L2 REST coins,
+    /// timing the snapshot and the computation on it separately
+    async fn get_orderbook_metrics(
+        &self,
+        coin: &str,
+        source: DataSource,
+        timing: &mut TimingBreakdown,
+    ) -> Option<OrderBookMetrics> {
+        let started = Instant::now();
+        let book = match source {
+            DataSource::L2Rest => self.get_l2_rest_book(coin).await,
+            DataSource::Poll | DataSource::Websocket => self.get_node_book(coin).await,
+        };
+        timing.book_snapshot_ms = elapsed_ms(started);
+
+        let started = Instant::now();
+        let metrics = book.and_then(|book| {
+            let mut metrics =
+                self.orderbook_metrics(coin, &book.bids, &book.asks, book.update_count, book.snapshot_age_ms)?;
+            metrics.depth_truncated = book.truncated_within(metrics.mid_price, self.config.widest_depth_band(coin));
+            Some(metrics)
+        });
+        timing.depth_computation_ms = elapsed_ms(started);
+        if metrics.is_none() {
+            self.emit(MonitorEvent::OrderBookUnavailable { coin: coin.to_string() });
+        }
+        metrics
+    }
+
+    /// Snapshot fetched from the `l2Book` endpoint
+    async fn get_l2_rest_book(&self, coin: &str) -> Option<BookLevels> {
+        let book = match self.hyperliquid_client.fetch_l2_book(coin).await {
+            Ok(book) => book,
+            Err(e) => {
+                error!("{coin}: Failed to fetch L2 book: {e}");
+                return None;
+            }
+        };
+        let snapshot_age_ms = snapshot_age_ms(book.time);
+        let limit = self.config.book_level_limit().unwrap_or(usize::MAX);
+        let ((bids, bid_cutoff), (asks, ask_cutoff)) = (top_levels(book.bids, limit), top_levels(book.asks, limit));
+        Some(BookLevels { bids, asks, cutoffs: [bid_cutoff, ask_cutoff], update_count: None, snapshot_age_ms })
+    }
+
+    /// Extract the coin's levels from the listener's snapshot
+    async fn get_node_book(&self, coin: &str) -> Option<BookLevels> {
+        let mut listener = self.orderbook_listener.lock().await;
+
+        // Get snapshot from listener
+        let snapshot = listener.compute_snapshot()?;
+        // Snapshot time is the block time of the last applied update
+        let snapshot_age_ms = snapshot_age_ms(snapshot.time);
+        let coin_obj = Coin::new(coin);
+        let update_count = listener.update_count(&coin_obj);
+
+        // Find the snapshot for this coin and store the value to extend its lifetime
+        let snapshot_value = snapshot.snapshot.value();
+        let (_, snapshot_data) = snapshot_value
+            .iter()
+            .find(|(c, _)| **c == coin_obj)?;
+
+        // Convert orders to Decimal via to_str() only until the best `limit`
+        // price levels are complete, noting the price of the first level left out
+        let limit = self.config.book_level_limit().unwrap_or(usize::MAX);
+        let [(bids, bid_cutoff), (asks, ask_cutoff)] = snapshot_data.as_ref().each_ref().map(|orders| {
+            let orders = orders.iter().filter_map(|order| {
+                Some((Decimal::from_str(&order.limit_px.to_str()).ok()?, Decimal::from_str(&order.sz.to_str()).ok()?))
+            });
+            top_levels(orders, limit)
+        });
+        drop(listener);
+
+        let cutoffs = [bid_cutoff, ask_cutoff];
+        Some(BookLevels { bids, asks, cutoffs, update_count: Some(update_count), snapshot_age_ms })
+    }
+
+    /// Compute orderbook metrics from `(price, size)` levels, best first
+    fn orderbook_metrics(
+        &self,
+        coin: &str,
+        bid_levels: &[(Decimal, Decimal)],
+        ask_levels: &[(Decimal, Decimal)],
+        update_count: Option<u64>,
+        snapshot_age_ms: Option<i32>,
+    ) -> Option<OrderBookMetrics> {
+        // Calculate best prices
+        let best_bid = bid_levels.first()?.0;
+        let best_ask = ask_levels.first()?.0;
+        let mid_price = (best_bid + best_ask) / Decimal::from(2);
+        if mid_price <= Decimal::ZERO {
+            warn!("{coin}: Skipping order book metrics, non-positive mid {mid_price} (bid {best_bid}, ask {best_ask})");
+            return None;
+        }
+        let book_crossed = best_bid >= best_ask;
+        if book_crossed {
+            warn!("{coin}: Crossed book, best bid {best_bid} >= best ask {best_ask}; flagging row");
+        }
+
+        // Calculate spread
+        let spread = best_ask - best_bid;
+        let spread_pct = (spread / mid_price) * Decimal::from(100);
 
-        results.push((bid_depth, ask_depth));
+        let top_size = |levels: &[(Decimal, Decimal)], best: Decimal| {
+            levels.iter().take_while(|(price, _)| *price == best).map(|(_, size)| size).sum()
+        };
+        let best_bid_size = top_size(bid_levels, best_bid);
+        let best_ask_size = top_size(ask_levels, best_ask);
+
+        // Calculate depth at various levels
+        let depth_levels =
+            calculate_liquidity_depth(bid_levels, ask_levels, mid_price, self.config.depth_levels_for(coin));
+        let spread_by_size =
+            OrderBookMetrics::spread_by_size(bid_levels, ask_levels, mid_price, &self.config.spread_sizes);
+        // The fixed 5/10/25% columns reuse configured levels and fill in the rest
+        let band = |pct: Decimal| {
+            depth_levels.iter().find(|(level, _)| *level == pct).map_or_else(
+                || calculate_liquidity_depth(bid_levels, ask_levels, mid_price, &[pct])[0].1,
+                |(_, depth)| *depth,
+            )
+        };
+        let [near, mid, far] = [5, 10, 25].map(|pct| band(Decimal::new(pct, 2)));
+        let (bid_qty_5pct, ask_qty_5pct) = quantity_within_band(bid_levels, ask_levels, mid_price, Decimal::new(5, 2));
+        let (size_multiple, min_distance_pct) =
+            (self.config.spoofing_size_multiple, self.config.spoofing_min_distance_pct);
+
+        Some(OrderBookMetrics {
+            best_bid,
+            best_ask,
+            mid_price,
+            spread,
+            spread_pct,
+            book_crossed,
+            size_weighted_spread: analytics::size_weighted_spread(bid_levels, ask_levels),
+            order_book_imbalance: analytics::depth_imbalance(near.0, near.1),
+            microprice: analytics::microprice(best_bid, best_ask, best_bid_size, best_ask_size),
+            best_bid_size,
+            best_ask_size,
+            total_bids: bid_levels.len(),
+            total_asks: ask_levels.len(),
+            update_count,
+            bid_depth_5pct: near.0,
+            ask_depth_5pct: near.1,
+            total_depth_5pct: near.0 + near.1,
+            bid_qty_5pct,
+            ask_qty_5pct,
+            bid_depth_10pct: mid.0,
+            ask_depth_10pct: mid.1,
+            total_depth_10pct: mid.0 + mid.1,
+            bid_depth_25pct: far.0,
+            ask_depth_25pct: far.1,
+            total_depth_25pct: far.0 + far.1,
+            // Set by the caller, which knows which levels were cut off
+            depth_truncated: false,
+            bid_depth_elasticity: analytics::depth_elasticity(bid_levels, mid_price),
+            ask_depth_elasticity: analytics::depth_elasticity(ask_levels, mid_price),
+            depth_levels,
+            spread_by_size,
+            slippage_curve: slippage_curve(bid_levels, ask_levels, mid_price, &self.config.spread_sizes),
+            snapshot_age_ms,
+            large_bids: analytics::large_far_levels(bid_levels, mid_price, size_multiple, min_distance_pct),
+            large_asks: analytics::large_far_levels(ask_levels, mid_price, size_multiple, min_distance_pct),
+            bid_prices: bid_levels.iter().map(|(price, _)| *price).collect(),
+            ask_prices: ask_levels.iter().map(|(price, _)| *price).collect(),
+        })
     }
+}
 
-    (
-        results[0].0,
-        results[0].1,
-        results[1].0,
-        results[1].1,
-        results[2].0,
-        results[2].1,
+/// Connect to the configured database and create the tables of every target
+/// market, the portfolio and any baskets
+async fn connect_database(config: &MetricsConfig) -> Result<MetricsDatabase, Box<dyn std::error::Error>> {
+    let mut database = MetricsDatabase::connect_with_tls(
+        &config.database_url,
+        config.max_db_connections,
+        &config.db_application_name,
+        config.db_sslmode,
+        config.db_ca_cert_path.as_deref().map(Path::new),
+        config.database_timeouts(),
     )
+    .await?
+    .with_empty_bucket_policy(config.ohlc_empty_buckets)
+    .with_tiered_resolution(config.tiered_recent_window(), config.tiered_history_bucket())
+    .with_conflict_policy(config.insert_on_conflict)
+    .with_partition_interval(config.partition_interval)
+    .with_tenant(config.tenant.as_deref(), config.tenant_schema)?
+    .with_table_prefix(&config.table_prefix)?
+    .with_table_name_template(&config.table_name_template)?
+    .with_unbounded_numeric(&config.unbounded_numeric_fields)?
+    .with_schema_snapshot_dir(config.schema_snapshot_dir.as_deref().map(Path::new));
+
+    // Ensure tables exist for all target markets
+    for market in &config.target_markets {
+        database.ensure_market_table(market).await?;
+    }
+    database.ensure_portfolio_table().await?;
+    if !config.baskets.is_empty() {
+        database.ensure_basket_table().await?;
+    }
+    Ok(database)
+}
+
+/// Hyperliquid API client with the configured polling, retries and caching,
+/// observing request latency in `exporter_metrics`
+fn hyperliquid_client(
+    config: &MetricsConfig,
+    exporter_metrics: Arc<ExporterMetrics>,
+) -> Result<HyperliquidClient, Box<dyn std::error::Error>> {
+    let mut client = HyperliquidClient::new(config.hyperliquid_api_url.clone(), config.poll_interval())
+        .with_coin_poll_intervals(config.coin_poll_intervals())
+        .with_retries(config.api_max_retries, config.api_retry_backoff())
+        .with_timeout(config.api_timeout())
+        .with_latency_metrics(exporter_metrics);
+    if let Some(ttl) = config.dns_cache_ttl() {
+        client = client.with_dns_cache_ttl(ttl)?;
+    }
+    if let Some(max_staleness) = config.max_market_data_staleness() {
+        client = client.with_max_staleness(max_staleness);
+    }
+    Ok(client)
+}
+
+/// Keep Hyperliquid data fresh from the websocket feed for streamed coins, and
+/// by polling for the rest. Returns the websocket subscriber if any market may be streamed.
+fn start_market_data(
+    config: &MetricsConfig,
+    hyperliquid_client: &Arc<HyperliquidClient>,
+    shutdown: &CancellationToken,
+) -> Result<Option<mpsc::UnboundedSender<String>>, Box<dyn std::error::Error>> {
+    let (streamed, polled): (Vec<_>, Vec<_>) =
+        config.target_markets.iter().cloned().partition(|market| config.data_source(market) == DataSource::Websocket);
+    // Markets added later may be streamed too, even when no target market is
+    let may_stream = !streamed.is_empty()
+        || config.use_websocket
+        || config.data_sources.values().any(|source| *source == DataSource::Websocket);
+    let ws_subscriber = if may_stream {
+        let cache = hyperliquid_client.stream_cache();
+        let ws_client = HyperliquidWsClient::new(&config.hyperliquid_ws_url, streamed, cache)?;
+        let subscriber = ws_client.subscriber();
+        ws_client.start(shutdown.clone());
+        Some(subscriber)
+    } else {
+        None
+    };
+    if !polled.is_empty() || config.discovery_interval().is_some() {
+        hyperliquid_client.clone().start_polling(shutdown.clone());
+    }
+    Ok(ws_subscriber)
+}
+
+/// Fetch every listed market and create the tables of those not excluded,
+/// failing if `exclusions` leave nothing to monitor
+async fn ensure_universe_tables(
+    config: &MetricsConfig,
+    hyperliquid_client: &HyperliquidClient,
+    exclusions: &MarketExclusions,
+    store: &MetricsStore,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Err(e) = hyperliquid_client.fetch_and_cache_all_markets().await {
+        warn!("Failed to fetch the market universe, markets start once a poll succeeds: {e}");
+        return Ok(());
+    }
+    let listed = hyperliquid_client.all_market_data().await;
+    let universe = listed.iter().filter(|data| !exclusions.excludes(&data.coin)).collect::<Vec<_>>();
+    if universe.is_empty() && config.target_markets.is_empty() {
+        return Err(ConfigError::AllMarketsExcluded.into());
+    }
+    info!("  - Monitoring {} of {} listed markets", universe.len(), listed.len());
+    if let MetricsStore::Postgres { database, .. } = store {
+        for data in universe {
+            database.lock().await.ensure_market_table(&data.coin).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The configured file sinks
+fn sinks(config: &MetricsConfig) -> Result<SinkDispatcher, Box<dyn std::error::Error>> {
+    let mut sinks = SinkDispatcher::default();
+    if let Some(path) = &config.csv_tail_path {
+        sinks.add(Box::new(CsvTailWriter::new(path, config.csv_tail_max_bytes).with_format(config.csv_tail_format)))?;
+    }
+    if let Some(path) = &config.jsonl_archive_path {
+        sinks.add(Box::new(
+            JsonlArchiveWriter::new(path, config.jsonl_archive_max_bytes)
+                .with_format(config.jsonl_archive_format)
+                .with_compression(config.jsonl_archive_compress),
+        ))?;
+    }
+    Ok(sinks)
+}
+
+/// Router sending alerts to the configured webhooks by severity
+fn alert_router(config: &MetricsConfig) -> Result<AlertRouter, Box<dyn std::error::Error>> {
+    let mut alert_router = AlertRouter::new(config.alert_routes.clone());
+    for (name, url) in &config.alert_webhooks {
+        alert_router.add_sink(name, Arc::new(WebhookSink::new(url.clone())));
+    }
+    alert_router.validate()?;
+    Ok(alert_router)
+}
+
+/// Log the settings the monitor starts with, `excluded` being the target
+/// markets `exclusions` removed
+fn log_settings(config: &MetricsConfig, exclusions: &MarketExclusions, excluded: &[String]) {
+    info!(" Market metrics monitor initialized");
+    info!("  - Target markets: {:?}", config.target_markets);
+    if !exclusions.is_empty() {
+        info!(
+            "  - Excluded markets: {:?}, patterns {:?}, removing {excluded:?} from target markets",
+            config.excluded_markets, config.excluded_market_patterns
+        );
+    }
+    info!("  - Monitoring interval: {:?}", config.monitoring_interval());
+    for market in &config.target_markets {
+        let interval = config.monitoring_interval_for(market);
+        if interval != config.monitoring_interval() {
+            info!("  - Monitoring interval for {market}: {interval:?}");
+        }
+    }
+    info!("  - Poll interval: {:?}", config.poll_interval());
+    info!(
+        "  - API timeout: {:?}, up to {} retries backing off from {:?}",
+        config.api_timeout(),
+        config.api_max_retries,
+        config.api_retry_backoff()
+    );
+    if let Some(tenant) = &config.tenant {
+        info!("  - Tenant: {tenant}");
+    }
+    if let Some(path) = &config.csv_tail_path {
+        info!("  - CSV tail file: {path}");
+    }
+    if let Some(path) = &config.jsonl_archive_path {
+        info!("  - JSONL archive: {path}");
+    }
+    if let Some(path) = &config.alert_state_path {
+        info!("  - Alert state file: {path}");
+    }
+    if !config.coin_poll_intervals_secs.is_empty() {
+        info!("  - Fast poll intervals: {:?}", config.coin_poll_intervals());
+    }
+    if !config.data_sources.is_empty() {
+        info!("  - Data source overrides: {:?}", config.data_sources);
+    }
+    if let Some(url) = &config.binance_api_url {
+        info!("  - Binance cross-check: {url}");
+    }
+}
+
+impl HealthCheck for MarketMetricsMonitor {
+    /// Healthy when the database answers, market data is fresher than the max
+    /// staleness and every pinned market had a row inserted within
+    /// `health_interval_multiple` of its monitoring interval
+    fn check(&self) -> HealthFuture<'_> {
+        Box::pin(async move {
+            // A dry run has no database to be unreachable
+            let database_reachable = match self.unlocked_database() {
+                // A prune or insert retry holding the database lock doesn't make it unreachable
+                Some(database) => {
+                    tokio::time::timeout(HEALTH_DATABASE_TIMEOUT, database.ping()).await.is_ok_and(|ping| ping.is_ok())
+                }
+                None => true,
+            };
+
+            let multiple = self.config.health_interval_multiple;
+            let max_market_data_age =
+                self.config.max_market_data_staleness().unwrap_or_else(|| self.config.poll_interval() * multiple);
+            let market_data_age = self.hyperliquid_client.last_update_age().await;
+
+            let now = Utc::now();
+            // A dry run inserts nothing, leaving only the market data age to judge freshness by
+            let dry_run = self.database().is_none();
+            let pinned = self.pinned_markets.lock().map(|pinned| pinned.clone()).unwrap_or_default();
+            let recorded = self.market_health.lock().map(|health| health.clone()).unwrap_or_default();
+            let markets = pinned
+                .into_iter()
+                .map(|coin| {
+                    // Rows are inserted by the flush, which runs every global monitoring interval
+                    let interval = self.config.monitoring_interval_for(&coin).max(self.config.monitoring_interval());
+                    let max_age = chrono::Duration::from_std(interval * multiple).unwrap_or(chrono::Duration::MAX);
+                    let mut health = recorded.get(&coin).cloned().unwrap_or_default();
+                    health.fresh = dry_run || health.last_insert.is_some_and(|at| now - at <= max_age);
+                    (coin, health)
+                })
+                .collect();
+
+            HealthReport::new(database_reachable, market_data_age, max_market_data_age, markets)
+        })
+    }
+}
+
+/// `(price, size)` levels of one coin's book, best first
+struct BookLevels {
+    bids: Vec<(Decimal, Decimal)>,
+    asks: Vec<(Decimal, Decimal)>,
+    /// Best bid and ask price levels left out by `max_book_levels`
+    cutoffs: [Option<Decimal>; 2],
+    update_count: Option<u64>,
+    snapshot_age_ms: Option<i32>,
+}
+
+impl BookLevels {
+    /// Whether a level left out by `max_book_levels` lies within `pct` of `mid_price`
+    fn truncated_within(&self, mid_price: Decimal, pct: Decimal) -> bool {
+        let [bid, ask] = self.cutoffs;
+        bid.is_some_and(|price| price >= mid_price * (Decimal::ONE - pct))
+            || ask.is_some_and(|price| price <= mid_price * (Decimal::ONE + pct))
+    }
+}
+
+/// The best `limit` price levels of `orders`, best first, merging orders at
+/// the same price, with the price of the first level left out
+fn top_levels(
+    orders: impl IntoIterator<Item = (Decimal, Decimal)>,
+    limit: usize,
+) -> (Vec<(Decimal, Decimal)>, Option<Decimal>) {
+    let mut levels: Vec<(Decimal, Decimal)> = Vec::new();
+    for (price, size) in orders {
+        if let Some((last, total)) = levels.last_mut()
+            && *last == price
+        {
+            *total += size;
+        } else if levels.len() == limit {
+            return (levels, Some(price));
+        } else {
+            levels.push((price, size));
+        }
+    }
+    (levels, None)
+}
+
+/// Last modification time of `path`, `None` if it can't be read
+fn modified_at(path: &str) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Wait for the next SIGHUP, forever if it isn't being listened for
+#[cfg(unix)]
+async fn hangup_received(hangup: &mut Option<tokio::signal::unix::Signal>) {
+    if let Some(signal) = hangup
+        && signal.recv().await.is_some()
+    {
+        return;
+    }
+    std::future::pending::<()>().await;
+}
+
+#[cfg(not(unix))]
+async fn hangup_received(_hangup: &mut Option<()>) {
+    std::future::pending::<()>().await;
+}
+
+/// A price-only row with a candle's close as the mid, stamped when that price
+/// was current: the close time, or now for a candle that is still open
+fn candle_metrics(coin: &str, candle: &Candle) -> Option<MarketMetrics> {
+    let mut metrics = MarketMetrics::new(coin.to_string());
+    metrics.timestamp = DateTime::from_timestamp_millis(candle.close_time)?.min(Utc::now());
+    metrics.mid_price = Some(candle.close);
+    metrics.source = Some(HYPERLIQUID_CANDLES_SOURCE.to_string());
+    Some(metrics)
+}
+
+/// `duration` before `time`, clamped to the earliest representable time
+/// rather than overflowing for a retention of millions of days
+fn time_before(time: DateTime<Utc>, duration: Duration) -> DateTime<Utc> {
+    chrono::Duration::from_std(duration)
+        .ok()
+        .and_then(|duration| time.checked_sub_signed(duration))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+/// Fractional milliseconds since `started`
+fn elapsed_ms(started: Instant) -> f64 {
+    started.elapsed().as_secs_f64() * 1000.0
+}
+
+/// Milliseconds since a snapshot taken at `time` epoch millis, saturating at `i32::MAX`
+fn snapshot_age_ms(time: u64) -> Option<i32> {
+    i64::try_from(time)
+        .ok()
+        .map(|time| i32::try_from((Utc::now().timestamp_millis() - time).max(0)).unwrap_or(i32::MAX))
+}
+
+/// Calculate `(bid, ask)` liquidity depth within each of `levels` (fractions of mid)
+fn calculate_liquidity_depth(
+    bids: &[(Decimal, Decimal)],
+    asks: &[(Decimal, Decimal)],
+    mid_price: Decimal,
+    levels: &[Decimal],
+) -> Vec<(Decimal, (Decimal, Decimal))> {
+    if mid_price <= Decimal::ZERO {
+        warn!("Invalid mid price {mid_price} for depth calculation, reporting zero depth");
+        return levels.iter().map(|level| (*level, Default::default())).collect();
+    }
+
+    levels.iter().map(|level| (*level, depth_within_band(bids, asks, mid_price, *level))).collect()
+}
+
+/// Notional (bid, ask) depth within `pct` of a positive `mid_price`
+fn depth_within_band(
+    bids: &[(Decimal, Decimal)],
+    asks: &[(Decimal, Decimal)],
+    mid_price: Decimal,
+    pct: Decimal,
+) -> (Decimal, Decimal) {
+    sum_within_band(bids, asks, mid_price, pct, |price, size| price * size)
+}
+
+/// Base-asset (bid, ask) quantity within `pct` of `mid_price`, zero when the mid isn't positive
+fn quantity_within_band(
+    bids: &[(Decimal, Decimal)],
+    asks: &[(Decimal, Decimal)],
+    mid_price: Decimal,
+    pct: Decimal,
+) -> (Decimal, Decimal) {
+    if mid_price <= Decimal::ZERO {
+        return Default::default();
+    }
+    sum_within_band(bids, asks, mid_price, pct, |_, size| size)
+}
+
+/// Sum `amount(price, size)` over the levels on each side within `pct` of `mid_price`
+fn sum_within_band(
+    bids: &[(Decimal, Decimal)],
+    asks: &[(Decimal, Decimal)],
+    mid_price: Decimal,
+    pct: Decimal,
+    amount: fn(Decimal, Decimal) -> Decimal,
+) -> (Decimal, Decimal) {
+    // A band wider than 100% would put the bid threshold below zero
+    let bid_threshold = (mid_price * (Decimal::ONE - pct)).max(Decimal::ZERO);
+    let ask_threshold = mid_price * (Decimal::ONE + pct);
+
+    let bid_sum: Decimal =
+        bids.iter().filter(|(price, _)| *price >= bid_threshold).map(|(price, size)| amount(*price, *size)).sum();
+
+    let ask_sum: Decimal =
+        asks.iter().filter(|(price, _)| *price <= ask_threshold).map(|(price, size)| amount(*price, *size)).sum();
+
+    (bid_sum, ask_sum)
+}
+
+/// Market buy and sell fills of each notional in `sizes`, walking `asks` and
+/// `bids` (`(price, size)`, best first) away from `mid_price`
+fn slippage_curve(
+    bids: &[(Decimal, Decimal)],
+    asks: &[(Decimal, Decimal)],
+    mid_price: Decimal,
+    sizes: &[Decimal],
+) -> Vec<SlippagePoint> {
+    sizes
+        .iter()
+        .map(|&size| SlippagePoint {
+            size,
+            buy: walk_book(asks, mid_price, size),
+            sell: walk_book(bids, mid_price, size),
+        })
+        .collect()
+}
+
+/// Fill `notional` against `levels` until it is filled or the levels run out
+fn walk_book(levels: &[(Decimal, Decimal)], mid_price: Decimal, notional: Decimal) -> FillEstimate {
+    let (filled, quantity) = fill_against(levels, notional);
+    let avg_price = (quantity > Decimal::ZERO).then(|| filled / quantity);
+    let slippage_pct = avg_price
+        .filter(|_| mid_price > Decimal::ZERO)
+        .map(|avg| (avg - mid_price).abs() / mid_price * Decimal::ONE_HUNDRED);
+    FillEstimate { avg_price, slippage_pct, filled_notional: filled, partial: filled < notional }
+}
+
+/// Coins whose 24h volume and open interest notional meet the discovery thresholds
+fn discover_markets(
+    market_data: &[HyperliquidMarketData],
+    min_volume: Decimal,
+    min_open_interest: Decimal,
+) -> HashSet<String> {
+    market_data
+        .iter()
+        .filter(|data| data.volume_24h >= min_volume && data.open_interest >= min_open_interest)
+        .map(|data| data.coin.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market_metrics::test_util::temp_dir;
+
+    fn levels(levels: &[(&str, &str)]) -> Vec<(Decimal, Decimal)> {
+        levels.iter().map(|(px, sz)| (Decimal::from_str(px).unwrap(), Decimal::from_str(sz).unwrap())).collect()
+    }
+
+    #[test]
+    fn test_depth_band_wider_than_mid_is_clamped() {
+        let bids = levels(&[("0.0002", "10"), ("0.0001", "10"), ("-1", "10")]);
+        let asks = levels(&[("0.0003", "10")]);
+
+        let (bid_depth, ask_depth) =
+            depth_within_band(&bids, &asks, Decimal::from_str("0.00025").unwrap(), Decimal::from_str("1.5").unwrap());
+
+        // Threshold clamps to zero, so the nonsensical negative level is excluded
+        assert_eq!(bid_depth, Decimal::from_str("0.003").unwrap());
+        assert_eq!(ask_depth, Decimal::from_str("0.003").unwrap());
+    }
+
+    #[test]
+    fn test_liquidity_depth_non_positive_mid_is_zeroed() {
+        let bids = levels(&[("1", "10")]);
+        let asks = levels(&[("2", "10")]);
+
+        let levels = [Decimal::new(5, 2), Decimal::new(10, 2)];
+        let expected = levels.map(|level| (level, (Decimal::ZERO, Decimal::ZERO))).to_vec();
+        assert_eq!(calculate_liquidity_depth(&bids, &asks, Decimal::ZERO, &levels), expected);
+        assert_eq!(calculate_liquidity_depth(&bids, &asks, Decimal::from(-1), &levels), expected);
+    }
+
+    #[test]
+    fn test_liquidity_depth_at_configured_levels() {
+        let bids = levels(&[("99.5", "10"), ("98.5", "10"), ("90", "10")]);
+        let asks = levels(&[("100.5", "10"), ("101.5", "20"), ("110", "10")]);
+        let (one, two) = (Decimal::new(1, 2), Decimal::new(2, 2));
+
+        let depths = calculate_liquidity_depth(&bids, &asks, Decimal::from(100), &[one, two]);
+        assert_eq!(
+            depths,
+            vec![(one, (Decimal::from(995), Decimal::from(1005))), (two, (Decimal::from(1980), Decimal::from(3035))),]
+        );
+    }
+
+    #[test]
+    fn test_quantity_within_band_sums_base_size() {
+        let bids = levels(&[("99.5", "10"), ("98.5", "10"), ("90", "10")]);
+        let asks = levels(&[("100.5", "10"), ("101.5", "20"), ("110", "10")]);
+        let (mid, five) = (Decimal::from(100), Decimal::new(5, 2));
+
+        assert_eq!(quantity_within_band(&bids, &asks, mid, five), (Decimal::from(20), Decimal::from(30)));
+        // Notional depth of the same band weights each level by its price
+        assert_eq!(depth_within_band(&bids, &asks, mid, five), (Decimal::from(1980), Decimal::from(3035)));
+        assert_eq!(quantity_within_band(&bids, &asks, Decimal::ZERO, five), (Decimal::ZERO, Decimal::ZERO));
+    }
+
+    #[test]
+    fn test_slippage_curve_walks_book() {
+        let bids = levels(&[("99", "100"), ("98", "100")]);
+        let asks = levels(&[("101", "100"), ("102", "100")]);
+        let sizes = [Decimal::from(9_900), Decimal::from(20_300), Decimal::from(50_000)];
+        let curve = slippage_curve(&bids, &asks, Decimal::from(100), &sizes);
+
+        // Within the best level, then through both ask levels
+        assert_eq!(curve[0].buy.avg_price, Some(Decimal::from(101)));
+        assert_eq!(curve[0].buy.slippage_pct, Some(Decimal::ONE));
+        assert!(!curve[0].buy.partial);
+        let avg = curve[1].buy.avg_price.unwrap();
+        assert!(avg > Decimal::from(101) && avg < Decimal::from(102), "avg {avg}");
+        assert_eq!(curve[1].buy.filled_notional, Decimal::from(20_300));
+        assert!(!curve[1].buy.partial);
+        assert_eq!(curve[0].sell.slippage_pct, Some(Decimal::ONE));
+
+        // The book runs out: report what could be filled and flag it
+        assert_eq!(curve[2].buy.filled_notional, Decimal::from(20_300));
+        assert_eq!(curve[2].sell.filled_notional, Decimal::from(19_700));
+        assert!(curve[2].buy.partial && curve[2].sell.partial);
+        assert_eq!(curve[2].sell.avg_price, Some(Decimal::from(19_700) / Decimal::from(200)));
+
+        let empty = slippage_curve(&[], &asks, Decimal::from(100), &sizes[..1]);
+        assert_eq!(
+            empty[0].sell,
+            FillEstimate { avg_price: None, slippage_pct: None, filled_notional: Decimal::ZERO, partial: true }
+        );
+    }
+
+    fn asset_ctx(volume: u64) -> serde_json::Value {
+        serde_json::json!({
+            "markPx": "10.0",
+            "oraclePx": "10.0",
+            "midPx": "10.0",
+            "funding": "0.0001",
+            "openInterest": "1000.0",
+            "dayNtlVlm": volume.to_string(),
+            "premium": "0.0",
+            "impactPxs": ["9.99", "10.01"]
+        })
+    }
+
+    /// Serve a two-market universe where DISCNEW's 24h volume is controlled by the test
+    async fn start_mock_api(new_coin_volume: Arc<AtomicU64>) -> String {
+        use axum::{Json, Router, routing::post};
+
+        let app = Router::new().route(
+            "/info",
+            post(async move || {
+                Json(serde_json::json!([
+                    { "universe": [{ "name": "DISCBASE" }, { "name": "DISCNEW" }] },
+                    [asset_ctx(50_000_000), asset_ctx(new_coin_volume.load(Ordering::SeqCst))]
+                ]))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/info", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    fn running_markets(monitor: &MarketMetricsMonitor) -> HashSet<String> {
+        monitor.market_tasks.lock().unwrap().keys().cloned().collect()
+    }
+
+    #[tokio::test]
+    async fn test_discovered_market_gets_loop_and_table() {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let (client, connection) = tokio_postgres::connect(&database_url, tokio_postgres::NoTls).await.unwrap();
+        tokio::spawn(connection);
+        client.batch_execute("DROP TABLE IF EXISTS market_metrics.discnew_metrics_raw").await.unwrap();
+
+        let new_coin_volume = Arc::new(AtomicU64::new(1_000));
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": database_url,
+            "target_markets": ["DISCBASE"],
+            "hyperliquid_api_url": start_mock_api(new_coin_volume.clone()).await,
+            "poll_interval_secs": 0.02,
+            "monitoring_interval_secs": 60.0,
+            "discovery_min_volume": "1000000",
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let monitor = Arc::new(MarketMetricsMonitor::new(config, listener).await.unwrap());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Below the volume threshold only the configured market runs
+        monitor.refresh_target_markets().await;
+        assert_eq!(running_markets(&monitor), HashSet::from(["DISCBASE".to_string()]));
+
+        new_coin_volume.store(5_000_000, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        monitor.refresh_target_markets().await;
+        assert!(running_markets(&monitor).contains("DISCNEW"));
+        let table =
+            client.query_one("SELECT to_regclass('market_metrics.discnew_metrics_raw')::text", &[]).await.unwrap();
+        assert_eq!(table.get::<_, Option<String>>(0).as_deref(), Some("market_metrics.discnew_metrics_raw"));
+
+        // Dropping back below the threshold stops the loop, the configured market stays
+        new_coin_volume.store(0, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        monitor.refresh_target_markets().await;
+        assert_eq!(running_markets(&monitor), HashSet::from(["DISCBASE".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_all_markets_follow_the_universe() {
+        use axum::{Json, Router, routing::post};
+        use std::sync::atomic::AtomicBool;
+
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let (client, connection) = tokio_postgres::connect(&database_url, tokio_postgres::NoTls).await.unwrap();
+        tokio::spawn(connection);
+        client.batch_execute("DROP TABLE IF EXISTS market_metrics.allb_metrics_raw").await.unwrap();
+
+        let listed = Arc::new(AtomicBool::new(true));
+        let serve_listed = listed.clone();
+        let app = Router::new().route(
+            "/info",
+            post(async move || {
+                if serve_listed.load(Ordering::SeqCst) {
+                    Json(serde_json::json!([[{ "name": "ALLA" }, { "name": "ALLB" }], [asset_ctx(1), asset_ctx(1)]]))
+                } else {
+                    Json(serde_json::json!([[{ "name": "ALLA" }], [asset_ctx(1)]]))
+                }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/info", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": database_url,
+            "target_markets": ["all"],
+            "hyperliquid_api_url": url,
+            "poll_interval_secs": 0.02,
+            "monitoring_interval_secs": 60.0,
+            "discovery_confirmations": 1,
+            "market_overrides": { "ALLB": { "monitoring_interval_secs": 30.0 } },
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let monitor = Arc::new(MarketMetricsMonitor::new(config, listener).await.unwrap());
+
+        // Tables for the whole universe exist before any loop starts, whatever their volume
+        let table = client.query_one("SELECT to_regclass('market_metrics.allb_metrics_raw')::text", &[]).await.unwrap();
+        assert_eq!(table.get::<_, Option<String>>(0).as_deref(), Some("market_metrics.allb_metrics_raw"));
+        assert!(monitor.pinned_markets.lock().unwrap().is_empty());
+        monitor.refresh_target_markets().await;
+        assert_eq!(running_markets(&monitor), HashSet::from(["ALLA".to_string(), "ALLB".to_string()]));
+
+        // A delisted market stops being sampled
+        listed.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        monitor.refresh_target_markets().await;
+        assert_eq!(running_markets(&monitor), HashSet::from(["ALLA".to_string()]));
+        monitor.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_partial_universe_keeps_discovered_loops() {
+        use axum::{Json, Router, routing::post};
+        use std::sync::atomic::AtomicBool;
+
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let partial = Arc::new(AtomicBool::new(false));
+        let serve_partial = partial.clone();
+        let app = Router::new().route(
+            "/info",
+            post(async move || {
+                if serve_partial.load(Ordering::SeqCst) {
+                    return Json(serde_json::json!([{ "universe": [{ "name": "PARTBASE" }] }, [asset_ctx(0)]]));
+                }
+                Json(serde_json::json!([
+                    { "universe": [{ "name": "PARTBASE" }, { "name": "PARTNEW" }] },
+                    [asset_ctx(0), asset_ctx(50_000_000)]
+                ]))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/info", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": database_url,
+            "target_markets": ["PARTBASE"],
+            "hyperliquid_api_url": url,
+            "poll_interval_secs": 0.02,
+            "monitoring_interval_secs": 60.0,
+            "discovery_min_volume": "1000000",
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let monitor = Arc::new(MarketMetricsMonitor::new(config, listener).await.unwrap());
+        let refresh = async |serve: bool| {
+            partial.store(serve, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            monitor.refresh_target_markets().await;
+            running_markets(&monitor)
+        };
+        let both = HashSet::from(["PARTBASE".to_string(), "PARTNEW".to_string()]);
+        assert_eq!(refresh(false).await, both);
+
+        // A single partial fetch is ignored, and the full universe resets it
+        assert_eq!(refresh(true).await, both);
+        assert_eq!(refresh(false).await, both);
+        assert_eq!(refresh(true).await, both);
+
+        // Missing from a second fetch in a row, the market stops
+        assert_eq!(refresh(true).await, HashSet::from(["PARTBASE".to_string()]));
+    }
+
+    #[test]
+    fn test_discovery_confirms_missing_markets_only() {
+        let set = |coins: &[&str]| coins.iter().map(ToString::to_string).collect::<HashSet<_>>();
+        let mut discovery = DiscoveryState::default();
+        let running = set(&["A", "B"]);
+
+        // Listed below the thresholds, B stops at once
+        assert_eq!(discovery.markets_to_stop(&running, &set(&["A"]), &running, 2), ["B"]);
+
+        // Missing from the universe, B stops on the second fetch in a row
+        assert!(discovery.markets_to_stop(&running, &set(&["A"]), &set(&["A"]), 2).is_empty());
+        // ... and a fetch listing it again resets the streak
+        assert!(discovery.markets_to_stop(&running, &running, &running, 2).is_empty());
+        assert!(discovery.markets_to_stop(&running, &set(&["A"]), &set(&["A"]), 2).is_empty());
+        assert_eq!(discovery.markets_to_stop(&running, &set(&["A"]), &set(&["A"]), 2), ["B"]);
+        let mut stopped = discovery.markets_to_stop(&running, &set(&[]), &set(&[]), 1);
+        stopped.sort();
+        assert_eq!(stopped, ["A", "B"]);
+    }
+
+    #[tokio::test]
+    async fn test_markets_added_and_removed_at_runtime() {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let (client, connection) = tokio_postgres::connect(&database_url, tokio_postgres::NoTls).await.unwrap();
+        tokio::spawn(connection);
+        client.batch_execute("DROP TABLE IF EXISTS market_metrics.rtadded_metrics_raw").await.unwrap();
+
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": database_url,
+            "target_markets": ["DISCBASE"],
+            "hyperliquid_api_url": start_mock_api(Arc::new(AtomicU64::new(0))).await,
+            "monitoring_interval_secs": 60.0,
+            "discovery_min_volume": "100000000",
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let monitor = Arc::new(MarketMetricsMonitor::new(config, listener).await.unwrap());
+        monitor.clone().start().await;
+
+        monitor.add_market("RTADDED").await.unwrap();
+        assert_eq!(running_markets(&monitor), HashSet::from(["DISCBASE".to_string(), "RTADDED".to_string()]));
+        let table =
+            client.query_one("SELECT to_regclass('market_metrics.rtadded_metrics_raw')::text", &[]).await.unwrap();
+        assert_eq!(table.get::<_, Option<String>>(0).as_deref(), Some("market_metrics.rtadded_metrics_raw"));
+
+        // Added markets survive discovery, removed configured markets stay removed
+        assert!(monitor.remove_market("DISCBASE").await);
+        assert!(!monitor.remove_market("DISCBASE").await);
+        monitor.refresh_target_markets().await;
+        assert_eq!(running_markets(&monitor), HashSet::from(["RTADDED".to_string()]));
+
+        assert!(monitor.remove_market("RTADDED").await);
+        assert!(running_markets(&monitor).is_empty());
+        monitor.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_collects_without_database() {
+        // Nothing listens on port 1, so any connection attempt would fail
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": "postgresql://metrics@127.0.0.1:1/metrics",
+            "dry_run": true,
+            "target_markets": ["DISCBASE"],
+            "hyperliquid_api_url": start_mock_api(Arc::new(AtomicU64::new(0))).await,
+            "poll_interval_secs": 0.02,
+            "monitoring_interval_secs": 60.0,
+            "backfill_hours": 1,
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let monitor = Arc::new(MarketMetricsMonitor::new(config.clone(), listener).await.unwrap());
+        assert!(monitor.database().is_none());
+        monitor.add_market("DISCNEW").await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        monitor.collect_and_store_metrics("DISCBASE", &mut MarketState::new(&config, "DISCBASE")).await.unwrap();
+        monitor.flush_pending_inserts().await;
+        assert!(monitor.pending_inserts.lock().unwrap().is_empty());
+        assert!(monitor.latest_metrics.lock().unwrap()["DISCBASE"].mark_price.is_some());
+        // Printed rows are not reported as inserted
+        let report = monitor.check().await;
+        assert!(report.database_reachable && report.markets["DISCBASE"].fresh);
+        assert_eq!(report.markets["DISCBASE"].last_insert, None);
+        assert!(monitor.exporter_metrics.render().contains("anthias_inserted_rows_total 0\n"));
+        monitor.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_excluded_markets_never_start() {
+        let new_coin_volume = Arc::new(AtomicU64::new(5_000_000));
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": "",
+            "dry_run": true,
+            "target_markets": ["DISCBASE", "PURR"],
+            "excluded_markets": ["purr"],
+            "excluded_market_patterns": ["DISCN*"],
+            "hyperliquid_api_url": start_mock_api(new_coin_volume).await,
+            "poll_interval_secs": 0.02,
+            "monitoring_interval_secs": 60.0,
+            "discovery_min_volume": "1000000",
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let monitor = Arc::new(MarketMetricsMonitor::new(config, listener).await.unwrap());
+        assert_eq!(monitor.config.target_markets, vec!["DISCBASE".to_string()]);
+
+        // DISCNEW meets the discovery threshold but matches a pattern
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        monitor.refresh_target_markets().await;
+        assert_eq!(running_markets(&monitor), HashSet::from(["DISCBASE".to_string()]));
+        let err = monitor.add_market("DISCNEW").await.unwrap_err();
+        assert_eq!(err.to_string(), "DISCNEW is excluded by excluded_markets or excluded_market_patterns");
+        assert_eq!(running_markets(&monitor), HashSet::from(["DISCBASE".to_string()]));
+        monitor.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_reload_reconciles_target_markets() {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let dir = temp_dir("reload_config");
+        let path = dir.join("config.json");
+        let path = path.to_str().unwrap();
+        let api_url = start_mock_api(Arc::new(AtomicU64::new(0))).await;
+        let file_config = |markets: &[&str]| {
+            serde_json::json!({
+                "database_url": database_url,
+                "target_markets": markets,
+                "hyperliquid_api_url": api_url,
+                "monitoring_interval_secs": 60.0,
+            })
+            .to_string()
+        };
+        let config: MetricsConfig = serde_json::from_str(&file_config(&["RELOADA", "RELOADB"])).unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let monitor = Arc::new(MarketMetricsMonitor::new(config, listener).await.unwrap());
+        monitor.clone().start().await;
+
+        std::fs::write(path, file_config(&["RELOADA", "reloadc"])).unwrap();
+        monitor.reload_target_markets(path).await;
+        assert_eq!(running_markets(&monitor), HashSet::from(["RELOADA".to_string(), "RELOADC".to_string()]));
+
+        // A broken file leaves the running markets alone
+        std::fs::write(path, "{ not json").unwrap();
+        monitor.reload_target_markets(path).await;
+        std::fs::write(path, file_config(&[])).unwrap();
+        monitor.reload_target_markets(path).await;
+        assert_eq!(running_markets(&monitor), HashSet::from(["RELOADA".to_string(), "RELOADC".to_string()]));
+
+        std::fs::remove_dir_all(dir).unwrap();
+        monitor.shutdown().await;
+    }
+
+    #[test]
+    fn test_book_truncation_flags_clipped_band() {
+        let bids = [100, 99, 80, 70].map(|price| (Decimal::from(price), Decimal::ONE)).to_vec();
+        let truncated = |limit: usize| {
+            let (levels, cutoff) = top_levels(bids.clone(), limit);
+            assert_eq!(levels.len(), limit.min(bids.len()));
+            let book = BookLevels {
+                bids: levels,
+                asks: Vec::new(),
+                cutoffs: [cutoff, None],
+                update_count: None,
+                snapshot_age_ms: None,
+            };
+            book.truncated_within(Decimal::from(100), Decimal::new(25, 2))
+        };
+
+        // The 80 bid is inside the 25% band, the 70 bid outside it
+        assert!(truncated(2));
+        assert!(!truncated(3));
+        assert!(!truncated(usize::MAX));
+
+        // The limit counts price levels, however many orders rest at each
+        let orders = [101, 101, 101, 124].map(|price| (Decimal::from(price), Decimal::ONE));
+        let (asks, cutoff) = top_levels(orders, 1);
+        assert_eq!(asks, [(Decimal::from(101), Decimal::from(3))]);
+        assert_eq!(cutoff, Some(Decimal::from(124)));
+        let book =
+            BookLevels { bids: Vec::new(), asks, cutoffs: [None, cutoff], update_count: None, snapshot_age_ms: None };
+        assert!(book.truncated_within(Decimal::from(100), Decimal::new(25, 2)));
+    }
+
+    #[test]
+    fn test_retention_cutoff_saturates() {
+        let now = Utc::now();
+        assert_eq!(time_before(now, Duration::from_mins(1)), now - chrono::Duration::minutes(1));
+        // RETENTION_DAYS=4294967295 would overflow the subtraction
+        let retention = Duration::from_hours(u64::from(u32::MAX) * 24);
+        assert_eq!(time_before(now, retention), DateTime::<Utc>::MIN_UTC);
+    }
+
+    #[test]
+    fn test_candle_rows_stamped_at_close() {
+        let candle = |open_time: i64| Candle {
+            open_time,
+            close_time: open_time + 59_999,
+            open: Decimal::from(100),
+            high: Decimal::from(102),
+            low: Decimal::from(99),
+            close: Decimal::from(101),
+            volume: Decimal::from(5),
+        };
+        let row = candle_metrics("BTC", &candle(1_700_000_000_000)).unwrap();
+        assert_eq!(row.timestamp, DateTime::from_timestamp_millis(1_700_000_059_999).unwrap());
+        assert_eq!(row.mid_price, Some(Decimal::from(101)));
+
+        // The close of a candle still open is the latest price, not a future one
+        let open = candle(Utc::now().timestamp_millis() - 1_000);
+        assert!(candle_metrics("BTC", &open).unwrap().timestamp <= Utc::now());
+    }
+
+    #[test]
+    fn test_discover_markets_thresholds() {
+        let market = |coin: &str, volume: i64, open_interest: i64| HyperliquidMarketData {
+            coin: coin.to_string(),
+            mark_price: Decimal::from(10),
+            oracle_price: Decimal::from(10),
+            mid_price: None,
+            funding_rate_pct: Decimal::ZERO,
+            open_interest: Decimal::from(open_interest),
+            volume_24h: Decimal::from(volume),
+            premium: Decimal::ZERO,
+            impact_px_bid: None,
+            impact_px_ask: None,
+            latency_ms: None,
+            fetched_at: Utc::now(),
+        };
+        let data = [market("A", 2_000_000, 1_000), market("B", 500_000, 1_000), market("C", 2_000_000, 500)];
+
+        // Open interest is already notional, so C's 500 misses the threshold whatever its price
+        let discovered = discover_markets(&data, Decimal::from(1_000_000), Decimal::from(1_000));
+        assert_eq!(discovered, HashSet::from(["A".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_coins_collected_from_assigned_sources() {
+        use axum::{Json, Router, routing::post};
+
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let app = Router::new().route(
+            "/info",
+            post(async |Json(body): Json<serde_json::Value>| {
+                if body["type"] == "l2Book" {
+                    return Json(serde_json::json!({
+                        "coin": body["coin"],
+                        "time": Utc::now().timestamp_millis(),
+                        "levels": [[{ "px": "9.9", "sz": "100", "n": 3 }], [{ "px": "10.1", "sz": "50", "n": 1 }]],
+                    }));
+                }
+                Json(serde_json::json!([
+                    { "universe": [{ "name": "SRCPOLL" }, { "name": "SRCREST" }] },
+                    [asset_ctx(1_000), asset_ctx(1_000)]
+                ]))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/info", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": database_url,
+            "target_markets": ["SRCPOLL", "SRCREST"],
+            "data_sources": { "SRCREST": "l2_rest" },
+            "hyperliquid_api_url": url,
+            "poll_interval_secs": 0.02,
+            "monitoring_interval_secs": 60.0,
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let monitor = MarketMetricsMonitor::new(config.clone(), listener).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        for coin in ["SRCPOLL", "SRCREST"] {
+            monitor.collect_and_store_metrics(coin, &mut MarketState::new(&config, coin)).await.unwrap();
+        }
+        let latest = monitor.latest_metrics.lock().unwrap().clone();
+
+        // Both read the polled market data, only the L2 REST coin has a book
+        // since the node listener has none
+        let (polled, rest) = (&latest["SRCPOLL"], &latest["SRCREST"]);
+        assert_eq!(polled.mark_price, Some(Decimal::from(10)));
+        assert_eq!(polled.best_bid, None);
+        assert_eq!(rest.mark_price, Some(Decimal::from(10)));
+        assert_eq!(rest.best_bid, Some(Decimal::new(99, 1)));
+        assert_eq!(rest.best_ask, Some(Decimal::new(101, 1)));
+        assert_eq!(rest.quote_update_rate, None);
+    }
+
+    /// Serve an `l2Book` one unit either side of `mid` and an empty universe
+    async fn start_mock_book(mid: Arc<AtomicU64>) -> String {
+        use axum::{Json, Router, routing::post};
+
+        let app = Router::new().route(
+            "/info",
+            post(async move |Json(body): Json<serde_json::Value>| {
+                if body["type"] != "l2Book" {
+                    return Json(serde_json::json!([{ "universe": [] }, []]));
+                }
+                let mid = mid.load(Ordering::SeqCst);
+                Json(serde_json::json!({
+                    "coin": body["coin"],
+                    "time": Utc::now().timestamp_millis(),
+                    "levels": [
+                        [{ "px": (mid - 1).to_string(), "sz": "10", "n": 1 }],
+                        [{ "px": (mid + 1).to_string(), "sz": "10", "n": 1 }],
+                    ],
+                }))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/info", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_bad_print_flagged_or_skipped() {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let mid = Arc::new(AtomicU64::new(100));
+        let url = start_mock_book(mid.clone()).await;
+
+        let cases = [
+            ("flag", vec![100, 300], vec![(100, false), (300, true)]),
+            // A skipped row leaves the last stored mid as the baseline
+            ("skip", vec![100, 300, 105], vec![(100, false), (105, false)]),
+        ];
+        for (policy, mids, stored) in cases {
+            let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+                "database_url": database_url,
+                "target_markets": ["BADPRINT"],
+                "data_sources": { "BADPRINT": "l2_rest" },
+                "hyperliquid_api_url": url,
+                "monitoring_interval_secs": 60.0,
+                "max_tick_move_pct": "0.5",
+                "bad_print_policy": policy,
+            }))
+            .unwrap();
+            let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+            let monitor = MarketMetricsMonitor::new(config.clone(), listener).await.unwrap();
+
+            let mut state = MarketState::new(&config, "BADPRINT");
+            for tick_mid in mids {
+                mid.store(tick_mid, Ordering::SeqCst);
+                monitor.collect_and_store_metrics("BADPRINT", &mut state).await.unwrap();
+            }
+            let pending = monitor.pending_inserts.lock().unwrap().clone();
+            let pending = pending.iter().map(|row| (row.mid_price, row.suspected_bad_print)).collect::<Vec<_>>();
+            let expected = stored.into_iter().map(|(mid, flag)| (Some(Decimal::from(mid)), flag)).collect::<Vec<_>>();
+            assert_eq!(pending, expected, "{policy}");
+        }
+    }
+
+    #[test]
+    fn test_round_for_storage_fits_column_scales() {
+        let mut config: MetricsConfig =
+            serde_json::from_value(serde_json::json!({ "database_url": "", "target_markets": ["BTC"] })).unwrap();
+        let extreme = Decimal::from_str("1.2345678901234567890123456789").unwrap();
+        let mut metrics = MarketMetrics::new("BTC".to_string());
+        metrics.mark_price = Some(extreme);
+        metrics.mid_price = Some(extreme);
+        metrics.spread_pct = Some(extreme);
+        metrics.bid_depth_5pct = Some(extreme);
+        metrics.funding_rate_pct = Some(extreme);
+        metrics.activity_staleness_secs = Some(extreme);
+        metrics.round_for_storage(&config);
+
+        let scale = |value: Option<Decimal>| value.unwrap().scale();
+        assert_eq!(scale(metrics.mark_price), 8);
+        assert_eq!(scale(metrics.spread_pct), 6);
+        assert_eq!(scale(metrics.bid_depth_5pct), 8);
+        assert_eq!(scale(metrics.funding_rate_pct), 10);
+        assert_eq!(scale(metrics.activity_staleness_secs), 3);
+
+        // Banker's rounding sends midpoints to the even digit
+        let mut metrics = MarketMetrics::new("BTC".to_string());
+        metrics.best_bid = Some(Decimal::from_str("0.000000025").unwrap());
+        metrics.best_ask = Some(Decimal::from_str("0.000000035").unwrap());
+        metrics.spread_pct = Some(Decimal::from_str("-0.0000005").unwrap());
+        metrics.round_for_storage(&config);
+        assert_eq!(metrics.best_bid, Some(Decimal::new(2, 8)));
+        assert_eq!(metrics.best_ask, Some(Decimal::new(4, 8)));
+        assert_eq!(metrics.spread_pct, Some(Decimal::ZERO));
+
+        // Configured scales apply, and unbounded columns keep every digit
+        config.price_scale = 2;
+        config.unbounded_numeric_fields = vec!["mid_price".to_string()];
+        let mut metrics = MarketMetrics::new("BTC".to_string());
+        metrics.mark_price = Some(extreme);
+        metrics.mid_price = Some(extreme);
+        metrics.round_for_storage(&config);
+        assert_eq!(metrics.mark_price, Some(Decimal::new(123, 2)));
+        assert_eq!(metrics.mid_price, Some(extreme));
+    }
+
+    #[tokio::test]
+    async fn test_config_validated_before_starting() {
+        // The monitor refuses the config before connecting to the database
+        let mut invalid: MetricsConfig =
+            serde_json::from_value(serde_json::json!({ "database_url": "", "target_markets": ["BTC", "ETH"] }))
+                .unwrap();
+        invalid.monitoring_interval_secs = 0.0;
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let err = MarketMetricsMonitor::new(invalid, listener).await.err().unwrap();
+        assert!(err.to_string().contains("monitoring_interval_secs"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_observer_called_per_collection() {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": database_url,
+            "target_markets": ["DISCBASE"],
+            "hyperliquid_api_url": start_mock_api(Arc::new(AtomicU64::new(0))).await,
+            "monitoring_interval_secs": 60.0,
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let mut monitor = MarketMetricsMonitor::new(config.clone(), listener).await.unwrap();
+
+        let calls = Arc::new(AtomicU64::new(0));
+        let counter = calls.clone();
+        monitor.with_observer(move |metrics| {
+            assert_eq!(metrics.coin, "DISCBASE");
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let mut state = MarketState::new(&config, "DISCBASE");
+        for _ in 0..3 {
+            monitor.collect_and_store_metrics("DISCBASE", &mut state).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_collected_rows_flushed_together() {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let (client, connection) = tokio_postgres::connect(&database_url, tokio_postgres::NoTls).await.unwrap();
+        tokio::spawn(connection);
+        client
+            .batch_execute(
+                "DROP TABLE IF EXISTS market_metrics.flusha_metrics_raw; \
+                 DROP TABLE IF EXISTS market_metrics.flushb_metrics_raw",
+            )
+            .await
+            .unwrap();
+
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": database_url,
+            "target_markets": ["FLUSHA", "FLUSHB"],
+            "hyperliquid_api_url": start_mock_api(Arc::new(AtomicU64::new(0))).await,
+            "monitoring_interval_secs": 60.0,
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let monitor = MarketMetricsMonitor::new(config.clone(), listener).await.unwrap();
+
+        let mut state = MarketState::new(&config, "FLUSHA");
+        for coin in ["FLUSHA", "FLUSHB", "FLUSHA"] {
+            monitor.collect_and_store_metrics(coin, &mut state).await.unwrap();
+        }
+        let count = async |table: &str| {
+            let row = client.query_one(&format!("SELECT COUNT(*) FROM market_metrics.{table}"), &[]).await.unwrap();
+            row.get::<_, i64>(0)
+        };
+        assert_eq!(count("flusha_metrics_raw").await, 0);
+
+        monitor.flush_pending_inserts().await;
+        assert_eq!(count("flusha_metrics_raw").await, 2);
+        assert_eq!(count("flushb_metrics_raw").await, 1);
+        assert!(monitor.pending_inserts.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_breaker_pauses_inserts_until_database_recovers() {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let (client, connection) = tokio_postgres::connect(&database_url, tokio_postgres::NoTls).await.unwrap();
+        tokio::spawn(connection);
+        client
+            .batch_execute(
+                "DROP TABLE IF EXISTS market_metrics.breaker_metrics_raw; \
+                 DROP TABLE IF EXISTS market_metrics.breaker_away",
+            )
+            .await
+            .unwrap();
+
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": database_url,
+            "target_markets": ["BREAKER"],
+            "hyperliquid_api_url": start_mock_api(Arc::new(AtomicU64::new(0))).await,
+            "monitoring_interval_secs": 60.0,
+            "insert_max_retries": 0,
+            "db_breaker_threshold": 2,
+            "db_breaker_cooldown_secs": 0.2,
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let (events_tx, mut events) = mpsc::channel(64);
+        let monitor = MarketMetricsMonitor::new_with_events(config.clone(), listener, Some(events_tx)).await.unwrap();
+        let breaker_events = |events: &mut mpsc::Receiver<MonitorEvent>| {
+            std::iter::from_fn(|| events.try_recv().ok())
+                .filter_map(|event| match event {
+                    MonitorEvent::InsertBreaker { state } => Some(state),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut state = MarketState::new(&config, "BREAKER");
+        let collect_and_flush = async |state: &mut MarketState| {
+            monitor.collect_and_store_metrics("BREAKER", state).await.unwrap();
+            monitor.flush_pending_inserts().await;
+        };
+
+        let rename = "ALTER TABLE market_metrics.breaker_metrics_raw RENAME TO breaker_away";
+        client.batch_execute(rename).await.unwrap();
+        collect_and_flush(&mut state).await;
+        assert!(breaker_events(&mut events).is_empty());
+        collect_and_flush(&mut state).await;
+        assert_eq!(breaker_events(&mut events), [BreakerState::Open]);
+
+        // Rows collected while open are kept, not inserted
+        client.batch_execute("ALTER TABLE market_metrics.breaker_away RENAME TO breaker_metrics_raw").await.unwrap();
+        collect_and_flush(&mut state).await;
+        collect_and_flush(&mut state).await;
+        let count = async || {
+            let row = client.query_one("SELECT COUNT(*) FROM market_metrics.breaker_metrics_raw", &[]).await.unwrap();
+            row.get::<_, i64>(0)
+        };
+        assert_eq!(count().await, 0);
+        assert!(breaker_events(&mut events).is_empty());
+
+        // The probe after the cooldown closes the breaker and queues the latest paused row
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        collect_and_flush(&mut state).await;
+        assert_eq!(breaker_events(&mut events), [BreakerState::HalfOpen, BreakerState::Closed]);
+        assert_eq!(count().await, 1);
+        assert_eq!(monitor.pending_inserts.lock().unwrap().len(), 1);
+        monitor.flush_pending_inserts().await;
+        assert_eq!(count().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_health_tracks_inserts_per_market() {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let (client, connection) = tokio_postgres::connect(&database_url, tokio_postgres::NoTls).await.unwrap();
+        tokio::spawn(connection);
+        let drop_tables = "DROP TABLE IF EXISTS market_metrics.healthya_metrics_raw; \
+                           DROP TABLE IF EXISTS market_metrics.healthyb_metrics_raw";
+        client.batch_execute(drop_tables).await.unwrap();
+
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": database_url,
+            "target_markets": ["HEALTHYA", "HEALTHYB"],
+            "hyperliquid_api_url": start_mock_api(Arc::new(AtomicU64::new(0))).await,
+            "poll_interval_secs": 0.02,
+            "monitoring_interval_secs": 60.0,
+            "insert_max_retries": 0,
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let monitor = MarketMetricsMonitor::new(config.clone(), listener).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        monitor.collect_and_store_metrics("HEALTHYA", &mut MarketState::new(&config, "HEALTHYA")).await.unwrap();
+        monitor.flush_pending_inserts().await;
+        let report = monitor.check().await;
+        assert!(report.database_reachable && report.market_data_fresh);
+        assert!(report.markets["HEALTHYA"].fresh && report.markets["HEALTHYA"].last_insert.is_some());
+        // A long prune or insert retry holding the database lock doesn't fail the ping
+        let held = monitor.database().unwrap().lock().await;
+        assert!(monitor.check().await.database_reachable);
+        drop(held);
+        // Nothing was inserted for the other target market yet
+        assert_eq!(report.markets["HEALTHYB"], MarketHealth::default());
+        assert!(!report.healthy);
+
+        // A rejected insert is reported without counting as fresh
+        monitor.collect_and_store_metrics("HEALTHYB", &mut MarketState::new(&config, "HEALTHYB")).await.unwrap();
+        client.batch_execute("DROP TABLE market_metrics.healthyb_metrics_raw").await.unwrap();
+        monitor.flush_pending_inserts().await;
+        let report = monitor.check().await;
+        assert!(!report.markets["HEALTHYB"].fresh);
+        assert!(report.markets["HEALTHYB"].last_error.is_some());
+        assert!(!report.healthy);
+    }
+
+    #[tokio::test]
+    async fn test_crossed_book_flagged() {
+        use axum::{Json, Router, routing::post};
+
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let app = Router::new().route(
+            "/info",
+            post(async |Json(body): Json<serde_json::Value>| {
+                if body["type"] == "l2Book" {
+                    let (bid, ask) = if body["coin"] == "CROSSED" { ("10.1", "9.8") } else { ("0", "0") };
+                    return Json(serde_json::json!({
+                        "coin": body["coin"],
+                        "time": Utc::now().timestamp_millis(),
+                        "levels": [[{ "px": bid, "sz": "100", "n": 3 }], [{ "px": ask, "sz": "50", "n": 1 }]],
+                    }));
+                }
+                Json(serde_json::json!([
+                    { "universe": [{ "name": "CROSSED" }, { "name": "ZEROMID" }] },
+                    [asset_ctx(10), asset_ctx(10)]
+                ]))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/info", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": database_url,
+            "target_markets": ["CROSSED", "ZEROMID"],
+            "data_sources": { "CROSSED": "l2_rest", "ZEROMID": "l2_rest" },
+            "hyperliquid_api_url": url,
+            "poll_interval_secs": 0.02,
+            "monitoring_interval_secs": 60.0,
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let monitor = MarketMetricsMonitor::new(config.clone(), listener).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        for coin in ["CROSSED", "ZEROMID"] {
+            monitor.collect_and_store_metrics(coin, &mut MarketState::new(&config, coin)).await.unwrap();
+        }
+        let latest = monitor.latest_metrics.lock().unwrap().clone();
+        let crossed = &latest["CROSSED"];
+        assert!(crossed.book_crossed);
+        assert_eq!(crossed.spread, Some(Decimal::new(-3, 1)));
+        // The latest row is already rounded to the stored scale
+        assert_eq!(crossed.spread_pct, Some(Decimal::new(-3_015_075, 6)));
+        // A zero mid leaves out the order book metrics instead of dividing by it
+        assert!(!latest["ZEROMID"].book_crossed);
+        assert_eq!(latest["ZEROMID"].spread_pct, None);
+    }
+
+    #[tokio::test]
+    async fn test_timing_breakdown_covers_collection() {
+        use axum::{Json, Router, routing::post};
+
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let app = Router::new().route(
+            "/info",
+            post(async |Json(body): Json<serde_json::Value>| {
+                if body["type"] == "l2Book" {
+                    tokio::time::sleep(Duration::from_millis(30)).await;
+                    return Json(serde_json::json!({
+                        "coin": body["coin"],
+                        "time": Utc::now().timestamp_millis(),
+                        "levels": [[{ "px": "9.9", "sz": "100", "n": 3 }], [{ "px": "10.1", "sz": "50", "n": 1 }]],
+                    }));
+                }
+                Json(serde_json::json!([{ "universe": [{ "name": "TIMED" }] }, [asset_ctx(1_000)]]))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/info", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": database_url,
+            "target_markets": ["TIMED"],
+            "data_sources": { "TIMED": "l2_rest" },
+            "hyperliquid_api_url": url,
+            "poll_interval_secs": 0.02,
+            "monitoring_interval_secs": 60.0,
+            "store_timing_breakdown": true,
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let monitor = MarketMetricsMonitor::new(config.clone(), listener).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut state = MarketState::new(&config, "TIMED");
+        monitor.collect_and_store_metrics("TIMED", &mut state).await.unwrap();
+
+        let timing = monitor.latest_metrics.lock().unwrap()["TIMED"].timing_breakdown.clone().unwrap();
+        let phases = timing.phases();
+        assert_eq!(phases.map(|(phase, _)| phase), ["hl_lookup", "book_snapshot", "depth_computation"]);
+        assert!(phases.iter().all(|(_, ms)| *ms >= 0.0));
+        // The slow `l2Book` response lands in the book snapshot phase
+        assert!(timing.book_snapshot_ms >= 30.0, "{timing:?}");
+        // The phases leave out only the derived metrics computed from the book
+        let sum: f64 = phases.iter().map(|(_, ms)| ms).sum();
+        assert!(sum <= timing.total_ms && timing.total_ms - sum < 50.0, "{sum} vs {}", timing.total_ms);
+    }
+
+    /// Quotes every coin but `UNLISTED` at a fixed mark
+    struct StubProvider;
+
+    impl MarketDataProvider for StubProvider {
+        fn source(&self) -> &'static str {
+            "stub"
+        }
+
+        fn market_data<'a>(&'a self, coin: &'a str) -> crate::market_metrics::market_data_provider::ProviderFuture<'a> {
+            Box::pin(async move {
+                (coin != "UNLISTED").then(|| ProviderMarketData {
+                    source: "stub".to_string(),
+                    symbol: format!("{coin}-PERP"),
+                    mark_price: Decimal::from(11),
+                    index_price: None,
+                    mid_price: None,
+                    funding_rate_pct: Some(Decimal::new(4, 3)),
+                    fetched_at: Utc::now(),
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provider_prices_tagged_with_source() {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": database_url,
+            "target_markets": ["DISCBASE", "UNLISTED"],
+            "hyperliquid_api_url": start_mock_api(Arc::new(AtomicU64::new(0))).await,
+            "poll_interval_secs": 0.02,
+            "monitoring_interval_secs": 60.0,
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let mut monitor = MarketMetricsMonitor::new(config.clone(), listener).await.unwrap();
+        monitor.with_provider(Arc::new(StubProvider));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        for coin in ["DISCBASE", "UNLISTED"] {
+            let mut state = MarketState::new(&config, coin);
+            monitor.collect_and_store_metrics(coin, &mut state).await.unwrap();
+        }
+        let latest = monitor.latest_metrics.lock().unwrap().clone();
+
+        let listed = &latest["DISCBASE"];
+        assert_eq!(listed.source.as_deref(), Some("hyperliquid"));
+        let prices: Vec<_> = listed.provider_prices.iter().map(|p| (p.source.as_str(), p.mark_price)).collect();
+        assert_eq!(prices, [("hyperliquid", Decimal::from(10)), ("stub", Decimal::from(11))]);
+        assert_eq!(listed.provider_prices[1].symbol, "DISCBASE-PERP");
+        // -100/11%, already rounded to the stored scale
+        assert_eq!(listed.basis_pct, Some(Decimal::new(-909_090_909, 8)));
+        // 0.01% hourly on Hyperliquid against 0.004% on the stub
+        assert_eq!(listed.funding_differential, Some(Decimal::new(6, 3)));
+
+        // Neither provider quotes it, so there is nothing to compare
+        assert_eq!(latest["UNLISTED"].source, None);
+        assert!(latest["UNLISTED"].provider_prices.is_empty());
+        assert_eq!((latest["UNLISTED"].basis_pct, latest["UNLISTED"].funding_differential), (None, None));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_loops_and_flushes() {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let (client, connection) = tokio_postgres::connect(&database_url, tokio_postgres::NoTls).await.unwrap();
+        tokio::spawn(connection);
+        client.batch_execute("DROP TABLE IF EXISTS market_metrics.stopped_metrics_raw").await.unwrap();
+
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": database_url,
+            "target_markets": ["STOPPED"],
+            "hyperliquid_api_url": start_mock_api(Arc::new(AtomicU64::new(0))).await,
+            "monitoring_interval_secs": 0.05,
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let monitor = Arc::new(MarketMetricsMonitor::new(config, listener).await.unwrap());
+        monitor.clone().start().await;
+        tokio::time::sleep(Duration::from_millis(220)).await;
+
+        monitor.shutdown().await;
+        assert!(monitor.tasks.is_empty());
+        assert!(monitor.pending_inserts.lock().unwrap().is_empty());
+
+        // Every collected row was inserted, and nothing is collected afterwards
+        let count = async || {
+            let row = client.query_one("SELECT COUNT(*) FROM market_metrics.stopped_metrics_raw", &[]).await.unwrap();
+            row.get::<_, i64>(0)
+        };
+        let inserted = count().await;
+        assert!(inserted >= 3, "only {inserted} rows inserted");
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(count().await, inserted);
+    }
+
+    #[tokio::test]
+    async fn test_events_report_collections_and_failed_inserts() {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else { return };
+        let (client, connection) = tokio_postgres::connect(&database_url, tokio_postgres::NoTls).await.unwrap();
+        tokio::spawn(connection);
+        client.batch_execute("DROP TABLE IF EXISTS market_metrics.events_metrics_raw").await.unwrap();
+
+        // EVENTS is not in the mock universe and the node listener has no book
+        let config: MetricsConfig = serde_json::from_value(serde_json::json!({
+            "database_url": database_url,
+            "target_markets": ["EVENTS"],
+            "hyperliquid_api_url": start_mock_api(Arc::new(AtomicU64::new(0))).await,
+            "monitoring_interval_secs": 0.05,
+            "insert_max_retries": 0,
+        }))
+        .unwrap();
+        let listener = Arc::new(Mutex::new(OrderBookListener::new(None, false)));
+        let (tx, mut rx) = mpsc::channel(OBSERVER_QUEUE_SIZE);
+        let monitor = Arc::new(MarketMetricsMonitor::new_with_events(config, listener, Some(tx)).await.unwrap());
+        monitor.clone().start().await;
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        client.batch_execute("DROP TABLE market_metrics.events_metrics_raw").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        monitor.shutdown().await;
+
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        let coin = "EVENTS".to_string();
+        // Collections report the timestamp their row is stored with
+        let latest = monitor.latest_metrics.lock().unwrap()[&coin].timestamp;
+        let collected = events.iter().rev().find_map(|e| match e {
+            MonitorEvent::CollectionSucceeded { coin: c, timestamp } if *c == coin => Some(*timestamp),
+            _ => None,
+        });
+        assert_eq!(collected, Some(latest));
+        assert!(events.contains(&MonitorEvent::HyperliquidStale { coin: coin.clone() }));
+        assert!(events.contains(&MonitorEvent::OrderBookUnavailable { coin: coin.clone() }));
+        assert!(events.iter().any(|e| matches!(e, MonitorEvent::InsertFailed { coin: c, .. } if *c == coin)));
+        assert!(!events.iter().any(|e| matches!(e, MonitorEvent::CollectionFailed { .. })));
+    }
 }
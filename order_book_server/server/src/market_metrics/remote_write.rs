@@ -0,0 +1,217 @@
+use crate::market_metrics::hyperliquid_client::ApiError;
+use crate::market_metrics::metrics_exporter::{ExporterMetrics, Sample};
+use crate::market_metrics::retry::retry_with_jitter;
+use chrono::Utc;
+use log::{error, warn};
+use prost::Message;
+use reqwest::Client;
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+
+/// Delay before the first push retry, doubled and jittered on each further retry
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// `prometheus.WriteRequest` from the remote-write 1.0 protobuf definition
+#[derive(Clone, PartialEq, Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TimeSeries {
+    /// Sorted by name, including the metric name as `__name__`
+    #[prost(message, repeated, tag = "1")]
+    labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<RemoteSample>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct RemoteSample {
+    #[prost(double, tag = "1")]
+    value: f64,
+    /// Milliseconds since the Unix epoch
+    #[prost(int64, tag = "2")]
+    timestamp: i64,
+}
+
+/// Snappy-compressed `WriteRequest` holding one series per sample, all at `timestamp_ms`
+fn encode_write_request(samples: &[Sample], timestamp_ms: i64) -> Result<Vec<u8>, snap::Error> {
+    let timeseries = samples
+        .iter()
+        .map(|sample| {
+            let mut labels = vec![Label { name: "__name__".to_string(), value: sample.name.to_string() }];
+            if let Some(coin) = &sample.coin {
+                labels.push(Label { name: "coin".to_string(), value: coin.clone() });
+            }
+            TimeSeries { labels, samples: vec![RemoteSample { value: sample.value, timestamp: timestamp_ms }] }
+        })
+        .collect();
+    snap::raw::Encoder::new().compress_vec(&WriteRequest { timeseries }.encode_to_vec())
+}
+
+/// Pushes the exporter metrics to a Prometheus remote-write endpoint
+pub struct RemoteWriteClient {
+    client: Client,
+    url: String,
+    headers: HeaderMap,
+    max_retries: u32,
+}
+
+impl RemoteWriteClient {
+    /// Client sending `headers` (e.g. `Authorization`) with every push.
+    /// Fails if a header name or value is not valid HTTP.
+    pub fn new(
+        url: String,
+        headers: &HashMap<String, String>,
+        max_retries: u32,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            header_map.insert(HeaderName::try_from(name.as_str())?, HeaderValue::try_from(value.as_str())?);
+        }
+        header_map.insert(CONTENT_TYPE, HeaderValue::from_static("application/x-protobuf"));
+        header_map.insert(CONTENT_ENCODING, HeaderValue::from_static("snappy"));
+        header_map.insert("X-Prometheus-Remote-Write-Version", HeaderValue::from_static("0.1.0"));
+        Ok(Self { client: Client::new(), url, headers: header_map, max_retries })
+    }
+
+    /// Push `samples` stamped with `timestamp_ms`, retrying connection errors, 429 and 5xx
+    pub async fn push(&self, samples: &[Sample], timestamp_ms: i64) -> Result<(), Box<dyn std::error::Error>> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        let body = encode_write_request(samples, timestamp_ms)?;
+        let (result, retries) = retry_with_jitter(self.max_retries, RETRY_BACKOFF, ApiError::is_retryable, || async {
+            let response = self
+                .client
+                .post(&self.url)
+                .headers(self.headers.clone())
+                .body(body.clone())
+                .timeout(Duration::from_secs(10))
+                .send()
+                .await?;
+            let status = response.status();
+            if status.is_success() { Ok(()) } else { Err(ApiError::from_status(status)) }
+        })
+        .await;
+
+        if retries > 0 && result.is_ok() {
+            warn!("Remote write succeeded after {retries} retries");
+        }
+        Ok(result?)
+    }
+
+    /// Push the current exporter metrics every `push_interval` until `shutdown` is cancelled
+    pub async fn run(self, metrics: Arc<ExporterMetrics>, push_interval: Duration, shutdown: CancellationToken) {
+        let mut interval = time::interval(push_interval);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = shutdown.cancelled() => return,
+            }
+            if let Err(e) = self.push(&metrics.samples(), Utc::now().timestamp_millis()).await {
+                error!("Failed to push metrics with remote write: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market_metrics::MarketMetrics;
+    use axum::body::Bytes;
+    use axum::http::{HeaderMap as RequestHeaders, StatusCode};
+    use axum::{Router, routing::post};
+    use rust_decimal::Decimal;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpListener;
+
+    type Received = Arc<Mutex<Vec<(RequestHeaders, Bytes)>>>;
+
+    /// Receiver that fails the first `failures` pushes with a 503 and records the rest
+    async fn start_receiver(failures: usize) -> (String, Received) {
+        let received = Received::default();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let app = Router::new().route(
+            "/api/v1/write",
+            post({
+                let received = received.clone();
+                async move |headers: RequestHeaders, body: Bytes| {
+                    if attempts.fetch_add(1, Ordering::SeqCst) < failures {
+                        return StatusCode::SERVICE_UNAVAILABLE;
+                    }
+                    received.lock().unwrap().push((headers, body));
+                    StatusCode::NO_CONTENT
+                }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v1/write", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn test_push_sends_snappy_protobuf_write_request() {
+        let metrics = ExporterMetrics::default();
+        let mut row = MarketMetrics::new("BTC".to_string());
+        row.mark_price = Some(Decimal::new(650_005, 1));
+        metrics.record_row(&row);
+        metrics.record_inserts(3, 0);
+
+        let (url, received) = start_receiver(1).await;
+        let headers = HashMap::from([("Authorization".to_string(), "Bearer secret".to_string())]);
+        let client = RemoteWriteClient::new(url, &headers, 2).unwrap();
+        client.push(&metrics.samples(), 1_700_000_000_000).await.unwrap();
+
+        let mut received = std::mem::take(&mut *received.lock().unwrap());
+        assert_eq!(received.len(), 1);
+        let (headers, body) = received.remove(0);
+        assert_eq!(headers["authorization"], "Bearer secret");
+        assert_eq!(headers["content-type"], "application/x-protobuf");
+        assert_eq!(headers["content-encoding"], "snappy");
+        assert_eq!(headers["x-prometheus-remote-write-version"], "0.1.0");
+
+        let decoded = snap::raw::Decoder::new().decompress_vec(&body).unwrap();
+        let request = WriteRequest::decode(decoded.as_slice()).unwrap();
+        let series = |name: &str| {
+            request.timeseries.iter().find(|ts| ts.labels[0].value == name).unwrap_or_else(|| panic!("no {name}"))
+        };
+        let mark = series("anthias_mark_price");
+        assert_eq!(mark.labels[0].name, "__name__");
+        assert_eq!((mark.labels[1].name.as_str(), mark.labels[1].value.as_str()), ("coin", "BTC"));
+        assert_eq!(mark.samples, vec![RemoteSample { value: 65000.5, timestamp: 1_700_000_000_000 }]);
+        let inserted = series("anthias_inserted_rows_total");
+        assert_eq!(inserted.labels.len(), 1);
+        assert_eq!(inserted.samples[0].value, 3.0);
+    }
+
+    #[tokio::test]
+    async fn test_push_gives_up_on_client_error() {
+        let app = Router::new().route("/api/v1/write", post(async || StatusCode::BAD_REQUEST));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v1/write", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = RemoteWriteClient::new(url, &HashMap::new(), 3).unwrap();
+        let sample = Sample { name: "anthias_failed_rows_total", coin: None, value: 1.0 };
+        assert!(client.push(&[sample], 0).await.is_err());
+        assert!(RemoteWriteClient::new(String::new(), &HashMap::from([("bad header".into(), "x".into())]), 0).is_err());
+    }
+}
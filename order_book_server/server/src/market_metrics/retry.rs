@@ -0,0 +1,207 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::time;
+
+/// Run `op`, retrying failures up to `max_retries` times.
+///
+/// The delay before retry `n` is `backoff * 2^(n - 1)`. Returns the final result
+/// along with the number of retries it took, so callers can keep the budget
+/// per tick and stop before the next tick is due.
+pub async fn retry_with_budget<T, E, F, Fut>(max_retries: u32, backoff: Duration, mut op: F) -> (Result<T, E>, u32)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut retries = 0;
+    loop {
+        // The failed result is dropped before sleeping so non-`Send` errors
+        // don't make the future `!Send`
+        match op().await {
+            Err(_) if retries < max_retries => {}
+            result => return (result, retries),
+        }
+        time::sleep(backoff * 2_u32.saturating_pow(retries)).await;
+        retries += 1;
+    }
+}
+
+/// Like [`retry_with_budget`], but only retries errors accepted by `is_retryable`.
+///
+/// Each delay is randomized to between half and all of `backoff * 2^(n - 1)`
+/// so clients failing together don't retry in lockstep.
+pub async fn retry_with_jitter<T, E, F, Fut>(
+    max_retries: u32,
+    backoff: Duration,
+    is_retryable: impl Fn(&E) -> bool,
+    mut op: F,
+) -> (Result<T, E>, u32)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut retries = 0;
+    loop {
+        match op().await {
+            Err(e) if retries < max_retries && is_retryable(&e) => {}
+            result => return (result, retries),
+        }
+        let delay = backoff * 2_u32.saturating_pow(retries);
+        time::sleep(delay.mul_f64(rand::random_range(0.5..=1.0))).await;
+        retries += 1;
+    }
+}
+
+/// State of a [`CircuitBreaker`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Calls go ahead
+    Closed,
+    /// Calls are skipped until the cooldown passes
+    Open,
+    /// The cooldown passed; the next call is a probe that closes the breaker
+    /// on success and reopens it on failure
+    HalfOpen,
+}
+
+/// Stops calling a failing dependency after `failure_threshold` consecutive
+/// failures, then lets a single probe through once `cooldown` has passed
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    /// Consecutive failures that open the breaker (0 never opens it)
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    #[must_use]
+    pub const fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self { failure_threshold, cooldown, state: BreakerState::Closed, consecutive_failures: 0, opened_at: None }
+    }
+
+    #[must_use]
+    pub const fn state(&self) -> BreakerState {
+        self.state
+    }
+
+    /// Whether a call may go ahead at `now`, moving an open breaker to
+    /// half-open once its cooldown has passed
+    pub fn allow(&mut self, now: Instant) -> bool {
+        if self.state == BreakerState::Open {
+            if self.opened_at.is_some_and(|opened_at| now.duration_since(opened_at) < self.cooldown) {
+                return false;
+            }
+            self.state = BreakerState::HalfOpen;
+        }
+        true
+    }
+
+    /// Record the outcome of a call [`Self::allow`] let through, returning the
+    /// new state if it changed
+    pub fn record(&mut self, success: bool, now: Instant) -> Option<BreakerState> {
+        let previous = self.state;
+        if success {
+            self.consecutive_failures = 0;
+            self.state = BreakerState::Closed;
+        } else {
+            self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+            let tripped = self.failure_threshold > 0 && self.consecutive_failures >= self.failure_threshold;
+            if previous == BreakerState::HalfOpen || tripped {
+                self.state = BreakerState::Open;
+                self.opened_at = Some(now);
+            }
+        }
+        (self.state != previous).then_some(self.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_retry_succeeds_within_budget() {
+        let attempts = AtomicU32::new(0);
+        let (result, retries) = retry_with_budget(2, Duration::from_millis(1), || async {
+            if attempts.fetch_add(1, Ordering::SeqCst) == 0 { Err("connection reset") } else { Ok(42) }
+        })
+        .await;
+
+        assert_eq!(result, Ok(42));
+        assert_eq!(retries, 1);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up_after_budget() {
+        let attempts = AtomicU32::new(0);
+        let (result, retries) = retry_with_budget(2, Duration::from_millis(1), || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>("connection reset")
+        })
+        .await;
+
+        assert_eq!(result, Err("connection reset"));
+        assert_eq!(retries, 2);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_jitter_retry_stops_on_permanent_error() {
+        let attempts = AtomicU32::new(0);
+        let (result, retries) = retry_with_jitter(
+            5,
+            Duration::from_millis(1),
+            |e: &&str| *e == "timeout",
+            || async {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 | 1 => Err("timeout"),
+                    _ => Err::<(), _>("bad request"),
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(result, Err("bad request"));
+        assert_eq!(retries, 2);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_breaker_opens_and_probes_after_cooldown() {
+        let cooldown = Duration::from_secs(30);
+        let mut breaker = CircuitBreaker::new(3, cooldown);
+        let start = Instant::now();
+
+        assert_eq!(breaker.record(false, start), None);
+        assert_eq!(breaker.record(true, start), None);
+        assert_eq!(breaker.record(false, start), None);
+        assert_eq!(breaker.record(false, start), None);
+        assert_eq!(breaker.record(false, start), Some(BreakerState::Open));
+        assert!(!breaker.allow(start + Duration::from_secs(29)));
+
+        // A failed probe reopens the breaker for another cooldown
+        assert!(breaker.allow(start + cooldown));
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        let reopened = start + cooldown;
+        assert_eq!(breaker.record(false, reopened), Some(BreakerState::Open));
+        assert!(!breaker.allow(reopened + Duration::from_secs(1)));
+
+        assert!(breaker.allow(reopened + cooldown));
+        assert_eq!(breaker.record(true, reopened + cooldown), Some(BreakerState::Closed));
+        assert!(breaker.allow(reopened + cooldown));
+    }
+
+    #[test]
+    fn test_zero_threshold_never_opens() {
+        let mut breaker = CircuitBreaker::new(0, Duration::from_secs(30));
+        let now = Instant::now();
+        for _ in 0..10 {
+            assert_eq!(breaker.record(false, now), None);
+        }
+        assert!(breaker.allow(now));
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+
+/// A secret that could not be resolved, naming where it was looked up but
+/// never its value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretError {
+    pub provider: &'static str,
+    /// Path, ARN or other location the secret was read from
+    pub location: String,
+    pub reason: String,
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to resolve secret from {} '{}': {}", self.provider, self.location, self.reason)
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+/// Somewhere secrets such as the database URL or API keys are kept outside
+/// plain environment variables
+pub trait SecretProvider {
+    /// Short name used in errors, e.g. `file`
+    fn name(&self) -> &'static str;
+
+    /// The secret stored at `location`, a path or ARN depending on the provider
+    fn resolve(&self, location: &str) -> Result<String, SecretError>;
+
+    fn error(&self, location: &str, reason: impl fmt::Display) -> SecretError
+    where
+        Self: Sized,
+    {
+        SecretError { provider: self.name(), location: location.to_string(), reason: reason.to_string() }
+    }
+}
+
+/// Reads a secret from a file, e.g. one mounted by Docker or Kubernetes,
+/// ignoring surrounding whitespace such as a trailing newline
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSecretProvider;
+
+impl SecretProvider for FileSecretProvider {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn resolve(&self, location: &str) -> Result<String, SecretError> {
+        let contents = std::fs::read_to_string(location).map_err(|e| self.error(location, e))?;
+        let secret = contents.trim();
+        if secret.is_empty() {
+            return Err(self.error(location, "file is empty"));
+        }
+        Ok(secret.to_string())
+    }
+}
+
+/// Reads the string value of an AWS Secrets Manager secret by ARN or name,
+/// with credentials and region from the usual AWS environment and profile
+#[cfg(feature = "aws-secrets")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AwsSecretsManagerProvider;
+
+#[cfg(feature = "aws-secrets")]
+impl SecretProvider for AwsSecretsManagerProvider {
+    fn name(&self) -> &'static str {
+        "aws-secrets-manager"
+    }
+
+    fn resolve(&self, location: &str) -> Result<String, SecretError> {
+        use aws_sdk_secretsmanager::error::DisplayErrorContext;
+
+        let fetch = async {
+            let config = aws_config::load_from_env().await;
+            let client = aws_sdk_secretsmanager::Client::new(&config);
+            let output = client
+                .get_secret_value()
+                .secret_id(location)
+                .send()
+                .await
+                .map_err(|e| DisplayErrorContext(e).to_string())?;
+            output.secret_string().map(str::to_string).ok_or_else(|| "secret has no string value".to_string())
+        };
+        // Config is loaded synchronously, possibly from within a runtime, so the
+        // request runs on its own thread and runtime
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build();
+                    runtime.map_err(|e| e.to_string())?.block_on(fetch)
+                })
+                .join()
+                .unwrap_or_else(|_| Err("secret lookup panicked".to_string()))
+        })
+        .map_err(|reason| self.error(location, reason))
+    }
+}
+
+/// The secret `name` from the environment, `None` when unset.
+///
+/// Read from the variable itself, the file named by `{name}_FILE`, or the AWS
+/// Secrets Manager secret named by `{name}_SECRET_ARN` with the `aws-secrets` feature.
+pub fn secret_from_env(name: &str) -> Result<Option<String>, String> {
+    let value = std::env::var(name).ok();
+    let file = std::env::var(format!("{name}_FILE")).ok();
+    let arn = std::env::var(format!("{name}_SECRET_ARN")).ok();
+    if [value.is_some(), file.is_some(), arn.is_some()].into_iter().filter(|set| *set).count() > 1 {
+        return Err(format!("Set only one of {name}, {name}_FILE and {name}_SECRET_ARN"));
+    }
+
+    if let Some(path) = file {
+        return FileSecretProvider.resolve(&path).map(Some).map_err(|e| format!("{name}_FILE: {e}"));
+    }
+    if let Some(arn) = arn {
+        #[cfg(feature = "aws-secrets")]
+        return AwsSecretsManagerProvider.resolve(&arn).map(Some).map_err(|e| format!("{name}_SECRET_ARN: {e}"));
+        #[cfg(not(feature = "aws-secrets"))]
+        return Err(format!("{name}_SECRET_ARN '{arn}' requires building with the `aws-secrets` feature"));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market_metrics::test_util::{is_env_child, report_to_parent, run_with_env, temp_dir};
+
+    #[test]
+    fn test_file_secret_is_trimmed() {
+        let dir = temp_dir("secrets_file");
+        let path = dir.join("db_url");
+        std::fs::write(&path, "postgresql://metrics:hunter2@db/metrics\n").unwrap();
+        let path = path.to_str().unwrap();
+        assert_eq!(FileSecretProvider.resolve(path).unwrap(), "postgresql://metrics:hunter2@db/metrics");
+
+        std::fs::write(path, " \n").unwrap();
+        let err = FileSecretProvider.resolve(path).unwrap_err();
+        assert_eq!((err.provider, err.reason.as_str()), ("file", "file is empty"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_secret_errors_name_location_not_value() {
+        let provider: &dyn SecretProvider = &FileSecretProvider;
+        let err = provider.resolve("/nonexistent/db_url").unwrap_err();
+        assert!(err.to_string().starts_with("Failed to resolve secret from file '/nonexistent/db_url'"), "{err}");
+    }
+
+    const NAME: &str = "SECRETS_TEST_API_KEY";
+
+    /// [`secret_from_env`] of [`NAME`] with only `vars` of its variables set
+    fn secret_with_env(vars: &[(&str, &str)]) -> Result<Option<String>, String> {
+        let unset = [NAME, "SECRETS_TEST_API_KEY_FILE", "SECRETS_TEST_API_KEY_SECRET_ARN"];
+        run_with_env("market_metrics::secrets::tests::test_secret_in_env_child", &unset, vars)
+    }
+
+    /// Run by [`secret_with_env`] in a child process, doing nothing otherwise
+    #[test]
+    fn test_secret_in_env_child() {
+        if is_env_child() {
+            report_to_parent(&secret_from_env(NAME));
+        }
+    }
+
+    #[test]
+    fn test_secret_from_env_sources() {
+        let dir = temp_dir("secrets_env");
+        let path = dir.join("api_key");
+        std::fs::write(&path, "from-file\n").unwrap();
+        let (path, missing) = (path.to_str().unwrap(), dir.join("missing"));
+
+        assert_eq!(secret_with_env(&[]), Ok(None));
+        assert_eq!(secret_with_env(&[(NAME, "from-env")]), Ok(Some("from-env".to_string())));
+
+        let both = secret_with_env(&[(NAME, "from-env"), ("SECRETS_TEST_API_KEY_FILE", path)]);
+        assert!(both.unwrap_err().contains("Set only one of"));
+        let file = secret_with_env(&[("SECRETS_TEST_API_KEY_FILE", path)]);
+        assert_eq!(file, Ok(Some("from-file".to_string())));
+
+        let err = secret_with_env(&[("SECRETS_TEST_API_KEY_FILE", missing.to_str().unwrap())]).unwrap_err();
+        assert!(err.starts_with(&format!("{NAME}_FILE: Failed to resolve secret from file")), "{err}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
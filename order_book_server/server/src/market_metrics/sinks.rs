@@ -0,0 +1,227 @@
+use crate::market_metrics::types::MarketMetrics;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, hash_map::Entry};
+use std::io;
+use std::str::FromStr;
+
+pub const CSV_HEADER: &str = "timestamp,coin,mark_price,oracle_price,mid_price,best_bid,best_ask,spread_pct,\
+funding_rate_pct,open_interest,volume_24h,total_depth_5pct,total_depth_10pct,total_depth_25pct";
+
+/// Line encoding a sink writes each metrics row in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkFormat {
+    /// One JSON object per line
+    #[default]
+    Json,
+    /// One CSV row per line, with the columns of [`CSV_HEADER`]
+    Csv,
+}
+
+impl SinkFormat {
+    /// Encode `metrics` as a single newline-terminated line
+    pub fn encode(self, metrics: &MarketMetrics) -> io::Result<String> {
+        match self {
+            Self::Json => {
+                let mut line = serde_json::to_string(metrics)?;
+                line.push('\n');
+                Ok(line)
+            }
+            Self::Csv => Ok(csv_row(metrics)),
+        }
+    }
+}
+
+impl FromStr for SinkFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            other => Err(format!("Unknown sink format '{other}': expected json or csv")),
+        }
+    }
+}
+
+fn csv_row(metrics: &MarketMetrics) -> String {
+    let fields = [
+        metrics.mark_price,
+        metrics.oracle_price,
+        metrics.mid_price,
+        metrics.best_bid,
+        metrics.best_ask,
+        metrics.spread_pct,
+        metrics.funding_rate_pct,
+        metrics.open_interest,
+        metrics.volume_24h,
+        metrics.total_depth_5pct,
+        metrics.total_depth_10pct,
+        metrics.total_depth_25pct,
+    ];
+    let values = fields
+        .iter()
+        .map(|value| value.map(|v: Decimal| v.to_string()).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{},{},{}\n", metrics.timestamp.to_rfc3339(), metrics.coin, values)
+}
+
+/// A destination for metrics rows besides the database
+pub trait MetricsSink: Send {
+    fn name(&self) -> &'static str;
+
+    /// Formats this sink is able to write
+    fn accepted_formats(&self) -> &'static [SinkFormat];
+
+    /// Format this sink is configured to receive
+    fn format(&self) -> SinkFormat;
+
+    /// Write one row, already encoded in [`Self::format`]
+    fn write(&mut self, metrics: &MarketMetrics, encoded: &str) -> io::Result<()>;
+
+    /// Finish any buffered or background work before shutdown
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Fans each metrics row out to the configured sinks, encoding it once per format
+#[derive(Default)]
+pub struct SinkDispatcher {
+    sinks: Vec<Box<dyn MetricsSink>>,
+}
+
+impl SinkDispatcher {
+    /// Add a sink, rejecting it if it can't write its configured format
+    pub fn add(&mut self, sink: Box<dyn MetricsSink>) -> Result<(), String> {
+        if !sink.accepted_formats().contains(&sink.format()) {
+            return Err(format!(
+                "Sink '{}' does not support the {:?} format (accepts {:?})",
+                sink.name(),
+                sink.format(),
+                sink.accepted_formats()
+            ));
+        }
+        self.sinks.push(sink);
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Write `metrics` to every sink, returning the sinks that failed
+    pub fn dispatch(&mut self, metrics: &MarketMetrics) -> Vec<(&'static str, io::Error)> {
+        let mut encoded = HashMap::new();
+        let mut failures = Vec::new();
+        for sink in &mut self.sinks {
+            let format = sink.format();
+            let line = match encoded.entry(format) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => match format.encode(metrics) {
+                    Ok(line) => entry.insert(line),
+                    Err(e) => {
+                        failures.push((sink.name(), e));
+                        continue;
+                    }
+                },
+            };
+            if let Err(e) = sink.write(metrics, line) {
+                failures.push((sink.name(), e));
+            }
+        }
+        failures
+    }
+
+    /// Flush every sink, returning the sinks that failed
+    pub fn flush(&mut self) -> Vec<(&'static str, io::Error)> {
+        self.sinks.iter_mut().filter_map(|sink| sink.flush().err().map(|e| (sink.name(), e))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct RecordingSink {
+        format: SinkFormat,
+        lines: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MetricsSink for RecordingSink {
+        fn name(&self) -> &'static str {
+            "recording"
+        }
+
+        fn accepted_formats(&self) -> &'static [SinkFormat] {
+            &[SinkFormat::Json, SinkFormat::Csv]
+        }
+
+        fn format(&self) -> SinkFormat {
+            self.format
+        }
+
+        fn write(&mut self, _metrics: &MarketMetrics, encoded: &str) -> io::Result<()> {
+            self.lines.lock().unwrap().push(encoded.to_string());
+            Ok(())
+        }
+    }
+
+    fn recording_sink(format: SinkFormat) -> (Box<dyn MetricsSink>, Arc<Mutex<Vec<String>>>) {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        (Box::new(RecordingSink { format, lines: lines.clone() }), lines)
+    }
+
+    #[test]
+    fn test_each_sink_receives_its_format() {
+        let (json_sink, json_lines) = recording_sink(SinkFormat::Json);
+        let (csv_sink, csv_lines) = recording_sink(SinkFormat::Csv);
+        let mut dispatcher = SinkDispatcher::default();
+        dispatcher.add(json_sink).unwrap();
+        dispatcher.add(csv_sink).unwrap();
+
+        let mut metrics = MarketMetrics::new("BTC".to_string());
+        metrics.mark_price = Some(Decimal::from(100));
+        assert!(dispatcher.dispatch(&metrics).is_empty());
+
+        let json = json_lines.lock().unwrap().clone();
+        let decoded: MarketMetrics = serde_json::from_str(json[0].trim_end()).unwrap();
+        assert_eq!(decoded.coin, "BTC");
+        assert_eq!(decoded.mark_price, Some(Decimal::from(100)));
+
+        let csv = csv_lines.lock().unwrap().clone();
+        assert_eq!(csv[0].split(',').count(), CSV_HEADER.split(',').count());
+        assert!(csv[0].contains(",BTC,100,"));
+    }
+
+    #[test]
+    fn test_unsupported_format_rejected() {
+        struct JsonOnlySink;
+
+        impl MetricsSink for JsonOnlySink {
+            fn name(&self) -> &'static str {
+                "json_only"
+            }
+
+            fn accepted_formats(&self) -> &'static [SinkFormat] {
+                &[SinkFormat::Json]
+            }
+
+            fn format(&self) -> SinkFormat {
+                SinkFormat::Csv
+            }
+
+            fn write(&mut self, _metrics: &MarketMetrics, _encoded: &str) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut dispatcher = SinkDispatcher::default();
+        assert!(dispatcher.add(Box::new(JsonOnlySink)).is_err());
+        assert!(dispatcher.is_empty());
+    }
+}
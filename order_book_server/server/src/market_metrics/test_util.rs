@@ -0,0 +1,53 @@
+use serde::{Serialize, de::DeserializeOwned};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// An empty directory `{name}_{pid}` under the system temp dir, cleared of any
+/// earlier run's files. Tests running in parallel need names of their own.
+pub(super) fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{name}_{}", std::process::id()));
+    let _unused = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Set in the environment of a test process spawned by [`run_with_env`]
+const CHILD_VAR: &str = "MARKET_METRICS_ENV_TEST_CHILD";
+
+/// Marks the result a child test reports, after whatever the harness printed on the line
+const RESULT_PREFIX: &str = "env-test-result: ";
+
+/// Run the test at `path` (e.g. `market_metrics::config::tests::test_x`) in a
+/// child process with the variables in `unset` removed and `vars` set, and
+/// return the value it passed to [`report_to_parent`].
+///
+/// `std::env::set_var` is unsound while any other thread reads the environment,
+/// which tests running in parallel may always do, so tests needing particular
+/// variables get a process of their own instead.
+pub(super) fn run_with_env<T: DeserializeOwned>(path: &str, unset: &[&str], vars: &[(&str, &str)]) -> T {
+    let mut command = Command::new(std::env::current_exe().unwrap());
+    command.args(["--exact", path, "--nocapture", "--test-threads=1"]).env(CHILD_VAR, "1");
+    for name in unset {
+        command.env_remove(name);
+    }
+    let output = command.envs(vars.iter().copied()).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{path} failed in its child process:\n{stdout}");
+    let result = stdout
+        .lines()
+        .find_map(|line| line.split_once(RESULT_PREFIX).map(|(_, result)| result))
+        .unwrap_or_else(|| panic!("{path} reported no result, is it the full test path?\n{stdout}"));
+    serde_json::from_str(result).unwrap()
+}
+
+/// Whether this is a child process spawned by [`run_with_env`], outside of
+/// which child tests should return at once
+pub(super) fn is_env_child() -> bool {
+    std::env::var_os(CHILD_VAR).is_some()
+}
+
+/// Hand `value` back to the [`run_with_env`] call that spawned this process
+pub(super) fn report_to_parent(value: &impl Serialize) {
+    println!("{RESULT_PREFIX}{}", serde_json::to_string(value).unwrap());
+}
@@ -0,0 +1,710 @@
+use rust_decimal::{Decimal, MathematicalOps};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Fixed-capacity window of the most recent samples
+#[derive(Debug, Clone)]
+pub struct RollingWindow {
+    values: VecDeque<Decimal>,
+    capacity: usize,
+}
+
+impl RollingWindow {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self { values: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    /// Add a sample, evicting the oldest one once the window is full
+    pub fn push(&mut self, value: Decimal) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        self.capacity > 0 && self.values.len() == self.capacity
+    }
+
+    /// Samples from oldest to newest
+    #[must_use]
+    pub fn to_vec(&self) -> Vec<Decimal> {
+        self.values.iter().copied().collect()
+    }
+
+    #[must_use]
+    pub fn sum(&self) -> Decimal {
+        self.values.iter().sum()
+    }
+
+    #[must_use]
+    pub fn mean(&self) -> Option<Decimal> {
+        if self.values.is_empty() {
+            return None;
+        }
+        let sum: Decimal = self.values.iter().sum();
+        Some(sum / Decimal::from(self.values.len()))
+    }
+}
+
+/// Converts a cumulative order book update count into updates per second
+#[derive(Debug, Default)]
+pub struct UpdateRateTracker {
+    last: Option<(u64, Instant)>,
+}
+
+impl UpdateRateTracker {
+    /// Returns the rate since the previous call, or `None` on the first sample
+    pub fn update(&mut self, count: u64, now: Instant) -> Option<Decimal> {
+        let previous = self.last.replace((count, now));
+        let (last_count, last_time) = previous?;
+
+        let elapsed = Decimal::try_from(now.duration_since(last_time).as_secs_f64()).ok()?;
+        if elapsed.is_zero() {
+            return None;
+        }
+        // The count restarts from zero when the book is re-initialized
+        let updates = count.checked_sub(last_count)?;
+        Some(Decimal::from(updates) / elapsed)
+    }
+}
+
+/// Flags quote update rates that exceed a rolling baseline by a configurable factor
+#[derive(Debug, Clone)]
+pub struct QuoteStuffingDetector {
+    baseline: RollingWindow,
+    factor: Decimal,
+}
+
+impl QuoteStuffingDetector {
+    #[must_use]
+    pub fn new(window: usize, factor: Decimal) -> Self {
+        Self { baseline: RollingWindow::new(window), factor }
+    }
+
+    /// Record a new rate and return whether it looks like quote stuffing.
+    ///
+    /// Nothing is flagged until the baseline window has filled, and flagged
+    /// samples are kept out of the baseline so a sustained burst stays flagged.
+    pub fn update(&mut self, rate: Decimal) -> bool {
+        let suspected = self.baseline.is_full()
+            && self.baseline.mean().is_some_and(|mean| mean > Decimal::ZERO && rate > mean * self.factor);
+
+        if !suspected {
+            self.baseline.push(rate);
+        }
+        suspected
+    }
+}
+
+/// One side of a book snapshot, as [`SpoofingDetector::update`] sees it
+#[derive(Debug, Clone, Copy)]
+pub struct BookSide<'a> {
+    /// Best price on this side
+    pub best: Decimal,
+    /// Price of every level
+    pub prices: &'a [Decimal],
+    /// `(price, size)` levels large and far enough from mid to be watched
+    pub large: &'a [(Decimal, Decimal)],
+}
+
+/// A side of the previous snapshot, kept whole so a large level that merely
+/// shrank or grew is told apart from one that came or went
+#[derive(Debug, Clone)]
+struct LastSide {
+    prices: HashSet<Decimal>,
+    large: Vec<(Decimal, Decimal)>,
+}
+
+impl LastSide {
+    fn new(side: BookSide<'_>) -> Self {
+        Self { prices: side.prices.iter().copied().collect(), large: side.large.to_vec() }
+    }
+
+    /// Large levels of `side` at prices this side had no level at
+    fn appeared(&self, side: BookSide<'_>) -> usize {
+        side.large.iter().filter(|(price, _)| !self.prices.contains(price)).count()
+    }
+
+    /// Large levels whose price `side` has no level at any more, and that
+    /// `reached` says the price did not trade through
+    fn pulled(&self, side: BookSide<'_>, reached: impl Fn(Decimal) -> bool) -> usize {
+        self.large.iter().filter(|(price, _)| !side.prices.contains(price) && !reached(*price)).count()
+    }
+}
+
+/// Flags large orders away from mid that repeatedly appear and are pulled
+/// before the price reaches them, a pattern typical of spoofing
+#[derive(Debug, Clone)]
+pub struct SpoofingDetector {
+    /// The previous snapshot, bids then asks
+    last: Option<(LastSide, LastSide)>,
+    /// Large levels that appeared, per snapshot
+    appeared: RollingWindow,
+    /// Large levels that vanished before the price reached them, per snapshot
+    pulled: RollingWindow,
+    min_pulls: usize,
+    suspected: bool,
+}
+
+impl SpoofingDetector {
+    /// Flag once `min_pulls` large levels were pulled within the last `window`
+    /// snapshots (0 disables the flag)
+    #[must_use]
+    pub fn new(window: usize, min_pulls: usize) -> Self {
+        Self {
+            last: None,
+            appeared: RollingWindow::new(window),
+            pulled: RollingWindow::new(window),
+            min_pulls,
+            suspected: false,
+        }
+    }
+
+    /// Record a book snapshot, returning whether spoofing is suspected and a
+    /// score: the fraction of large levels that came and went within the
+    /// window without trading.
+    ///
+    /// Levels are compared against the whole previous book, so a large level
+    /// that shrinks below the size threshold or a small one that grows past it
+    /// neither appears nor is pulled. A vanished bid counts as filled rather than
+    /// pulled once the best bid is at or below its price, and likewise for asks,
+    /// so large orders consumed by a genuine move are not held against the book.
+    pub fn update(&mut self, bids: BookSide<'_>, asks: BookSide<'_>) -> (bool, Decimal) {
+        if let Some((last_bids, last_asks)) = &self.last {
+            let appeared = last_bids.appeared(bids) + last_asks.appeared(asks);
+            let pulled =
+                last_bids.pulled(bids, |price| bids.best <= price) + last_asks.pulled(asks, |price| asks.best >= price);
+            self.appeared.push(Decimal::from(appeared));
+            self.pulled.push(Decimal::from(pulled));
+        }
+        self.last = Some((LastSide::new(bids), LastSide::new(asks)));
+
+        // Levels already resting at the first snapshot can be pulled without
+        // having been seen to appear, so the score is capped at 1
+        let pulled = self.pulled.sum();
+        let score = if pulled.is_zero() { Decimal::ZERO } else { pulled / self.appeared.sum().max(pulled) };
+        self.suspected = self.min_pulls > 0 && pulled >= Decimal::from(self.min_pulls);
+        (self.suspected, score)
+    }
+
+    /// Whether the latest snapshot was flagged
+    #[must_use]
+    pub const fn is_suspected(&self) -> bool {
+        self.suspected
+    }
+}
+
+/// Detects a sudden drop in depth relative to its short rolling average
+#[derive(Debug, Clone)]
+pub struct DepthCollapseDetector {
+    recent: RollingWindow,
+    drop_pct: Decimal,
+}
+
+impl DepthCollapseDetector {
+    /// `drop_pct` is a fraction, e.g. `0.5` fires when depth halves
+    #[must_use]
+    pub fn new(window: usize, drop_pct: Decimal) -> Self {
+        Self { recent: RollingWindow::new(window), drop_pct }
+    }
+
+    /// Record the current depth, returning the rolling average it collapsed
+    /// from when it is below `(1 - drop_pct) * rolling_avg`
+    pub fn update(&mut self, depth: Decimal) -> Option<Decimal> {
+        let collapsed_from = if self.recent.is_full() {
+            self.recent.mean().filter(|avg| *avg > Decimal::ZERO && depth < (Decimal::ONE - self.drop_pct) * avg)
+        } else {
+            None
+        };
+        self.recent.push(depth);
+        collapsed_from
+    }
+}
+
+/// Flags an oracle price that stops moving while the mark price keeps moving
+#[derive(Debug, Clone)]
+pub struct OracleStalenessTracker {
+    threshold: usize,
+    last: Option<(Decimal, Decimal)>,
+    unchanged_ticks: usize,
+}
+
+impl OracleStalenessTracker {
+    #[must_use]
+    pub const fn new(threshold: usize) -> Self {
+        Self { threshold, last: None, unchanged_ticks: 0 }
+    }
+
+    #[must_use]
+    pub const fn is_stale(&self) -> bool {
+        self.threshold > 0 && self.unchanged_ticks >= self.threshold
+    }
+
+    /// Record the latest oracle and mark prices, returning whether the oracle is stale.
+    ///
+    /// Only ticks where the mark moved but the oracle did not count towards the
+    /// threshold; any oracle change resets it.
+    pub fn update(&mut self, oracle_price: Decimal, mark_price: Decimal) -> bool {
+        if let Some((last_oracle, last_mark)) = self.last {
+            if oracle_price != last_oracle {
+                self.unchanged_ticks = 0;
+            } else if mark_price != last_mark {
+                self.unchanged_ticks += 1;
+            }
+        }
+        self.last = Some((oracle_price, mark_price));
+        self.is_stale()
+    }
+}
+
+/// Flags 24h volume jumps that come without any open interest or price movement
+#[derive(Debug, Clone)]
+pub struct VolumeAnomalyDetector {
+    spike_pct: Decimal,
+    flat_pct: Decimal,
+    last: Option<(Decimal, Decimal, Decimal)>,
+}
+
+impl VolumeAnomalyDetector {
+    /// Flags volume rising by more than `spike_pct` in one tick while open
+    /// interest and price each move less than `flat_pct` (both fractions)
+    #[must_use]
+    pub const fn new(spike_pct: Decimal, flat_pct: Decimal) -> Self {
+        Self { spike_pct, flat_pct, last: None }
+    }
+
+    /// Record the latest volume, open interest and price, returning whether
+    /// the volume change looks anomalous
+    pub fn update(&mut self, volume: Decimal, open_interest: Decimal, price: Decimal) -> bool {
+        let Some((last_volume, last_oi, last_price)) = self.last.replace((volume, open_interest, price)) else {
+            return false;
+        };
+
+        let relative_change =
+            |current: Decimal, previous: Decimal| (!previous.is_zero()).then(|| (current - previous) / previous);
+        let Some(volume_change) = relative_change(volume, last_volume) else {
+            return false;
+        };
+        let is_flat = |change: Option<Decimal>| change.is_some_and(|c| c.abs() < self.flat_pct);
+
+        volume_change > self.spike_pct
+            && is_flat(relative_change(open_interest, last_oi))
+            && is_flat(relative_change(price, last_price))
+    }
+}
+
+/// Seconds since 24h volume last changed meaningfully, a proxy for time since
+/// the last trade when no trade feed is available
+#[derive(Debug, Clone)]
+pub struct ActivityStalenessTracker {
+    min_change_pct: Decimal,
+    last_activity: Option<(Decimal, Instant)>,
+}
+
+impl ActivityStalenessTracker {
+    /// Volume moving by at least `min_change_pct` (a fraction) from its value
+    /// at the last activity counts as new activity
+    #[must_use]
+    pub const fn new(min_change_pct: Decimal) -> Self {
+        Self { min_change_pct, last_activity: None }
+    }
+
+    /// Record the latest 24h volume, returning the seconds since the last activity.
+    ///
+    /// Small changes are measured against the volume at the last activity, so
+    /// a slow drift still registers once it adds up to `min_change_pct`.
+    pub fn update(&mut self, volume: Decimal, now: Instant) -> Option<Decimal> {
+        let (reference, since) = match self.last_activity {
+            Some((reference, since)) => (reference, since),
+            None => (volume, now),
+        };
+        let changed = if reference.is_zero() {
+            !volume.is_zero()
+        } else {
+            ((volume - reference) / reference).abs() >= self.min_change_pct
+        };
+        let since = if changed { now } else { since };
+        self.last_activity = Some((if changed { volume } else { reference }, since));
+
+        Decimal::try_from(now.duration_since(since).as_secs_f64()).ok()
+    }
+}
+
+/// What happens to a row whose mid is flagged by [`BadPrintGuard`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BadPrintPolicy {
+    /// Store the row with `suspected_bad_print` set
+    #[default]
+    Flag,
+    /// Drop the row without storing it
+    Skip,
+}
+
+impl FromStr for BadPrintPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "flag" => Ok(Self::Flag),
+            "skip" => Ok(Self::Skip),
+            other => Err(format!("Unknown bad print policy '{other}': expected flag or skip")),
+        }
+    }
+}
+
+/// Consecutive suspect ticks after which [`BadPrintGuard::check`] takes the mid
+/// as the new baseline, treating the move as real
+pub const BAD_PRINT_REBASELINE_TICKS: u32 = 3;
+
+/// Flags mids that moved implausibly far from the last stored mid within one tick
+#[derive(Debug, Clone)]
+pub struct BadPrintGuard {
+    max_move_pct: Option<Decimal>,
+    last_stored: Option<Decimal>,
+    consecutive_suspect: u32,
+}
+
+impl BadPrintGuard {
+    /// Flag moves larger than `max_move_pct` as a fraction, never flagging when it is `None`
+    #[must_use]
+    pub const fn new(max_move_pct: Option<Decimal>) -> Self {
+        Self { max_move_pct, last_stored: None, consecutive_suspect: 0 }
+    }
+
+    /// Whether `mid` moved more than the allowed fraction from the last stored mid
+    #[must_use]
+    pub fn is_suspect(&self, mid: Decimal) -> bool {
+        let (Some(max_move_pct), Some(last)) = (self.max_move_pct, self.last_stored) else {
+            return false;
+        };
+        !last.is_zero() && ((mid - last) / last).abs() > max_move_pct
+    }
+
+    /// Like [`Self::is_suspect`], but once [`BAD_PRINT_REBASELINE_TICKS`] ticks
+    /// in a row were suspect the next suspect mid becomes the baseline and is
+    /// accepted, so a genuine jump or a bad first mid doesn't flag every tick after it
+    pub fn check(&mut self, mid: Decimal) -> bool {
+        if !self.is_suspect(mid) {
+            self.consecutive_suspect = 0;
+            return false;
+        }
+        if self.consecutive_suspect >= BAD_PRINT_REBASELINE_TICKS {
+            self.record(mid);
+            return false;
+        }
+        self.consecutive_suspect += 1;
+        true
+    }
+
+    /// Record the mid of a stored row as the baseline for the next tick
+    pub const fn record(&mut self, mid: Decimal) {
+        self.last_stored = Some(mid);
+        self.consecutive_suspect = 0;
+    }
+}
+
+/// Seconds in a 365-day year, used to annualize realized volatility
+const SECONDS_PER_YEAR: u64 = 31_536_000;
+
+/// Log returns needed before realized volatility is reported
+const MIN_VOL_RETURNS: usize = 10;
+
+/// Hard cap on buffered mids, so very short ticks can't grow the buffer with the window
+const MAX_VOL_SAMPLES: usize = 10_000;
+
+/// Annualized realized volatility of mid log returns over a rolling time window.
+///
+/// Each sample keeps its log return from the previous one, and running sums
+/// of the returns in the window make every update O(1).
+#[derive(Debug, Clone)]
+pub struct VolatilityTracker {
+    window: Duration,
+    /// `(time, mid, log return from the previous sample)`, the front's return
+    /// is not part of the window
+    samples: VecDeque<(Instant, Decimal, Decimal)>,
+    sum: Decimal,
+    sum_sq: Decimal,
+}
+
+impl VolatilityTracker {
+    #[must_use]
+    pub const fn new(window: Duration) -> Self {
+        Self { window, samples: VecDeque::new(), sum: Decimal::ZERO, sum_sq: Decimal::ZERO }
+    }
+
+    /// Record a mid, returning the annualized standard deviation of log returns
+    /// across the window, or `None` until the window holds enough returns
+    pub fn update(&mut self, mid: Decimal, now: Instant) -> Option<Decimal> {
+        if mid <= Decimal::ZERO {
+            return None;
+        }
+        while self.samples.front().is_some_and(|(at, _, _)| now.duration_since(*at) > self.window)
+            || self.samples.len() >= MAX_VOL_SAMPLES
+        {
+            self.evict_oldest();
+        }
+        let log_return = self.samples.back().and_then(|(_, prev, _)| (mid / prev).checked_ln()).unwrap_or_default();
+        if !self.samples.is_empty() {
+            self.sum += log_return;
+            self.sum_sq += log_return * log_return;
+        }
+        self.samples.push_back((now, mid, log_return));
+        self.realized_vol()
+    }
+
+    /// Drop the oldest sample, taking the next sample's return out of the window
+    fn evict_oldest(&mut self) {
+        self.samples.pop_front();
+        if let Some((_, _, log_return)) = self.samples.front() {
+            self.sum -= log_return;
+            self.sum_sq -= log_return * log_return;
+        }
+    }
+
+    fn realized_vol(&self) -> Option<Decimal> {
+        let returns = self.samples.len().saturating_sub(1);
+        if returns < MIN_VOL_RETURNS {
+            return None;
+        }
+        let n = Decimal::from(returns);
+        let variance = ((self.sum_sq - self.sum * self.sum / n) / (n - Decimal::ONE)).max(Decimal::ZERO);
+
+        // Scale the per-return variance by how many returns of the observed spacing fit in a year
+        let (first, _, _) = self.samples.front()?;
+        let (last, _, _) = self.samples.back()?;
+        let span = Decimal::try_from(last.duration_since(*first).as_secs_f64()).ok()?;
+        if span.is_zero() {
+            return None;
+        }
+        (variance * Decimal::from(SECONDS_PER_YEAR) * n / span).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volatility_tracker_annualizes_log_returns() {
+        let mut tracker = VolatilityTracker::new(Duration::from_mins(1));
+        let start = Instant::now();
+        // Alternating +/-1% moves once a second
+        let mids = (0..=MIN_VOL_RETURNS).map(|i| if i % 2 == 0 { Decimal::from(100) } else { Decimal::from(101) });
+        let vols = mids
+            .enumerate()
+            .map(|(i, mid)| tracker.update(mid, start + Duration::from_secs(i as u64)))
+            .collect::<Vec<_>>();
+        assert!(vols[..MIN_VOL_RETURNS].iter().all(Option::is_none));
+
+        // Returns of +/-ln(1.01) have a sample variance of ln(1.01)^2 * 10 / 9, annualized per second
+        let ln = Decimal::new(101, 2).ln();
+        let variance = ln * ln * Decimal::from(10) / Decimal::from(9);
+        let expected = (variance * Decimal::from(SECONDS_PER_YEAR)).sqrt().unwrap();
+        let vol = vols[MIN_VOL_RETURNS].unwrap();
+        assert!((vol - expected).abs() < Decimal::new(1, 4), "{vol} vs {expected}");
+
+        // Samples older than the window are evicted, leaving too few returns
+        assert_eq!(tracker.update(Decimal::from(100), start + Duration::from_secs(65)), None);
+        assert_eq!(tracker.samples.len(), 7);
+    }
+
+    #[test]
+    fn test_volatility_tracker_buffer_is_bounded() {
+        let mut tracker = VolatilityTracker::new(Duration::from_hours(1));
+        let start = Instant::now();
+        for i in 0..MAX_VOL_SAMPLES + 50 {
+            tracker.update(Decimal::from(100 + i % 3), start + Duration::from_millis(i as u64));
+        }
+        assert_eq!(tracker.samples.len(), MAX_VOL_SAMPLES);
+    }
+
+    #[test]
+    fn test_rolling_window_evicts_oldest() {
+        let mut window = RollingWindow::new(3);
+        for v in [1, 2, 3, 4] {
+            window.push(Decimal::from(v));
+        }
+        assert_eq!(window.len(), 3);
+        assert_eq!(window.mean(), Some(Decimal::from(3)));
+    }
+
+    #[test]
+    fn test_update_rate_tracker() {
+        let mut tracker = UpdateRateTracker::default();
+        let start = Instant::now();
+        assert_eq!(tracker.update(100, start), None);
+        assert_eq!(tracker.update(150, start + Duration::from_secs(2)), Some(Decimal::from(25)));
+    }
+
+    #[test]
+    fn test_quote_stuffing_burst_trips_flag() {
+        let mut detector = QuoteStuffingDetector::new(10, Decimal::from(5));
+        for _ in 0..10 {
+            assert!(!detector.update(Decimal::from(20)));
+        }
+
+        // Modest increase stays below the factor
+        assert!(!detector.update(Decimal::from(60)));
+
+        // Burst of updates well above 5x the baseline
+        assert!(detector.update(Decimal::from(500)));
+        assert!(detector.update(Decimal::from(450)));
+    }
+
+    #[test]
+    fn test_spoofing_flagged_for_repeatedly_pulled_levels() {
+        let side = |best, prices, large| BookSide { best, prices, large };
+        let d = Decimal::from;
+        let mut detector = SpoofingDetector::new(10, 3);
+        let (quiet_bids, wall_bids) = ([d(100), d(99)], [d(100), d(99), d(90)]);
+        let (quiet_asks, wall_asks) = ([d(101), d(102)], [d(101), d(102), d(110)]);
+        let (bid_wall, ask_wall) = ([(d(90), d(500))], [(d(110), d(400))]);
+        let (quiet_bid, quiet_ask) = (side(d(100), &quiet_bids, &[]), side(d(101), &quiet_asks, &[]));
+        assert_eq!(detector.update(quiet_bid, quiet_ask), (false, Decimal::ZERO));
+
+        // A bid wall far below mid appears and is pulled while the price holds
+        for _ in 0..2 {
+            assert!(!detector.update(side(d(100), &wall_bids, &bid_wall), quiet_ask).0);
+            assert!(!detector.update(quiet_bid, quiet_ask).0);
+        }
+        assert!(!detector.update(quiet_bid, side(d(101), &wall_asks, &ask_wall)).0);
+        assert!(!detector.is_suspected());
+        let (suspected, score) = detector.update(quiet_bid, quiet_ask);
+        assert!(suspected && detector.is_suspected());
+        assert_eq!(score, Decimal::ONE);
+
+        // A wall that stays put lowers the score
+        let (resting_bids, resting) = ([d(100), d(99), d(89)], [(d(89), d(500))]);
+        let (_, score) = detector.update(side(d(100), &resting_bids, &resting), quiet_ask);
+        assert_eq!(score, Decimal::new(75, 2));
+
+        // A wall that shrinks below the size threshold is still resting, and
+        // growing back past it is not a new appearance
+        let mut detector = SpoofingDetector::new(10, 1);
+        detector.update(side(d(100), &wall_bids, &bid_wall), quiet_ask);
+        assert_eq!(detector.update(side(d(100), &wall_bids, &[]), quiet_ask), (false, Decimal::ZERO));
+        assert_eq!(detector.update(side(d(100), &wall_bids, &bid_wall), quiet_ask), (false, Decimal::ZERO));
+
+        // A wall the price trades down to is filled, not pulled
+        let mut detector = SpoofingDetector::new(10, 1);
+        detector.update(quiet_bid, quiet_ask);
+        detector.update(side(d(100), &wall_bids, &bid_wall), quiet_ask);
+        let (traded_bids, traded_asks) = ([d(89), d(88)], [d(90), d(91)]);
+        let traded = detector.update(side(d(89), &traded_bids, &[]), side(d(90), &traded_asks, &[]));
+        assert_eq!(traded, (false, Decimal::ZERO));
+    }
+
+    #[test]
+    fn test_depth_collapse_detected() {
+        let mut detector = DepthCollapseDetector::new(5, Decimal::new(5, 1));
+        for depth in [1000, 1020, 980, 1000, 1000] {
+            assert_eq!(detector.update(Decimal::from(depth)), None);
+        }
+
+        // A 40% dip is within tolerance, a 70% collapse is not
+        assert_eq!(detector.update(Decimal::from(600)), None);
+        let avg = detector.update(Decimal::from(300)).unwrap();
+        assert!(avg > Decimal::from(900));
+    }
+
+    #[test]
+    fn test_oracle_stale_after_threshold() {
+        let mut tracker = OracleStalenessTracker::new(3);
+        let oracle = Decimal::from(100);
+        assert!(!tracker.update(oracle, Decimal::from(100)));
+
+        // Mark keeps moving while the oracle is frozen
+        assert!(!tracker.update(oracle, Decimal::from(101)));
+        assert!(!tracker.update(oracle, Decimal::from(102)));
+        assert!(tracker.update(oracle, Decimal::from(103)));
+        assert!(tracker.update(oracle, Decimal::from(104)));
+
+        // An oracle update resets the count
+        assert!(!tracker.update(Decimal::from(104), Decimal::from(104)));
+    }
+
+    #[test]
+    fn test_oracle_not_stale_in_quiet_market() {
+        let mut tracker = OracleStalenessTracker::new(2);
+        for _ in 0..10 {
+            assert!(!tracker.update(Decimal::from(100), Decimal::from(100)));
+        }
+    }
+
+    #[test]
+    fn test_volume_spike_with_flat_oi_and_price() {
+        let mut detector = VolumeAnomalyDetector::new(Decimal::new(5, 2), Decimal::new(1, 3));
+        let (oi, price) = (Decimal::from(1000), Decimal::from(100));
+        assert!(!detector.update(Decimal::from(1_000_000), oi, price));
+        assert!(!detector.update(Decimal::from(1_001_000), oi, price));
+
+        // 20% volume jump while OI and price stand still
+        assert!(detector.update(Decimal::from(1_200_000), oi, price));
+
+        // The same jump alongside a real price move is not flagged
+        assert!(!detector.update(Decimal::from(1_500_000), oi, Decimal::from(103)));
+    }
+
+    #[test]
+    fn test_activity_staleness_resets_on_volume_change() {
+        let mut tracker = ActivityStalenessTracker::new(Decimal::new(1, 4));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        assert_eq!(tracker.update(Decimal::from(1_000_000), at(0)), Some(Decimal::ZERO));
+
+        // Volume stagnates, with a tick below the threshold
+        assert_eq!(tracker.update(Decimal::from(1_000_000), at(10)), Some(Decimal::from(10)));
+        assert_eq!(tracker.update(Decimal::from(1_000_050), at(20)), Some(Decimal::from(20)));
+        assert_eq!(tracker.update(Decimal::from(1_000_050), at(30)), Some(Decimal::from(30)));
+
+        // Trading picks up and the staleness restarts from the new volume
+        assert_eq!(tracker.update(Decimal::from(1_005_000), at(40)), Some(Decimal::ZERO));
+        assert_eq!(tracker.update(Decimal::from(1_005_000), at(45)), Some(Decimal::from(5)));
+        assert_eq!(tracker.update(Decimal::from(1_010_000), at(50)), Some(Decimal::ZERO));
+    }
+
+    #[test]
+    fn test_bad_print_guard_flags_large_moves() {
+        let mut guard = BadPrintGuard::new(Some(Decimal::new(5, 1)));
+        assert!(!guard.is_suspect(Decimal::from(100)));
+        guard.record(Decimal::from(100));
+
+        assert!(!guard.is_suspect(Decimal::from(140)));
+        assert!(guard.is_suspect(Decimal::from(151)));
+        assert!(guard.is_suspect(Decimal::from(49)));
+
+        // A move that persists becomes the new baseline
+        for _ in 0..BAD_PRINT_REBASELINE_TICKS {
+            assert!(guard.check(Decimal::from(200)));
+        }
+        assert!(!guard.check(Decimal::from(201)));
+        assert!(!guard.check(Decimal::from(205)));
+        // A one-tick spike is flagged without moving the baseline
+        assert!(guard.check(Decimal::from(1_000)));
+        assert!(!guard.check(Decimal::from(202)));
+
+        // Without a threshold nothing is flagged
+        let mut disabled = BadPrintGuard::new(None);
+        disabled.record(Decimal::from(100));
+        assert!(!disabled.is_suspect(Decimal::from(1_000)));
+    }
+}
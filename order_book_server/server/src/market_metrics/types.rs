@@ -1,5 +1,6 @@
+use crate::market_metrics::{analytics, config::MetricsConfig, hyperliquid_client::HYPERLIQUID_SOURCE};
 use chrono::{DateTime, Utc};
-use rust_decimal::Decimal;
+use rust_decimal::{Decimal, RoundingStrategy};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,33 +12,96 @@ pub struct MarketMetrics {
     pub mark_price: Option<Decimal>,
     pub oracle_price: Option<Decimal>,
     pub mid_price: Option<Decimal>,
+    pub oracle_stale: bool,
+    /// Provider the mark, oracle and funding data came from
+    pub source: Option<String>,
+    /// Prices from every configured provider, tagged with their source
+    pub provider_prices: Vec<ProviderMarketData>,
+    /// The mid jumped implausibly far from the last stored mid, see [`crate::market_metrics::trackers::BadPrintGuard`]
+    pub suspected_bad_print: bool,
 
     // Order book data
     pub best_bid: Option<Decimal>,
     pub best_ask: Option<Decimal>,
     pub spread: Option<Decimal>,
     pub spread_pct: Option<Decimal>,
+    /// Best bid was at or above best ask, so `spread` and `spread_pct` are zero or negative
+    pub book_crossed: bool,
+    pub spread_autocorr: Option<Decimal>,
+    /// Size-weighted average ask minus size-weighted average bid across the book
+    pub size_weighted_spread: Option<Decimal>,
+    /// 95% band around the mid, see [`crate::market_metrics::analytics::mid_confidence`]
+    pub mid_ci_low: Option<Decimal>,
+    pub mid_ci_high: Option<Decimal>,
+    /// Annualized volatility of mid log returns over `realized_vol_window_secs`
+    pub realized_vol: Option<Decimal>,
+    /// Bid/ask skew of the 5% depth band, see [`crate::market_metrics::analytics::depth_imbalance`]
+    pub order_book_imbalance: Option<Decimal>,
+    /// Mid weighted by top-of-book sizes, see [`crate::market_metrics::analytics::microprice`]
+    pub microprice: Option<Decimal>,
 
     // Market data from Hyperliquid
     pub funding_rate_pct: Option<Decimal>,
+    /// Funding scaled by available depth, see [`crate::market_metrics::analytics::liquidity_weighted_funding`]
+    pub liquidity_weighted_funding: Option<Decimal>,
+    /// Mark-oracle basis beyond what funding explains, see [`crate::market_metrics::analytics::basis_divergence`]
+    pub basis_divergence: Option<Decimal>,
+    /// Hyperliquid mark over the other exchange's, see [`crate::market_metrics::analytics::cross_exchange_basis_pct`]
+    pub basis_pct: Option<Decimal>,
+    /// Hyperliquid hourly funding minus the other exchange's, in percentage points
+    pub funding_differential: Option<Decimal>,
     pub open_interest: Option<Decimal>,
     pub volume_24h: Option<Decimal>,
+    pub volume_anomaly: bool,
+    /// Seconds since 24h volume last changed meaningfully, a proxy for time since the last trade
+    pub activity_staleness_secs: Option<Decimal>,
 
     // Liquidity depth from order book
     pub bid_depth_5pct: Option<Decimal>,
     pub ask_depth_5pct: Option<Decimal>,
     pub total_depth_5pct: Option<Decimal>,
+    /// Base-asset quantity resting within 5% of the mid on each side
+    pub bid_qty_5pct: Option<Decimal>,
+    pub ask_qty_5pct: Option<Decimal>,
+    /// Steadiness of `total_depth_5pct` over a rolling window, see
+    /// [`crate::market_metrics::analytics::depth_stability`]
+    pub depth_stability: Option<Decimal>,
     pub bid_depth_10pct: Option<Decimal>,
     pub ask_depth_10pct: Option<Decimal>,
     pub total_depth_10pct: Option<Decimal>,
     pub bid_depth_25pct: Option<Decimal>,
     pub ask_depth_25pct: Option<Decimal>,
     pub total_depth_25pct: Option<Decimal>,
+    /// `max_book_levels` cut off levels inside the widest depth band, so the depths are lower bounds
+    pub depth_truncated: bool,
+    pub bid_depth_elasticity: Option<Decimal>,
+    pub ask_depth_elasticity: Option<Decimal>,
+    /// `(level, (bid, ask))` notional depth at each configured depth level
+    pub depth_levels: Vec<(Decimal, (Decimal, Decimal))>,
+    /// `(band_pct, imbalance)` for each depth band, see [`OrderBookMetrics::imbalance_term_structure`]
+    pub imbalance_term_structure: Vec<(Decimal, Decimal)>,
+    /// `(size, effective_spread_pct)` cost curve, see [`OrderBookMetrics::spread_by_size`]
+    pub spread_by_size: Vec<(Decimal, Option<Decimal>)>,
+    /// Buy and sell fills of each configured order size against the live book
+    pub slippage_curve: Vec<SlippagePoint>,
+    /// Some size in `slippage_curve` could only be partially filled
+    pub slippage_partial_fill: bool,
+    /// Per-phase collection timings, kept when `store_timing_breakdown` is set
+    pub timing_breakdown: Option<TimingBreakdown>,
 
     // Impact prices from Hyperliquid
     pub premium: Option<Decimal>,
     pub impact_px_bid: Option<Decimal>,
     pub impact_px_ask: Option<Decimal>,
+    pub impact_adjusted_mid: Option<Decimal>,
+
+    // Order book activity
+    pub quote_update_rate: Option<Decimal>,
+    pub quote_stuffing_suspected: bool,
+    /// Large levels away from mid were repeatedly pulled before the price reached them
+    pub spoofing_suspected: bool,
+    /// Fraction of recent large levels away from mid that were pulled without trading
+    pub spoofing_score: Option<Decimal>,
 
     // Latency metrics
     pub node_latency_ms: Option<i32>,
@@ -50,13 +114,18 @@ pub struct HyperliquidMarketData {
     pub coin: String,
     pub mark_price: Decimal,
     pub oracle_price: Decimal,
-    pub mid_price: Decimal,
+    /// `None` when Hyperliquid reports no mid, e.g. during a trading halt
+    pub mid_price: Option<Decimal>,
     pub funding_rate_pct: Decimal,
     pub open_interest: Decimal,
     pub volume_24h: Decimal,
     pub premium: Decimal,
     pub impact_px_bid: Option<Decimal>,
     pub impact_px_ask: Option<Decimal>,
+    /// Round trip of the API request this data came from
+    pub latency_ms: Option<i32>,
+    /// When this data was received from Hyperliquid
+    pub fetched_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,17 +135,165 @@ pub struct OrderBookMetrics {
     pub mid_price: Decimal,
     pub spread: Decimal,
     pub spread_pct: Decimal,
+    /// `best_bid >= best_ask`, from a fast market or a stale snapshot
+    pub book_crossed: bool,
+    pub size_weighted_spread: Option<Decimal>,
+    /// `(bid_depth_5pct - ask_depth_5pct) / total_depth_5pct`, `None` on an empty band
+    pub order_book_imbalance: Option<Decimal>,
+    /// Top-of-book microprice, `None` without size at the best prices
+    pub microprice: Option<Decimal>,
+    /// Resting size across all orders at the best bid and best ask
+    pub best_bid_size: Decimal,
+    pub best_ask_size: Decimal,
     pub total_bids: usize,
     pub total_asks: usize,
+    /// Cumulative book diffs applied for this coin, used to derive the update
+    /// rate; `None` for sources that only deliver snapshots
+    pub update_count: Option<u64>,
     pub bid_depth_5pct: Decimal,
     pub ask_depth_5pct: Decimal,
     pub total_depth_5pct: Decimal,
+    /// Base-asset quantity within the 5% band, in the coin's own units so only
+    /// comparable over time for the same coin
+    pub bid_qty_5pct: Decimal,
+    pub ask_qty_5pct: Decimal,
     pub bid_depth_10pct: Decimal,
     pub ask_depth_10pct: Decimal,
     pub total_depth_10pct: Decimal,
     pub bid_depth_25pct: Decimal,
     pub ask_depth_25pct: Decimal,
     pub total_depth_25pct: Decimal,
+    /// Levels inside the widest depth band were left out by `max_book_levels`,
+    /// see [`MetricsConfig::widest_depth_band`]
+    pub depth_truncated: bool,
+    pub bid_depth_elasticity: Option<Decimal>,
+    pub ask_depth_elasticity: Option<Decimal>,
+    /// `(level, (bid, ask))` notional depth within each configured fraction of mid
+    pub depth_levels: Vec<(Decimal, (Decimal, Decimal))>,
+    /// `(size, effective_spread_pct)` for each configured trade size
+    pub spread_by_size: Vec<(Decimal, Option<Decimal>)>,
+    /// Buy and sell fills for each configured order size
+    pub slippage_curve: Vec<SlippagePoint>,
+    /// Age of the node order book snapshot when the metrics were computed
+    pub snapshot_age_ms: Option<i32>,
+    /// `(price, size)` levels large and far enough from mid to be watched for spoofing
+    pub large_bids: Vec<(Decimal, Decimal)>,
+    pub large_asks: Vec<(Decimal, Decimal)>,
+    /// Price of every level, for telling large levels that were pulled from ones that shrank
+    pub bid_prices: Vec<Decimal>,
+    pub ask_prices: Vec<Decimal>,
+}
+
+/// Market order of a given notional filled against one side of the book
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FillEstimate {
+    /// Average fill price, over the filled part on a partial fill; `None` on an empty side
+    pub avg_price: Option<Decimal>,
+    /// Distance of `avg_price` from mid, as a percent of mid
+    pub slippage_pct: Option<Decimal>,
+    /// Notional the book could fill, short of the order size on a partial fill
+    pub filled_notional: Decimal,
+    /// The side ran out of depth before the full size was filled
+    pub partial: bool,
+}
+
+/// Market buy and sell fills for one order size
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlippagePoint {
+    /// Order notional
+    pub size: Decimal,
+    /// Buy walking the asks
+    pub buy: FillEstimate,
+    /// Sell walking the bids
+    pub sell: FillEstimate,
+}
+
+/// Wall-clock time spent in each phase of one collection, in milliseconds
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TimingBreakdown {
+    /// Hyperliquid market data lookup
+    pub hl_lookup_ms: f64,
+    /// Order book snapshot from the node listener or the `l2Book` endpoint
+    pub book_snapshot_ms: f64,
+    /// Depth, spread and slippage computed from the snapshot
+    pub depth_computation_ms: f64,
+    /// Whole collection; rows are inserted in batches afterwards, so insert
+    /// time is logged per batch rather than attributed to a row
+    pub total_ms: f64,
+}
+
+impl TimingBreakdown {
+    /// `(phase, ms)` for every phase, in collection order
+    #[must_use]
+    pub const fn phases(&self) -> [(&'static str, f64); 3] {
+        [
+            ("hl_lookup", self.hl_lookup_ms),
+            ("book_snapshot", self.book_snapshot_ms),
+            ("depth_computation", self.depth_computation_ms),
+        ]
+    }
+}
+
+/// A coin's prices from one exchange, see [`crate::market_metrics::market_data_provider::MarketDataProvider`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderMarketData {
+    /// Provider the prices came from, e.g. `hyperliquid` or `binance`
+    pub source: String,
+    /// The exchange's symbol for the coin, e.g. `BTCUSDT` on Binance for `BTC`
+    pub symbol: String,
+    pub mark_price: Decimal,
+    pub index_price: Option<Decimal>,
+    pub mid_price: Option<Decimal>,
+    /// Funding rate in percent per hour, normalized from the exchange's funding interval
+    pub funding_rate_pct: Option<Decimal>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// Mid-price candle for one time bucket
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Candle {
+    /// Start of the bucket
+    pub timestamp: DateTime<Utc>,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+}
+
+/// Liquidity snapshot aggregated across all monitored markets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PortfolioLiquidity {
+    pub timestamp: DateTime<Utc>,
+    /// Sum of `total_depth_5pct` over markets reporting depth
+    pub total_depth_5pct: Decimal,
+    /// `spread_pct` weighted by open interest notional
+    pub weighted_spread_pct: Option<Decimal>,
+    /// Number of markets contributing depth
+    pub market_count: i32,
+}
+
+/// Weighted aggregate of a basket's constituent markets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BasketMetrics {
+    /// Weighted mid over components with a mid, `None` when none have one
+    pub mid_price: Option<Decimal>,
+    /// Weighted `spread_pct` over components with a spread
+    pub spread_pct: Option<Decimal>,
+    /// Sum of the components' `total_depth_5pct`
+    pub total_depth_5pct: Decimal,
+    /// Number of components with a mid
+    pub component_count: i32,
+}
+
+/// How buckets without any stored mids are represented in candle queries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmptyBucketPolicy {
+    /// Leave empty buckets out of the result
+    #[default]
+    Skip,
+    /// Fill empty buckets with a flat candle at the previous close
+    CarryForward,
 }
 
 impl MarketMetrics {
@@ -87,25 +304,60 @@ impl MarketMetrics {
             mark_price: None,
             oracle_price: None,
             mid_price: None,
+            oracle_stale: false,
+            source: None,
+            provider_prices: Vec::new(),
+            suspected_bad_print: false,
             best_bid: None,
             best_ask: None,
             spread: None,
             spread_pct: None,
+            book_crossed: false,
+            spread_autocorr: None,
+            size_weighted_spread: None,
+            mid_ci_low: None,
+            mid_ci_high: None,
+            realized_vol: None,
+            order_book_imbalance: None,
+            microprice: None,
             funding_rate_pct: None,
+            liquidity_weighted_funding: None,
+            basis_divergence: None,
+            basis_pct: None,
+            funding_differential: None,
             open_interest: None,
             volume_24h: None,
+            volume_anomaly: false,
+            activity_staleness_secs: None,
             bid_depth_5pct: None,
             ask_depth_5pct: None,
             total_depth_5pct: None,
+            bid_qty_5pct: None,
+            ask_qty_5pct: None,
+            depth_stability: None,
             bid_depth_10pct: None,
             ask_depth_10pct: None,
             total_depth_10pct: None,
             bid_depth_25pct: None,
             ask_depth_25pct: None,
             total_depth_25pct: None,
+            depth_truncated: false,
+            bid_depth_elasticity: None,
+            ask_depth_elasticity: None,
+            depth_levels: Vec::new(),
+            imbalance_term_structure: Vec::new(),
+            spread_by_size: Vec::new(),
+            slippage_curve: Vec::new(),
+            slippage_partial_fill: false,
+            timing_breakdown: None,
             premium: None,
             impact_px_bid: None,
             impact_px_ask: None,
+            impact_adjusted_mid: None,
+            quote_update_rate: None,
+            quote_stuffing_suspected: false,
+            spoofing_suspected: false,
+            spoofing_score: None,
             node_latency_ms: None,
             websocket_latency_ms: None,
             total_latency_ms: None,
@@ -113,6 +365,7 @@ impl MarketMetrics {
     }
 
     pub fn merge_hyperliquid_data(&mut self, data: HyperliquidMarketData) {
+        self.source = Some(HYPERLIQUID_SOURCE.to_string());
         self.mark_price = Some(data.mark_price);
         self.oracle_price = Some(data.oracle_price);
         self.funding_rate_pct = Some(data.funding_rate_pct);
@@ -121,6 +374,8 @@ impl MarketMetrics {
         self.premium = Some(data.premium);
         self.impact_px_bid = data.impact_px_bid;
         self.impact_px_ask = data.impact_px_ask;
+        self.node_latency_ms = data.latency_ms;
+        self.update_total_latency();
     }
 
     pub fn merge_orderbook_data(&mut self, data: OrderBookMetrics) {
@@ -129,14 +384,294 @@ impl MarketMetrics {
         self.mid_price = Some(data.mid_price);
         self.spread = Some(data.spread);
         self.spread_pct = Some(data.spread_pct);
+        self.book_crossed = data.book_crossed;
+        self.size_weighted_spread = data.size_weighted_spread;
+        self.order_book_imbalance = data.order_book_imbalance;
+        self.microprice = data.microprice;
         self.bid_depth_5pct = Some(data.bid_depth_5pct);
         self.ask_depth_5pct = Some(data.ask_depth_5pct);
         self.total_depth_5pct = Some(data.total_depth_5pct);
+        self.bid_qty_5pct = Some(data.bid_qty_5pct);
+        self.ask_qty_5pct = Some(data.ask_qty_5pct);
         self.bid_depth_10pct = Some(data.bid_depth_10pct);
         self.ask_depth_10pct = Some(data.ask_depth_10pct);
         self.total_depth_10pct = Some(data.total_depth_10pct);
         self.bid_depth_25pct = Some(data.bid_depth_25pct);
         self.ask_depth_25pct = Some(data.ask_depth_25pct);
         self.total_depth_25pct = Some(data.total_depth_25pct);
+        self.depth_truncated = data.depth_truncated;
+        self.bid_depth_elasticity = data.bid_depth_elasticity;
+        self.ask_depth_elasticity = data.ask_depth_elasticity;
+        self.imbalance_term_structure = data.imbalance_term_structure();
+        self.depth_levels = data.depth_levels;
+        self.spread_by_size = data.spread_by_size;
+        self.slippage_partial_fill = data.slippage_curve.iter().any(|point| point.buy.partial || point.sell.partial);
+        self.slippage_curve = data.slippage_curve;
+        self.websocket_latency_ms = data.snapshot_age_ms;
+        self.update_total_latency();
+    }
+
+    /// Round every decimal column to the scale it is stored at, with banker's
+    /// rounding, so arithmetic noise never exceeds the column's scale.
+    ///
+    /// Price, percentage and depth columns use the configured scales, the rest
+    /// their declared ones; columns in `unbounded_numeric_fields` keep every digit.
+    pub fn round_for_storage(&mut self, config: &MetricsConfig) {
+        let round = |column: &str, value: &mut Option<Decimal>, scale: u32| {
+            if let Some(value) = value
+                && !config.unbounded_numeric_fields.iter().any(|field| field == column)
+            {
+                *value = value.round_dp_with_strategy(scale, RoundingStrategy::MidpointNearestEven);
+            }
+        };
+        let prices = [
+            ("mark_price", &mut self.mark_price),
+            ("oracle_price", &mut self.oracle_price),
+            ("mid_price", &mut self.mid_price),
+            ("best_bid", &mut self.best_bid),
+            ("best_ask", &mut self.best_ask),
+            ("spread", &mut self.spread),
+            ("size_weighted_spread", &mut self.size_weighted_spread),
+            ("mid_ci_low", &mut self.mid_ci_low),
+            ("mid_ci_high", &mut self.mid_ci_high),
+            ("microprice", &mut self.microprice),
+            ("impact_px_bid", &mut self.impact_px_bid),
+            ("impact_px_ask", &mut self.impact_px_ask),
+            ("impact_adjusted_mid", &mut self.impact_adjusted_mid),
+        ];
+        for (column, value) in prices {
+            round(column, value, config.price_scale);
+        }
+        for (column, value) in [("spread_pct", &mut self.spread_pct), ("spread_autocorr", &mut self.spread_autocorr)] {
+            round(column, value, config.pct_scale);
+        }
+        let depths = [
+            ("bid_depth_5pct", &mut self.bid_depth_5pct),
+            ("ask_depth_5pct", &mut self.ask_depth_5pct),
+            ("total_depth_5pct", &mut self.total_depth_5pct),
+            ("bid_qty_5pct", &mut self.bid_qty_5pct),
+            ("ask_qty_5pct", &mut self.ask_qty_5pct),
+            ("bid_depth_10pct", &mut self.bid_depth_10pct),
+            ("ask_depth_10pct", &mut self.ask_depth_10pct),
+            ("total_depth_10pct", &mut self.total_depth_10pct),
+            ("bid_depth_25pct", &mut self.bid_depth_25pct),
+            ("ask_depth_25pct", &mut self.ask_depth_25pct),
+            ("total_depth_25pct", &mut self.total_depth_25pct),
+        ];
+        for (column, value) in depths {
+            round(column, value, config.depth_scale);
+        }
+        let declared = [
+            ("realized_vol", &mut self.realized_vol, 8),
+            ("order_book_imbalance", &mut self.order_book_imbalance, 8),
+            ("funding_rate_pct", &mut self.funding_rate_pct, 10),
+            ("liquidity_weighted_funding", &mut self.liquidity_weighted_funding, 10),
+            ("basis_divergence", &mut self.basis_divergence, 8),
+            ("basis_pct", &mut self.basis_pct, 8),
+            ("funding_differential", &mut self.funding_differential, 10),
+            ("open_interest", &mut self.open_interest, 8),
+            ("volume_24h", &mut self.volume_24h, 8),
+            ("activity_staleness_secs", &mut self.activity_staleness_secs, 3),
+            ("depth_stability", &mut self.depth_stability, 8),
+            ("bid_depth_elasticity", &mut self.bid_depth_elasticity, 6),
+            ("ask_depth_elasticity", &mut self.ask_depth_elasticity, 6),
+            ("premium", &mut self.premium, 10),
+            ("quote_update_rate", &mut self.quote_update_rate, 8),
+            ("spoofing_score", &mut self.spoofing_score, 8),
+        ];
+        for (column, value, scale) in declared {
+            round(column, value, scale);
+        }
+    }
+
+    /// Sum of whichever latencies have been measured
+    fn update_total_latency(&mut self) {
+        self.total_latency_ms = match (self.node_latency_ms, self.websocket_latency_ms) {
+            (Some(node), Some(websocket)) => Some(node.saturating_add(websocket)),
+            (node, websocket) => node.or(websocket),
+        };
+    }
+}
+
+impl OrderBookMetrics {
+    /// Effective spread of a round trip of each notional size in `sizes`, as `(size, spread_pct)`.
+    ///
+    /// Walks `bid_levels` and `ask_levels` (`(price, size)`, best first) to the
+    /// average price of buying and of selling `size` notional, and reports their
+    /// gap as a percent of `mid`. Sizes either side of the book can't fill are
+    /// marked with `None`.
+    #[must_use]
+    pub fn spread_by_size(
+        bid_levels: &[(Decimal, Decimal)],
+        ask_levels: &[(Decimal, Decimal)],
+        mid: Decimal,
+        sizes: &[Decimal],
+    ) -> Vec<(Decimal, Option<Decimal>)> {
+        sizes
+            .iter()
+            .map(|&size| {
+                let spread_pct = (mid > Decimal::ZERO)
+                    .then(|| Some((fill_price(ask_levels, size)? - fill_price(bid_levels, size)?) / mid))
+                    .flatten()
+                    .map(|spread| spread * Decimal::ONE_HUNDRED);
+                (size, spread_pct)
+            })
+            .collect()
+    }
+
+    /// Depth imbalance `(bid - ask) / (bid + ask)` at each depth level, as `(band_pct, imbalance)`.
+    ///
+    /// Positive values mean more bid depth. Comparing bands shows whether an
+    /// imbalance near the touch persists further out. Bands with no depth on
+    /// either side are omitted.
+    #[must_use]
+    pub fn imbalance_term_structure(&self) -> Vec<(Decimal, Decimal)> {
+        self.depth_levels
+            .iter()
+            .filter_map(|(level, (bid, ask))| {
+                Some(((level * Decimal::ONE_HUNDRED).normalize(), analytics::depth_imbalance(*bid, *ask)?))
+            })
+            .collect()
+    }
+}
+
+/// Average price paid to fill `notional` against `levels`, `None` if they can't fill it
+fn fill_price(levels: &[(Decimal, Decimal)], notional: Decimal) -> Option<Decimal> {
+    if notional <= Decimal::ZERO {
+        return None;
+    }
+    let (filled, quantity) = fill_against(levels, notional);
+    (filled == notional).then(|| notional / quantity)
+}
+
+/// Fill up to `notional` against `levels` (`(price, size)`, best first), as the
+/// notional filled and the quantity it bought, short of `notional` if the levels run out
+pub(crate) fn fill_against(levels: &[(Decimal, Decimal)], notional: Decimal) -> (Decimal, Decimal) {
+    let mut filled = Decimal::ZERO;
+    let mut quantity = Decimal::ZERO;
+    for &(price, size) in levels.iter().filter(|(price, _)| *price > Decimal::ZERO) {
+        if filled >= notional {
+            break;
+        }
+        let take = (notional - filled).min(price * size);
+        filled += take;
+        quantity += take / price;
+    }
+    (filled, quantity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Order book metrics with `(bid, ask)` depths for the 5%, 10% and 25% bands
+    fn orderbook_metrics(depths: [(i64, i64); 3]) -> OrderBookMetrics {
+        let [near, mid, far] = depths.map(|(bid, ask)| (Decimal::from(bid), Decimal::from(ask)));
+        OrderBookMetrics {
+            best_bid: Decimal::from(99),
+            best_ask: Decimal::from(101),
+            mid_price: Decimal::from(100),
+            spread: Decimal::from(2),
+            spread_pct: Decimal::from(2),
+            book_crossed: false,
+            size_weighted_spread: None,
+            order_book_imbalance: None,
+            microprice: None,
+            best_bid_size: Decimal::ONE,
+            best_ask_size: Decimal::ONE,
+            total_bids: 0,
+            total_asks: 0,
+            update_count: None,
+            bid_depth_5pct: near.0,
+            ask_depth_5pct: near.1,
+            total_depth_5pct: near.0 + near.1,
+            bid_qty_5pct: Decimal::ZERO,
+            ask_qty_5pct: Decimal::ZERO,
+            bid_depth_10pct: mid.0,
+            ask_depth_10pct: mid.1,
+            total_depth_10pct: mid.0 + mid.1,
+            bid_depth_25pct: far.0,
+            ask_depth_25pct: far.1,
+            total_depth_25pct: far.0 + far.1,
+            depth_truncated: false,
+            bid_depth_elasticity: None,
+            ask_depth_elasticity: None,
+            depth_levels: [5, 10, 25].map(|pct| Decimal::new(pct, 2)).into_iter().zip([near, mid, far]).collect(),
+            spread_by_size: Vec::new(),
+            slippage_curve: Vec::new(),
+            snapshot_age_ms: None,
+            large_bids: Vec::new(),
+            large_asks: Vec::new(),
+            bid_prices: Vec::new(),
+            ask_prices: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_spread_by_size_grows_with_size() {
+        let ladder = |prices: [i64; 3]| prices.map(|price| (Decimal::from(price), Decimal::from(10))).to_vec();
+        let (bids, asks) = (ladder([99, 98, 97]), ladder([101, 102, 103]));
+        let sizes = [500, 1500, 2500, 5000].map(Decimal::from);
+
+        let curve = OrderBookMetrics::spread_by_size(&bids, &asks, Decimal::from(100), &sizes);
+        let spreads = curve.iter().map(|(_, spread)| *spread).collect::<Vec<_>>();
+
+        // Filled at the touch, the effective spread is the quoted one
+        assert_eq!(spreads[0], Some(Decimal::from(2)));
+        assert!(spreads[0] < spreads[1] && spreads[1] < spreads[2]);
+        // Each side only holds about 3000 of notional
+        assert_eq!(curve[3], (Decimal::from(5000), None));
+
+        assert!(OrderBookMetrics::spread_by_size(&bids, &asks, Decimal::ZERO, &sizes).iter().all(|(_, s)| s.is_none()));
+    }
+
+    #[test]
+    fn test_imbalance_term_structure() {
+        // Bid-heavy near the touch, balanced further out
+        let metrics = orderbook_metrics([(300, 100), (500, 300), (1000, 1000)]);
+        assert_eq!(
+            metrics.imbalance_term_structure(),
+            vec![
+                (Decimal::from(5), Decimal::new(5, 1)),
+                (Decimal::from(10), Decimal::new(25, 2)),
+                (Decimal::from(25), Decimal::ZERO),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_imbalance_term_structure_skips_empty_bands() {
+        let metrics = orderbook_metrics([(0, 0), (0, 200), (100, 300)]);
+        assert_eq!(
+            metrics.imbalance_term_structure(),
+            vec![(Decimal::from(10), -Decimal::ONE), (Decimal::from(25), Decimal::new(-5, 1))]
+        );
+    }
+
+    #[test]
+    fn test_total_latency_sums_sources() {
+        let mut metrics = MarketMetrics::new("BTC".to_string());
+        let mut orderbook = orderbook_metrics([(1, 1); 3]);
+        orderbook.snapshot_age_ms = Some(40);
+        metrics.merge_orderbook_data(orderbook);
+        assert_eq!(metrics.total_latency_ms, Some(40));
+
+        metrics.merge_hyperliquid_data(HyperliquidMarketData {
+            coin: "BTC".to_string(),
+            mark_price: Decimal::from(100),
+            oracle_price: Decimal::from(100),
+            mid_price: None,
+            funding_rate_pct: Decimal::ZERO,
+            open_interest: Decimal::ZERO,
+            volume_24h: Decimal::ZERO,
+            premium: Decimal::ZERO,
+            impact_px_bid: None,
+            impact_px_ask: None,
+            latency_ms: Some(120),
+            fetched_at: Utc::now(),
+        });
+        assert_eq!(metrics.node_latency_ms, Some(120));
+        assert_eq!(metrics.websocket_latency_ms, Some(40));
+        assert_eq!(metrics.total_latency_ms, Some(160));
     }
 }
@@ -55,11 +55,11 @@ pub async fn run_websocket_server(
     }
 
     // Start market metrics monitoring if enabled (spawn after server starts to avoid blocking)
-    if enable_metrics {
+    let metrics_monitor = enable_metrics.then(|| {
         info!("📊 Market metrics monitoring enabled - will initialize in background");
         let listener_for_metrics = listener.clone();
-        tokio::spawn(init_metrics_monitor(listener_for_metrics));
-    }
+        tokio::spawn(init_metrics_monitor(listener_for_metrics))
+    });
 
     let websocket_opts =
         yawc::Options::default().with_compression_level(yawc::CompressionLevel::new(compression_level));
@@ -76,14 +76,56 @@ pub async fn run_websocket_server(
     let listener = TcpListener::bind(address).await?;
     info!("WebSocket server running at ws://{address}");
 
-    if let Err(err) = axum::serve(listener, app.into_make_service()).await {
-        error!("Server fatal error: {err}");
-        std::process::exit(2);
+    select! {
+        result = axum::serve(listener, app.into_make_service()) => {
+            if let Err(err) = result {
+                error!("Server fatal error: {err}");
+                std::process::exit(2);
+            }
+        }
+        () = shutdown_signal() => info!("Shutdown signal received"),
+    }
+
+    // Let in-flight metrics collections finish and flush before exiting
+    if let Some(handle) = metrics_monitor
+        && let Ok(Some(monitor)) = handle.await
+    {
+        monitor.shutdown().await;
     }
 
     Ok(())
 }
 
+/// Resolves on Ctrl-C, or SIGTERM on Unix
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(err) => {
+                error!("Failed to listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
+
 fn ws_handler(
     incoming: yawc::IncomingUpgrade,
     internal_message_tx: Sender<Arc<InternalMessage>>,
@@ -384,32 +426,38 @@ impl Subscription {
     }
 }
 
-async fn init_metrics_monitor(listener: Arc<Mutex<OrderBookListener>>) {
-    use crate::market_metrics::{MarketMetricsMonitor, MetricsConfig};
+/// Start metrics monitoring, returning the running monitor so it can be shut down
+async fn init_metrics_monitor(
+    listener: Arc<Mutex<OrderBookListener>>,
+) -> Option<Arc<crate::market_metrics::MarketMetricsMonitor>> {
+    use crate::market_metrics::{MarketMetricsMonitor, MetricsConfig, config::redact_credentials};
 
     info!("📊 Initializing market metrics monitoring...");
 
-    match MetricsConfig::from_env() {
+    match MetricsConfig::load() {
         Ok(config) => {
             info!("✅ Metrics config loaded: {:?}", config.target_markets);
-            info!("   Database: {}...", &config.database_url[..config.database_url.len().min(30)]);
+            info!("   Database: {}", redact_credentials(&config.database_url));
 
-            match MarketMetricsMonitor::new(config, listener).await {
+            match MarketMetricsMonitor::new(config, listener).await.map_err(|e| e.to_string()) {
                 Ok(monitor) => {
                     info!("✅ Market metrics monitor initialized");
                     let monitor = Arc::new(monitor);
-                    monitor.start().await;
+                    monitor.clone().start().await;
+                    Some(monitor)
                 }
                 Err(e) => {
                     error!("❌ Failed to initialize market metrics monitor: {}", e);
                     error!("   Server will continue without metrics monitoring");
+                    None
                 }
             }
         }
         Err(e) => {
             error!("❌ Failed to load metrics config: {}", e);
-            error!("   Required: DATABASE_URL and TARGET_MARKETS environment variables");
+            error!("   Required: DATABASE_URL and TARGET_MARKETS environment variables, or METRICS_CONFIG_FILE");
             error!("   Server will continue without metrics monitoring");
+            None
         }
     }
 }